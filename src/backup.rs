@@ -0,0 +1,249 @@
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{backup::Backup, Connection};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+const PREFIX: &str = "tabletop-bot-";
+const EXTENSION: &str = "sqlite";
+
+#[derive(Debug)]
+pub(crate) enum Error {
+    Io(std::io::Error),
+    R2d2(r2d2::Error),
+    Sqlite(rusqlite::Error),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<r2d2::Error> for Error {
+    fn from(e: r2d2::Error) -> Self {
+        Error::R2d2(e)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Sqlite(e)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "IO error: {}", e),
+            Error::R2d2(e) => write!(f, "R2D2 error: {}", e),
+            Error::Sqlite(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Where backups are written, and how many are kept.
+#[derive(Clone, Debug)]
+pub(crate) struct Backups {
+    pub dir: PathBuf,
+    pub retain: usize,
+}
+
+impl Backups {
+    /// Backs up the database to a new timestamped file, then prunes old backups.
+    pub(crate) fn run(&self, conn: &Connection) -> Result<PathBuf> {
+        let path = backup(conn, &self.dir)?;
+        let pruned = prune(&self.dir, self.retain)?;
+        if pruned > 0 {
+            log::info!("Pruned {} old backup(s)", pruned);
+        }
+
+        Ok(path)
+    }
+}
+
+/// Copies the database to a timestamped file in `dir` using SQLite's online backup API, which is
+/// safe to run while other connections are writing.
+pub(crate) fn backup(conn: &Connection, dir: &Path) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+
+    let name = format!(
+        "{}{}.{}",
+        PREFIX,
+        Utc::now().format("%Y%m%dT%H%M%S%.3fZ"),
+        EXTENSION
+    );
+    let path = dir.join(name);
+
+    let mut dst = Connection::open(&path)?;
+    let backup = Backup::new(conn, &mut dst)?;
+    backup.run_to_completion(100, Duration::from_millis(50), None)?;
+
+    log::info!("Backed up database to {}", path.display());
+    Ok(path)
+}
+
+/// Deletes all but the newest `retain` backups in `dir`, returning how many were deleted.
+pub(crate) fn prune(dir: &Path, retain: usize) -> Result<usize> {
+    let mut backups = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| is_backup(path))
+        .collect::<Vec<_>>();
+
+    // Timestamps in the names sort chronologically.
+    backups.sort();
+
+    let expired = backups.len().saturating_sub(retain);
+    for path in &backups[..expired] {
+        fs::remove_file(path)?;
+    }
+
+    Ok(expired)
+}
+
+/// The time of the most recent backup in `dir`, if any.
+pub(crate) fn latest(dir: &Path) -> Result<Option<DateTime<Utc>>> {
+    if !dir.exists() {
+        return Ok(None);
+    }
+
+    let latest = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| is_backup(&entry.path()))
+        .filter_map(|entry| entry.metadata().and_then(|meta| meta.modified()).ok())
+        .max()
+        .map(DateTime::<Utc>::from);
+
+    Ok(latest)
+}
+
+fn is_backup(path: &Path) -> bool {
+    let name = path.file_name().and_then(|name| name.to_str());
+    let extension = path.extension().and_then(|extension| extension.to_str());
+
+    name.is_some_and(|name| name.starts_with(PREFIX)) && extension == Some(EXTENSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    /// A directory for a test's backups, deleted when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "tabletop-bot-backup-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            Self(dir)
+        }
+
+        fn files(&self) -> Vec<String> {
+            let mut files = fs::read_dir(&self.0)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect::<Vec<_>>();
+            files.sort();
+            files
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn touch(dir: &Path, name: &str) {
+        fs::write(dir.join(name), "").unwrap();
+    }
+
+    #[test]
+    fn backup_contains_the_rows() {
+        let dir = TempDir::new("rows");
+        let conn = db::test_connection();
+        db::set_setting(&conn, 1, "prefix", "?").unwrap();
+
+        let path = backup(&conn, &dir.0).unwrap();
+
+        assert!(is_backup(&path));
+        let copy = Connection::open(&path).unwrap();
+        assert_eq!(
+            db::get_setting::<String>(&copy, 1, "prefix").unwrap(),
+            Some("?".to_string())
+        );
+    }
+
+    #[test]
+    fn oldest_backups_are_pruned() {
+        let dir = TempDir::new("prune");
+        fs::create_dir_all(&dir.0).unwrap();
+        for name in [
+            "tabletop-bot-20240103T000000.000Z.sqlite",
+            "tabletop-bot-20240101T000000.000Z.sqlite",
+            "tabletop-bot-20240102T000000.000Z.sqlite",
+            "notes.sqlite",
+            "tabletop-bot-20230101T000000.000Z.txt",
+        ] {
+            touch(&dir.0, name);
+        }
+
+        assert_eq!(prune(&dir.0, 2).unwrap(), 1);
+        assert_eq!(
+            dir.files(),
+            vec![
+                "notes.sqlite",
+                "tabletop-bot-20230101T000000.000Z.txt",
+                "tabletop-bot-20240102T000000.000Z.sqlite",
+                "tabletop-bot-20240103T000000.000Z.sqlite",
+            ]
+        );
+        assert_eq!(prune(&dir.0, 2).unwrap(), 0);
+        assert_eq!(prune(&dir.0, 0).unwrap(), 2);
+    }
+
+    #[test]
+    fn run_backs_up_and_prunes() {
+        let dir = TempDir::new("run");
+        fs::create_dir_all(&dir.0).unwrap();
+        touch(&dir.0, "tabletop-bot-20240101T000000.000Z.sqlite");
+        let backups = Backups {
+            dir: dir.0.clone(),
+            retain: 1,
+        };
+
+        let path = backups.run(&db::test_connection()).unwrap();
+
+        assert_eq!(
+            dir.files(),
+            vec![path.file_name().unwrap().to_str().unwrap()]
+        );
+    }
+
+    #[test]
+    fn latest_backup_time() {
+        let dir = TempDir::new("latest");
+
+        assert!(latest(&dir.0).unwrap().is_none());
+        fs::create_dir_all(&dir.0).unwrap();
+        touch(&dir.0, "notes.sqlite");
+        assert!(latest(&dir.0).unwrap().is_none());
+
+        let before = Utc::now() - chrono::Duration::seconds(5);
+        backup(&db::test_connection(), &dir.0).unwrap();
+        assert!(latest(&dir.0).unwrap().unwrap() >= before);
+    }
+}
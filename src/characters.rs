@@ -0,0 +1,112 @@
+use std::fmt::Display;
+
+use poise::serenity_prelude as serenity;
+
+use crate::db::{Character, Player};
+
+/// The longest a character's name or class can be.
+pub(crate) const MAX_NAME_LEN: usize = 60;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Error {
+    EmptyName,
+    NameTooLong,
+    ClassTooLong,
+    InvalidSheetUrl,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::EmptyName => write!(f, "A character needs a name"),
+            Error::NameTooLong => write!(
+                f,
+                "A character's name can be at most {} characters long",
+                MAX_NAME_LEN
+            ),
+            Error::ClassTooLong => write!(
+                f,
+                "A character's class can be at most {} characters long",
+                MAX_NAME_LEN
+            ),
+            Error::InvalidSheetUrl => {
+                write!(f, "A sheet link must be an http:// or https:// URL")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Builds a character from what was given to `/character set`, trimming each field. Blank
+/// optional fields are left out.
+pub(crate) fn normalize(
+    name: &str,
+    class: Option<&str>,
+    sheet_url: Option<&str>,
+) -> Result<Character, Error> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Error::EmptyName);
+    }
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(Error::NameTooLong);
+    }
+
+    let class = class.map(str::trim).filter(|class| !class.is_empty());
+    if class.is_some_and(|class| class.chars().count() > MAX_NAME_LEN) {
+        return Err(Error::ClassTooLong);
+    }
+
+    let sheet_url = sheet_url.map(str::trim).filter(|url| !url.is_empty());
+    if sheet_url.is_some_and(|url| !is_web_url(url)) {
+        return Err(Error::InvalidSheetUrl);
+    }
+
+    Ok(Character {
+        name: name.to_string(),
+        class: class.map(str::to_string),
+        sheet_url: sheet_url.map(str::to_string),
+    })
+}
+
+/// Whether `url` is an http(s) URL with a host, which is all Discord needs to link it.
+fn is_web_url(url: &str) -> bool {
+    let lower = url.to_lowercase();
+    let Some(rest) = lower
+        .strip_prefix("https://")
+        .or_else(|| lower.strip_prefix("http://"))
+    else {
+        return false;
+    };
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+
+    !host.is_empty() && !url.contains(char::is_whitespace)
+}
+
+/// How to show a player: their character's name with their own in brackets, like
+/// `Valeros (Dave)`, or just their own without a character.
+pub(crate) fn display_name(player: &Player, nick: &str) -> String {
+    match &player.character {
+        Some(character) => format!("{} ({})", character.name, nick),
+        None => nick.to_string(),
+    }
+}
+
+/// Builds the embed showing a player's character, linking their sheet.
+pub(crate) fn embed(player: &Player, character: &Character) -> serenity::CreateEmbed {
+    let mut embed = serenity::CreateEmbed::new()
+        .title(&character.name)
+        .field("Player", format!("<@{}>", player.id), true)
+        .field("Experience", player.experience.to_string(), true);
+    if let Some(class) = &character.class {
+        embed = embed.description(class);
+    }
+    if let Some(sheet_url) = &character.sheet_url {
+        embed = embed
+            .url(sheet_url)
+            .field("Sheet", format!("[Open]({})", sheet_url), false);
+    }
+
+    embed
+}
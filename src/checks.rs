@@ -0,0 +1,96 @@
+use std::fmt::Display;
+
+use evaluroll::ast::Output;
+use poise::serenity_prelude as serenity;
+use rand::Rng;
+use tabletop_bot::roll::{self, Crit, Note};
+
+use crate::discord;
+
+/// The most players a group check rolls for at once.
+pub(crate) const MAX_PLAYERS: usize = 20;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Error {
+    NoPlayers,
+    TooManyPlayers,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::NoPlayers => write!(
+                f,
+                "Mention who's rolling, or say `all` for every registered player"
+            ),
+            Error::TooManyPlayers => write!(
+                f,
+                "A group check can roll for at most {} players",
+                MAX_PLAYERS
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Who's rolling a group check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Players {
+    /// Every registered player.
+    All,
+    Mentioned(Vec<serenity::UserId>),
+}
+
+/// Parses who's rolling a group check: `all`, or mentions of each of them.
+pub(crate) fn parse_players(text: &str) -> Result<Players, Error> {
+    if text.trim().eq_ignore_ascii_case("all") {
+        return Ok(Players::All);
+    }
+
+    let users = discord::parse_mentions(text);
+    match users.len() {
+        0 => Err(Error::NoPlayers),
+        count if count > MAX_PLAYERS => Err(Error::TooManyPlayers),
+        _ => Ok(Players::Mentioned(users)),
+    }
+}
+
+/// One player's roll in a group check.
+pub(crate) struct CheckRoll<T> {
+    pub player: T,
+    pub crit: Option<Crit>,
+    pub output: Output,
+    pub notes: Vec<Note>,
+}
+
+/// Rolls the same dice for each player, independently.
+pub(crate) fn roll_each<T: Clone, R: Rng + ?Sized>(
+    dice: &str,
+    players: &[T],
+    rng: &mut R,
+) -> Result<Vec<CheckRoll<T>>, evaluroll::Error> {
+    players
+        .iter()
+        .map(|player| {
+            let (crit, output, notes) = roll::roll(dice, rng)?;
+            Ok(CheckRoll {
+                player: player.clone(),
+                crit,
+                output,
+                notes,
+            })
+        })
+        .collect()
+}
+
+/// Whether a roll's total beats a DC, which it does by meeting it.
+pub(crate) fn passes(total: i32, dc: i32) -> bool {
+    total >= dc
+}
+
+/// Whether a group succeeds at a check, given how many of them passed: it does when at least
+/// half of them did.
+pub(crate) fn group_passes(passed: usize, players: usize) -> bool {
+    players > 0 && passed * 2 >= players
+}
@@ -1,199 +1,4000 @@
-use crate::{db, discord, Context, Error, Result};
-use futures::future;
-use poise::{command, serenity_prelude as serenity};
+use crate::{
+    backup, characters, checks, components, customdice, db, deathsaves, discord, downtime,
+    generator, help, initiative, loot,
+    notify::{self, Event, Toggle},
+    permissions::{in_roll_channel, is_gm},
+    polls, recaps, resources,
+    scheduler::Scheduler,
+    selfcheck,
+    settings::{self, RollMode, Settings},
+    sql, tables, templates, timers, BotData, Context, Error, Result,
+};
+use futures::{future, future::BoxFuture};
+use poise::{command, serenity_prelude as serenity, CreateReply};
+use rand::Rng;
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tabletop_bot::roll::{self, Crit};
 
-// Adds experience to a player
-#[command(slash_command)]
+/// Suggests the registered players in this server whose names start with what's been typed.
+async fn autocomplete_player(ctx: Context<'_>, partial: &str) -> Vec<serenity::AutocompleteChoice> {
+    let guild_id = ctx.guild_id().map(|guild_id| guild_id.get() as i64);
+    let players = ctx
+        .data()
+        .pool
+        .get()
+        .map_err(Error::from)
+        .and_then(|conn| Ok(db::get_players(&conn)?));
+    let players = match players {
+        Ok(players) => players
+            .into_iter()
+            .filter(|player| player.guild_id.is_none() || player.guild_id == guild_id)
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            log::error!("Error getting players to autocomplete: {}", e);
+            return Vec::new();
+        }
+    };
+
+    // Nicknames are cached, so only the first lookup of each player goes to Discord.
+    let names = future::join_all(
+        players
+            .iter()
+            .map(|player| discord::get_nick_or_name_by_id(ctx, &player.id)),
+    )
+    .await;
+
+    let partial = partial.to_lowercase();
+    players
+        .iter()
+        .zip(names)
+        .filter_map(|(player, name)| {
+            let name = name.ok()?;
+            name.to_lowercase()
+                .starts_with(&partial)
+                .then(|| serenity::AutocompleteChoice::new(name, player.id.to_string()))
+        })
+        .take(discord::AUTOCOMPLETE_LIMIT)
+        .collect()
+}
+
+/// Adds experience to a player
+#[command(slash_command, check = "is_gm", help_text_fn = "help::exp")]
 pub async fn exp(
     ctx: Context<'_>,
-    #[description = "Player"] player: serenity::Member,
+    #[description = "Player"]
+    #[autocomplete = "autocomplete_player"]
+    player: String,
     #[description = "Experience"] experience: u32,
+    #[description = "What it's for, e.g. defeated the wight"] reason: Option<String>,
 ) -> Result<()> {
-    let conn = ctx.data().pool.clone().get()?;
+    if !claim_interaction(ctx).await? {
+        return Ok(());
+    }
+    ctx.defer().await?;
 
-    let player_id = player.user.id.get() as i64;
-    let curr_xp = db::get_xp(&conn, player_id)?;
-    let new_xp = curr_xp + experience as i64;
+    let Some(player_id) = discord::parse_user_id(&player) else {
+        ctx.say("Pick a registered player from the suggestions.")
+            .await?;
+        return Ok(());
+    };
+    let reason = reason
+        .as_deref()
+        .map(str::trim)
+        .filter(|reason| !reason.is_empty());
+    let settings = guild_settings(ctx)?;
+    if settings.xp_require_reason && reason.is_none() {
+        ctx.say("This server requires a reason for every experience grant.")
+            .await?;
+        return Ok(());
+    }
+    let name = discord::get_nick_or_name_by_id(ctx, &(player_id.get() as i64))
+        .await
+        .unwrap_or(player);
+    let player_id = player_id.get() as i64;
+
+    let repeated = ctx
+        .data()
+        .recent_grants
+        .record((ctx.author().id, player_id, experience), Instant::now());
+    if repeated
+        && !discord::confirm(
+            ctx,
+            &format!(
+                "You just granted {} {}xp. Grant it again?",
+                name, experience
+            ),
+        )
+        .await?
+    {
+        return Ok(());
+    }
 
-    db::set_xp(&conn, player_id, new_xp)?;
+    // In one transaction, so a grant made at the same time isn't overwritten.
+    let granted = {
+        let mut conn = ctx.data().pool.clone().get()?;
+        let tx = conn.transaction()?;
+        let granted = db::get_player(&tx, player_id).and_then(|player| {
+            let new_xp = player.experience + experience as i64;
+            db::set_xp(&tx, player_id, new_xp)?;
+            Ok((player, new_xp))
+        });
+        if granted.is_ok() {
+            tx.commit()?;
+        }
+        granted
+    };
+    let (player, new_xp) = match granted {
+        Err(db::Error::UnknownPlayer(_)) => {
+            ctx.say(format!(
+                "{} is not registered. Use /registerplayer first.",
+                name
+            ))
+            .await?;
+            return Ok(());
+        }
+        granted => granted?,
+    };
 
     let response = format!(
         "Updated {}'s account from {}xp to {}xp.",
-        player.user.name, curr_xp, new_xp
+        name, player.experience, new_xp
     );
+    ctx.data().processed.reply(ctx.id(), &response);
     ctx.say(response).await?;
+
+    if let Some(channel_id) = settings.xp_announce_channel {
+        let granted_by = discord::get_nick_or_name(ctx, ctx.author().clone()).await;
+        let line = discord::format_xp_grant(
+            experience,
+            &characters::display_name(&player, &name),
+            reason,
+            &granted_by,
+        );
+        discord::announce(ctx, channel_id, &line).await?;
+    }
+
+    let event = Event::XpGranted {
+        experience,
+        reason: reason.map(str::to_string),
+        threshold: settings.xp_notify_threshold,
+    };
+    notify::send(ctx, &ctx.data().pool, &event, &[player_id]).await;
     Ok(())
 }
 
-// Returns the experience of all players.
-#[command(slash_command)]
+/// Lists the experience of all players
+#[command(slash_command, prefix_command, help_text_fn = "help::experience")]
 pub async fn experience(ctx: Context<'_>) -> Result<()> {
     log::debug!("Getting experience");
     let conn = ctx.data().pool.clone().get()?;
 
-    let id_xp = db::get_all_xp(&conn)?;
-    if id_xp.is_empty() {
+    let players = db::get_players(&conn)?;
+    if players.is_empty() {
         ctx.say("No experience yet").await?;
         return Ok(());
     }
 
-    let user_xp_futures = id_xp
+    let ids = players.iter().map(|player| player.id).collect::<Vec<_>>();
+    let nicks = discord::resolve_nicks(ctx, &ids).await?;
+    let user_xp = players
         .iter()
-        .map(|(id, xp)| async move {
-            let user = discord::get_user(ctx, id).await?;
-            let nick = discord::get_nick_or_name(ctx, user).await;
-            Ok::<_, Error>(format!("{}: {}", nick, xp))
+        .map(|player| {
+            let name = characters::display_name(player, &nicks[&player.id]);
+            format!("{}: {}", name, player.experience)
         })
         .collect::<Vec<_>>();
+    let pages = discord::paginate(&user_xp, discord::MESSAGE_LIMIT);
 
-    let user_xp = future::try_join_all(user_xp_futures).await?.join("\n");
-    let user_xp = user_xp.trim();
-
-    if user_xp.trim().is_empty() {
+    if pages.is_empty() {
         ctx.say("No experience yet").await?;
         return Ok(());
     }
 
-    log::debug!("Sending experience: {}", user_xp);
-    ctx.say(user_xp).await?;
+    log::debug!("Sending {} page(s) of experience", pages.len());
+    discord::send_pages(ctx, &pages).await?;
 
     log::debug!("Done sending experience");
     Ok(())
 }
 
-// Nominates a player as the MVP
-#[command(slash_command)]
+/// Shows a player's experience
+#[command(context_menu_command = "Show XP", help_text_fn = "help::show_xp")]
+pub async fn show_xp(ctx: Context<'_>, user: serenity::User) -> Result<()> {
+    let found = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::get_player(&conn, user.id.get() as i64)
+    };
+    let nick = discord::get_nick_or_name(ctx, user).await;
+    match found {
+        Err(db::Error::UnknownPlayer(_)) => {
+            ctx.say(format!("{} is not registered.", nick)).await?;
+        }
+        player => {
+            let player = player?;
+            ctx.say(format!(
+                "{} has {}xp.",
+                characters::display_name(&player, &nick),
+                player.experience
+            ))
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Votes for a player as the MVP
+#[command(slash_command, prefix_command, help_text_fn = "help::mvp")]
 pub async fn mvp(ctx: Context<'_>, #[description = "MVP"] mvp: serenity::Member) -> Result<()> {
-    let conn = ctx.data().pool.clone().get()?;
+    vote_for_mvp(ctx, mvp.user).await
+}
 
-    let player_id = ctx.author().id.get() as i64;
-    let mvp_id = mvp.user.id.get() as i64;
+/// Votes for a player as the MVP
+#[command(context_menu_command = "Vote MVP", help_text_fn = "help::mvp")]
+pub async fn vote_mvp(ctx: Context<'_>, user: serenity::User) -> Result<()> {
+    vote_for_mvp(ctx, user).await
+}
 
-    let result = db::vote_for_mvp(&conn, player_id, mvp_id);
-    match result {
-        Ok(_) => {
-            let nick = discord::get_nick_or_name(ctx, mvp.user).await;
-            ctx.say(format!("Your vote for {} was registered", nick))
-                .await?;
-        }
+/// Registers the invoker's MVP vote, for both /mvp and the Vote MVP menu.
+async fn vote_for_mvp(ctx: Context<'_>, mvp: serenity::User) -> Result<()> {
+    if !claim_interaction(ctx).await? {
+        return Ok(());
+    }
 
-        Err(e) => {
-            ctx.say(format!("Error voting for MVP: {}", e)).await?;
-            return Ok(());
+    let voter = ctx.author().id.get() as i64;
+    let result = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::vote_for_mvp(&conn, voter, mvp.id.get() as i64)
+    };
+    let nick = discord::get_nick_or_name(ctx, mvp).await;
+    let response = match result {
+        Err(db::Error::UnknownPlayer(id)) if id == voter => {
+            "You're not registered yet. Ask a GM to add you with /registerplayer.".to_string()
+        }
+        Err(db::Error::UnknownPlayer(_)) => {
+            format!("{} isn't registered, so can't be voted for.", nick)
+        }
+        result => {
+            result?;
+            format!("Your vote for {} was registered", nick)
         }
+    };
+    ctx.data().processed.reply(ctx.id(), &response);
+    ctx.say(response).await?;
+    Ok(())
+}
+
+/// Turns DMs about what concerns you on or off
+#[command(slash_command, help_text_fn = "help::notify")]
+pub async fn notify(
+    ctx: Context<'_>,
+    #[description = "Whether to be DMed"] notifications: Toggle,
+) -> Result<()> {
+    let on = notifications == Toggle::On;
+    {
+        let conn = ctx.data().pool.clone().get()?;
+        db::set_notify(&conn, ctx.author().id.get() as i64, on)?;
     }
+
+    let msg = if on {
+        "You'll be DMed when you win MVP, your scheduled message is sent, or you're granted a lot \
+        of experience."
+    } else {
+        "You won't be DMed any more."
+    };
+    ctx.send(CreateReply::default().content(msg).ephemeral(true))
+        .await?;
     Ok(())
 }
 
-// Registers a player
-#[command(slash_command, rename = "registerplayer")]
+/// Registers a player
+#[command(
+    slash_command,
+    rename = "registerplayer",
+    check = "is_gm",
+    help_text_fn = "help::register_player"
+)]
 pub async fn register_player(
     ctx: Context<'_>,
     #[description = "Player"] player: serenity::Member,
 ) -> Result<()> {
+    if !claim_interaction(ctx).await? {
+        return Ok(());
+    }
+
     let conn = ctx.data().pool.clone().get()?;
     let player_id = player.user.id.get() as i64;
+    let guild_id = player.guild_id.get() as i64;
 
-    db::create_player(&conn, player_id)?;
-    ctx.say(format!("Created {} with 0 experience.", player.user.name))
-        .await?;
+    match db::create_player(&conn, player_id, guild_id) {
+        Err(db::Error::PlayerExists(_)) => {
+            ctx.say(format!("{} is already registered.", player.user.name))
+                .await?;
+        }
+        created => {
+            created?;
+            let response = format!("Created {} with 0 experience.", player.user.name);
+            ctx.data().processed.reply(ctx.id(), &response);
+            ctx.say(response).await?;
+        }
+    }
     Ok(())
 }
 
-// Resolves the MVP
-#[command(slash_command, rename = "resolve-mvp")]
-pub async fn resolve_mvp(ctx: Context<'_>) -> Result<()> {
-    let mut conn = ctx.data().pool.clone().get()?;
+/// Registers every member with a role as a player
+#[command(
+    slash_command,
+    rename = "registerrole",
+    guild_only,
+    check = "is_gm",
+    help_text_fn = "help::register_role"
+)]
+pub async fn register_role(
+    ctx: Context<'_>,
+    #[description = "Role"] role: serenity::Role,
+) -> Result<()> {
+    if !claim_interaction(ctx).await? {
+        return Ok(());
+    }
+    ctx.defer().await?;
+
+    let guild_id = ctx.guild_id().expect("registerrole is guild_only");
+    let members = match discord::members_with_role(ctx, guild_id, role.id).await {
+        Ok(members) => members,
+        Err(e) if discord::is_forbidden(&e) => {
+            ctx.say(
+                "I'm not allowed to list this server's members. Turn on the Server Members \
+                intent for the bot in the Discord developer portal, or register players one at a \
+                time with /registerplayer.",
+            )
+            .await?;
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
 
-    match db::resolve_mvp(&mut conn) {
-        Ok(mvp_id) => {
-            let mvp = discord::get_user(ctx, &mvp_id).await?;
-            let nick = discord::get_nick_or_name(ctx, mvp).await;
+    let ids = members.iter().map(|id| id.get() as i64).collect::<Vec<_>>();
+    let registered = {
+        let mut conn = ctx.data().pool.clone().get()?;
+        db::create_players(&mut conn, &ids, guild_id.get() as i64)?
+    };
 
-            ctx.say(format!("The MVP is {}!", nick)).await?;
+    let response = if ids.is_empty() {
+        format!("Nobody has the {} role.", role.name)
+    } else {
+        let mut response = format!(
+            "Registered {} from {}, {} already registered.",
+            plural(registered.created, "new player", "new players"),
+            role.name,
+            plural(registered.existing, "was", "were")
+        );
+        // Players can only be registered in one server.
+        if !registered.elsewhere.is_empty() {
+            response.push_str(&format!(
+                " {} registered in another server, so not here.",
+                plural(registered.elsewhere.len(), "is", "are")
+            ));
         }
+        response
+    };
+    ctx.data().processed.reply(ctx.id(), &response);
+    ctx.say(response).await?;
+    Ok(())
+}
 
-        Err(e) => match e {
-            db::Error::MissingVotes => {
-                ctx.say("Not everyone has voted").await?;
-            }
-            db::Error::Chrono(e) => {
-                ctx.say(format!("Error parsing datetime: {}", e)).await?;
-            }
-            db::Error::Sqlite(e) => {
-                ctx.say(format!("Error querying database: {}", e)).await?;
-            }
-        },
+/// Counts something, e.g. "1 new player" or "3 new players".
+fn plural(count: usize, one: &str, many: &str) -> String {
+    if count == 1 {
+        format!("1 {}", one)
+    } else {
+        format!("{} {}", count, many)
     }
+}
+
+/// Shows and sets up players' characters
+#[command(
+    slash_command,
+    help_text_fn = "help::character",
+    subcommands("character_set", "character_show"),
+    subcommand_required
+)]
+pub async fn character(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Sets up your character, replacing the one you had
+#[command(slash_command, rename = "set")]
+pub async fn character_set(
+    ctx: Context<'_>,
+    #[description = "Your character's name"] name: String,
+    #[description = "Their class, e.g. Fighter 3"] class: Option<String>,
+    #[description = "A link to their character sheet"] sheet_url: Option<String>,
+) -> Result<()> {
+    let character = match characters::normalize(&name, class.as_deref(), sheet_url.as_deref()) {
+        Ok(character) => character,
+        Err(e) => {
+            ctx.say(format!("{}.", e)).await?;
+            return Ok(());
+        }
+    };
 
+    let set = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::set_character(&conn, ctx.author().id.get() as i64, &character)
+    };
+    match set {
+        Err(db::Error::UnknownPlayer(_)) => {
+            ctx.say("You're not registered yet. Ask a GM to add you with /registerplayer.")
+                .await?;
+        }
+        set => {
+            set?;
+            ctx.say(format!("You're now playing {}.", character.name))
+                .await?;
+        }
+    }
     Ok(())
 }
 
-// Rolls dice
-#[command(slash_command)]
-pub async fn roll(ctx: Context<'_>, #[description = "Dice"] dice: String) -> Result<()> {
-    let mut rng = ctx.data().rng.clone();
+/// Shows a player's character
+#[command(slash_command, rename = "show")]
+pub async fn character_show(
+    ctx: Context<'_>,
+    #[description = "Whose character to show, by default yours"] player: Option<serenity::User>,
+) -> Result<()> {
+    let user = player.unwrap_or_else(|| ctx.author().clone());
+    let found = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::get_player(&conn, user.id.get() as i64)
+    };
+    let player = match found {
+        Err(db::Error::UnknownPlayer(_)) => {
+            let name = discord::get_nick_or_name(ctx, user).await;
+            ctx.say(format!("{} is not registered.", name)).await?;
+            return Ok(());
+        }
+        player => player?,
+    };
 
-    match evaluroll::eval(&mut rng, &dice).map_err(|e| e.to_string()) {
-        Ok(results) => {
+    match &player.character {
+        Some(character) => {
+            ctx.send(CreateReply::default().embed(characters::embed(&player, character)))
+                .await?;
+        }
+        None => {
+            let name = discord::get_nick_or_name(ctx, user).await;
             ctx.say(format!(
-                "Rolled **{}** = {}",
-                dice,
-                discord::Output(&results)
+                "{} hasn't set up a character yet. Use /character set to add one.",
+                name
             ))
             .await?;
         }
+    }
+    Ok(())
+}
+
+/// Resolves the MVP
+#[command(
+    slash_command,
+    rename = "resolve-mvp",
+    guild_only,
+    check = "is_gm",
+    help_text_fn = "help::resolve_mvp"
+)]
+pub async fn resolve_mvp(ctx: Context<'_>) -> Result<()> {
+    if !discord::confirm(ctx, "Resolve the MVP? This clears all current votes.").await? {
+        return Ok(());
+    }
+
+    let guild_id = ctx.guild_id().expect("resolve-mvp is guild_only").get() as i64;
+    let resolved = {
+        let mut conn = ctx.data().pool.clone().get()?;
+        db::resolve_mvp(&mut conn, guild_id)
+    };
+
+    match resolved {
+        Ok(result) => {
+            let embed = discord::mvp_embed("MVP results", &result);
+
+            ctx.send(CreateReply::default().embed(embed)).await?;
+            notify::send(ctx, &ctx.data().pool, &Event::MvpWon, &result.winners).await;
+        }
+
+        Err(db::Error::MissingVotes) => {
+            ctx.say("Not everyone has voted").await?;
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(())
+}
+
+/// Discards the current MVP votes without resolving them
+#[command(
+    slash_command,
+    rename = "mvp-reset",
+    guild_only,
+    check = "is_gm",
+    help_text_fn = "help::mvp_reset"
+)]
+pub async fn mvp_reset(ctx: Context<'_>) -> Result<()> {
+    if !discord::confirm(ctx, "Discard all current MVP votes without picking an MVP?").await? {
+        return Ok(());
+    }
 
+    let guild_id = ctx.guild_id().expect("mvp-reset is guild_only").get() as i64;
+    let cleared = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::clear_votes(&conn, guild_id)?
+    };
+
+    let plural = if cleared == 1 { "" } else { "s" };
+    ctx.say(format!("Cleared {} MVP vote{}.", cleared, plural))
+        .await?;
+
+    Ok(())
+}
+
+/// Suggests the dice the invoker has rolled recently that match what's been typed.
+async fn autocomplete_dice(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let history = ctx
+        .data()
+        .pool
+        .get()
+        .map_err(Error::from)
+        .and_then(|conn| Ok(db::get_recent_rolls(&conn, ctx.author().id.get() as i64)?));
+    match history {
+        Ok(history) => roll::suggest(&history, partial, discord::AUTOCOMPLETE_LIMIT)
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
         Err(e) => {
-            ctx.say(format!("Error: {}", e)).await?;
+            log::error!("Error getting recent rolls to autocomplete: {}", e);
+            Vec::new()
         }
     }
+}
+
+/// Rolls dice
+#[command(
+    slash_command,
+    prefix_command,
+    check = "in_roll_channel",
+    help_text_fn = "help::roll",
+    description_localized("fr", "Lance des dés"),
+    description_localized("es-ES", "Tira dados")
+)]
+pub async fn roll(
+    ctx: Context<'_>,
+    #[description = "Dice, by default the server's default_dice"]
+    #[description_localized("fr", "Les dés, par défaut le default_dice du serveur")]
+    #[description_localized("es-ES", "Los dados, por defecto el default_dice del servidor")]
+    #[autocomplete = "autocomplete_dice"]
+    #[rest]
+    dice: Option<String>,
+) -> Result<()> {
+    // Dice that are given always win over the server's default.
+    let dice = match dice.filter(|dice| !dice.trim().is_empty()) {
+        Some(dice) => dice,
+        None => match guild_settings(ctx)?.default_dice {
+            Some(dice) => dice,
+            None => {
+                ctx.say("What should I roll? Give some dice, e.g. `/roll 1d20+5`.")
+                    .await?;
+                return Ok(());
+            }
+        },
+    };
+
+    if let Rolled::Invalid = roll_and_reply(ctx, &dice, 1).await? {
+        return Ok(());
+    }
+
+    let conn = ctx.data().pool.clone().get()?;
+    db::log_roll(&conn, ctx.author().id.get() as i64, dice.trim())?;
     Ok(())
 }
 
-// Schedules a game
-#[command(slash_command)]
-pub async fn schedule(
+/// Rolls your last dice again
+#[command(
+    slash_command,
+    rename = "roll-last",
+    check = "in_roll_channel",
+    help_text_fn = "help::roll_last"
+)]
+pub async fn roll_last(
     ctx: Context<'_>,
-    #[description = "Channel"] channel: serenity::Channel,
-    #[description = "Message"] msg: String,
-    #[description = "On"] on: serenity::Timestamp,
+    #[description = "How many times to roll it"]
+    #[min = 1]
+    #[max = 10]
+    count: Option<u32>,
 ) -> Result<()> {
-    log::info!("Scheduling message: {} on {}", msg, on);
+    let last = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::get_last_roll(&conn, ctx.author().id.get() as i64)?
+    };
+    let Some(dice) = last else {
+        ctx.say("You haven't rolled anything yet").await?;
+        return Ok(());
+    };
 
-    let channel_id = channel.id().get();
+    roll_and_reply(ctx, &dice, count.unwrap_or(1)).await?;
+    Ok(())
+}
 
-    let sch = db::ScheduledMessage {
-        channel_id,
-        msg,
-        on: (*on).into(),
+/// Shows a cheat sheet of the dice notation
+#[command(slash_command, rename = "roll-help", help_text_fn = "help::roll_help")]
+pub async fn roll_help(ctx: Context<'_>) -> Result<()> {
+    ctx.send(
+        CreateReply::default()
+            .embed(discord::syntax_embed())
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Sets how your rolls look
+#[command(
+    slash_command,
+    help_text_fn = "help::myroll",
+    subcommands("myroll_color"),
+    subcommand_required
+)]
+pub async fn myroll(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Sets the accent color of your rolls
+#[command(slash_command, rename = "color")]
+pub async fn myroll_color(
+    ctx: Context<'_>,
+    #[description = "A hex color like #ff8800, or default for plain rolls"] color: String,
+) -> Result<()> {
+    let color = match color.trim() {
+        "default" => None,
+        hex => match discord::parse_color(hex) {
+            Some(color) => Some(color),
+            None => {
+                ctx.send(
+                    CreateReply::default()
+                        .content(format!(
+                            "`{}` isn't a hex color. Give one like `#ff8800`.",
+                            hex
+                        ))
+                        .ephemeral(true),
+                )
+                .await?;
+                return Ok(());
+            }
+        },
     };
 
     {
-        let mut scheduler = ctx
-            .data()
-            .scheduler
-            .write()
-            .expect("Unable to get mut scheduler");
+        let conn = ctx.data().pool.clone().get()?;
+        db::set_roll_color(&conn, ctx.author().id.get() as i64, color)?;
+    }
 
-        log::info!("Scheduling message");
-        scheduler.schedule(&sch)?;
-        log::info!("Scheduled message");
+    let reply = match color {
+        Some(color) => CreateReply::default().embed(discord::roll_embed(
+            "Your rolls will look like this.",
+            Some(color),
+        )),
+        None => CreateReply::default().content("Your rolls are back to plain messages."),
+    };
+    ctx.send(reply.ephemeral(true)).await?;
+    Ok(())
+}
+
+/// What came of rolling dice with [`roll_and_reply`].
+enum Rolled {
+    /// The dice couldn't be rolled, and the error was replied instead.
+    Invalid,
+    Replied,
+}
+
+/// Routes reroll buttons, whose custom ids are `reroll:roller_id:rerolls:dice`.
+pub(crate) const REROLL_PREFIX: &str = "reroll";
+
+/// How long the roller can press a roll's button to roll it again.
+const REROLL_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// How many times one roll can be rolled again.
+const MAX_REROLLS: u32 = 3;
+
+/// The button to roll `dice` again, which has been rerolled `rerolls` times so far, or None when
+/// the dice are too long to fit in its custom id.
+fn reroll_button(
+    roller: serenity::UserId,
+    rerolls: u32,
+    dice: &str,
+) -> Option<serenity::CreateButton> {
+    let id = components::encode(
+        REROLL_PREFIX,
+        &[&roller.to_string(), &rerolls.to_string(), dice.trim()],
+    );
+    (id.len() <= components::MAX_ID_LEN).then(|| {
+        serenity::CreateButton::new(id)
+            .emoji('🔁')
+            .label("Reroll")
+            .style(serenity::ButtonStyle::Secondary)
+    })
+}
+
+/// Removes the reroll button from a roll's message once it's timed out. This is best effort, so a
+/// failure is only logged.
+fn expire_reroll(ctx: Context<'_>, message: &serenity::Message) {
+    let http = ctx.serenity_context().http.clone();
+    let (channel_id, message_id) = (message.channel_id, message.id);
+    tokio::spawn(async move {
+        tokio::time::sleep(REROLL_TIMEOUT).await;
+        let edit = serenity::EditMessage::new().components(vec![]);
+        if let Err(e) = channel_id.edit_message(&http, message_id, edit).await {
+            log::warn!("Error removing the reroll button: {}", e);
+        }
+    });
+}
+
+/// The accent color the invoker picked for their rolls with `/myroll color`, if any.
+fn roll_color(ctx: Context<'_>) -> Result<Option<u32>> {
+    let conn = ctx.data().pool.clone().get()?;
+    Ok(db::get_roll_color(&conn, ctx.author().id.get() as i64)?)
+}
+
+/// The message the server's `roll_triggers` setting adds for a roll, if any. Only rolls of a
+/// single die trigger one, since which die counts is ambiguous otherwise.
+fn roll_trigger<'a>(
+    settings: &'a Settings,
+    results: &evaluroll::ast::Output,
+    notes: &[roll::Note],
+) -> Option<&'a str> {
+    match (results.rolls.as_slice(), notes) {
+        ([die], []) if die.keep => settings.roll_triggers.get(&die.result).map(String::as_str),
+        _ => None,
     }
+}
 
-    ctx.say("Message scheduled!").await?;
+/// Formats a roll of `dice` as a line of the reply. Compact rolls leave out the dice.
+fn format_roll(
+    settings: &Settings,
+    mode: RollMode,
+    dice: &str,
+    crit: Option<Crit>,
+    results: &evaluroll::ast::Output,
+    notes: &[roll::Note],
+) -> String {
+    let output = discord::Output {
+        output: results,
+        notes,
+        group_thousands: settings.group_thousands,
+        locale: settings.locale,
+    };
+    // Without any dice, the expression is probably a typo, unless the guild uses the bot as a
+    // calculator.
+    let no_dice = settings.no_dice_note && results.rolls.is_empty() && notes.is_empty();
+    let annotation = match crit {
+        Some(crit) => format!(" — {}", settings.locale.crit(crit)),
+        None if no_dice => format!(" {}", settings.locale.no_dice()),
+        None => String::new(),
+    };
+    let trigger = match roll_trigger(settings, results, notes) {
+        Some(message) => format!(" — *{}*", message),
+        None => String::new(),
+    };
+    let output = match mode {
+        RollMode::Compact => format!("**{}**", output.total()),
+        RollMode::Verbose | RollMode::Embed => output.to_string(),
+    };
+    format!(
+        "{} **{}** = {}{}{}",
+        settings.locale.rolled(),
+        dice.trim(),
+        output,
+        annotation,
+        trigger
+    )
+}
 
-    Ok(())
+/// How rolls are shown in the channel the command was invoked in.
+fn channel_roll_mode(ctx: Context<'_>, settings: &Settings) -> Result<RollMode> {
+    let conn = ctx.data().pool.clone().get()?;
+    Ok(settings::roll_mode(
+        &conn,
+        settings,
+        ctx.channel_id().get(),
+    )?)
 }
 
-#[command(slash_command)]
-pub async fn connections(ctx: Context<'_>) -> Result<()> {
-    let pool = ctx.data().pool.clone();
-    ctx.say(format!(
-        "Connections: {}, Idle connections: {}",
-        pool.state().connections,
-        pool.state().idle_connections
-    ))
-    .await?;
-    Ok(())
+/// A single roll of a dice expression, as returned by [`roll::roll`].
+type Roll = (Option<Crit>, evaluroll::ast::Output, Vec<roll::Note>);
+
+/// Rolls dice `count` times with the generator shared by every command, so each roll advances its
+/// state rather than starting over from the same seed.
+fn roll_shared<R: Rng + ?Sized>(
+    rng: &Mutex<R>,
+    dice: &str,
+    count: u32,
+) -> core::result::Result<Vec<Roll>, evaluroll::Error> {
+    let mut rng = rng.lock().expect("Unable to lock rng");
+    (0..count).map(|_| roll::roll(dice, &mut *rng)).collect()
+}
+
+/// Rolls dice `count` times and replies with the results. A single roll that fits in one message
+/// gets a button to roll it again, see [`reroll`].
+async fn roll_and_reply(ctx: Context<'_>, dice: &str, count: u32) -> Result<Rolled> {
+    let settings = guild_settings(ctx)?;
+    let mode = channel_roll_mode(ctx, &settings)?;
+
+    let rolled = roll_shared(&ctx.data().rng, dice, count);
+    let rolled = match rolled {
+        Ok(rolled) => {
+            ctx.data().metrics.record_rolls(rolled.len() as u64);
+            rolled
+        }
+        Err(e) => {
+            ctx.say(format!("Error: {}", e)).await?;
+            return Ok(Rolled::Invalid);
+        }
+    };
+
+    let lines = rolled
+        .iter()
+        .map(|(crit, results, notes)| format_roll(&settings, mode, dice, *crit, results, notes))
+        .collect::<Vec<_>>();
+    let color = roll_color(ctx)?;
+    // A color is shown in an embed, whatever the mode.
+    let embed = mode == RollMode::Embed || color.is_some();
+
+    // A single roll gets a reaction for a crit, several are annotated line by line.
+    match rolled.as_slice() {
+        [(crit, _, _)] => {
+            let button = reroll_button(ctx.author().id, 0, dice)
+                .filter(|_| lines[0].chars().count() <= discord::MESSAGE_LIMIT);
+            let reply = match button {
+                Some(button) => {
+                    let reply = if embed {
+                        CreateReply::default().embed(discord::roll_embed(&lines[0], color))
+                    } else {
+                        CreateReply::default().content(&lines[0])
+                    }
+                    .components(vec![serenity::CreateActionRow::Buttons(vec![button])]);
+                    let reply = ctx.send(reply).await?;
+                    expire_reroll(ctx, &*reply.message().await?);
+                    Some(reply)
+                }
+                None => discord::send_chunked(ctx, &lines[0]).await?,
+            };
+
+            if let (Some(crit), Some(reply)) = (crit, &reply) {
+                let emoji = match crit {
+                    Crit::Success => &settings.crit_emoji,
+                    Crit::Fumble => &settings.fumble_emoji,
+                };
+                discord::react(ctx, reply, emoji).await;
+            }
+
+            Ok(Rolled::Replied)
+        }
+        _ => {
+            let pages = discord::paginate(&lines, discord::MESSAGE_LIMIT);
+            discord::send_pages(ctx, &pages).await?;
+            Ok(Rolled::Replied)
+        }
+    }
+}
+
+/// Routes a press of a reroll button to [`reroll`].
+pub(crate) fn handle_reroll<'a>(
+    ctx: &'a serenity::Context,
+    press: &'a serenity::ComponentInteraction,
+    data: &'a BotData,
+    payload: &'a str,
+) -> BoxFuture<'a, Result<()>> {
+    Box::pin(reroll(ctx, press, data, payload))
+}
+
+/// Rolls the dice on a roll's message again when its roller presses the reroll button, whose
+/// custom id's payload is `roller_id:rerolls:dice`. The message is edited to strike out the
+/// earlier results and show the new one, and keeps the button until it's been pressed
+/// [`MAX_REROLLS`] times. Anyone else is told it isn't their roll.
+async fn reroll(
+    ctx: &serenity::Context,
+    press: &serenity::ComponentInteraction,
+    data: &BotData,
+    payload: &str,
+) -> Result<()> {
+    let Some((roller, rerolls, dice)) = components::fields(payload, 3).and_then(|fields| {
+        Some((
+            fields[0].parse::<u64>().ok()?,
+            fields[1].parse::<u32>().ok()?,
+            fields[2],
+        ))
+    }) else {
+        log::warn!(
+            "Ignoring a malformed reroll button: {}",
+            press.data.custom_id
+        );
+        return Ok(());
+    };
+    let roller = serenity::UserId::new(roller);
+
+    let reply_ephemeral = |content: String| {
+        let response = serenity::CreateInteractionResponseMessage::new()
+            .content(content)
+            .ephemeral(true);
+        press.create_response(ctx, serenity::CreateInteractionResponse::Message(response))
+    };
+    if press.user.id != roller {
+        reply_ephemeral("That's not your roll.".to_string()).await?;
+        return Ok(());
+    }
+    // The button is removed once it times out, but that can fail, or the bot may have restarted.
+    let age = chrono::Utc::now().timestamp() - press.message.timestamp.unix_timestamp();
+    if age > REROLL_TIMEOUT.as_secs() as i64 {
+        let response = serenity::CreateInteractionResponseMessage::new().components(vec![]);
+        press
+            .create_response(
+                ctx,
+                serenity::CreateInteractionResponse::UpdateMessage(response),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let (settings, mode, color) = {
+        let conn = data.pool.get()?;
+        let settings = match press.guild_id {
+            Some(guild_id) => Settings::load(&conn, guild_id.get() as i64)?,
+            None => Settings::default(),
+        };
+        let mode = settings::roll_mode(&conn, &settings, press.channel_id.get())?;
+        let color = db::get_roll_color(&conn, roller.get() as i64)?;
+        (settings, mode, color)
+    };
+    let rolled = {
+        let mut rng = data.rng.lock().expect("Unable to lock rng");
+        roll::roll(dice, &mut *rng)
+    };
+    let (crit, results, notes) = match rolled {
+        Ok(rolled) => rolled,
+        Err(e) => {
+            reply_ephemeral(format!("Error: {}", e)).await?;
+            return Ok(());
+        }
+    };
+    data.metrics.record_rolls(1);
+
+    let line = format_roll(&settings, mode, dice, crit, &results, &notes);
+    let previous = press
+        .message
+        .embeds
+        .first()
+        .and_then(|embed| embed.description.clone())
+        .unwrap_or_else(|| press.message.content.clone());
+    let embed = mode == RollMode::Embed || color.is_some();
+    let limit = if embed {
+        discord::EMBED_DESCRIPTION_LIMIT
+    } else {
+        discord::MESSAGE_LIMIT
+    };
+    let content = discord::reroll_content(&previous, &line, limit);
+
+    let rerolls = rerolls + 1;
+    let button = reroll_button(roller, rerolls, dice).filter(|_| rerolls < MAX_REROLLS);
+    let response = serenity::CreateInteractionResponseMessage::new().components(
+        button
+            .map(|button| vec![serenity::CreateActionRow::Buttons(vec![button])])
+            .unwrap_or_default(),
+    );
+    let response = if embed {
+        response
+            .content("")
+            .embed(discord::roll_embed(&content, color))
+    } else {
+        response.content(content).embeds(vec![])
+    };
+    press
+        .create_response(
+            ctx,
+            serenity::CreateInteractionResponse::UpdateMessage(response),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Rolls the same check for several players at once
+#[command(
+    slash_command,
+    guild_only,
+    check = "in_roll_channel",
+    help_text_fn = "help::groupcheck"
+)]
+pub async fn groupcheck(
+    ctx: Context<'_>,
+    #[description = "Dice"] expr: String,
+    #[description = "Mentions of who's rolling, or all for every registered player"]
+    players: String,
+    #[description = "The DC to beat"] dc: Option<i32>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("groupcheck is guild_only").get() as i64;
+    let players = match checks::parse_players(&players) {
+        Ok(checks::Players::Mentioned(users)) => users
+            .into_iter()
+            .map(|user| user.get() as i64)
+            .collect::<Vec<_>>(),
+        Ok(checks::Players::All) => {
+            let conn = ctx.data().pool.clone().get()?;
+            db::get_players(&conn)?
+                .into_iter()
+                .filter(|player| player.guild_id.is_none() || player.guild_id == Some(guild_id))
+                .map(|player| player.id)
+                .collect()
+        }
+        Err(e) => {
+            ctx.say(format!("{}.", e)).await?;
+            return Ok(());
+        }
+    };
+    if players.is_empty() {
+        ctx.say("No one has registered yet.").await?;
+        return Ok(());
+    }
+    if players.len() > checks::MAX_PLAYERS {
+        ctx.say(format!("{}.", checks::Error::TooManyPlayers))
+            .await?;
+        return Ok(());
+    }
+
+    let settings = guild_settings(ctx)?;
+    let rolled = {
+        let mut rng = ctx.data().rng.lock().expect("Unable to lock rng");
+        checks::roll_each(&expr, &players, &mut *rng)
+    };
+    let rolled = match rolled {
+        Ok(rolled) => {
+            ctx.data().metrics.record_rolls(rolled.len() as u64);
+            rolled
+        }
+        Err(e) => {
+            ctx.say(format!("Error: {}", e)).await?;
+            return Ok(());
+        }
+    };
+
+    let names = future::join_all(
+        rolled
+            .iter()
+            .map(|rolled| discord::get_nick_or_name_by_id(ctx, &rolled.player)),
+    )
+    .await;
+
+    let mut lines = vec![match dc {
+        Some(dc) => format!("🎲 **{}** vs DC {}", expr.trim(), dc),
+        None => format!("🎲 **{}**", expr.trim()),
+    }];
+    let mut passed = 0;
+    for (rolled, name) in rolled.iter().zip(names) {
+        let name = name.unwrap_or_else(|_| format!("<@{}>", rolled.player));
+        let output = discord::Output {
+            output: &rolled.output,
+            notes: &rolled.notes,
+            group_thousands: settings.group_thousands,
+            locale: settings.locale,
+        };
+        let result = match dc {
+            Some(dc) if checks::passes(rolled.output.total, dc) => {
+                passed += 1;
+                " ✅"
+            }
+            Some(_) => " ❌",
+            None => "",
+        };
+        let crit = match rolled.crit {
+            Some(crit) => format!(" — {}", settings.locale.crit(crit)),
+            None => String::new(),
+        };
+        lines.push(format!("{}: {}{}{}", name, output, result, crit));
+    }
+
+    if let Some(dc) = dc {
+        let outcome = if checks::group_passes(passed, rolled.len()) {
+            "the group succeeds"
+        } else {
+            "the group fails"
+        };
+        lines.push(format!(
+            "{} of {} passed DC {}: {}.",
+            passed,
+            rolled.len(),
+            dc,
+            outcome
+        ));
+    }
+
+    discord::send_chunked(ctx, &lines.join("\n")).await?;
+    Ok(())
+}
+
+/// Defines and rolls custom dice with labelled faces
+#[command(
+    slash_command,
+    guild_only,
+    check = "in_roll_channel",
+    help_text_fn = "help::deck",
+    subcommands("deck_define", "deck_roll", "deck_shuffle", "deck_draw"),
+    subcommand_required
+)]
+pub async fn deck(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+async fn autocomplete_custom_die(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Vec::new();
+    };
+
+    let names = ctx
+        .data()
+        .pool
+        .get()
+        .map_err(Error::from)
+        .and_then(|conn| Ok(db::get_custom_dice_names(&conn, guild_id.get() as i64)?));
+    match names {
+        Ok(names) => names
+            .into_iter()
+            .filter(|name| name.starts_with(&partial.to_lowercase()))
+            .take(discord::AUTOCOMPLETE_LIMIT)
+            .collect(),
+        Err(e) => {
+            log::error!("Error getting custom dice to autocomplete: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Defines a custom die, replacing any with the same name
+#[command(slash_command, rename = "define")]
+pub async fn deck_define(
+    ctx: Context<'_>,
+    #[description = "Name"] name: String,
+    #[description = "Comma-separated faces, e.g. Fire, Water, Earth, Air"] faces: String,
+) -> Result<()> {
+    let parsed = customdice::normalize_name(&name)
+        .and_then(|name| Ok((name, customdice::parse_faces(&faces)?)));
+    let (name, faces) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            ctx.say(format!("{}.", e)).await?;
+            return Ok(());
+        }
+    };
+
+    let guild_id = ctx.guild_id().expect("deck is guild_only").get();
+    {
+        let conn = ctx.data().pool.clone().get()?;
+        db::define_custom_die(&conn, guild_id as i64, &name, &faces)?;
+    }
+
+    discord::send_chunked(
+        ctx,
+        &format!("Defined {} with faces: {}", name, faces.join(", ")),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Rolls a custom die
+#[command(slash_command, rename = "roll")]
+pub async fn deck_roll(
+    ctx: Context<'_>,
+    #[description = "Name"]
+    #[autocomplete = "autocomplete_custom_die"]
+    name: String,
+    #[description = "How many times to roll it"]
+    #[min = 1]
+    #[max = 100]
+    count: Option<u32>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("deck is guild_only").get();
+    let name = name.trim().to_lowercase();
+
+    let faces = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::get_custom_die(&conn, guild_id as i64, &name)?
+    };
+    let Some(faces) = faces else {
+        ctx.say(format!(
+            "There's no custom die called {}. Define it with /deck define first.",
+            name
+        ))
+        .await?;
+        return Ok(());
+    };
+
+    let count = count.unwrap_or(1).min(customdice::MAX_ROLLS);
+    let rolled = customdice::roll(
+        &faces,
+        count,
+        &mut *ctx.data().rng.lock().expect("Unable to lock rng"),
+    );
+    ctx.data().metrics.record_rolls(rolled.len() as u64);
+
+    discord::send_chunked(ctx, &format!("🎲 {}: {}", name, rolled.join(", "))).await?;
+    Ok(())
+}
+
+/// Shuffles a custom die's faces into this channel's draw pile
+#[command(slash_command, rename = "shuffle")]
+pub async fn deck_shuffle(
+    ctx: Context<'_>,
+    #[description = "Name"]
+    #[autocomplete = "autocomplete_custom_die"]
+    name: String,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("deck is guild_only").get();
+    let name = name.trim().to_lowercase();
+
+    let conn = ctx.data().pool.clone().get()?;
+    let Some(faces) = db::get_custom_die(&conn, guild_id as i64, &name)? else {
+        ctx.say(format!(
+            "There's no custom die called {}. Define it with /deck define first.",
+            name
+        ))
+        .await?;
+        return Ok(());
+    };
+
+    let cards = customdice::shuffle(
+        &faces,
+        &mut *ctx.data().rng.lock().expect("Unable to lock rng"),
+    );
+    let state = db::DeckState { name, cards };
+    db::set_deck_state(&conn, ctx.channel_id().get() as i64, &state)?;
+    drop(conn);
+
+    ctx.say(format!(
+        "Shuffled {} cards from {}. Draw with /deck draw.",
+        state.cards.len(),
+        state.name
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Draws cards from this channel's draw pile, without replacement
+#[command(slash_command, rename = "draw")]
+pub async fn deck_draw(
+    ctx: Context<'_>,
+    #[description = "How many cards to draw"]
+    #[min = 1]
+    #[max = 100]
+    count: Option<u32>,
+) -> Result<()> {
+    let channel_id = ctx.channel_id().get() as i64;
+
+    let drawn = {
+        let mut conn = ctx.data().pool.clone().get()?;
+        let tx = conn.transaction()?;
+        let drawn = db::get_deck_state(&tx, channel_id)?.map(|mut state| {
+            let drawn = customdice::draw(&mut state.cards, count.unwrap_or(1) as usize);
+            (state, drawn)
+        });
+        if let Some((state, _)) = &drawn {
+            db::set_deck_state(&tx, channel_id, state)?;
+        }
+        tx.commit()?;
+        drawn
+    };
+
+    let msg = match drawn {
+        None => "There's no deck in this channel. Shuffle one with /deck shuffle.".to_string(),
+        // Reshuffling is left to the players, so an empty deck stays empty until they do.
+        Some((state, drawn)) if drawn.is_empty() => format!(
+            "The {} deck is empty. Reshuffle it with /deck shuffle {}.",
+            state.name, state.name
+        ),
+        Some((state, drawn)) => format!(
+            "🃏 {}: {} ({} left)",
+            state.name,
+            drawn.join(", "),
+            state.cards.len()
+        ),
+    };
+
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Rolls on random tables of weighted entries
+#[command(
+    slash_command,
+    guild_only,
+    check = "in_roll_channel",
+    help_text_fn = "help::table",
+    subcommands(
+        "table_create",
+        "table_addentry",
+        "table_import",
+        "table_roll",
+        "table_list",
+        "table_delete"
+    ),
+    subcommand_required
+)]
+pub async fn table(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+async fn autocomplete_table(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Vec::new();
+    };
+
+    let tables = ctx
+        .data()
+        .pool
+        .get()
+        .map_err(Error::from)
+        .and_then(|conn| Ok(db::get_tables(&conn, guild_id.get() as i64)?));
+    match tables {
+        Ok(tables) => tables
+            .into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| name.starts_with(&partial.to_lowercase()))
+            .take(discord::AUTOCOMPLETE_LIMIT)
+            .collect(),
+        Err(e) => {
+            log::error!("Error getting random tables to autocomplete: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+fn no_table(name: &str) -> String {
+    format!(
+        "There's no random table called {}. Create it with /table create first.",
+        name
+    )
+}
+
+/// Creates an empty random table
+#[command(slash_command, rename = "create")]
+pub async fn table_create(ctx: Context<'_>, #[description = "Name"] name: String) -> Result<()> {
+    let name = match tables::normalize_name(&name) {
+        Ok(name) => name,
+        Err(e) => {
+            ctx.say(format!("{}.", e)).await?;
+            return Ok(());
+        }
+    };
+
+    let guild_id = ctx.guild_id().expect("table is guild_only").get();
+    let created = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::create_table(&conn, guild_id as i64, &name)?
+    };
+
+    let msg = if created {
+        format!(
+            "Created {}. Add entries with /table addentry or /table import.",
+            name
+        )
+    } else {
+        format!("There's already a random table called {}.", name)
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Adds an entry to a random table
+#[command(slash_command, rename = "addentry")]
+pub async fn table_addentry(
+    ctx: Context<'_>,
+    #[description = "Table"]
+    #[autocomplete = "autocomplete_table"]
+    name: String,
+    #[description = "How likely it is compared to the other entries, e.g. 2 for twice as likely"]
+    #[max = 1000]
+    weight: u32,
+    #[description = "Text"] text: String,
+) -> Result<()> {
+    let text = match tables::normalize_entry(&text) {
+        Ok(text) => text,
+        Err(e) => {
+            ctx.say(format!("{}.", e)).await?;
+            return Ok(());
+        }
+    };
+
+    let guild_id = ctx.guild_id().expect("table is guild_only").get();
+    let name = name.trim().to_lowercase();
+    let entry = db::TableEntry { weight, text };
+    let added = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::add_table_entries(&conn, guild_id as i64, &name, std::slice::from_ref(&entry))?
+    };
+
+    let msg = if added {
+        format!(
+            "Added {} to {} (weight {}).",
+            entry.text, name, entry.weight
+        )
+    } else {
+        no_table(&name)
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Adds entries to a random table from a text file, one per line
+#[command(slash_command, rename = "import")]
+pub async fn table_import(
+    ctx: Context<'_>,
+    #[description = "Table"]
+    #[autocomplete = "autocomplete_table"]
+    name: String,
+    #[description = "A text file with one entry per line"] file: serenity::Attachment,
+) -> Result<()> {
+    if file.size > tables::MAX_IMPORT_BYTES {
+        ctx.say(format!(
+            "That file is too big to import. It can be at most {} KB.",
+            tables::MAX_IMPORT_BYTES / 1024
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    ctx.defer().await?;
+    let contents = file.download().await?;
+    let Ok(contents) = String::from_utf8(contents) else {
+        ctx.say("That file isn't text. Import a .txt file with one entry per line.")
+            .await?;
+        return Ok(());
+    };
+    let entries = match tables::parse_entries(&contents) {
+        Ok(entries) => entries,
+        Err(e) => {
+            ctx.say(format!("{}.", e)).await?;
+            return Ok(());
+        }
+    };
+
+    let guild_id = ctx.guild_id().expect("table is guild_only").get();
+    let name = name.trim().to_lowercase();
+    let added = {
+        let mut conn = ctx.data().pool.clone().get()?;
+        let tx = conn.transaction()?;
+        let added = db::add_table_entries(&tx, guild_id as i64, &name, &entries)?;
+        tx.commit()?;
+        added
+    };
+
+    let msg = if added {
+        format!("Imported {} entries into {}.", entries.len(), name)
+    } else {
+        no_table(&name)
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Draws entries from a random table, without repeats where possible
+#[command(slash_command, rename = "roll")]
+pub async fn table_roll(
+    ctx: Context<'_>,
+    #[description = "Table"]
+    #[autocomplete = "autocomplete_table"]
+    name: String,
+    #[description = "How many entries to draw"]
+    #[min = 1]
+    #[max = 20]
+    count: Option<u32>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("table is guild_only").get();
+    let name = name.trim().to_lowercase();
+
+    let entries = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::get_table_entries(&conn, guild_id as i64, &name)?
+    };
+    let Some(entries) = entries else {
+        ctx.say(no_table(&name)).await?;
+        return Ok(());
+    };
+
+    let count = count.unwrap_or(1).min(tables::MAX_DRAWS);
+    let drawn = tables::draw(
+        &entries,
+        count,
+        &mut *ctx.data().rng.lock().expect("Unable to lock rng"),
+    );
+    if drawn.is_empty() {
+        ctx.say(format!(
+            "{} has no entries to draw. Add some with /table addentry.",
+            name
+        ))
+        .await?;
+        return Ok(());
+    }
+    ctx.data().metrics.record_rolls(drawn.len() as u64);
+
+    let msg = match drawn.as_slice() {
+        [entry] => format!("📜 {}: {}", name, entry.text),
+        drawn => drawn.iter().fold(format!("📜 {}:", name), |msg, entry| {
+            format!("{}\n- {}", msg, entry.text)
+        }),
+    };
+    discord::send_chunked(ctx, &msg).await?;
+    Ok(())
+}
+
+/// Lists this server's random tables, or the entries on one
+#[command(slash_command, rename = "list")]
+pub async fn table_list(
+    ctx: Context<'_>,
+    #[description = "Table"]
+    #[autocomplete = "autocomplete_table"]
+    name: Option<String>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("table is guild_only").get() as i64;
+
+    let lines = {
+        let conn = ctx.data().pool.clone().get()?;
+        match name.map(|name| name.trim().to_lowercase()) {
+            None => {
+                let tables = db::get_tables(&conn, guild_id)?;
+                if tables.is_empty() {
+                    vec![
+                        "There are no random tables yet. Create one with /table create."
+                            .to_string(),
+                    ]
+                } else {
+                    tables
+                        .into_iter()
+                        .map(|(name, entries)| format!("{} ({} entries)", name, entries))
+                        .collect()
+                }
+            }
+            Some(name) => match db::get_table_entries(&conn, guild_id, &name)? {
+                None => vec![no_table(&name)],
+                Some(entries) if entries.is_empty() => vec![format!(
+                    "{} has no entries yet. Add some with /table addentry.",
+                    name
+                )],
+                Some(entries) => {
+                    let total = entries.iter().map(|e| u64::from(e.weight)).sum::<u64>();
+                    std::iter::once(format!("{} (total weight {}):", name, total))
+                        .chain(
+                            entries
+                                .iter()
+                                .map(|e| format!("- {} (weight {})", e.text, e.weight)),
+                        )
+                        .collect()
+                }
+            },
+        }
+    };
+
+    let pages = discord::paginate(&lines, discord::MESSAGE_LIMIT);
+    discord::send_pages(ctx, &pages).await?;
+    Ok(())
+}
+
+/// Deletes a random table and all its entries
+#[command(slash_command, rename = "delete")]
+pub async fn table_delete(
+    ctx: Context<'_>,
+    #[description = "Table"]
+    #[autocomplete = "autocomplete_table"]
+    name: String,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("table is guild_only").get();
+    let name = name.trim().to_lowercase();
+
+    if !discord::confirm(
+        ctx,
+        &format!("Delete {} and all its entries? This can't be undone.", name),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let deleted = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::delete_table(&conn, guild_id as i64, &name)?
+    };
+
+    let msg = if deleted {
+        format!("Deleted {}.", name)
+    } else {
+        no_table(&name)
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Generates a random NPC, tavern or town
+#[command(
+    slash_command,
+    check = "in_roll_channel",
+    help_text_fn = "help::generate"
+)]
+pub async fn generate(
+    ctx: Context<'_>,
+    #[description = "What to generate"] kind: generator::Kind,
+) -> Result<()> {
+    // Random tables named like `tavern.animal` override the defaults in a guild.
+    let mut overrides = std::collections::HashMap::new();
+    if let Some(guild_id) = ctx.guild_id() {
+        let guild_id = guild_id.get() as i64;
+        let conn = ctx.data().pool.clone().get()?;
+        let prefix = kind.table_prefix();
+        for (name, _) in db::get_tables(&conn, guild_id)? {
+            let Some(part) = name.strip_prefix(&prefix) else {
+                continue;
+            };
+            if let Some(entries) = db::get_table_entries(&conn, guild_id, &name)? {
+                overrides.insert(part.to_string(), entries);
+            }
+        }
+    }
+
+    let generator = generator::default_generator(kind).with_overrides(overrides);
+    let generated = generator.generate(&mut *ctx.data().rng.lock().expect("Unable to lock rng"));
+    let Some(generated) = generated else {
+        ctx.say(format!(
+            "There are no templates to generate a {} from. Add some to the {}templates table.",
+            kind.key(),
+            kind.table_prefix()
+        ))
+        .await?;
+        return Ok(());
+    };
+    ctx.data().metrics.record_rolls(1);
+
+    ctx.say(format!("🎲 {}", generated)).await?;
+    Ok(())
+}
+
+/// Counts down in this channel
+#[command(
+    slash_command,
+    help_text_fn = "help::timer",
+    subcommands("timer_start", "timer_cancel"),
+    subcommand_required
+)]
+pub async fn timer(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Starts a countdown that pings you when it ends
+#[command(slash_command, rename = "start")]
+pub async fn timer_start(
+    ctx: Context<'_>,
+    #[description = "How many minutes to count down"]
+    #[min = 1]
+    #[max = 720]
+    minutes: u32,
+    #[description = "What it's for, e.g. Turn timer"] label: Option<String>,
+) -> Result<()> {
+    let minutes = minutes.min(timers::MAX_MINUTES);
+    let timer = db::Timer {
+        channel_id: ctx.channel_id().get() as i64,
+        message_id: 0,
+        user_id: ctx.author().id.get() as i64,
+        label: label
+            .map(|label| label.trim().to_string())
+            .filter(|label| !label.is_empty()),
+        ends_at: chrono::Utc::now() + chrono::Duration::minutes(minutes.into()),
+    };
+
+    let reply = ctx
+        .say(timers::running_text(
+            &timer,
+            std::time::Duration::from_secs(u64::from(minutes) * 60),
+        ))
+        .await?;
+    let timer = db::Timer {
+        message_id: reply.message().await?.id.get() as i64,
+        ..timer
+    };
+
+    let started = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::start_timer(&conn, &timer)?
+    };
+    if !started {
+        reply
+            .edit(
+                ctx,
+                CreateReply::default().content(
+                    "There's already a timer running in this channel. Cancel it with /timer cancel first.",
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    ctx.data().timers.spawn(
+        ctx.serenity_context().clone(),
+        ctx.data().pool.clone(),
+        timer,
+    );
+    Ok(())
+}
+
+/// Cancels this channel's countdown
+#[command(slash_command, rename = "cancel")]
+pub async fn timer_cancel(ctx: Context<'_>) -> Result<()> {
+    let channel_id = ctx.channel_id().get();
+    ctx.data().timers.cancel(channel_id);
+    let cancelled = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::delete_timer(&conn, channel_id as i64)?
+    };
+
+    let msg = if cancelled {
+        "Cancelled the timer."
+    } else {
+        "There's no timer running in this channel."
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Tracks the experience players earn over a session
+#[command(
+    slash_command,
+    guild_only,
+    check = "is_gm",
+    help_text_fn = "help::session",
+    subcommands("session_start", "session_end"),
+    subcommand_required
+)]
+pub async fn session(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Starts a session, noting everyone's experience
+#[command(slash_command, rename = "start")]
+pub async fn session_start(ctx: Context<'_>) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("session is guild_only").get() as i64;
+    let started = {
+        let mut conn = ctx.data().pool.clone().get()?;
+        db::start_session(&mut conn, guild_id)?
+    };
+
+    let msg = if started {
+        "Session started. Use /session end afterwards to see what everyone earned."
+    } else {
+        "A session is already running. End it with /session end first."
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Ends the session and posts what everyone earned
+#[command(slash_command, rename = "end")]
+pub async fn session_end(ctx: Context<'_>) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("session is guild_only").get() as i64;
+    let (summary, players) = {
+        let mut conn = ctx.data().pool.clone().get()?;
+        (
+            db::end_session(&mut conn, guild_id)?,
+            db::get_players(&conn)?,
+        )
+    };
+    let Some(summary) = summary else {
+        ctx.say("There's no session running. Start one with /session start.")
+            .await?;
+        return Ok(());
+    };
+
+    let mut lines = Vec::new();
+    for gain in &summary.gains {
+        let name = match players.iter().find(|player| player.id == gain.player_id) {
+            Some(player) => discord::get_player_name(ctx, player).await?,
+            None => discord::get_nick_or_name_by_id(ctx, &gain.player_id).await?,
+        };
+        lines.push(format!(
+            "{}: +{}xp (now {}xp)",
+            name,
+            gain.gained(),
+            gain.end
+        ));
+    }
+
+    ctx.send(CreateReply::default().embed(discord::session_embed(&summary, &lines)))
+        .await?;
+    Ok(())
+}
+
+/// Tracks the downtime days players have between sessions
+#[command(
+    slash_command,
+    guild_only,
+    help_text_fn = "help::downtime",
+    subcommands("downtime_grant", "downtime_spend", "downtime_show", "downtime_log"),
+    subcommand_required
+)]
+pub async fn downtime(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Grants the mentioned players days of downtime
+#[command(slash_command, rename = "grant", check = "is_gm")]
+pub async fn downtime_grant(
+    ctx: Context<'_>,
+    #[description = "How many days"]
+    #[min = 1]
+    #[max = 365]
+    days: u32,
+    #[description = "Mentions of each player"] players: String,
+) -> Result<()> {
+    let players = discord::parse_mentions(&players);
+    if players.is_empty() {
+        ctx.say("Mention who's getting the downtime.").await?;
+        return Ok(());
+    }
+
+    let guild_id = ctx.guild_id().expect("downtime is guild_only").get() as i64;
+    let days = i64::from(days.min(downtime::MAX_DAYS));
+    {
+        let mut conn = ctx.data().pool.clone().get()?;
+        let player_ids = players
+            .iter()
+            .map(|player| player.get() as i64)
+            .collect::<Vec<_>>();
+        db::grant_downtime(&mut conn, guild_id, &player_ids, days)?;
+    }
+
+    let mentions = players
+        .iter()
+        .map(|player| format!("<@{}>", player))
+        .collect::<Vec<_>>()
+        .join(", ");
+    discord::send_chunked(
+        ctx,
+        &format!(
+            "🗓️ Granted {} of downtime to {}.",
+            downtime::days(days),
+            mentions
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Spends some of a player's downtime on an activity
+#[command(slash_command, rename = "spend")]
+pub async fn downtime_spend(
+    ctx: Context<'_>,
+    #[description = "Whose downtime to spend"] player: serenity::User,
+    #[description = "How many days"]
+    #[min = 1]
+    #[max = 365]
+    days: u32,
+    #[description = "What they're spent on, e.g. crafting a longsword"] activity: String,
+) -> Result<()> {
+    let activity = match downtime::normalize_activity(&activity) {
+        Ok(activity) => activity,
+        Err(e) => {
+            ctx.say(format!("{}.", e)).await?;
+            return Ok(());
+        }
+    };
+
+    let guild_id = ctx.guild_id().expect("downtime is guild_only").get() as i64;
+    let player_id = player.id.get() as i64;
+    let days = i64::from(days.min(downtime::MAX_DAYS));
+    let spent = {
+        let mut conn = ctx.data().pool.clone().get()?;
+        db::spend_downtime(&mut conn, guild_id, player_id, days, &activity)
+    };
+    let msg = match spent {
+        Err(db::Error::InsufficientDowntime(left)) => format!(
+            "There are only {} left for <@{}>, not {}.",
+            downtime::days(left),
+            player_id,
+            days
+        ),
+        spent => format!(
+            "<@{}> spent {} {}. {} left.",
+            player_id,
+            downtime::days(days),
+            activity,
+            downtime::days(spent?)
+        ),
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Shows how much downtime everyone has left
+#[command(slash_command, rename = "show")]
+pub async fn downtime_show(ctx: Context<'_>) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("downtime is guild_only").get() as i64;
+    let balances = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::get_downtime_balances(&conn, guild_id)?
+    };
+    if balances.is_empty() {
+        ctx.say("No one has any downtime yet. A GM can grant some with /downtime grant.")
+            .await?;
+        return Ok(());
+    }
+
+    let mut lines = Vec::new();
+    for (player_id, balance) in balances {
+        let name = discord::get_nick_or_name_by_id(ctx, &player_id).await?;
+        lines.push(format!("{}: {}", name, downtime::days(balance)));
+    }
+
+    let pages = discord::paginate(&lines, discord::MESSAGE_LIMIT);
+    discord::send_pages(ctx, &pages).await?;
+    Ok(())
+}
+
+/// Shows the downtime a player has been granted and spent, newest first
+#[command(slash_command, rename = "log")]
+pub async fn downtime_log(
+    ctx: Context<'_>,
+    #[description = "Whose downtime to show"] player: serenity::User,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("downtime is guild_only").get() as i64;
+    let entries = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::get_downtime_log(&conn, guild_id, player.id.get() as i64)?
+    };
+    if entries.is_empty() {
+        ctx.say(format!("<@{}> hasn't had any downtime yet.", player.id))
+            .await?;
+        return Ok(());
+    }
+
+    let name = discord::get_nick_or_name(ctx, player).await;
+    let lines = std::iter::once(format!("**{}**", name))
+        .chain(entries.iter().map(downtime::format_entry))
+        .collect::<Vec<_>>();
+    let pages = discord::paginate(&lines, discord::MESSAGE_LIMIT);
+    discord::send_pages(ctx, &pages).await?;
+    Ok(())
+}
+
+/// Keeps recaps of past sessions
+#[command(
+    slash_command,
+    guild_only,
+    help_text_fn = "help::recap",
+    subcommands("recap_add", "recap_last", "recap_list", "recap_search"),
+    subcommand_required
+)]
+pub async fn recap(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Adds a recap of a session
+#[command(slash_command, rename = "add")]
+pub async fn recap_add(
+    ctx: Context<'_>,
+    #[description = "What happened"]
+    #[max_length = 4000]
+    text: String,
+    #[description = "Which session it recaps, by default the one after the last recap"]
+    #[min = 1]
+    session: Option<i64>,
+) -> Result<()> {
+    let text = match recaps::normalize_text(&text) {
+        Ok(text) => text,
+        Err(e) => {
+            ctx.say(format!("{}.", e)).await?;
+            return Ok(());
+        }
+    };
+
+    let guild_id = ctx.guild_id().expect("recap is guild_only").get();
+    let recap = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::add_recap(
+            &conn,
+            guild_id as i64,
+            ctx.author().id.get() as i64,
+            session,
+            &text,
+        )?
+    };
+
+    ctx.send(
+        CreateReply::default()
+            .content(format!("Added the recap for session {}.", recap.session))
+            .embed(recaps::embed(&recap)),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Shows the most recent recap
+#[command(slash_command, rename = "last")]
+pub async fn recap_last(ctx: Context<'_>) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("recap is guild_only").get();
+    let recap = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::get_last_recap(&conn, guild_id as i64)?
+    };
+
+    match recap {
+        Some(recap) => {
+            ctx.send(CreateReply::default().embed(recaps::embed(&recap)))
+                .await?;
+        }
+        None => {
+            ctx.say("There are no recaps yet. Write one with /recap add.")
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Lists the recaps, newest first
+#[command(slash_command, rename = "list")]
+pub async fn recap_list(ctx: Context<'_>) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("recap is guild_only").get();
+    let recaps = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::get_recaps(&conn, guild_id as i64, None)?
+    };
+
+    if recaps.is_empty() {
+        ctx.say("There are no recaps yet. Write one with /recap add.")
+            .await?;
+        return Ok(());
+    }
+
+    let lines = recaps.iter().map(recaps::summary).collect::<Vec<_>>();
+    let pages = discord::paginate(&lines, discord::MESSAGE_LIMIT);
+    discord::send_pages(ctx, &pages).await?;
+    Ok(())
+}
+
+/// Finds the recaps that mention something
+#[command(slash_command, rename = "search")]
+pub async fn recap_search(
+    ctx: Context<'_>,
+    #[description = "What to look for"] term: String,
+) -> Result<()> {
+    let term = term.trim();
+    if term.is_empty() {
+        ctx.say("Search for some text, e.g. /recap search dragon")
+            .await?;
+        return Ok(());
+    }
+
+    let guild_id = ctx.guild_id().expect("recap is guild_only").get();
+    let recaps = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::get_recaps(&conn, guild_id as i64, Some(term))?
+    };
+
+    match recaps.as_slice() {
+        [] => {
+            ctx.say(format!("No recaps mention {}.", term)).await?;
+        }
+        [recap] => {
+            ctx.send(CreateReply::default().embed(recaps::embed(recap)))
+                .await?;
+        }
+        recaps => {
+            let lines = recaps.iter().map(recaps::summary).collect::<Vec<_>>();
+            let pages = discord::paginate(&lines, discord::MESSAGE_LIMIT);
+            discord::send_pages(ctx, &pages).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Puts a question to a vote
+#[command(
+    slash_command,
+    help_text_fn = "help::poll",
+    subcommands("poll_start", "poll_close"),
+    subcommand_required
+)]
+pub async fn poll(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Starts a poll with a button for each option
+#[command(slash_command, rename = "start")]
+#[allow(clippy::too_many_arguments)]
+pub async fn poll_start(
+    ctx: Context<'_>,
+    #[description = "What to vote on"] question: String,
+    #[description = "The first option"] option1: String,
+    #[description = "The second option"] option2: String,
+    #[description = "The third option"] option3: Option<String>,
+    #[description = "The fourth option"] option4: Option<String>,
+    #[description = "The fifth option"] option5: Option<String>,
+    #[description = "How many minutes until it closes, by default when you run /poll close"]
+    #[min = 1]
+    #[max = 10080]
+    minutes: Option<u32>,
+) -> Result<()> {
+    let parsed = polls::normalize_question(&question).and_then(|question| {
+        let options = [Some(option1), Some(option2), option3, option4, option5];
+        Ok((question, polls::normalize_options(&options)?))
+    });
+    let (question, options) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            ctx.say(format!("{}.", e)).await?;
+            return Ok(());
+        }
+    };
+
+    let closes_at = minutes.map(|minutes| {
+        chrono::Utc::now() + chrono::Duration::minutes(minutes.min(polls::MAX_MINUTES).into())
+    });
+    let poll = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::create_poll(
+            &conn,
+            ctx.channel_id().get() as i64,
+            ctx.author().id.get() as i64,
+            &question,
+            &options,
+            closes_at,
+        )?
+    };
+
+    let reply = ctx
+        .send(
+            CreateReply::default()
+                .embed(polls::embed(&poll, &polls::tally(options.len(), &[])))
+                .components(polls::buttons(&poll)),
+        )
+        .await?;
+    let message_id = reply.message().await?.id;
+    {
+        let conn = ctx.data().pool.clone().get()?;
+        db::set_poll_message(&conn, poll.id, message_id.get() as i64)?;
+    }
+
+    if let Some(closes_at) = closes_at {
+        ctx.data()
+            .scheduler
+            .write()
+            .expect("Unable to get mut scheduler")
+            .schedule_poll_close(poll.id, closes_at)?;
+    }
+    Ok(())
+}
+
+/// Closes your latest poll in this channel and announces the result
+#[command(slash_command, rename = "close")]
+pub async fn poll_close(ctx: Context<'_>) -> Result<()> {
+    let poll = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::get_latest_open_poll(
+            &conn,
+            ctx.channel_id().get() as i64,
+            ctx.author().id.get() as i64,
+        )?
+    };
+    let Some(poll) = poll else {
+        ctx.send(
+            CreateReply::default()
+                .content("You don't have an open poll in this channel.")
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    };
+
+    ctx.data()
+        .scheduler
+        .write()
+        .expect("Unable to get mut scheduler")
+        .cancel_poll(poll.id);
+    polls::close(ctx.serenity_context(), &ctx.data().pool, poll.id).await?;
+
+    ctx.send(
+        CreateReply::default()
+            .content(format!("Closed \"{}\".", poll.question))
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Tracks limited resources like spell slots
+#[command(
+    slash_command,
+    guild_only,
+    help_text_fn = "help::resource",
+    subcommands("resource_create", "resource_use", "resource_restore", "resource_show"),
+    subcommand_required
+)]
+pub async fn resource(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Suggests the invoker's resources whose names start with what's been typed.
+async fn autocomplete_resource(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Vec::new();
+    };
+
+    let resources = ctx.data().pool.get().map_err(Error::from).and_then(|conn| {
+        Ok(db::get_resources(
+            &conn,
+            guild_id.get() as i64,
+            Some(ctx.author().id.get() as i64),
+        )?)
+    });
+    match resources {
+        Ok(resources) => resources
+            .into_iter()
+            .map(|resource| resource.name)
+            .filter(|name| name.starts_with(&partial.to_lowercase()))
+            .take(discord::AUTOCOMPLETE_LIMIT)
+            .collect(),
+        Err(e) => {
+            log::error!("Error getting resources to autocomplete: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+fn no_resource(name: &str) -> String {
+    format!(
+        "You don't have a resource called {}. Create it with /resource create first.",
+        name
+    )
+}
+
+/// Creates a resource for you, full, replacing any with the same name
+#[command(slash_command, rename = "create")]
+pub async fn resource_create(
+    ctx: Context<'_>,
+    #[description = "Name, e.g. spell_slots_3"] name: String,
+    #[description = "How many you have when it's full"]
+    #[min = 1]
+    #[max = 1000]
+    max: u32,
+    #[description = "Whether /rest restores it, by default true"] restore_on_rest: Option<bool>,
+) -> Result<()> {
+    let name = match resources::normalize_name(&name) {
+        Ok(name) => name,
+        Err(e) => {
+            ctx.say(format!("{}.", e)).await?;
+            return Ok(());
+        }
+    };
+
+    let guild_id = ctx.guild_id().expect("resource is guild_only").get() as i64;
+    let resource = db::Resource {
+        player_id: ctx.author().id.get() as i64,
+        name,
+        current: i64::from(max.min(resources::MAX_AMOUNT)),
+        max: i64::from(max.min(resources::MAX_AMOUNT)),
+        restore_on_rest: restore_on_rest.unwrap_or(true),
+    };
+    {
+        let conn = ctx.data().pool.clone().get()?;
+        db::create_resource(&conn, guild_id, &resource)?;
+    }
+
+    ctx.say(format!(
+        "Created {}.",
+        resources::format_resource(&resource)
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Uses some of one of your resources
+#[command(slash_command, rename = "use")]
+pub async fn resource_use(
+    ctx: Context<'_>,
+    #[description = "Resource"]
+    #[autocomplete = "autocomplete_resource"]
+    name: String,
+    #[description = "How many to use, by default 1"]
+    #[min = 1]
+    #[max = 1000]
+    amount: Option<u32>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("resource is guild_only").get() as i64;
+    let player_id = ctx.author().id.get() as i64;
+    let name = name.trim().to_lowercase();
+    let amount = i64::from(amount.unwrap_or(1));
+
+    let msg = {
+        let conn = ctx.data().pool.clone().get()?;
+        match db::get_resource(&conn, guild_id, player_id, &name)? {
+            None => no_resource(&name),
+            Some(resource) => match resources::spend(resource.current, amount) {
+                Err(e) => format!("{} of {}, not {}.", e, name, amount),
+                Ok(current) => {
+                    db::set_resource_current(&conn, guild_id, player_id, &name, current)?;
+                    format!(
+                        "Used {} {}. {}",
+                        amount,
+                        name,
+                        resources::format_resource(&db::Resource {
+                            current,
+                            ..resource
+                        })
+                    )
+                }
+            },
+        }
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Restores some or all of one of your resources
+#[command(slash_command, rename = "restore")]
+pub async fn resource_restore(
+    ctx: Context<'_>,
+    #[description = "Resource"]
+    #[autocomplete = "autocomplete_resource"]
+    name: String,
+    #[description = "How many to restore, by default all of them"]
+    #[min = 1]
+    #[max = 1000]
+    amount: Option<u32>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("resource is guild_only").get() as i64;
+    let player_id = ctx.author().id.get() as i64;
+    let name = name.trim().to_lowercase();
+
+    let msg = {
+        let conn = ctx.data().pool.clone().get()?;
+        match db::get_resource(&conn, guild_id, player_id, &name)? {
+            None => no_resource(&name),
+            Some(resource) => {
+                let current =
+                    resources::restore(resource.current, resource.max, amount.map(i64::from));
+                db::set_resource_current(&conn, guild_id, player_id, &name, current)?;
+                format!(
+                    "Restored {} {}. {}",
+                    current - resource.current,
+                    name,
+                    resources::format_resource(&db::Resource {
+                        current,
+                        ..resource
+                    })
+                )
+            }
+        }
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Shows a player's resources, or everyone's
+#[command(slash_command, rename = "show")]
+pub async fn resource_show(
+    ctx: Context<'_>,
+    #[description = "Whose resources to show, by default everyone's"] player: Option<
+        serenity::User,
+    >,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("resource is guild_only").get() as i64;
+    let resources = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::get_resources(
+            &conn,
+            guild_id,
+            player.as_ref().map(|player| player.id.get() as i64),
+        )?
+    };
+    if resources.is_empty() {
+        ctx.say("There are no resources yet. Create one with /resource create.")
+            .await?;
+        return Ok(());
+    }
+
+    let mut lines = Vec::new();
+    for (player_id, group) in resources::group_by_player(&resources) {
+        let name = discord::get_nick_or_name_by_id(ctx, &player_id).await?;
+        lines.push(format!("**{}**", name));
+        lines.extend(
+            group
+                .into_iter()
+                .map(|resource| format!("- {}", resources::format_resource(resource))),
+        );
+    }
+
+    let pages = discord::paginate(&lines, discord::MESSAGE_LIMIT);
+    discord::send_pages(ctx, &pages).await?;
+    Ok(())
+}
+
+/// Takes a rest, restoring everyone's resources that recover on one
+#[command(
+    slash_command,
+    guild_only,
+    check = "is_gm",
+    help_text_fn = "help::rest"
+)]
+pub async fn rest(ctx: Context<'_>) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("rest is guild_only").get() as i64;
+    let restored = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::rest(&conn, guild_id)?
+    };
+
+    let msg = match restored {
+        0 => "Everyone rested, but nothing needed restoring.".to_string(),
+        1 => "Everyone rested, restoring 1 resource.".to_string(),
+        restored => format!("Everyone rested, restoring {} resources.", restored),
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Rolls death saves for dying players
+#[command(
+    slash_command,
+    guild_only,
+    check = "in_roll_channel",
+    help_text_fn = "help::deathsave",
+    subcommands("deathsave_roll", "deathsave_reset"),
+    subcommand_required
+)]
+pub async fn deathsave(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Rolls a death save and shows the running tally
+#[command(slash_command, rename = "roll")]
+pub async fn deathsave_roll(
+    ctx: Context<'_>,
+    #[description = "Who's dying, by default you"] player: Option<serenity::User>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("deathsave is guild_only").get() as i64;
+    let user = player.unwrap_or_else(|| ctx.author().clone());
+    let player_id = user.id.get() as i64;
+
+    let (roll, saves, outcome) = {
+        let conn = ctx.data().pool.clone().get()?;
+        let saves = db::get_death_saves(&conn, guild_id, player_id)?;
+        let rolled = deathsaves::roll(
+            saves,
+            &mut *ctx.data().rng.lock().expect("Unable to lock rng"),
+        );
+        db::set_death_saves(&conn, guild_id, player_id, &rolled.1)?;
+        rolled
+    };
+    ctx.data().metrics.record_rolls(1);
+
+    let name = discord::get_nick_or_name(ctx, user).await;
+    ctx.say(deathsaves::announcement(&name, roll, &saves, outcome))
+        .await?;
+    Ok(())
+}
+
+/// Clears a player's death saves, e.g. after they're healed
+#[command(slash_command, rename = "reset")]
+pub async fn deathsave_reset(
+    ctx: Context<'_>,
+    #[description = "Whose death saves to clear, by default yours"] player: Option<serenity::User>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("deathsave is guild_only").get() as i64;
+    let user = player.unwrap_or_else(|| ctx.author().clone());
+    let reset = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::reset_death_saves(&conn, guild_id, user.id.get() as i64)?
+    };
+
+    let name = discord::get_nick_or_name(ctx, user).await;
+    let msg = if reset {
+        format!("Cleared {}'s death saves.", name)
+    } else {
+        format!("{} has no death saves to clear.", name)
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Splits coins and hands out items after a haul
+#[command(
+    slash_command,
+    help_text_fn = "help::loot",
+    subcommands("loot_split", "loot_roll"),
+    subcommand_required
+)]
+pub async fn loot(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Splits coins evenly between players
+#[command(slash_command, rename = "split")]
+pub async fn loot_split(
+    ctx: Context<'_>,
+    #[description = "Gold pieces to split"] total_gp: u32,
+    #[description = "The number of players, or mentions of each of them"] players: String,
+    #[description = "Silver pieces to split as well"] sp: Option<u32>,
+    #[description = "Copper pieces to split as well"] cp: Option<u32>,
+) -> Result<()> {
+    let players = match loot::parse_players(&players) {
+        Ok(players) => players,
+        Err(e) => {
+            ctx.say(format!("{}.", e)).await?;
+            return Ok(());
+        }
+    };
+
+    let total = loot::Coins {
+        gp: total_gp.into(),
+        sp: sp.unwrap_or(0).into(),
+        cp: cp.unwrap_or(0).into(),
+    };
+    let (share, remainder) = loot::split(total, players.count());
+    let between = match &players {
+        loot::Players::Count(1) => "1 player".to_string(),
+        loot::Players::Count(count) => format!("{} players", count),
+        loot::Players::Mentioned(users) => users
+            .iter()
+            .map(|user| format!("<@{}>", user))
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    let left_over = if remainder.to_cp() > 0 {
+        format!(", with {} left over", remainder)
+    } else {
+        String::new()
+    };
+
+    discord::send_chunked(
+        ctx,
+        &format!(
+            "💰 Splitting {} between {}: {} each{}.",
+            total, between, share, left_over
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Awards an item to one of the mentioned players at random
+#[command(slash_command, rename = "roll", check = "in_roll_channel")]
+pub async fn loot_roll(
+    ctx: Context<'_>,
+    #[description = "The item"] item: String,
+    #[description = "Mentions of everyone who wants it"] players: String,
+) -> Result<()> {
+    let item = item.trim();
+    let players = match loot::parse_players(&players) {
+        Ok(loot::Players::Mentioned(users)) => users,
+        Ok(loot::Players::Count(_)) => {
+            ctx.say("Mention everyone who wants it, so I know who to pick from.")
+                .await?;
+            return Ok(());
+        }
+        Err(e) => {
+            ctx.say(format!("{}.", e)).await?;
+            return Ok(());
+        }
+    };
+    if item.is_empty() {
+        ctx.say("Name the item being handed out.").await?;
+        return Ok(());
+    }
+
+    let (roll, winner) = loot::award(
+        &players,
+        &mut *ctx.data().rng.lock().expect("Unable to lock rng"),
+    )
+    .expect("parse_players mentions at least one player");
+    ctx.data().metrics.record_rolls(1);
+
+    ctx.say(format!(
+        "🎲 d{} → {}: <@{}> gets **{}**!",
+        players.len(),
+        roll,
+        winner,
+        item
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Tracks initiative for a fight in this channel
+#[command(
+    slash_command,
+    guild_only,
+    help_text_fn = "help::init",
+    subcommands(
+        "init_start",
+        "init_add",
+        "init_list",
+        "init_next",
+        "init_remove",
+        "init_end"
+    ),
+    subcommand_required
+)]
+pub async fn init(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+const NO_COMBAT: &str = "There's no fight in this channel. Start one with /init start.";
+
+/// Loads this channel's fight, in turn order.
+fn channel_combat(ctx: Context<'_>) -> Result<Option<db::Combat>> {
+    let conn = ctx.data().pool.clone().get()?;
+    let combat = db::get_combat(&conn, ctx.channel_id().get() as i64)?;
+
+    Ok(combat.map(|mut combat| {
+        initiative::sort(&mut combat.combatants);
+        combat
+    }))
+}
+
+async fn autocomplete_combatant(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let combat = match channel_combat(ctx) {
+        Ok(combat) => combat,
+        Err(e) => {
+            log::error!("Error getting combatants to autocomplete: {}", e);
+            return Vec::new();
+        }
+    };
+
+    combat
+        .map(|combat| combat.combatants)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|c| c.name)
+        .filter(|name| name.to_lowercase().starts_with(&partial.to_lowercase()))
+        .take(discord::AUTOCOMPLETE_LIMIT)
+        .collect()
+}
+
+/// Starts a fight in this channel
+#[command(slash_command, rename = "start")]
+pub async fn init_start(ctx: Context<'_>) -> Result<()> {
+    let started = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::start_combat(&conn, ctx.channel_id().get() as i64)?
+    };
+
+    let msg = if started {
+        "⚔️ The fight is on! Add combatants with /init add."
+    } else {
+        "There's already a fight in this channel. End it with /init end first."
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Adds a combatant, rolling their initiative
+#[command(slash_command, rename = "add")]
+pub async fn init_add(
+    ctx: Context<'_>,
+    #[description = "Name"] name: String,
+    #[description = "Initiative modifier"] modifier: Option<i64>,
+    #[description = "Initiative they rolled themselves, instead of rolling 1d20 + modifier"]
+    roll: Option<i64>,
+) -> Result<()> {
+    let name = match initiative::normalize_name(&name) {
+        Ok(name) => name,
+        Err(e) => {
+            ctx.say(format!("{}.", e)).await?;
+            return Ok(());
+        }
+    };
+    let modifier = modifier.unwrap_or(0);
+
+    let combatant = {
+        let mut rng = ctx.data().rng.lock().expect("Unable to lock rng");
+        initiative::roll(name, modifier, roll, &mut *rng)
+    };
+
+    let added = {
+        let mut conn = ctx.data().pool.clone().get()?;
+        let channel_id = ctx.channel_id().get() as i64;
+        let tx = conn.transaction()?;
+        let added = db::get_combat(&tx, channel_id)?.is_some();
+        if added {
+            db::add_combatant(&tx, channel_id, &combatant)?;
+        }
+        tx.commit()?;
+        added
+    };
+
+    if !added {
+        ctx.say(NO_COMBAT).await?;
+        return Ok(());
+    }
+
+    let msg = match roll {
+        Some(_) => format!(
+            "{} joins the fight at {}.",
+            combatant.name, combatant.initiative
+        ),
+        None => format!(
+            "🎲 {} rolled {} for initiative ({} {:+}).",
+            combatant.name,
+            combatant.initiative,
+            combatant.initiative - modifier,
+            modifier
+        ),
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Shows the turn order
+#[command(slash_command, rename = "list")]
+pub async fn init_list(ctx: Context<'_>) -> Result<()> {
+    let msg = match channel_combat(ctx)? {
+        Some(combat) => initiative::format_order(
+            &combat.combatants,
+            &combat.conditions,
+            combat.current.as_deref(),
+            combat.round,
+        ),
+        None => NO_COMBAT.to_string(),
+    };
+
+    discord::send_chunked(ctx, &msg).await?;
+    Ok(())
+}
+
+/// Moves on to the next combatant's turn
+#[command(slash_command, rename = "next")]
+pub async fn init_next(ctx: Context<'_>) -> Result<()> {
+    let Some(combat) = channel_combat(ctx)? else {
+        ctx.say(NO_COMBAT).await?;
+        return Ok(());
+    };
+    let Some((current, round)) =
+        initiative::next_turn(&combat.combatants, combat.current.as_deref(), combat.round)
+    else {
+        ctx.say("No one has joined the fight yet. Add combatants with /init add.")
+            .await?;
+        return Ok(());
+    };
+
+    // Timed conditions count down as each new round starts.
+    let (conditions, expired) = if round > combat.round {
+        initiative::tick(combat.conditions)
+    } else {
+        (combat.conditions, Vec::new())
+    };
+
+    {
+        let mut conn = ctx.data().pool.clone().get()?;
+        let channel_id = ctx.channel_id().get() as i64;
+        let tx = conn.transaction()?;
+        db::set_turn(&tx, channel_id, Some(&current), round)?;
+        for condition in conditions
+            .iter()
+            .filter(|condition| condition.rounds.is_some())
+        {
+            db::set_condition(&tx, channel_id, condition)?;
+        }
+        for condition in &expired {
+            db::remove_condition(&tx, channel_id, &condition.target, &condition.name)?;
+        }
+        tx.commit()?;
+    }
+
+    let mut msg = expired
+        .iter()
+        .map(|condition| format!("{} has ended on {}.\n", condition.name, condition.target))
+        .collect::<String>();
+    msg += &format!(
+        "**{}**, you're up!\n\n{}",
+        current,
+        initiative::format_order(&combat.combatants, &conditions, Some(&current), round)
+    );
+    discord::send_chunked(ctx, &msg).await?;
+    Ok(())
+}
+
+/// Removes a combatant from a fight, passing the turn on if it was theirs. Returns false if
+/// there was no such combatant.
+fn remove_from_fight(
+    conn: &rusqlite::Connection,
+    channel_id: i64,
+    combat: &db::Combat,
+    name: &str,
+) -> Result<bool> {
+    let removed = db::remove_combatant(conn, channel_id, name)?;
+    if removed {
+        let (current, round) = initiative::after_removal(
+            &combat.combatants,
+            combat.current.as_deref(),
+            combat.round,
+            name,
+        );
+        db::set_turn(conn, channel_id, current.as_deref(), round)?;
+    }
+
+    Ok(removed)
+}
+
+/// Removes a combatant from the fight
+#[command(slash_command, rename = "remove")]
+pub async fn init_remove(
+    ctx: Context<'_>,
+    #[description = "Name"]
+    #[autocomplete = "autocomplete_combatant"]
+    name: String,
+) -> Result<()> {
+    let Some(combat) = channel_combat(ctx)? else {
+        ctx.say(NO_COMBAT).await?;
+        return Ok(());
+    };
+    let name = name.trim();
+
+    let removed = {
+        let mut conn = ctx.data().pool.clone().get()?;
+        let tx = conn.transaction()?;
+        let removed = remove_from_fight(&tx, ctx.channel_id().get() as i64, &combat, name)?;
+        tx.commit()?;
+        removed
+    };
+
+    let msg = if removed {
+        format!("{} has left the fight.", name)
+    } else {
+        format!("There's no {} in this fight.", name)
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Ends the fight in this channel
+#[command(slash_command, rename = "end")]
+pub async fn init_end(ctx: Context<'_>) -> Result<()> {
+    let ended = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::end_combat(&conn, ctx.channel_id().get() as i64)?
+    };
+
+    let msg = if ended {
+        "The fight is over."
+    } else {
+        NO_COMBAT
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Finds a combatant in this channel's fight, or says why there isn't one.
+async fn find_combatant(
+    ctx: Context<'_>,
+    name: &str,
+) -> Result<Option<(db::Combat, db::Combatant)>> {
+    let Some(combat) = channel_combat(ctx)? else {
+        ctx.say(NO_COMBAT).await?;
+        return Ok(None);
+    };
+    let Some(combatant) = combat.combatants.iter().find(|c| c.name == name).cloned() else {
+        ctx.say(format!("There's no {} in this fight.", name))
+            .await?;
+        return Ok(None);
+    };
+
+    Ok(Some((combat, combatant)))
+}
+
+/// Sets a combatant's hit points
+#[command(
+    slash_command,
+    rename = "hp-set",
+    guild_only,
+    help_text_fn = "help::hp"
+)]
+pub async fn hp_set(
+    ctx: Context<'_>,
+    #[description = "Combatant"]
+    #[autocomplete = "autocomplete_combatant"]
+    name: String,
+    #[description = "Current hit points"]
+    #[min = 0]
+    current: i64,
+    #[description = "Maximum hit points"]
+    #[min = 1]
+    max: Option<i64>,
+    #[description = "Only show whether they're healthy or bloodied, e.g. for monsters"]
+    hidden: Option<bool>,
+) -> Result<()> {
+    let Some((_, mut combatant)) = find_combatant(ctx, name.trim()).await? else {
+        return Ok(());
+    };
+    combatant.hp = Some(current);
+    combatant.max_hp = max.or(combatant.max_hp);
+    combatant.hidden_hp = hidden.unwrap_or(combatant.hidden_hp);
+
+    {
+        let conn = ctx.data().pool.clone().get()?;
+        db::set_hp(&conn, ctx.channel_id().get() as i64, &combatant)?;
+    }
+
+    ctx.say(format!(
+        "{} is {}.",
+        combatant.name,
+        initiative::format_hp(&combatant).unwrap_or_default()
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Damages a combatant
+#[command(slash_command, guild_only, help_text_fn = "help::hp")]
+pub async fn damage(
+    ctx: Context<'_>,
+    #[description = "Combatant"]
+    #[autocomplete = "autocomplete_combatant"]
+    name: String,
+    #[description = "Damage"]
+    #[min = 1]
+    amount: i64,
+    #[description = "Take monsters with hidden hit points out of the fight when they drop to 0"]
+    remove: Option<bool>,
+) -> Result<()> {
+    let Some((combat, mut combatant)) = find_combatant(ctx, name.trim()).await? else {
+        return Ok(());
+    };
+    let Some(hp) = combatant.hp else {
+        ctx.say(format!(
+            "{} has no hit points yet. Set them with /hp-set.",
+            combatant.name
+        ))
+        .await?;
+        return Ok(());
+    };
+    combatant.hp = Some(initiative::damage(hp, amount));
+    let down = combatant.hp == Some(0);
+    let remove = down && combatant.hidden_hp && remove.unwrap_or(false);
+
+    {
+        let mut conn = ctx.data().pool.clone().get()?;
+        let channel_id = ctx.channel_id().get() as i64;
+        let tx = conn.transaction()?;
+        db::set_hp(&tx, channel_id, &combatant)?;
+        if remove {
+            remove_from_fight(&tx, channel_id, &combat, &combatant.name)?;
+        }
+        tx.commit()?;
+    }
+
+    let mut msg = format!(
+        "{} takes {} damage ({}).",
+        combatant.name,
+        amount,
+        initiative::format_hp(&combatant).unwrap_or_default()
+    );
+    if down {
+        msg += &format!("\n💀 {} is down!", combatant.name);
+    }
+    if remove {
+        msg += &format!(" {} has left the fight.", combatant.name);
+    }
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Heals a combatant
+#[command(slash_command, guild_only, help_text_fn = "help::hp")]
+pub async fn heal(
+    ctx: Context<'_>,
+    #[description = "Combatant"]
+    #[autocomplete = "autocomplete_combatant"]
+    name: String,
+    #[description = "Healing"]
+    #[min = 1]
+    amount: i64,
+    #[description = "Temporary hit points, which can go past the maximum"] temp: Option<bool>,
+) -> Result<()> {
+    let Some((_, mut combatant)) = find_combatant(ctx, name.trim()).await? else {
+        return Ok(());
+    };
+    let Some(hp) = combatant.hp else {
+        ctx.say(format!(
+            "{} has no hit points yet. Set them with /hp-set.",
+            combatant.name
+        ))
+        .await?;
+        return Ok(());
+    };
+    combatant.hp = Some(initiative::heal(
+        hp,
+        combatant.max_hp,
+        amount,
+        temp.unwrap_or(false),
+    ));
+
+    {
+        let conn = ctx.data().pool.clone().get()?;
+        db::set_hp(&conn, ctx.channel_id().get() as i64, &combatant)?;
+    }
+
+    ctx.say(format!(
+        "{} is healed for {} ({}).",
+        combatant.name,
+        amount,
+        initiative::format_hp(&combatant).unwrap_or_default()
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Tracks conditions on combatants in this channel's fight
+#[command(
+    slash_command,
+    guild_only,
+    help_text_fn = "help::condition",
+    subcommands("condition_add", "condition_remove", "condition_list"),
+    subcommand_required
+)]
+pub async fn condition(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Puts a condition on a combatant
+#[command(slash_command, rename = "add")]
+pub async fn condition_add(
+    ctx: Context<'_>,
+    #[description = "Combatant"]
+    #[autocomplete = "autocomplete_combatant"]
+    target: String,
+    #[description = "Condition, e.g. Frightened"] condition: String,
+    #[description = "How many rounds it lasts, or until it's removed if not set"]
+    #[min = 1]
+    rounds: Option<u32>,
+) -> Result<()> {
+    let Some(combat) = channel_combat(ctx)? else {
+        ctx.say(NO_COMBAT).await?;
+        return Ok(());
+    };
+    let target = target.trim();
+    if !combat.combatants.iter().any(|c| c.name == target) {
+        ctx.say(format!("There's no {} in this fight.", target))
+            .await?;
+        return Ok(());
+    }
+    let condition = match initiative::normalize_name(&condition) {
+        Ok(condition) => db::Condition {
+            target: target.to_string(),
+            name: condition,
+            rounds: rounds.map(i64::from),
+        },
+        Err(_) => {
+            ctx.say("A condition needs a name.").await?;
+            return Ok(());
+        }
+    };
+
+    {
+        let conn = ctx.data().pool.clone().get()?;
+        db::set_condition(&conn, ctx.channel_id().get() as i64, &condition)?;
+    }
+
+    ctx.say(format!(
+        "{} is {}.",
+        target,
+        initiative::format_condition(&condition)
+    ))
+    .await?;
+    Ok(())
+}
+
+async fn autocomplete_condition(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let combat = match channel_combat(ctx) {
+        Ok(combat) => combat,
+        Err(e) => {
+            log::error!("Error getting conditions to autocomplete: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let mut names = combat
+        .map(|combat| combat.conditions)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|condition| condition.name)
+        .filter(|name| name.to_lowercase().starts_with(&partial.to_lowercase()))
+        .collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+    names.truncate(discord::AUTOCOMPLETE_LIMIT);
+    names
+}
+
+/// Removes a condition from a combatant
+#[command(slash_command, rename = "remove")]
+pub async fn condition_remove(
+    ctx: Context<'_>,
+    #[description = "Combatant"]
+    #[autocomplete = "autocomplete_combatant"]
+    target: String,
+    #[description = "Condition"]
+    #[autocomplete = "autocomplete_condition"]
+    condition: String,
+) -> Result<()> {
+    let (target, condition) = (target.trim(), condition.trim());
+    let removed = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::remove_condition(&conn, ctx.channel_id().get() as i64, target, condition)?
+    };
+
+    let msg = if removed {
+        format!("{} is no longer {}.", target, condition)
+    } else {
+        format!("{} isn't {}.", target, condition)
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Lists the conditions on everyone in the fight
+#[command(slash_command, rename = "list")]
+pub async fn condition_list(ctx: Context<'_>) -> Result<()> {
+    let msg = match channel_combat(ctx)? {
+        Some(combat) if combat.conditions.is_empty() => "No one has any conditions.".to_string(),
+        Some(combat) => combat
+            .conditions
+            .iter()
+            .map(|condition| {
+                format!(
+                    "{}: {}",
+                    condition.target,
+                    initiative::format_condition(condition)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        None => NO_COMBAT.to_string(),
+    };
+
+    discord::send_chunked(ctx, &msg).await?;
+    Ok(())
+}
+
+/// Schedules a game
+#[command(slash_command, check = "is_gm", help_text_fn = "help::schedule")]
+#[allow(clippy::too_many_arguments)]
+pub async fn schedule(
+    ctx: Context<'_>,
+    #[description = "Channel"] channel: serenity::Channel,
+    #[description = "On"] on: serenity::Timestamp,
+    #[description = "Message"] msg: Option<String>,
+    #[description = "A template saved with /template save, instead of a message"]
+    #[autocomplete = "autocomplete_template"]
+    template: Option<String>,
+    #[description = "Title, sends the message as an embed"] title: Option<String>,
+    #[description = "Image URL, sends the message as an embed"] image_url: Option<String>,
+    #[description = "Only show what would be scheduled, without saving it"] dry_run: Option<bool>,
+) -> Result<()> {
+    if !claim_interaction(ctx).await? {
+        return Ok(());
+    }
+    ctx.defer().await?;
+
+    let (msg, templated) = match (msg, template) {
+        (Some(msg), None) => (msg, false),
+        (None, Some(name)) => {
+            let Some(guild_id) = ctx.guild_id() else {
+                ctx.say("Templates can only be used in a server.").await?;
+                return Ok(());
+            };
+            let name = name.trim().to_lowercase();
+            let text = {
+                let conn = ctx.data().pool.clone().get()?;
+                db::get_template(&conn, guild_id.get() as i64, &name)?
+            };
+            match text {
+                Some(text) => (text, true),
+                None => {
+                    ctx.say(no_template(&name)).await?;
+                    return Ok(());
+                }
+            }
+        }
+        _ => {
+            ctx.say("Give either a message or a template to send.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    log::info!("Scheduling message: {} on {}", msg, on);
+
+    let channel_id = channel.id().get();
+
+    let sch = db::ScheduledMessage {
+        channel_id,
+        msg,
+        on: (*on).into(),
+        title,
+        image_url,
+        created_by: Some(ctx.author().id.get()),
+        guild_id: ctx.guild_id().map(|guild_id| guild_id.get()),
+        templated,
+    };
+
+    if dry_run.unwrap_or(false) {
+        let unix = on.unix_timestamp();
+        let mut lines = vec![format!(
+            "🧪 Dry run, nothing was saved. This would be sent in <#{}> on <t:{unix}:F> \
+            (<t:{unix}:R>), which is {} UTC.",
+            channel_id,
+            on.format("%Y-%m-%d %H:%M")
+        )];
+        if on.unix_timestamp() <= chrono::Utc::now().timestamp() {
+            lines.push("⚠️ That's in the past, so it would be sent right away.".to_string());
+        }
+        if sch.templated {
+            lines.push("Its placeholders would be filled in when it's sent.".to_string());
+        }
+        // An embed previews itself, anything else is quoted.
+        let embed = discord::schedule_embed(&sch);
+        if embed.is_none() {
+            lines.extend(sch.msg.lines().map(|line| format!("> {}", line)));
+        }
+
+        let content = discord::truncate(&lines.join("\n"), discord::MESSAGE_LIMIT - 1);
+        let mut reply = CreateReply::default()
+            .content(content)
+            .allowed_mentions(serenity::CreateAllowedMentions::new());
+        if let Some(embed) = embed {
+            reply = reply.embed(embed);
+        }
+        ctx.send(reply).await?;
+        return Ok(());
+    }
+
+    {
+        let mut scheduler = ctx
+            .data()
+            .scheduler
+            .write()
+            .expect("Unable to get mut scheduler");
+
+        log::info!("Scheduling message");
+        scheduler.schedule(&sch)?;
+        log::info!("Scheduled message");
+    }
+
+    let unix = on.unix_timestamp();
+    let response = format!("Message scheduled for <t:{unix}:F> (<t:{unix}:R>)!");
+    ctx.data().processed.reply(ctx.id(), &response);
+    ctx.say(response).await?;
+
+    Ok(())
+}
+
+/// Changes the scheduled message
+#[command(
+    slash_command,
+    rename = "schedule-edit",
+    check = "is_gm",
+    help_text_fn = "help::schedule_edit"
+)]
+pub async fn schedule_edit(
+    ctx: Context<'_>,
+    #[description = "New channel"] channel: Option<serenity::Channel>,
+    #[description = "New message"] msg: Option<String>,
+    #[description = "New time"] on: Option<serenity::Timestamp>,
+) -> Result<()> {
+    if !claim_interaction(ctx).await? {
+        return Ok(());
+    }
+
+    let current = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::get_schedule(&conn)?
+    };
+    let Some(current) = current else {
+        ctx.say("Nothing is scheduled.").await?;
+        return Ok(());
+    };
+    if channel.is_none() && msg.is_none() && on.is_none() {
+        ctx.say("Give a new channel, message or time to change.")
+            .await?;
+        return Ok(());
+    }
+    if on.is_some_and(|on| on.unix_timestamp() <= chrono::Utc::now().timestamp()) {
+        ctx.say("The new time has to be in the future.").await?;
+        return Ok(());
+    }
+    // A template's placeholders are still filled in when it's sent, so they're checked again.
+    let msg = match msg {
+        Some(msg) if current.templated => match templates::normalize_text(&msg) {
+            Ok(msg) => Some(msg),
+            Err(e) => {
+                ctx.say(format!("{}.", e)).await?;
+                return Ok(());
+            }
+        },
+        msg => msg,
+    };
+
+    let sch = db::ScheduledMessage {
+        channel_id: channel.map_or(current.channel_id, |channel| channel.id().get()),
+        msg: msg.unwrap_or_else(|| current.msg.clone()),
+        on: on.map_or(current.on, |on| (*on).into()),
+        ..current.clone()
+    };
+    let rescheduled = ctx
+        .data()
+        .scheduler
+        .write()
+        .expect("Unable to get mut scheduler")
+        .reschedule(&sch)?;
+    if !rescheduled {
+        ctx.say("Nothing is scheduled any more, it may have just been sent.")
+            .await?;
+        return Ok(());
+    }
+
+    let mut changes = Vec::new();
+    if sch.channel_id != current.channel_id {
+        changes.push(format!(
+            "Channel: <#{}> → <#{}>",
+            current.channel_id, sch.channel_id
+        ));
+    }
+    if sch.on != current.on {
+        changes.push(format!(
+            "Time: <t:{}:F> → <t:{unix}:F> (<t:{unix}:R>)",
+            current.on.timestamp(),
+            unix = sch.on.timestamp()
+        ));
+    }
+    if sch.msg != current.msg {
+        changes.push("Message:".to_string());
+        changes.extend(sch.msg.lines().map(|line| format!("> {}", line)));
+    }
+    let response = if changes.is_empty() {
+        "Nothing changed.".to_string()
+    } else {
+        format!("Updated the scheduled message.\n{}", changes.join("\n"))
+    };
+    let response = discord::truncate(&response, discord::MESSAGE_LIMIT - 1);
+    ctx.data().processed.reply(ctx.id(), &response);
+    ctx.send(
+        CreateReply::default()
+            .content(response)
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Shows how long until the next game
+#[command(slash_command, rename = "next-game", help_text_fn = "help::next_game")]
+pub async fn next_game(ctx: Context<'_>) -> Result<()> {
+    let now = chrono::Local::now();
+    let next = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::get_next_schedule(&conn, now)?
+    };
+
+    let msg = match next {
+        // The relative timestamp keeps counting down for anyone reading this later.
+        Some(sch) => format!(
+            "🎲 Game night in {}: <t:{unix}:F> (<t:{unix}:R>) in <#{}>.",
+            discord::countdown(sch.on - now),
+            sch.channel_id,
+            unix = sch.on.timestamp()
+        ),
+        None => "No game is scheduled yet.".to_string(),
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Sends the scheduled game message now
+#[command(
+    slash_command,
+    rename = "schedule-fire",
+    check = "is_gm",
+    help_text_fn = "help::schedule_fire"
+)]
+pub async fn schedule_fire(ctx: Context<'_>) -> Result<()> {
+    ctx.defer().await?;
+    // Stopped before it's read, so the timer can't send it as well while it's sent here.
+    ctx.data()
+        .scheduler
+        .write()
+        .expect("Unable to get mut scheduler")
+        .cancel_pending();
+
+    let pool = ctx.data().pool.clone();
+    let sch = {
+        let conn = pool.get()?;
+        db::get_schedule(&conn)?
+    };
+    let Some(sch) = sch else {
+        ctx.say("Nothing is scheduled.").await?;
+        return Ok(());
+    };
+
+    if let Err(e) = Scheduler::send_now(ctx.serenity_context(), &pool, &sch).await {
+        // It's still saved, so it's waited for again as if it was never fired.
+        ctx.data()
+            .scheduler
+            .write()
+            .expect("Unable to get mut scheduler")
+            .sync_schedule()?;
+        return Err(e.into());
+    }
+
+    ctx.say(format!(
+        "Sent the message scheduled for <t:{}:F> to <#{}>.",
+        sch.on.timestamp(),
+        sch.channel_id
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Saves messages with placeholders for /schedule
+#[command(
+    slash_command,
+    guild_only,
+    check = "is_gm",
+    help_text_fn = "help::template",
+    subcommands("template_save", "template_list", "template_delete"),
+    subcommand_required
+)]
+pub async fn template(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+async fn autocomplete_template(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Vec::new();
+    };
+
+    let templates = ctx
+        .data()
+        .pool
+        .get()
+        .map_err(Error::from)
+        .and_then(|conn| Ok(db::get_templates(&conn, guild_id.get() as i64)?));
+    match templates {
+        Ok(templates) => templates
+            .into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| name.starts_with(&partial.to_lowercase()))
+            .take(discord::AUTOCOMPLETE_LIMIT)
+            .collect(),
+        Err(e) => {
+            log::error!("Error getting templates to autocomplete: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+fn no_template(name: &str) -> String {
+    format!(
+        "There's no template called {}. Save it with /template save first.",
+        name
+    )
+}
+
+/// Saves a message template, replacing any with the same name
+#[command(slash_command, rename = "save")]
+pub async fn template_save(
+    ctx: Context<'_>,
+    #[description = "Name"] name: String,
+    #[description = "Text, with placeholders like {date}, {relative}, {mention} or {session_number}"]
+    text: String,
+) -> Result<()> {
+    let normalized = templates::normalize_name(&name)
+        .and_then(|name| Ok((name, templates::normalize_text(&text)?)));
+    let (name, text) = match normalized {
+        Ok(normalized) => normalized,
+        Err(e) => {
+            ctx.say(format!("{}.", e)).await?;
+            return Ok(());
+        }
+    };
+
+    let guild_id = ctx.guild_id().expect("template is guild_only").get();
+    let replaced = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::save_template(&conn, guild_id as i64, &name, &text)?
+    };
+
+    let msg = if replaced {
+        format!("Replaced the {} template.", name)
+    } else {
+        format!(
+            "Saved the {} template. Send it with /schedule template:{}.",
+            name, name
+        )
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Lists the message templates
+#[command(slash_command, rename = "list")]
+pub async fn template_list(ctx: Context<'_>) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("template is guild_only").get();
+    let templates = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::get_templates(&conn, guild_id as i64)?
+    };
+
+    if templates.is_empty() {
+        ctx.say("There are no templates yet. Save one with /template save.")
+            .await?;
+        return Ok(());
+    }
+
+    let lines = templates
+        .iter()
+        .map(|(name, text)| format!("**{}**: {}", name, text))
+        .collect::<Vec<_>>();
+    let pages = discord::paginate(&lines, discord::MESSAGE_LIMIT);
+    discord::send_pages(ctx, &pages).await?;
+    Ok(())
+}
+
+/// Deletes a message template
+#[command(slash_command, rename = "delete")]
+pub async fn template_delete(
+    ctx: Context<'_>,
+    #[description = "Template"]
+    #[autocomplete = "autocomplete_template"]
+    name: String,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("template is guild_only").get();
+    let name = name.trim().to_lowercase();
+    let deleted = {
+        let conn = ctx.data().pool.clone().get()?;
+        db::delete_template(&conn, guild_id as i64, &name)?
+    };
+
+    let msg = if deleted {
+        format!("Deleted the {} template.", name)
+    } else {
+        no_template(&name)
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Claims the interaction of a command that changes something, so a retry of it isn't applied
+/// twice. Returns whether the command should go ahead; retries get the first reply again, or
+/// nothing while the first is still running, since its reply answers them too. Record the reply
+/// with `processed.reply` once the change is made; a claim with no reply is released when the
+/// command finishes.
+async fn claim_interaction(ctx: Context<'_>) -> Result<bool> {
+    match ctx.data().processed.claim(ctx.id()) {
+        None => Ok(true),
+        Some(discord::Processed::Replied(reply)) => {
+            log::info!("Answering a retried interaction {} again", ctx.id());
+            ctx.say(reply).await?;
+            Ok(false)
+        }
+        Some(discord::Processed::Running) => {
+            log::info!("Ignoring a retried interaction {} while it runs", ctx.id());
+            Ok(false)
+        }
+    }
+}
+
+/// Loads the settings for the guild the command was invoked in, or the defaults outside a guild.
+fn guild_settings(ctx: Context<'_>) -> Result<Settings> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(Settings::default());
+    };
+
+    let conn = ctx.data().pool.clone().get()?;
+    Ok(Settings::load(&conn, guild_id.get() as i64)?)
+}
+
+/// Sets how rolls are shown in this channel
+#[command(
+    slash_command,
+    rename = "channelmode",
+    guild_only,
+    required_permissions = "ADMINISTRATOR",
+    help_text_fn = "help::channelmode"
+)]
+pub async fn channel_mode(
+    ctx: Context<'_>,
+    #[description = "How rolls are shown here, or leave it out to use the server's roll_mode"]
+    mode: Option<RollMode>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().expect("channelmode is guild_only").get() as i64;
+    let settings = guild_settings(ctx)?;
+    {
+        let conn = ctx.data().pool.clone().get()?;
+        let stored = mode.map(|mode| mode.to_string());
+        db::set_channel_roll_mode(
+            &conn,
+            guild_id,
+            ctx.channel_id().get() as i64,
+            stored.as_deref(),
+        )?;
+    }
+
+    let msg = match mode {
+        Some(mode) => format!("Rolls in this channel are now {}.", mode),
+        None => format!(
+            "Rolls in this channel now follow the server's roll_mode, {}.",
+            RollMode::resolve(None, settings.roll_mode)
+        ),
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Shows or changes the settings for this server
+#[command(
+    slash_command,
+    help_text_fn = "help::settings",
+    guild_only,
+    required_permissions = "ADMINISTRATOR",
+    subcommands("settings_show", "settings_set", "settings_unset"),
+    subcommand_required
+)]
+pub async fn settings(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+async fn autocomplete_setting(_ctx: Context<'_>, partial: &str) -> Vec<String> {
+    settings::Key::ALL
+        .iter()
+        .map(|key| key.name().to_string())
+        .filter(|name| name.starts_with(partial))
+        .collect()
+}
+
+/// Shows the current settings
+#[command(slash_command, rename = "show")]
+pub async fn settings_show(ctx: Context<'_>) -> Result<()> {
+    let settings = guild_settings(ctx)?;
+
+    let lines = settings::Key::ALL
+        .iter()
+        .map(|key| {
+            format!(
+                "`{}`: {} — {}",
+                key,
+                settings.display(*key),
+                key.description()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    ctx.send(
+        CreateReply::default()
+            .content(lines)
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Changes a setting
+#[command(slash_command, rename = "set")]
+pub async fn settings_set(
+    ctx: Context<'_>,
+    #[description = "Setting"]
+    #[autocomplete = "autocomplete_setting"]
+    key: String,
+    #[description = "Value"] value: String,
+) -> Result<()> {
+    let parsed = key
+        .parse::<settings::Key>()
+        .and_then(|key| Ok((key, key.normalize(&value)?)));
+    let (key, value) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            ctx.say(e).await?;
+            return Ok(());
+        }
+    };
+
+    let guild_id = ctx.guild_id().expect("settings is guild_only").get();
+    {
+        let conn = ctx.data().pool.clone().get()?;
+        db::set_setting(&conn, guild_id as i64, key.name(), &value)?;
+    }
+    sync_setting(ctx, key, guild_id)?;
+
+    ctx.say(format!("Set {} to `{}`.", key, value)).await?;
+    Ok(())
+}
+
+/// Resets a setting to its default
+#[command(slash_command, rename = "unset")]
+pub async fn settings_unset(
+    ctx: Context<'_>,
+    #[description = "Setting"]
+    #[autocomplete = "autocomplete_setting"]
+    key: String,
+) -> Result<()> {
+    let key = match key.parse::<settings::Key>() {
+        Ok(key) => key,
+        Err(e) => {
+            ctx.say(e).await?;
+            return Ok(());
+        }
+    };
+
+    let guild_id = ctx.guild_id().expect("settings is guild_only").get();
+    {
+        let conn = ctx.data().pool.clone().get()?;
+        db::delete_setting(&conn, guild_id as i64, key.name())?;
+    }
+    sync_setting(ctx, key, guild_id)?;
+
+    let default = Settings::default().display(key);
+    ctx.say(format!("Reset {} to the default, `{}`.", key, default))
+        .await?;
+    Ok(())
+}
+
+/// Applies a changed setting to anything that was set up from it.
+fn sync_setting(ctx: Context<'_>, key: settings::Key, guild_id: u64) -> Result<()> {
+    if matches!(key, settings::Key::MvpChannel | settings::Key::MvpTime) {
+        ctx.data()
+            .scheduler
+            .write()
+            .expect("Unable to get mut scheduler")
+            .sync_mvp(guild_id)?;
+    }
+
+    Ok(())
+}
+
+/// Discord's attachment size limit for bots.
+const ATTACHMENT_LIMIT: u64 = 8 * 1024 * 1024;
+
+/// Backs up the database
+#[command(
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    help_text_fn = "help::backup"
+)]
+pub async fn backup(ctx: Context<'_>) -> Result<()> {
+    let Some(backups) = ctx.data().backups.clone() else {
+        ctx.say("Backups aren't configured. Set BACKUP_DIR to enable them.")
+            .await?;
+        return Ok(());
+    };
+
+    ctx.defer().await?;
+
+    let pool = ctx.data().pool.clone();
+    let path = tokio::task::spawn_blocking(move || {
+        let conn = pool.get()?;
+        backups.run(&conn)
+    })
+    .await??;
+
+    let size = std::fs::metadata(&path)?.len();
+    let msg = format!("Backed up {} bytes to `{}`.", size, path.display());
+
+    if size < ATTACHMENT_LIMIT {
+        let attachment = serenity::CreateAttachment::path(&path).await?;
+        ctx.send(CreateReply::default().content(msg).attachment(attachment))
+            .await?;
+    } else {
+        ctx.say(msg).await?;
+    }
+
+    Ok(())
+}
+
+/// Reports database statistics and runs an integrity check
+#[command(
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    help_text_fn = "help::dbstats"
+)]
+pub async fn dbstats(ctx: Context<'_>) -> Result<()> {
+    let reply = ctx.say("Checking the database…").await?;
+
+    let pool = ctx.data().pool.clone();
+    let stats = tokio::task::spawn_blocking(move || -> Result<_> {
+        let conn = pool.get()?;
+        Ok(db::stats(&conn)?)
+    })
+    .await??;
+
+    let last_backup = match &ctx.data().backups {
+        Some(backups) => backup::latest(&backups.dir)?,
+        None => None,
+    };
+
+    reply
+        .edit(
+            ctx,
+            CreateReply::default()
+                .content("")
+                .embed(discord::stats_embed(&stats, last_backup)),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Checks the bot's channels, roles and database for problems
+#[command(
+    slash_command,
+    owners_only,
+    hide_in_help,
+    help_text_fn = "help::selfcheck"
+)]
+pub async fn selfcheck(ctx: Context<'_>) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+
+    let problems = selfcheck::run(ctx.serenity_context(), &ctx.data().pool).await?;
+    let embed = discord::selfcheck_embed(&selfcheck::report(&problems), problems.is_empty());
+    ctx.send(CreateReply::default().embed(embed).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// Shows the bot's version, uptime and latency
+#[command(slash_command, help_text_fn = "help::about")]
+pub async fn about(ctx: Context<'_>) -> Result<()> {
+    let pool = ctx.data().pool.clone();
+    let schema_version = tokio::task::spawn_blocking(move || -> Result<_> {
+        let conn = pool.get()?;
+        Ok(db::schema_version(&conn)?)
+    })
+    .await??;
+
+    let about = discord::About {
+        version: env!("CARGO_PKG_VERSION"),
+        git_hash: option_env!("GIT_HASH"),
+        uptime: ctx.data().started.elapsed(),
+        latency: ctx.ping().await,
+        guilds: ctx.cache().guild_count(),
+        schema_version,
+        command_counts: ctx.data().metrics.command_counts(),
+    };
+    ctx.send(CreateReply::default().embed(discord::about_embed(&about)))
+        .await?;
+    Ok(())
+}
+
+/// Shows the database connection pool's state
+#[command(slash_command, help_text_fn = "help::connections")]
+pub async fn connections(ctx: Context<'_>) -> Result<()> {
+    let pool = ctx.data().pool.clone();
+    ctx.say(format!(
+        "Connections: {}, Idle connections: {}",
+        pool.state().connections,
+        pool.state().idle_connections
+    ))
+    .await?;
+    Ok(())
+}
+
+async fn autocomplete_command(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    ctx.framework()
+        .options()
+        .commands
+        .iter()
+        .map(|command| command.name.clone())
+        .filter(|name| name.starts_with(partial))
+        .take(discord::AUTOCOMPLETE_LIMIT)
+        .collect()
+}
+
+/// Lists the commands, or explains one
+#[command(slash_command, help_text_fn = "help::help")]
+pub async fn help(
+    ctx: Context<'_>,
+    #[description = "Command"]
+    #[autocomplete = "autocomplete_command"]
+    command: Option<String>,
+) -> Result<()> {
+    let config = poise::builtins::HelpConfiguration {
+        extra_text_at_bottom: "Use /help command for more about a command.",
+        ephemeral: true,
+        show_subcommands: true,
+        ..Default::default()
+    };
+    poise::builtins::help(ctx, command.as_deref(), config).await?;
+
+    Ok(())
+}
+
+/// Runs a SQL statement against the bot's database
+#[command(slash_command, owners_only, hide_in_help, help_text_fn = "help::sql")]
+pub async fn sql(
+    ctx: Context<'_>,
+    #[description = "A single SQL statement"] query: String,
+    #[description = "Allow the statement to change the database"] write: Option<bool>,
+) -> Result<()> {
+    let write = write.unwrap_or(false);
+    log::warn!("/sql by {} (write: {}): {}", ctx.author().id, write, query);
+    ctx.defer_ephemeral().await?;
+
+    let pool = ctx.data().pool.clone();
+    let outcome = tokio::task::spawn_blocking(move || -> Result<_> {
+        let conn = pool.get()?;
+        Ok(sql::run(&conn, &query, write)?)
+    })
+    .await??;
+
+    let (columns, rows, truncated) = match outcome {
+        sql::Outcome::Changed(changed) => {
+            log::warn!("/sql changed {} row(s)", changed);
+            ctx.say(format!("Changed {} row(s).", changed)).await?;
+            return Ok(());
+        }
+        sql::Outcome::Rows {
+            columns,
+            rows,
+            truncated,
+        } => (columns, rows, truncated),
+    };
+
+    let mut msg = format!("{} row(s)", rows.len());
+    if truncated {
+        msg += &format!(", stopped at {}", sql::MAX_ROWS);
+    }
+    let table = sql::format_table(&columns, &rows);
+    let inline = format!("{}\n```\n{}\n```", msg, table);
+
+    if inline.chars().count() <= discord::MESSAGE_LIMIT {
+        ctx.say(inline).await?;
+    } else {
+        let attachment = serenity::CreateAttachment::bytes(table.into_bytes(), "result.txt");
+        ctx.send(CreateReply::default().content(msg).attachment(attachment))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Registers or unregisters the bot's slash commands
+#[command(
+    slash_command,
+    owners_only,
+    hide_in_help,
+    help_text_fn = "help::register"
+)]
+pub async fn register(ctx: Context<'_>) -> Result<()> {
+    poise::builtins::register_application_commands_buttons(ctx).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_hc::Hc128Rng;
+
+    use super::*;
+
+    #[test]
+    fn shared_rng_advances_across_rolls() {
+        let seeded = Hc128Rng::seed_from_u64(1336);
+        let shared = Mutex::new(seeded.clone());
+
+        let first = roll_shared(&shared, "10d20", 1).unwrap();
+        let second = roll_shared(&shared, "10d20", 1).unwrap();
+
+        // Rolling with a fresh copy of the seed each time, as every command used to, repeats the
+        // first roll.
+        let fresh = roll_shared(&Mutex::new(seeded.clone()), "10d20", 1).unwrap();
+        assert_eq!(first[0].1.rolls, fresh[0].1.rolls);
+        assert_ne!(second[0].1.rolls, fresh[0].1.rolls);
+    }
 }
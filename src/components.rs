@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use futures::future::BoxFuture;
+use poise::serenity_prelude as serenity;
+
+use crate::BotData;
+
+/// The longest custom id Discord accepts.
+pub(crate) const MAX_ID_LEN: usize = 100;
+
+/// Separates the parts of a routed custom id, like `poll:12:3`.
+const SEPARATOR: char = ':';
+
+/// Handles a press of a routed component, given the custom id's payload after its prefix.
+pub(crate) type Handler = for<'a> fn(
+    &'a serenity::Context,
+    &'a serenity::ComponentInteraction,
+    &'a BotData,
+    &'a str,
+) -> BoxFuture<'a, crate::Result<()>>;
+
+/// Builds a custom id that's routed to the handler registered for `prefix`, like `poll:12:3`.
+/// Only the last field may contain the separator, since [`fields`] gives it the rest of the id.
+pub(crate) fn encode(prefix: &str, fields: &[&str]) -> String {
+    let mut id = prefix.to_string();
+    for field in fields {
+        id.push(SEPARATOR);
+        id.push_str(field);
+    }
+
+    id
+}
+
+/// Splits a custom id into its prefix and payload, or None for ids that aren't routed, like those
+/// a command collects itself while it runs.
+pub(crate) fn parse(custom_id: &str) -> Option<(&str, &str)> {
+    custom_id.split_once(SEPARATOR)
+}
+
+/// Splits a payload into exactly `count` fields, the last of which keeps any separators in the
+/// rest of it.
+pub(crate) fn fields(payload: &str, count: usize) -> Option<Vec<&str>> {
+    let fields = payload.splitn(count, SEPARATOR).collect::<Vec<_>>();
+    (fields.len() == count).then_some(fields)
+}
+
+/// Routes presses of components that outlive the command that sent them, like poll buttons, to
+/// the handler registered for their custom id's prefix.
+#[derive(Default)]
+pub(crate) struct Router {
+    handlers: HashMap<&'static str, Handler>,
+}
+
+impl Router {
+    pub(crate) fn register(&mut self, prefix: &'static str, handler: Handler) {
+        self.handlers.insert(prefix, handler);
+    }
+
+    /// Handles a press. Routed ids without a handler, e.g. from a feature that's been removed,
+    /// get an ephemeral reply so Discord doesn't show the interaction as failed. Ids that aren't
+    /// routed are ignored, since the command that sent them collects them.
+    pub(crate) async fn dispatch(
+        &self,
+        ctx: &serenity::Context,
+        press: &serenity::ComponentInteraction,
+        data: &BotData,
+    ) -> crate::Result<()> {
+        let Some((prefix, payload)) = parse(&press.data.custom_id) else {
+            return Ok(());
+        };
+
+        match self.handlers.get(prefix) {
+            Some(handler) => handler(ctx, press, data, payload).await,
+            None => {
+                log::warn!("No handler for component {}", press.data.custom_id);
+                let response = serenity::CreateInteractionResponseMessage::new()
+                    .content("This doesn't do anything any more.")
+                    .ephemeral(true);
+                press
+                    .create_response(ctx, serenity::CreateInteractionResponse::Message(response))
+                    .await?;
+                Ok(())
+            }
+        }
+    }
+}
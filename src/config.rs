@@ -0,0 +1,618 @@
+use std::{env, fmt::Display, net::SocketAddr, path::PathBuf, str::FromStr, time::Duration};
+
+use poise::serenity_prelude::{GuildId, UserId};
+
+use crate::backup::Backups;
+
+#[derive(Debug)]
+pub(crate) enum Error {
+    /// A required variable isn't set.
+    Missing(&'static str),
+    /// A variable is set, but to something that can't be used.
+    Invalid {
+        var: &'static str,
+        value: String,
+        expected: &'static str,
+    },
+    /// Commands were asked to be registered both globally and to specific guilds.
+    ConflictingRegistration,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Missing(var) => write!(f, "Expected {} in the environment", var),
+            Error::Invalid {
+                var,
+                value,
+                expected,
+            } => write!(f, "{} must be {}, but was `{}`", var, expected, value),
+            Error::ConflictingRegistration => write!(
+                f,
+                "REGISTER_GLOBAL=true can't be combined with GUILD_IDS or GUILD_ID"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Every problem with the configuration.
+#[derive(Debug)]
+pub(crate) struct ConfigError(pub Vec<Error>);
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, e) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "- {}", e)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Where slash commands are registered.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Registration {
+    /// To every guild the bot is in. Discord can take a while to show changes.
+    Global,
+    /// To each of these guilds, which shows changes immediately.
+    Guilds(Vec<GuildId>),
+}
+
+/// The bot's configuration, read from the environment at startup. Not `Debug`, so the token
+/// can't end up in logs.
+#[derive(Clone)]
+pub(crate) struct Config {
+    pub token: String,
+    pub db_path: String,
+    /// Whether to create the database's directory when it doesn't exist.
+    pub create_db_dir: bool,
+    /// Log filters, in `RUST_LOG` syntax.
+    pub log_level: Option<String>,
+    pub registration: Registration,
+    pub pool_max_size: u32,
+    pub pool_timeout: Duration,
+    pub backups: Option<Backups>,
+    /// How often to back up, if at all.
+    pub backup_interval: Option<Duration>,
+    /// Where to serve Prometheus metrics, if anywhere.
+    pub metrics_addr: Option<SocketAddr>,
+    /// A bot owner, who can run owner-only commands like `/sql`, besides the application's owner.
+    pub owner_id: Option<UserId>,
+    /// How long granting a player the same experience again asks for confirmation. Zero never
+    /// asks.
+    pub exp_repeat_window: Duration,
+    /// Whether text commands like `!roll 2d6` are accepted besides slash commands. They need the
+    /// privileged Message Content intent, enabled for the bot in the Discord developer portal.
+    pub prefix_commands: bool,
+}
+
+impl Config {
+    pub(crate) fn from_env() -> Result<Self, ConfigError> {
+        Self::from_vars(|var| env::var(var).ok())
+    }
+
+    /// Reads the configuration from `var`, which looks up a variable by name. Every problem is
+    /// reported at once, rather than only the first.
+    pub(crate) fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let mut errors = Vec::new();
+
+        let token = check(
+            &mut errors,
+            var("DISCORD_TOKEN").ok_or(Error::Missing("DISCORD_TOKEN")),
+        );
+        let db_path = check(
+            &mut errors,
+            var("DATABASE_PATH").ok_or(Error::Missing("DATABASE_PATH")),
+        );
+        let create_db_dir = check(&mut errors, parse(&var, "CREATE_DB_DIR", "true or false"));
+        let log_level = var("RUST_LOG");
+
+        let register_global = check(&mut errors, parse(&var, "REGISTER_GLOBAL", "true or false"));
+        // GUILD_ID is the single guild the bot used to be limited to.
+        let guild_ids = match var("GUILD_IDS") {
+            Some(ids) => check(&mut errors, parse_guild_ids("GUILD_IDS", &ids).map(Some)),
+            None => check(
+                &mut errors,
+                var("GUILD_ID")
+                    .map(|id| parse_guild_ids("GUILD_ID", &id))
+                    .transpose(),
+            ),
+        };
+        let registration = match (register_global, guild_ids) {
+            // Either was invalid, which has already been reported.
+            (None, _) | (_, None) => None,
+            (Some(register_global), Some(guild_ids)) => check(
+                &mut errors,
+                match (register_global.unwrap_or(false), guild_ids) {
+                    (true, None) => Ok(Registration::Global),
+                    (true, Some(_)) => Err(Error::ConflictingRegistration),
+                    (false, Some(ids)) => Ok(Registration::Guilds(ids)),
+                    (false, None) => Err(Error::Missing("GUILD_IDS (or REGISTER_GLOBAL=true)")),
+                },
+            ),
+        };
+
+        let pool_max_size = check(
+            &mut errors,
+            parse_positive(&var, "DB_POOL_MAX", "a positive number"),
+        );
+        let pool_timeout = check(
+            &mut errors,
+            parse_positive(&var, "DB_POOL_TIMEOUT", "a positive number of seconds"),
+        );
+
+        let backup_dir = var("BACKUP_DIR").map(PathBuf::from);
+        let backup_retain = check(&mut errors, parse(&var, "BACKUP_RETAIN", "a number"));
+        let backup_daily = check(&mut errors, parse(&var, "BACKUP_DAILY", "true or false"));
+        let backup_interval = check(
+            &mut errors,
+            parse_positive::<u32>(&var, "BACKUP_INTERVAL", "a positive number of hours"),
+        );
+        // BACKUP_DAILY=true is shorthand for BACKUP_INTERVAL=24, which takes precedence.
+        let backup_interval = match (backup_interval, backup_daily) {
+            (Some(Some(hours)), _) => Some(Some(Duration::from_secs(u64::from(hours) * 60 * 60))),
+            (Some(None), Some(Some(true))) => Some(Some(Duration::from_secs(24 * 60 * 60))),
+            (Some(None), Some(_)) => Some(None),
+            _ => None,
+        };
+        if matches!(backup_interval, Some(Some(_))) && backup_dir.is_none() {
+            errors.push(Error::Missing(
+                "BACKUP_DIR (for BACKUP_INTERVAL or BACKUP_DAILY)",
+            ));
+        }
+
+        let metrics_addr = check(
+            &mut errors,
+            parse(&var, "METRICS_ADDR", "an address like 127.0.0.1:9090"),
+        );
+
+        let owner_id = check(
+            &mut errors,
+            parse_positive::<u64>(&var, "OWNER_ID", "a user id"),
+        );
+        let exp_repeat_window = check(
+            &mut errors,
+            parse(&var, "EXP_REPEAT_WINDOW", "a number of seconds"),
+        );
+
+        let prefix_commands = check(&mut errors, parse(&var, "PREFIX_COMMANDS", "true or false"));
+
+        if !errors.is_empty() {
+            return Err(ConfigError(errors));
+        }
+        match (
+            token,
+            db_path,
+            create_db_dir,
+            registration,
+            pool_max_size,
+            pool_timeout,
+            backup_retain,
+            backup_interval,
+            metrics_addr,
+            owner_id,
+            exp_repeat_window,
+            prefix_commands,
+        ) {
+            (
+                Some(token),
+                Some(db_path),
+                Some(create_db_dir),
+                Some(registration),
+                Some(pool_max_size),
+                Some(pool_timeout),
+                Some(backup_retain),
+                Some(backup_interval),
+                Some(metrics_addr),
+                Some(owner_id),
+                Some(exp_repeat_window),
+                Some(prefix_commands),
+            ) => Ok(Self {
+                token,
+                db_path,
+                create_db_dir: create_db_dir.unwrap_or(false),
+                log_level,
+                registration,
+                pool_max_size: pool_max_size.unwrap_or(10),
+                pool_timeout: Duration::from_secs(pool_timeout.unwrap_or(30)),
+                backups: backup_dir.map(|dir| Backups {
+                    dir,
+                    retain: backup_retain.unwrap_or(7),
+                }),
+                backup_interval,
+                metrics_addr,
+                owner_id: owner_id.map(UserId::new),
+                exp_repeat_window: Duration::from_secs(exp_repeat_window.unwrap_or(10)),
+                prefix_commands: prefix_commands.unwrap_or(false),
+            }),
+            _ => unreachable!("every missing value reports an error"),
+        }
+    }
+}
+
+impl Display for Config {
+    /// A summary for the startup log, with the token redacted.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "DISCORD_TOKEN: <redacted>")?;
+        writeln!(f, "DATABASE_PATH: {}", self.db_path)?;
+        if self.create_db_dir {
+            writeln!(f, "CREATE_DB_DIR: true")?;
+        }
+        writeln!(
+            f,
+            "RUST_LOG: {}",
+            self.log_level.as_deref().unwrap_or("<default>")
+        )?;
+        match &self.registration {
+            Registration::Global => writeln!(f, "Commands: registered globally")?,
+            Registration::Guilds(ids) => writeln!(
+                f,
+                "Commands: registered in guilds {}",
+                ids.iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?,
+        }
+        writeln!(
+            f,
+            "DB pool: {} connections, {}s timeout",
+            self.pool_max_size,
+            self.pool_timeout.as_secs()
+        )?;
+        match &self.backups {
+            Some(backups) => {
+                write!(
+                    f,
+                    "Backups: {}, keeping {}",
+                    backups.dir.display(),
+                    backups.retain
+                )?;
+                match self.backup_interval {
+                    Some(interval) => writeln!(f, ", every {}h", interval.as_secs() / 60 / 60)?,
+                    None => writeln!(f, ", on demand")?,
+                }
+            }
+            None => writeln!(f, "Backups: disabled")?,
+        }
+        match self.metrics_addr {
+            Some(addr) => writeln!(f, "Metrics: http://{}/metrics", addr)?,
+            None => writeln!(f, "Metrics: disabled")?,
+        }
+        match self.owner_id {
+            Some(owner_id) => writeln!(f, "Owner: {}", owner_id)?,
+            None => writeln!(f, "Owner: the application's owner")?,
+        }
+        writeln!(
+            f,
+            "Repeated /exp grants confirmed within {}s",
+            self.exp_repeat_window.as_secs()
+        )?;
+        if self.prefix_commands {
+            write!(f, "Text commands: enabled")
+        } else {
+            write!(f, "Text commands: disabled")
+        }
+    }
+}
+
+/// Records an error, so they can all be reported together.
+fn check<T>(errors: &mut Vec<Error>, result: Result<T>) -> Option<T> {
+    result.map_err(|e| errors.push(e)).ok()
+}
+
+/// Parses an optional variable, which is an error only when it's set to something invalid.
+fn parse<T: FromStr>(
+    var: impl Fn(&str) -> Option<String>,
+    name: &'static str,
+    expected: &'static str,
+) -> Result<Option<T>> {
+    var(name)
+        .map(|value| {
+            value.trim().parse().map_err(|_| Error::Invalid {
+                var: name,
+                value,
+                expected,
+            })
+        })
+        .transpose()
+}
+
+/// Parses an optional variable that can't be zero, like a pool size.
+fn parse_positive<T: FromStr + Default + PartialEq>(
+    var: impl Fn(&str) -> Option<String>,
+    name: &'static str,
+    expected: &'static str,
+) -> Result<Option<T>> {
+    match parse(&var, name, expected)? {
+        Some(value) if value == T::default() => Err(Error::Invalid {
+            var: name,
+            value: var(name).unwrap_or_default(),
+            expected,
+        }),
+        value => Ok(value),
+    }
+}
+
+/// Parses a comma-separated list of guild ids, e.g. `123,456`.
+fn parse_guild_ids(name: &'static str, ids: &str) -> Result<Vec<GuildId>> {
+    let invalid = || Error::Invalid {
+        var: name,
+        value: ids.to_string(),
+        expected: "a comma-separated list of guild ids",
+    };
+
+    let ids = ids
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| match id.parse::<u64>() {
+            Ok(id) if id != 0 => Ok(GuildId::new(id)),
+            _ => Err(invalid()),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if ids.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// The variables the bot can't start without.
+    const REQUIRED: &[(&str, &str)] = &[
+        ("DISCORD_TOKEN", "secret-token"),
+        ("DATABASE_PATH", "bot.sqlite"),
+        ("GUILD_ID", "1"),
+    ];
+
+    /// Reads a configuration from just `vars`, rather than the process's environment, which is
+    /// shared between tests. Later variables replace earlier ones of the same name.
+    fn config(vars: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let vars = vars
+            .iter()
+            .map(|(var, value)| (var.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+        Config::from_vars(|var| vars.get(var).cloned())
+    }
+
+    fn with(vars: &[(&'static str, &'static str)]) -> Vec<(&'static str, &'static str)> {
+        [REQUIRED, vars].concat()
+    }
+
+    fn errors(vars: &[(&str, &str)]) -> Vec<Error> {
+        match config(vars) {
+            Ok(_) => panic!("expected the configuration to be invalid"),
+            Err(ConfigError(errors)) => errors,
+        }
+    }
+
+    fn registration(vars: &[(&str, &str)]) -> Registration {
+        config(vars).unwrap().registration
+    }
+
+    fn guilds(ids: &[u64]) -> Registration {
+        Registration::Guilds(ids.iter().copied().map(GuildId::new).collect())
+    }
+
+    #[test]
+    fn registering_in_one_guild() {
+        assert_eq!(registration(REQUIRED), guilds(&[1]));
+    }
+
+    #[test]
+    fn registering_in_several_guilds() {
+        // GUILD_IDS replaces GUILD_ID.
+        assert_eq!(
+            registration(&with(&[("GUILD_IDS", "2, 3,")])),
+            guilds(&[2, 3])
+        );
+    }
+
+    #[test]
+    fn registering_globally() {
+        let vars = [
+            ("DISCORD_TOKEN", "secret-token"),
+            ("DATABASE_PATH", "bot.sqlite"),
+            ("REGISTER_GLOBAL", "true"),
+        ];
+
+        assert_eq!(registration(&vars), Registration::Global);
+    }
+
+    #[test]
+    fn registering_nowhere() {
+        let vars = [
+            ("DISCORD_TOKEN", "secret-token"),
+            ("DATABASE_PATH", "bot.sqlite"),
+            ("REGISTER_GLOBAL", "false"),
+        ];
+
+        assert!(matches!(
+            errors(&vars)[..],
+            [Error::Missing("GUILD_IDS (or REGISTER_GLOBAL=true)")]
+        ));
+    }
+
+    #[test]
+    fn registering_globally_and_in_guilds() {
+        assert!(matches!(
+            errors(&with(&[("REGISTER_GLOBAL", "true")]))[..],
+            [Error::ConflictingRegistration]
+        ));
+    }
+
+    #[test]
+    fn malformed_guild_ids() {
+        for ids in ["", " , ", "2,x", "0", "-2", "2;3"] {
+            assert!(
+                matches!(
+                    errors(&with(&[("GUILD_IDS", ids)]))[..],
+                    [Error::Invalid {
+                        var: "GUILD_IDS",
+                        ..
+                    }]
+                ),
+                "{} was accepted",
+                ids
+            );
+        }
+        assert!(matches!(
+            errors(&with(&[("REGISTER_GLOBAL", "yes")]))[..],
+            [Error::Invalid {
+                var: "REGISTER_GLOBAL",
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn defaults() {
+        let config = config(REQUIRED).unwrap();
+
+        assert_eq!(config.token, "secret-token");
+        assert_eq!(config.db_path, "bot.sqlite");
+        assert!(!config.create_db_dir);
+        assert_eq!(config.log_level, None);
+        assert_eq!(config.pool_max_size, 10);
+        assert_eq!(config.pool_timeout, Duration::from_secs(30));
+        assert!(config.backups.is_none());
+        assert_eq!(config.backup_interval, None);
+        assert_eq!(config.metrics_addr, None);
+        assert_eq!(config.owner_id, None);
+        assert_eq!(config.exp_repeat_window, Duration::from_secs(10));
+        assert!(!config.prefix_commands);
+    }
+
+    #[test]
+    fn missing_token() {
+        assert!(matches!(
+            errors(&[("DATABASE_PATH", "bot.sqlite"), ("GUILD_ID", "1")])[..],
+            [Error::Missing("DISCORD_TOKEN")]
+        ));
+    }
+
+    #[test]
+    fn bad_guild_id() {
+        assert!(matches!(
+            &errors(&with(&[("GUILD_ID", "my-server")]))[..],
+            [Error::Invalid {
+                var: "GUILD_ID",
+                value,
+                ..
+            }] if value == "my-server"
+        ));
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let errors = errors(&[
+            ("GUILD_ID", "my-server"),
+            ("DB_POOL_MAX", "0"),
+            ("DB_POOL_TIMEOUT", "soon"),
+        ]);
+
+        assert!(matches!(
+            errors[..],
+            [
+                Error::Missing("DISCORD_TOKEN"),
+                Error::Missing("DATABASE_PATH"),
+                Error::Invalid {
+                    var: "GUILD_ID",
+                    ..
+                },
+                Error::Invalid {
+                    var: "DB_POOL_MAX",
+                    ..
+                },
+                Error::Invalid {
+                    var: "DB_POOL_TIMEOUT",
+                    ..
+                },
+            ]
+        ));
+        assert_eq!(
+            ConfigError(errors).to_string(),
+            "- Expected DISCORD_TOKEN in the environment\n\
+            - Expected DATABASE_PATH in the environment\n\
+            - GUILD_ID must be a comma-separated list of guild ids, but was `my-server`\n\
+            - DB_POOL_MAX must be a positive number, but was `0`\n\
+            - DB_POOL_TIMEOUT must be a positive number of seconds, but was `soon`"
+        );
+    }
+
+    #[test]
+    fn pool_size() {
+        let config = config(&with(&[("DB_POOL_MAX", " 4 "), ("DB_POOL_TIMEOUT", "5")])).unwrap();
+        assert_eq!(config.pool_max_size, 4);
+        assert_eq!(config.pool_timeout, Duration::from_secs(5));
+
+        for size in ["0", "-1", "four"] {
+            assert!(matches!(
+                errors(&with(&[("DB_POOL_MAX", size)]))[..],
+                [Error::Invalid {
+                    var: "DB_POOL_MAX",
+                    ..
+                }]
+            ));
+        }
+    }
+
+    #[test]
+    fn backups() {
+        let daily = config(&with(&[
+            ("BACKUP_DIR", "backups"),
+            ("BACKUP_DAILY", "true"),
+        ]))
+        .unwrap();
+        assert_eq!(daily.backups.unwrap().retain, 7);
+        assert_eq!(
+            daily.backup_interval,
+            Some(Duration::from_secs(24 * 60 * 60))
+        );
+
+        // The interval takes precedence over BACKUP_DAILY.
+        let every_six_hours = config(&with(&[
+            ("BACKUP_DIR", "backups"),
+            ("BACKUP_DAILY", "true"),
+            ("BACKUP_INTERVAL", "6"),
+            ("BACKUP_RETAIN", "3"),
+        ]))
+        .unwrap();
+        assert_eq!(every_six_hours.backups.unwrap().retain, 3);
+        assert_eq!(
+            every_six_hours.backup_interval,
+            Some(Duration::from_secs(6 * 60 * 60))
+        );
+
+        assert!(matches!(
+            errors(&with(&[("BACKUP_INTERVAL", "6")]))[..],
+            [Error::Missing(
+                "BACKUP_DIR (for BACKUP_INTERVAL or BACKUP_DAILY)"
+            )]
+        ));
+    }
+
+    #[test]
+    fn summary_redacts_the_token() {
+        let summary = config(REQUIRED).unwrap().to_string();
+
+        assert!(summary.contains("DISCORD_TOKEN: <redacted>"));
+        assert!(!summary.contains("secret-token"));
+        assert!(summary.contains("Commands: registered in guilds 1"));
+    }
+}
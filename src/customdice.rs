@@ -0,0 +1,73 @@
+use std::fmt::Display;
+
+use rand::{seq::SliceRandom, Rng};
+
+/// The most times a custom die can be rolled at once.
+pub(crate) const MAX_ROLLS: u32 = 100;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Error {
+    EmptyName,
+    NoFaces,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::EmptyName => write!(f, "A custom die needs a name"),
+            Error::NoFaces => write!(f, "A custom die needs at least one face, e.g. Fire, Water"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Normalizes a custom die's name, so names are matched regardless of case.
+pub(crate) fn normalize_name(name: &str) -> Result<String, Error> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() {
+        return Err(Error::EmptyName);
+    }
+
+    Ok(name)
+}
+
+/// Parses comma-separated faces, ignoring blank ones.
+pub(crate) fn parse_faces(faces: &str) -> Result<Vec<String>, Error> {
+    let faces = faces
+        .split(',')
+        .map(str::trim)
+        .filter(|face| !face.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if faces.is_empty() {
+        return Err(Error::NoFaces);
+    }
+
+    Ok(faces)
+}
+
+/// Rolls a custom die `count` times, each roll picking a face at random.
+pub(crate) fn roll<'a, R: Rng + ?Sized>(
+    faces: &'a [String],
+    count: u32,
+    rng: &mut R,
+) -> Vec<&'a str> {
+    (0..count)
+        .filter_map(|_| faces.choose(rng))
+        .map(String::as_str)
+        .collect()
+}
+
+/// Shuffles a custom die's faces into a draw pile.
+pub(crate) fn shuffle<R: Rng + ?Sized>(faces: &[String], rng: &mut R) -> Vec<String> {
+    let mut cards = faces.to_vec();
+    cards.shuffle(rng);
+    cards
+}
+
+/// Draws up to `count` cards from the end of a pile, without replacement.
+pub(crate) fn draw(cards: &mut Vec<String>, count: usize) -> Vec<String> {
+    let remaining = cards.len().saturating_sub(count);
+    cards.split_off(remaining).into_iter().rev().collect()
+}
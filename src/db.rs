@@ -1,13 +1,33 @@
-use std::fmt::Display;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs::{self, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use chrono::{DateTime, Local};
-use rusqlite::{named_params, Connection};
+use chrono::{DateTime, Local, Utc};
+use rusqlite::{named_params, types::Type, Connection, OptionalExtension, Row};
 
 #[derive(Debug)]
 pub(crate) enum Error {
     MissingVotes,
+    /// The player hasn't been registered.
+    UnknownPlayer(i64),
+    /// The player has already been registered.
+    PlayerExists(i64),
+    /// Spending more downtime than the player has, with how many days they have.
+    InsufficientDowntime(i64),
     Sqlite(rusqlite::Error),
     Chrono(chrono::ParseError),
+    Json(serde_json::Error),
+    /// The database has a newer schema than this version of the bot supports.
+    UnknownSchemaVersion(i64),
+    /// The directory the database goes in doesn't exist.
+    MissingDir(PathBuf),
+    /// The directory the database goes in couldn't be created or written to.
+    UnwritableDir(PathBuf, io::Error),
 }
 
 impl From<rusqlite::Error> for Error {
@@ -22,9 +42,43 @@ impl From<chrono::ParseError> for Error {
     }
 }
 
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Error: {:?}", self)
+        match self {
+            Error::UnknownSchemaVersion(version) => write!(
+                f,
+                "Error: database schema version {} is newer than the supported version {}",
+                version,
+                MIGRATIONS.len()
+            ),
+            Error::UnknownPlayer(id) => write!(f, "Error: player {} is not registered", id),
+            Error::PlayerExists(id) => write!(f, "Error: player {} is already registered", id),
+            Error::InsufficientDowntime(left) => {
+                write!(f, "Error: only {} days of downtime are left", left)
+            }
+            Error::Json(e) => write!(f, "Error: invalid JSON in the database: {}", e),
+            Error::MissingDir(dir) => write!(
+                f,
+                "Error: the database directory {} doesn't exist. Create it, or set \
+                CREATE_DB_DIR=true to have it created.",
+                dir.display()
+            ),
+            Error::UnwritableDir(dir, e) => write!(
+                f,
+                "Error: the database directory {} isn't writable: {}",
+                dir.display(),
+                e
+            ),
+            Error::MissingVotes => write!(f, "Error: not everyone has voted for MVP"),
+            Error::Sqlite(e) => write!(f, "Error: {}", e),
+            Error::Chrono(e) => write!(f, "Error: invalid datetime in the database: {}", e),
+        }
     }
 }
 
@@ -32,20 +86,79 @@ impl std::error::Error for Error {}
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
-// Get the xp of a single player.
-pub(crate) fn get_xp(conn: &Connection, player_id: i64) -> Result<i64> {
-    let xp = conn.query_row(
-        "SELECT experience FROM players WHERE players.id = :id",
-        named_params! { ":id": player_id },
-        |row| row.get(0),
-    )?;
+/// A registered player.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Player {
+    pub id: i64,
+    /// The guild the player registered in. `None` for players registered before guilds were
+    /// tracked.
+    pub guild_id: Option<i64>,
+    pub experience: i64,
+    /// `None` for players registered before registration times were tracked.
+    pub registered_at: Option<DateTime<Utc>>,
+    /// `None` until the player sets up their character with `/character set`.
+    pub character: Option<Character>,
+}
+
+/// The character a player is playing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Character {
+    pub name: String,
+    pub class: Option<String>,
+    /// A link to their character sheet.
+    pub sheet_url: Option<String>,
+}
+
+impl Player {
+    const COLUMNS: &'static str = "id, guild_id, experience, registered_at, character_name, \
+        character_class, sheet_url";
+
+    /// Maps a row selected with [`Player::COLUMNS`].
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let registered_at = row
+            .get::<_, Option<String>>(3)?
+            .map(|on| {
+                DateTime::parse_from_rfc3339(&on)
+                    .map(|on| on.with_timezone(&Utc))
+                    .map_err(|e| {
+                        rusqlite::Error::FromSqlConversionFailure(3, Type::Text, Box::new(e))
+                    })
+            })
+            .transpose()?;
+        let character = row.get::<_, Option<String>>(4)?.map(|name| {
+            Ok::<_, rusqlite::Error>(Character {
+                name,
+                class: row.get(5)?,
+                sheet_url: row.get(6)?,
+            })
+        });
+
+        Ok(Self {
+            id: row.get(0)?,
+            guild_id: row.get(1)?,
+            experience: row.get(2)?,
+            registered_at,
+            character: character.transpose()?,
+        })
+    }
+}
+
+// Get a single player.
+pub(crate) fn get_player(conn: &Connection, player_id: i64) -> Result<Player> {
+    let query = format!(
+        "SELECT {} FROM players WHERE players.id = :id",
+        Player::COLUMNS
+    );
+    let player = conn
+        .query_row(&query, named_params! { ":id": player_id }, Player::from_row)
+        .optional()?;
 
-    Ok(xp)
+    player.ok_or(Error::UnknownPlayer(player_id))
 }
 
 pub(crate) fn set_xp(conn: &Connection, player_id: i64, xp: i64) -> Result<()> {
     let query = "UPDATE players SET experience = :xp WHERE players.id = :id";
-    conn.execute(
+    let updated = conn.execute(
         query,
         named_params! {
             ":id": player_id,
@@ -53,10 +166,26 @@ pub(crate) fn set_xp(conn: &Connection, player_id: i64, xp: i64) -> Result<()> {
         },
     )?;
 
+    if updated == 0 {
+        return Err(Error::UnknownPlayer(player_id));
+    }
+
     Ok(())
 }
 
+/// Records a player's MVP vote. Both the voter and who they vote for must be registered.
 pub(crate) fn vote_for_mvp(conn: &Connection, player_id: i64, mvp_id: i64) -> Result<()> {
+    for id in [player_id, mvp_id] {
+        let registered: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM players WHERE id = :id)",
+            named_params! { ":id": id },
+            |row| row.get(0),
+        )?;
+        if !registered {
+            return Err(Error::UnknownPlayer(id));
+        }
+    }
+
     // Perform an upsert, which allows players to update their votes.
     let query = "INSERT INTO mvp (playerid, mvpid) VALUES (:playerid, :mvpid)
     ON CONFLICT(playerid) DO UPDATE SET mvpid = :mvpid";
@@ -71,46 +200,211 @@ pub(crate) fn vote_for_mvp(conn: &Connection, player_id: i64, mvp_id: i64) -> Re
     Ok(())
 }
 
-pub(crate) fn resolve_mvp(conn: &mut Connection) -> Result<i64> {
+/// Sets up a player's character, replacing any they had before.
+pub(crate) fn set_character(
+    conn: &Connection,
+    player_id: i64,
+    character: &Character,
+) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE players
+        SET character_name = :name, character_class = :class, sheet_url = :sheet_url
+        WHERE id = :id",
+        named_params! {
+            ":id": player_id,
+            ":name": character.name,
+            ":class": character.class,
+            ":sheet_url": character.sheet_url,
+        },
+    )?;
+
+    if updated == 0 {
+        return Err(Error::UnknownPlayer(player_id));
+    }
+
+    Ok(())
+}
+
+/// The outcome of an MVP vote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MvpResult {
+    /// Everyone tied for the most votes.
+    pub winners: Vec<i64>,
+    /// Each candidate and their vote count, most votes first.
+    pub tally: Vec<(i64, i64)>,
+    /// The character names of the candidates who have set one, by player id.
+    pub characters: HashMap<i64, String>,
+}
+
+impl MvpResult {
+    fn from_tally(tally: Vec<(i64, i64)>) -> Option<Self> {
+        let (_, most) = *tally.first()?;
+        let winners = tally
+            .iter()
+            .take_while(|(_, votes)| *votes == most)
+            .map(|(id, _)| *id)
+            .collect();
+
+        Some(Self {
+            winners,
+            tally,
+            characters: HashMap::new(),
+        })
+    }
+}
+
+/// Tallies a guild's MVP votes and clears them.
+///
+/// Ties for the most votes aren't broken: everyone tied is a co-MVP. The tally is ordered by votes
+/// and then by player id, so the same votes always resolve the same way. Players registered before
+/// guilds were tracked vote in every guild, as they do for sessions.
+pub(crate) fn resolve_mvp(conn: &mut Connection, guild_id: i64) -> Result<MvpResult> {
     let tx = conn.transaction()?;
 
-    let query =
-        "SELECT (SELECT COUNT(*) FROM mvp)=(SELECT COUNT(*) FROM players) as RowCountResult";
-    let has_everyone_voted: bool = tx.query_row(query, [], |row| row.get(0))?;
+    let query = "SELECT
+        (SELECT COUNT(*) FROM mvp WHERE playerid IN
+            (SELECT id FROM players WHERE guild_id = :guild_id OR guild_id IS NULL))
+        = (SELECT COUNT(*) FROM players WHERE guild_id = :guild_id OR guild_id IS NULL)
+        as RowCountResult";
+    let has_everyone_voted: bool =
+        tx.query_row(query, named_params! { ":guild_id": guild_id }, |row| {
+            row.get(0)
+        })?;
     if !has_everyone_voted {
         tx.rollback()?;
 
         return Err(Error::MissingVotes);
     }
 
-    let query = "SELECT mvpid, COUNT(*) FROM mvp GROUP BY mvpid ORDER BY COUNT(*) DESC LIMIT 1";
-    let mvp = tx.query_row(query, [], |row| row.get(0))?;
+    let tally = tx
+        .prepare(
+            "SELECT mvpid, COUNT(*) FROM mvp
+            WHERE playerid IN
+                (SELECT id FROM players WHERE guild_id = :guild_id OR guild_id IS NULL)
+            GROUP BY mvpid ORDER BY COUNT(*) DESC, mvpid",
+        )?
+        .query_map(named_params! { ":guild_id": guild_id }, |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    // With no players registered, everyone has trivially voted but there's no one to pick.
+    let Some(mut result) = MvpResult::from_tally(tally) else {
+        tx.rollback()?;
+
+        return Err(Error::MissingVotes);
+    };
+    result.characters = tx
+        .prepare(
+            "SELECT id, character_name FROM players
+            WHERE character_name IS NOT NULL AND id IN
+                (SELECT mvpid FROM mvp WHERE playerid IN
+                    (SELECT id FROM players WHERE guild_id = :guild_id OR guild_id IS NULL))",
+        )?
+        .query_map(named_params! { ":guild_id": guild_id }, |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect::<Result<_, _>>()?;
 
-    tx.execute("DELETE FROM mvp", [])?;
+    tx.execute(
+        "DELETE FROM mvp WHERE playerid IN
+            (SELECT id FROM players WHERE guild_id = :guild_id OR guild_id IS NULL)",
+        named_params! { ":guild_id": guild_id },
+    )?;
 
     tx.commit()?;
 
-    Ok(mvp)
+    Ok(result)
 }
 
-pub(crate) fn get_all_xp(conn: &Connection) -> Result<Vec<(i64, i64)>> {
-    let mut stmt = conn.prepare("SELECT id, experience FROM players")?;
+/// Discards a guild's MVP votes without resolving them, returning how many were cleared.
+pub(crate) fn clear_votes(conn: &Connection, guild_id: i64) -> Result<usize> {
+    Ok(conn.execute(
+        "DELETE FROM mvp WHERE playerid IN
+            (SELECT id FROM players WHERE guild_id = :guild_id OR guild_id IS NULL)",
+        named_params! { ":guild_id": guild_id },
+    )?)
+}
 
-    let all_xp = stmt
-        .query_map((), |row| {
-            let id = row.get(0)?;
-            let xp = row.get(1)?;
-            Ok((id, xp))
-        })
-        .map(|iter| iter.filter_map(|x| x.ok()).collect::<Vec<_>>())?;
+pub(crate) fn get_players(conn: &Connection) -> Result<Vec<Player>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM players", Player::COLUMNS))?;
 
-    Ok(all_xp)
+    let players = stmt
+        .query_map((), Player::from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(players)
 }
 
-pub(crate) fn create_player(conn: &Connection, player_id: i64) -> Result<()> {
-    let mut stmt = conn.prepare("INSERT INTO players (id) VALUES (:id)")?;
-    stmt.execute(named_params! { ":id": player_id })?;
-    Ok(())
+/// How registering several players at once went.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Registered {
+    /// How many were newly registered.
+    pub created: usize,
+    /// How many were already registered in the guild, or before guilds were tracked.
+    pub existing: usize,
+    /// Who is registered in another guild, and so wasn't registered in this one.
+    pub elsewhere: Vec<i64>,
+}
+
+/// Registers several players in a guild at once, skipping any that are already registered here or
+/// in another guild.
+pub(crate) fn create_players(
+    conn: &mut Connection,
+    player_ids: &[i64],
+    guild_id: i64,
+) -> Result<Registered> {
+    let tx = conn.transaction()?;
+
+    let mut registered = Registered::default();
+    {
+        let mut find = tx.prepare("SELECT guild_id FROM players WHERE id = :id")?;
+        let mut insert = tx.prepare(
+            "INSERT INTO players (id, guild_id, registered_at)
+            VALUES (:id, :guild_id, :registered_at)",
+        )?;
+        let registered_at = Utc::now().to_rfc3339();
+        for player_id in player_ids {
+            let found: Option<Option<i64>> = find
+                .query_row(named_params! { ":id": player_id }, |row| row.get(0))
+                .optional()?;
+            match found {
+                None => {
+                    insert.execute(named_params! {
+                        ":id": player_id,
+                        ":guild_id": guild_id,
+                        ":registered_at": registered_at
+                    })?;
+                    registered.created += 1;
+                }
+                Some(Some(other)) if other != guild_id => registered.elsewhere.push(*player_id),
+                Some(_) => registered.existing += 1,
+            }
+        }
+    }
+
+    tx.commit()?;
+
+    Ok(registered)
+}
+
+pub(crate) fn create_player(conn: &Connection, player_id: i64, guild_id: i64) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "INSERT INTO players (id, guild_id, registered_at) VALUES (:id, :guild_id, :registered_at)",
+    )?;
+    let created = stmt.execute(named_params! {
+        ":id": player_id,
+        ":guild_id": guild_id,
+        ":registered_at": Utc::now().to_rfc3339()
+    });
+
+    match created {
+        Err(rusqlite::Error::SqliteFailure(e, _))
+            if e.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_PRIMARYKEY =>
+        {
+            Err(Error::PlayerExists(player_id))
+        }
+        result => result.map(|_| ()).map_err(Error::from),
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -118,50 +412,123 @@ pub struct ScheduledMessage {
     pub channel_id: u64,
     pub msg: String,
     pub on: DateTime<Local>,
+    /// When either `title` or `image_url` is set, the message is sent as an embed.
+    pub title: Option<String>,
+    pub image_url: Option<String>,
+    /// Who scheduled it, told when it's sent. Messages scheduled before this was kept have none.
+    pub created_by: Option<u64>,
+    /// The guild it was scheduled in. Messages scheduled before this was kept have none.
+    pub guild_id: Option<u64>,
+    /// Whether `msg` is a template whose placeholders are filled in when it's sent.
+    pub templated: bool,
+}
+
+impl ScheduledMessage {
+    const COLUMNS: &'static str =
+        "channel_id, scheduled, msg, title, image_url, created_by, guild_id, templated";
+
+    /// Maps a row selected with [`ScheduledMessage::COLUMNS`].
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let on = DateTime::parse_from_rfc3339(&row.get::<_, String>(1)?).map_err(|e| {
+            log::error!("Error parsing datetime: {}", e);
+            rusqlite::Error::FromSqlConversionFailure(1, Type::Text, Box::new(e))
+        })?;
+
+        Ok(Self {
+            channel_id: row.get(0)?,
+            on: on.into(),
+            msg: row.get(2)?,
+            title: row.get(3)?,
+            image_url: row.get(4)?,
+            created_by: row.get(5)?,
+            guild_id: row.get(6)?,
+            templated: row.get(7)?,
+        })
+    }
 }
 
 pub(crate) fn create_schedule(conn: &Connection, sch: &ScheduledMessage) -> Result<()> {
     let mut stmt = conn.prepare(
-        "INSERT INTO schedule (id, channel_id, scheduled, msg) VALUES (1, :channel_id, :scheduled, :msg)
+        "INSERT INTO schedule
+        (id, channel_id, scheduled, msg, title, image_url, created_by, guild_id, templated)
+    VALUES
+        (1, :channel_id, :scheduled, :msg, :title, :image_url, :created_by, :guild_id, :templated)
     ON CONFLICT (id) DO UPDATE SET
         channel_id = excluded.channel_id,
         scheduled = excluded.scheduled,
-        msg = excluded.msg",
+        msg = excluded.msg,
+        title = excluded.title,
+        image_url = excluded.image_url,
+        created_by = excluded.created_by,
+        guild_id = excluded.guild_id,
+        templated = excluded.templated",
     )?;
     stmt.execute(named_params! {
         ":channel_id": sch.channel_id,
         ":scheduled": sch.on.to_rfc3339(),
-        ":msg": sch.msg
+        ":msg": sch.msg,
+        ":title": sch.title,
+        ":image_url": sch.image_url,
+        ":created_by": sch.created_by,
+        ":guild_id": sch.guild_id,
+        ":templated": sch.templated
     })?;
     Ok(())
 }
 
+/// Changes the scheduled message. False if nothing is scheduled, e.g. because it was sent while
+/// it was being edited.
+pub(crate) fn update_schedule(conn: &Connection, sch: &ScheduledMessage) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE schedule SET
+            channel_id = :channel_id,
+            scheduled = :scheduled,
+            msg = :msg,
+            title = :title,
+            image_url = :image_url,
+            templated = :templated
+        WHERE id = 1",
+        named_params! {
+            ":channel_id": sch.channel_id,
+            ":scheduled": sch.on.to_rfc3339(),
+            ":msg": sch.msg,
+            ":title": sch.title,
+            ":image_url": sch.image_url,
+            ":templated": sch.templated
+        },
+    )?;
+
+    Ok(updated > 0)
+}
+
 pub(crate) fn get_schedule(conn: &Connection) -> Result<Option<ScheduledMessage>> {
-    let query = "SELECT channel_id, scheduled, msg FROM schedule";
+    let query = format!("SELECT {} FROM schedule", ScheduledMessage::COLUMNS);
 
-    let query_results = conn.query_row(query, [], |row| {
-        let channel_id = row.get(0)?;
-        let on = row.get(1)?;
-        let msg = row.get(2)?;
-        Ok(Some((channel_id, on, msg)))
-    });
+    Ok(conn
+        .query_row(&query, [], ScheduledMessage::from_row)
+        .optional()?)
+}
 
-    let scheduled_message: Option<(u64, String, String)> = {
-        match query_results {
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            sch @ Ok(_) => sch,
-            e @ Err(_) => e,
-        }
-    }?;
+/// Gets the soonest scheduled message that's still to come.
+pub(crate) fn get_next_schedule(
+    conn: &Connection,
+    now: DateTime<Local>,
+) -> Result<Option<ScheduledMessage>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM schedule",
+        ScheduledMessage::COLUMNS
+    ))?;
 
-    match scheduled_message {
-        Some((channel_id, on, msg)) => Ok(Some(ScheduledMessage {
-            channel_id,
-            on: parse_schedule(on)?,
-            msg,
-        })),
-        None => Ok(None),
+    // Times are stored with their offsets, so they're compared once parsed rather than as text.
+    let mut next: Option<ScheduledMessage> = None;
+    for sch in stmt.query_map([], ScheduledMessage::from_row)? {
+        let sch = sch?;
+        if sch.on > now && next.as_ref().is_none_or(|next| sch.on < next.on) {
+            next = Some(sch);
+        }
     }
+
+    Ok(next)
 }
 
 pub(crate) fn delete_schedule(conn: &Connection) -> Result<()> {
@@ -170,42 +537,2565 @@ pub(crate) fn delete_schedule(conn: &Connection) -> Result<()> {
     Ok(())
 }
 
-fn parse_schedule(on: String) -> Result<DateTime<Local>> {
-    match DateTime::parse_from_rfc3339(&on) {
-        Ok(on) => Ok(on.into()),
-        Err(e) => {
-            log::error!("Error parsing datetime: {}", e);
-            Err(e.into())
+/// Gets a guild's setting, if it has been configured and is a valid `T`.
+pub(crate) fn get_setting<T: FromStr>(
+    conn: &Connection,
+    guild_id: i64,
+    key: &str,
+) -> Result<Option<T>> {
+    let value: Option<String> = conn
+        .query_row(
+            "SELECT value FROM guild_settings WHERE guild_id = :guild_id AND key = :key",
+            named_params! { ":guild_id": guild_id, ":key": key },
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(value.and_then(|value| match value.parse() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            log::warn!("Ignoring invalid value `{}` for setting {}", value, key);
+            None
         }
+    }))
+}
+
+/// Defines a guild's custom die, replacing any with the same name.
+pub(crate) fn define_custom_die(
+    conn: &Connection,
+    guild_id: i64,
+    name: &str,
+    faces: &[String],
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO custom_dice (guild_id, name, faces) VALUES (:guild_id, :name, :faces)
+        ON CONFLICT(guild_id, name) DO UPDATE SET faces = excluded.faces",
+        named_params! {
+            ":guild_id": guild_id,
+            ":name": name,
+            ":faces": serde_json::to_string(faces)?,
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Gets the faces of a guild's custom die, if it has been defined.
+pub(crate) fn get_custom_die(
+    conn: &Connection,
+    guild_id: i64,
+    name: &str,
+) -> Result<Option<Vec<String>>> {
+    let faces: Option<String> = conn
+        .query_row(
+            "SELECT faces FROM custom_dice WHERE guild_id = :guild_id AND name = :name",
+            named_params! { ":guild_id": guild_id, ":name": name },
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(faces
+        .map(|faces| serde_json::from_str(&faces))
+        .transpose()?)
+}
+
+/// Gets the names of a guild's custom dice, in alphabetical order.
+pub(crate) fn get_custom_dice_names(conn: &Connection, guild_id: i64) -> Result<Vec<String>> {
+    let mut stmt =
+        conn.prepare("SELECT name FROM custom_dice WHERE guild_id = :guild_id ORDER BY name")?;
+
+    let names = stmt
+        .query_map(named_params! { ":guild_id": guild_id }, |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(names)
+}
+
+/// An entry on a random table, drawn in proportion to its weight.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TableEntry {
+    pub weight: u32,
+    pub text: String,
+}
+
+/// Creates an empty random table for a guild. False if it already has one with that name.
+pub(crate) fn create_table(conn: &Connection, guild_id: i64, name: &str) -> Result<bool> {
+    let created = conn.execute(
+        "INSERT INTO random_tables (guild_id, name) VALUES (:guild_id, :name)
+        ON CONFLICT(guild_id, name) DO NOTHING",
+        named_params! { ":guild_id": guild_id, ":name": name },
+    )?;
+
+    Ok(created > 0)
+}
+
+/// Adds entries to a guild's random table. False if there's no such table.
+pub(crate) fn add_table_entries(
+    conn: &Connection,
+    guild_id: i64,
+    name: &str,
+    entries: &[TableEntry],
+) -> Result<bool> {
+    if !table_exists(conn, guild_id, name)? {
+        return Ok(false);
+    }
+
+    let mut stmt = conn.prepare(
+        "INSERT INTO random_table_entries (guild_id, table_name, weight, text)
+        VALUES (:guild_id, :table_name, :weight, :text)",
+    )?;
+    for entry in entries {
+        stmt.execute(named_params! {
+            ":guild_id": guild_id,
+            ":table_name": name,
+            ":weight": entry.weight,
+            ":text": entry.text,
+        })?;
     }
+
+    Ok(true)
 }
 
-// TODO: Move this to a migration.
-pub(crate) fn setup(conn: &Connection) -> Result<()> {
-    conn.execute_batch(
-        "BEGIN;
-    CREATE TABLE IF NOT EXISTS players (
-        id INTEGER PRIMARY KEY,
-        experience INTEGER NOT NULL DEFAULT 0
-    );
+fn table_exists(conn: &Connection, guild_id: i64, name: &str) -> Result<bool> {
+    let exists = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM random_tables WHERE guild_id = :guild_id AND name = :name)",
+        named_params! { ":guild_id": guild_id, ":name": name },
+        |row| row.get(0),
+    )?;
 
-    CREATE TABLE IF NOT EXISTS mvp (
-        id INTEGER PRIMARY KEY,
-        playerid INTEGER NOT NULL UNIQUE,
-        mvpid INTEGER NOT NULL,
-        FOREIGN KEY(playerid) REFERENCES players(id),
-        FOREIGN KEY(mvpid) REFERENCES players(id)
-    );
+    Ok(exists)
+}
 
-    CREATE TABLE IF NOT EXISTS schedule (
-        id INTEGER PRIMARY KEY,
-        channel_id INTEGER NOT NULL,
-        scheduled TEXT NOT NULL,
-        msg TEXT NOT NULL
-    );
+/// Gets the entries on a guild's random table in the order they were added, if it has been
+/// created.
+pub(crate) fn get_table_entries(
+    conn: &Connection,
+    guild_id: i64,
+    name: &str,
+) -> Result<Option<Vec<TableEntry>>> {
+    if !table_exists(conn, guild_id, name)? {
+        return Ok(None);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT weight, text FROM random_table_entries
+        WHERE guild_id = :guild_id AND table_name = :table_name ORDER BY id",
+    )?;
+    let entries = stmt
+        .query_map(
+            named_params! { ":guild_id": guild_id, ":table_name": name },
+            |row| {
+                Ok(TableEntry {
+                    weight: row.get(0)?,
+                    text: row.get(1)?,
+                })
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Some(entries))
+}
+
+/// Gets the names of a guild's random tables and how many entries each has, in alphabetical
+/// order.
+pub(crate) fn get_tables(conn: &Connection, guild_id: i64) -> Result<Vec<(String, i64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT name, (
+            SELECT COUNT(*) FROM random_table_entries
+            WHERE guild_id = random_tables.guild_id AND table_name = random_tables.name
+        )
+        FROM random_tables WHERE guild_id = :guild_id ORDER BY name",
+    )?;
+
+    let tables = stmt
+        .query_map(named_params! { ":guild_id": guild_id }, |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(tables)
+}
+
+/// Deletes a guild's random table and its entries. False if there was no such table.
+pub(crate) fn delete_table(conn: &Connection, guild_id: i64, name: &str) -> Result<bool> {
+    let deleted = conn.execute(
+        "DELETE FROM random_tables WHERE guild_id = :guild_id AND name = :name",
+        named_params! { ":guild_id": guild_id, ":name": name },
+    )?;
+
+    Ok(deleted > 0)
+}
+
+/// Saves a guild's message template, replacing any with the same name. True if it replaced one.
+pub(crate) fn save_template(
+    conn: &Connection,
+    guild_id: i64,
+    name: &str,
+    text: &str,
+) -> Result<bool> {
+    let replaced = get_template(conn, guild_id, name)?.is_some();
+    conn.execute(
+        "INSERT INTO templates (guild_id, name, text) VALUES (:guild_id, :name, :text)
+        ON CONFLICT(guild_id, name) DO UPDATE SET text = excluded.text",
+        named_params! { ":guild_id": guild_id, ":name": name, ":text": text },
+    )?;
+
+    Ok(replaced)
+}
+
+/// Gets the text of a guild's message template, if there's one by that name.
+pub(crate) fn get_template(conn: &Connection, guild_id: i64, name: &str) -> Result<Option<String>> {
+    Ok(conn
+        .query_row(
+            "SELECT text FROM templates WHERE guild_id = :guild_id AND name = :name",
+            named_params! { ":guild_id": guild_id, ":name": name },
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+/// Gets a guild's message templates, as their names and text, by name.
+pub(crate) fn get_templates(conn: &Connection, guild_id: i64) -> Result<Vec<(String, String)>> {
+    let mut stmt =
+        conn.prepare("SELECT name, text FROM templates WHERE guild_id = :guild_id ORDER BY name")?;
+
+    let templates = stmt
+        .query_map(named_params! { ":guild_id": guild_id }, |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(templates)
+}
+
+/// Deletes a guild's message template. False if there was no such template.
+pub(crate) fn delete_template(conn: &Connection, guild_id: i64, name: &str) -> Result<bool> {
+    let deleted = conn.execute(
+        "DELETE FROM templates WHERE guild_id = :guild_id AND name = :name",
+        named_params! { ":guild_id": guild_id, ":name": name },
+    )?;
+
+    Ok(deleted > 0)
+}
+
+/// How many rolls are kept per user.
+const ROLL_LOG_LIMIT: i64 = 25;
+
+/// Records a user's roll, keeping only their most recent ones.
+pub(crate) fn log_roll(conn: &Connection, user_id: i64, expression: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO roll_log (user_id, expression, rolled_at)
+        VALUES (:user_id, :expression, :rolled_at)",
+        named_params! {
+            ":user_id": user_id,
+            ":expression": expression,
+            ":rolled_at": Utc::now().to_rfc3339(),
+        },
+    )?;
+    conn.execute(
+        "DELETE FROM roll_log WHERE user_id = :user_id AND id NOT IN (
+            SELECT id FROM roll_log WHERE user_id = :user_id ORDER BY id DESC LIMIT :limit
+        )",
+        named_params! { ":user_id": user_id, ":limit": ROLL_LOG_LIMIT },
+    )?;
+
+    Ok(())
+}
+
+/// Gets the expression a user rolled most recently.
+pub(crate) fn get_last_roll(conn: &Connection, user_id: i64) -> Result<Option<String>> {
+    let expression = conn
+        .query_row(
+            "SELECT expression FROM roll_log WHERE user_id = :user_id ORDER BY id DESC LIMIT 1",
+            named_params! { ":user_id": user_id },
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(expression)
+}
+
+/// Gets the distinct expressions a user has rolled, most recent first.
+pub(crate) fn get_recent_rolls(conn: &Connection, user_id: i64) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT expression FROM roll_log WHERE user_id = :user_id
+        GROUP BY expression ORDER BY MAX(id) DESC",
+    )?;
+
+    let expressions = stmt
+        .query_map(named_params! { ":user_id": user_id }, |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(expressions)
+}
+
+/// A channel's draw pile, shuffled from a custom die's faces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct DeckState {
+    pub name: String,
+    /// The cards left to draw, drawn from the end.
+    pub cards: Vec<String>,
+}
+
+pub(crate) fn get_deck_state(conn: &Connection, channel_id: i64) -> Result<Option<DeckState>> {
+    let state: Option<(String, String)> = conn
+        .query_row(
+            "SELECT name, cards FROM deck_state WHERE channel_id = :channel_id",
+            named_params! { ":channel_id": channel_id },
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+
+    match state {
+        Some((name, cards)) => Ok(Some(DeckState {
+            name,
+            cards: serde_json::from_str(&cards)?,
+        })),
+        None => Ok(None),
+    }
+}
+
+/// Sets a channel's draw pile, replacing any it had.
+pub(crate) fn set_deck_state(conn: &Connection, channel_id: i64, state: &DeckState) -> Result<()> {
+    conn.execute(
+        "INSERT INTO deck_state (channel_id, name, cards) VALUES (:channel_id, :name, :cards)
+        ON CONFLICT(channel_id) DO UPDATE SET name = excluded.name, cards = excluded.cards",
+        named_params! {
+            ":channel_id": channel_id,
+            ":name": state.name,
+            ":cards": serde_json::to_string(&state.cards)?,
+        },
+    )?;
+
+    Ok(())
+}
+
+/// A recap of a game session.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Recap {
+    pub session: i64,
+    pub author_id: i64,
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Recap {
+    const COLUMNS: &'static str = "session, author_id, text, created_at";
+
+    /// Maps a row selected with [`Recap::COLUMNS`].
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let created_at = DateTime::parse_from_rfc3339(&row.get::<_, String>(3)?)
+            .map(|on| on.with_timezone(&Utc))
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(3, Type::Text, Box::new(e)))?;
+
+        Ok(Self {
+            session: row.get(0)?,
+            author_id: row.get(1)?,
+            text: row.get(2)?,
+            created_at,
+        })
+    }
+}
+
+/// Adds a guild's recap for a session, by default the one after its latest recap.
+pub(crate) fn add_recap(
+    conn: &Connection,
+    guild_id: i64,
+    author_id: i64,
+    session: Option<i64>,
+    text: &str,
+) -> Result<Recap> {
+    let session = match session {
+        Some(session) => session,
+        None => conn.query_row(
+            "SELECT COALESCE(MAX(session), 0) + 1 FROM recaps WHERE guild_id = :guild_id",
+            named_params! { ":guild_id": guild_id },
+            |row| row.get(0),
+        )?,
+    };
+    let recap = Recap {
+        session,
+        author_id,
+        text: text.to_string(),
+        created_at: Utc::now(),
+    };
+
+    conn.execute(
+        "INSERT INTO recaps (guild_id, session, author_id, text, created_at)
+        VALUES (:guild_id, :session, :author_id, :text, :created_at)",
+        named_params! {
+            ":guild_id": guild_id,
+            ":session": recap.session,
+            ":author_id": recap.author_id,
+            ":text": recap.text,
+            ":created_at": recap.created_at.to_rfc3339(),
+        },
+    )?;
+
+    Ok(recap)
+}
+
+/// Gets a guild's most recently written recap.
+pub(crate) fn get_last_recap(conn: &Connection, guild_id: i64) -> Result<Option<Recap>> {
+    let recap = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM recaps WHERE guild_id = :guild_id ORDER BY id DESC LIMIT 1",
+                Recap::COLUMNS
+            ),
+            named_params! { ":guild_id": guild_id },
+            Recap::from_row,
+        )
+        .optional()?;
+
+    Ok(recap)
+}
+
+/// Gets a guild's recaps, newest first, optionally only those containing `term` in any case.
+pub(crate) fn get_recaps(
+    conn: &Connection,
+    guild_id: i64,
+    term: Option<&str>,
+) -> Result<Vec<Recap>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM recaps
+        WHERE guild_id = :guild_id AND (:pattern IS NULL OR text LIKE :pattern ESCAPE '\\')
+        ORDER BY id DESC",
+        Recap::COLUMNS
+    ))?;
+    let pattern = term.map(|term| format!("%{}%", escape_like(term)));
+
+    let recaps = stmt
+        .query_map(
+            named_params! { ":guild_id": guild_id, ":pattern": pattern },
+            Recap::from_row,
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(recaps)
+}
+
+/// Escapes a search term for a `LIKE ... ESCAPE '\'` pattern, so `%` and `_` match themselves.
+fn escape_like(term: &str) -> String {
+    term.chars().fold(String::new(), |mut escaped, c| {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        escaped
+    })
+}
+
+/// A question put to a vote with buttons.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Poll {
+    pub id: i64,
+    pub channel_id: i64,
+    /// The message with the poll's buttons, once it's been sent.
+    pub message_id: Option<i64>,
+    /// Who asked, and can close it.
+    pub creator_id: i64,
+    pub question: String,
+    pub options: Vec<String>,
+    /// When it closes by itself, if ever.
+    pub closes_at: Option<DateTime<Utc>>,
+    pub closed: bool,
+}
+
+impl Poll {
+    const COLUMNS: &'static str =
+        "id, channel_id, message_id, creator_id, question, options, closes_at, closed";
+
+    /// Maps a row selected with [`Poll::COLUMNS`].
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let options = serde_json::from_str(&row.get::<_, String>(5)?)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(5, Type::Text, Box::new(e)))?;
+        let closes_at = row
+            .get::<_, Option<String>>(6)?
+            .map(|on| {
+                DateTime::parse_from_rfc3339(&on)
+                    .map(|on| on.with_timezone(&Utc))
+                    .map_err(|e| {
+                        rusqlite::Error::FromSqlConversionFailure(6, Type::Text, Box::new(e))
+                    })
+            })
+            .transpose()?;
+
+        Ok(Self {
+            id: row.get(0)?,
+            channel_id: row.get(1)?,
+            message_id: row.get(2)?,
+            creator_id: row.get(3)?,
+            question: row.get(4)?,
+            options,
+            closes_at,
+            closed: row.get(7)?,
+        })
+    }
+}
+
+/// Creates an open poll without a message yet, returning it with its id.
+pub(crate) fn create_poll(
+    conn: &Connection,
+    channel_id: i64,
+    creator_id: i64,
+    question: &str,
+    options: &[String],
+    closes_at: Option<DateTime<Utc>>,
+) -> Result<Poll> {
+    conn.execute(
+        "INSERT INTO polls (channel_id, creator_id, question, options, closes_at)
+        VALUES (:channel_id, :creator_id, :question, :options, :closes_at)",
+        named_params! {
+            ":channel_id": channel_id,
+            ":creator_id": creator_id,
+            ":question": question,
+            ":options": serde_json::to_string(options)?,
+            ":closes_at": closes_at.map(|on| on.to_rfc3339()),
+        },
+    )?;
+
+    Ok(Poll {
+        id: conn.last_insert_rowid(),
+        channel_id,
+        message_id: None,
+        creator_id,
+        question: question.to_string(),
+        options: options.to_vec(),
+        closes_at,
+        closed: false,
+    })
+}
 
-    COMMIT;",
+/// Records the message a poll was sent in.
+pub(crate) fn set_poll_message(conn: &Connection, poll_id: i64, message_id: i64) -> Result<()> {
+    conn.execute(
+        "UPDATE polls SET message_id = :message_id WHERE id = :id",
+        named_params! { ":id": poll_id, ":message_id": message_id },
     )?;
 
     Ok(())
 }
+
+pub(crate) fn get_poll(conn: &Connection, poll_id: i64) -> Result<Option<Poll>> {
+    let poll = conn
+        .query_row(
+            &format!("SELECT {} FROM polls WHERE id = :id", Poll::COLUMNS),
+            named_params! { ":id": poll_id },
+            Poll::from_row,
+        )
+        .optional()?;
+
+    Ok(poll)
+}
+
+/// Gets the open polls that close by themselves, for arming them after a restart.
+pub(crate) fn get_timed_polls(conn: &Connection) -> Result<Vec<Poll>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM polls WHERE closed = 0 AND closes_at IS NOT NULL",
+        Poll::COLUMNS
+    ))?;
+    let polls = stmt
+        .query_map([], Poll::from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(polls)
+}
+
+/// Gets the most recent poll someone asked in a channel that's still open.
+pub(crate) fn get_latest_open_poll(
+    conn: &Connection,
+    channel_id: i64,
+    creator_id: i64,
+) -> Result<Option<Poll>> {
+    let poll = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM polls
+                WHERE channel_id = :channel_id AND creator_id = :creator_id AND closed = 0
+                ORDER BY id DESC LIMIT 1",
+                Poll::COLUMNS
+            ),
+            named_params! { ":channel_id": channel_id, ":creator_id": creator_id },
+            Poll::from_row,
+        )
+        .optional()?;
+
+    Ok(poll)
+}
+
+/// Records someone's vote on a poll, replacing any vote they'd already cast.
+pub(crate) fn vote(conn: &Connection, poll_id: i64, user_id: i64, option: usize) -> Result<()> {
+    conn.execute(
+        "INSERT INTO poll_votes (poll_id, user_id, option) VALUES (:poll_id, :user_id, :option)
+        ON CONFLICT(poll_id, user_id) DO UPDATE SET option = excluded.option",
+        named_params! { ":poll_id": poll_id, ":user_id": user_id, ":option": option },
+    )?;
+
+    Ok(())
+}
+
+/// Gets the option each voter picked on a poll.
+pub(crate) fn get_votes(conn: &Connection, poll_id: i64) -> Result<Vec<usize>> {
+    let mut stmt = conn.prepare("SELECT option FROM poll_votes WHERE poll_id = :poll_id")?;
+    let votes = stmt
+        .query_map(named_params! { ":poll_id": poll_id }, |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(votes)
+}
+
+/// Closes a poll to further votes. False if it was already closed.
+pub(crate) fn close_poll(conn: &Connection, poll_id: i64) -> Result<bool> {
+    let closed = conn.execute(
+        "UPDATE polls SET closed = 1 WHERE id = :id AND closed = 0",
+        named_params! { ":id": poll_id },
+    )?;
+
+    Ok(closed > 0)
+}
+
+/// A countdown running in a channel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Timer {
+    pub channel_id: i64,
+    /// The message showing the time left.
+    pub message_id: i64,
+    /// Who started it, and is pinged when it ends.
+    pub user_id: i64,
+    pub label: Option<String>,
+    pub ends_at: DateTime<Utc>,
+}
+
+impl Timer {
+    const COLUMNS: &'static str = "channel_id, message_id, user_id, label, ends_at";
+
+    /// Maps a row selected with [`Timer::COLUMNS`].
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let ends_at = DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
+            .map(|on| on.with_timezone(&Utc))
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(4, Type::Text, Box::new(e)))?;
+
+        Ok(Self {
+            channel_id: row.get(0)?,
+            message_id: row.get(1)?,
+            user_id: row.get(2)?,
+            label: row.get(3)?,
+            ends_at,
+        })
+    }
+}
+
+/// Records a channel's timer. False if the channel already has one.
+pub(crate) fn start_timer(conn: &Connection, timer: &Timer) -> Result<bool> {
+    let started = conn.execute(
+        "INSERT INTO timers (channel_id, message_id, user_id, label, ends_at)
+        VALUES (:channel_id, :message_id, :user_id, :label, :ends_at)
+        ON CONFLICT(channel_id) DO NOTHING",
+        named_params! {
+            ":channel_id": timer.channel_id,
+            ":message_id": timer.message_id,
+            ":user_id": timer.user_id,
+            ":label": timer.label,
+            ":ends_at": timer.ends_at.to_rfc3339(),
+        },
+    )?;
+
+    Ok(started > 0)
+}
+
+/// Gets every channel's timer, for resuming them after a restart.
+pub(crate) fn get_timers(conn: &Connection) -> Result<Vec<Timer>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM timers", Timer::COLUMNS))?;
+    let timers = stmt
+        .query_map([], Timer::from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(timers)
+}
+
+/// Removes a channel's timer. False if it didn't have one.
+pub(crate) fn delete_timer(conn: &Connection, channel_id: i64) -> Result<bool> {
+    let deleted = conn.execute(
+        "DELETE FROM timers WHERE channel_id = :channel_id",
+        named_params! { ":channel_id": channel_id },
+    )?;
+
+    Ok(deleted > 0)
+}
+
+/// A limited resource a player tracks, like spell slots.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Resource {
+    pub player_id: i64,
+    pub name: String,
+    /// How many are left.
+    pub current: i64,
+    pub max: i64,
+    /// Whether `/rest` restores it.
+    pub restore_on_rest: bool,
+}
+
+impl Resource {
+    const COLUMNS: &'static str = "player_id, name, current, max, restore_on_rest";
+
+    /// Maps a row selected with [`Resource::COLUMNS`].
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            player_id: row.get(0)?,
+            name: row.get(1)?,
+            current: row.get(2)?,
+            max: row.get(3)?,
+            restore_on_rest: row.get(4)?,
+        })
+    }
+}
+
+/// Creates a resource for a player in a guild, replacing any with the same name. It starts
+/// full.
+pub(crate) fn create_resource(conn: &Connection, guild_id: i64, resource: &Resource) -> Result<()> {
+    conn.execute(
+        "INSERT INTO resources (guild_id, player_id, name, current, max, restore_on_rest)
+        VALUES (:guild_id, :player_id, :name, :max, :max, :restore_on_rest)
+        ON CONFLICT (guild_id, player_id, name) DO UPDATE
+        SET current = excluded.current, max = excluded.max,
+            restore_on_rest = excluded.restore_on_rest",
+        named_params! {
+            ":guild_id": guild_id,
+            ":player_id": resource.player_id,
+            ":name": resource.name,
+            ":max": resource.max,
+            ":restore_on_rest": resource.restore_on_rest,
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Gets one of a player's resources, if they have it.
+pub(crate) fn get_resource(
+    conn: &Connection,
+    guild_id: i64,
+    player_id: i64,
+    name: &str,
+) -> Result<Option<Resource>> {
+    let query = format!(
+        "SELECT {} FROM resources
+        WHERE guild_id = :guild_id AND player_id = :player_id AND name = :name",
+        Resource::COLUMNS
+    );
+    let resource = conn
+        .query_row(
+            &query,
+            named_params! {
+                ":guild_id": guild_id,
+                ":player_id": player_id,
+                ":name": name,
+            },
+            Resource::from_row,
+        )
+        .optional()?;
+
+    Ok(resource)
+}
+
+/// Gets a guild's resources, of one player or everyone, sorted by player and then name.
+pub(crate) fn get_resources(
+    conn: &Connection,
+    guild_id: i64,
+    player_id: Option<i64>,
+) -> Result<Vec<Resource>> {
+    let query = format!(
+        "SELECT {} FROM resources
+        WHERE guild_id = :guild_id AND (:player_id IS NULL OR player_id = :player_id)
+        ORDER BY player_id, name",
+        Resource::COLUMNS
+    );
+    let mut stmt = conn.prepare(&query)?;
+
+    let resources = stmt
+        .query_map(
+            named_params! { ":guild_id": guild_id, ":player_id": player_id },
+            Resource::from_row,
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(resources)
+}
+
+/// Sets how many of a player's resource are left. False if they don't have it.
+pub(crate) fn set_resource_current(
+    conn: &Connection,
+    guild_id: i64,
+    player_id: i64,
+    name: &str,
+    current: i64,
+) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE resources SET current = :current
+        WHERE guild_id = :guild_id AND player_id = :player_id AND name = :name",
+        named_params! {
+            ":guild_id": guild_id,
+            ":player_id": player_id,
+            ":name": name,
+            ":current": current,
+        },
+    )?;
+
+    Ok(updated > 0)
+}
+
+/// Fully restores every resource in a guild that's restored on a rest, returning how many
+/// weren't full.
+pub(crate) fn rest(conn: &Connection, guild_id: i64) -> Result<usize> {
+    let restored = conn.execute(
+        "UPDATE resources SET current = max
+        WHERE guild_id = :guild_id AND restore_on_rest = 1 AND current < max",
+        named_params! { ":guild_id": guild_id },
+    )?;
+
+    Ok(restored)
+}
+
+/// A dying player's death saves so far.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct DeathSaves {
+    pub successes: i64,
+    pub failures: i64,
+}
+
+/// Gets a player's death saves, which are all 0 when they have none.
+pub(crate) fn get_death_saves(
+    conn: &Connection,
+    guild_id: i64,
+    player_id: i64,
+) -> Result<DeathSaves> {
+    let saves = conn
+        .query_row(
+            "SELECT successes, failures FROM death_saves
+            WHERE guild_id = :guild_id AND player_id = :player_id",
+            named_params! { ":guild_id": guild_id, ":player_id": player_id },
+            |row| {
+                Ok(DeathSaves {
+                    successes: row.get(0)?,
+                    failures: row.get(1)?,
+                })
+            },
+        )
+        .optional()?;
+
+    Ok(saves.unwrap_or_default())
+}
+
+/// Records a player's death saves. Saves that are all 0 are removed, like [`reset_death_saves`].
+pub(crate) fn set_death_saves(
+    conn: &Connection,
+    guild_id: i64,
+    player_id: i64,
+    saves: &DeathSaves,
+) -> Result<()> {
+    if *saves == DeathSaves::default() {
+        reset_death_saves(conn, guild_id, player_id)?;
+        return Ok(());
+    }
+
+    conn.execute(
+        "INSERT INTO death_saves (guild_id, player_id, successes, failures)
+        VALUES (:guild_id, :player_id, :successes, :failures)
+        ON CONFLICT (guild_id, player_id) DO UPDATE
+        SET successes = excluded.successes, failures = excluded.failures",
+        named_params! {
+            ":guild_id": guild_id,
+            ":player_id": player_id,
+            ":successes": saves.successes,
+            ":failures": saves.failures,
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Clears a player's death saves. False if they didn't have any.
+pub(crate) fn reset_death_saves(conn: &Connection, guild_id: i64, player_id: i64) -> Result<bool> {
+    let deleted = conn.execute(
+        "DELETE FROM death_saves WHERE guild_id = :guild_id AND player_id = :player_id",
+        named_params! { ":guild_id": guild_id, ":player_id": player_id },
+    )?;
+
+    Ok(deleted > 0)
+}
+
+/// An entry in a player's downtime log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct DowntimeEntry {
+    /// Days granted, or negative for days spent.
+    pub days: i64,
+    /// What spent days were spent on.
+    pub activity: Option<String>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+impl DowntimeEntry {
+    const COLUMNS: &'static str = "days, activity, recorded_at";
+
+    /// Maps a row selected with [`DowntimeEntry::COLUMNS`].
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let recorded_at = DateTime::parse_from_rfc3339(&row.get::<_, String>(2)?)
+            .map(|on| on.with_timezone(&Utc))
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(2, Type::Text, Box::new(e)))?;
+
+        Ok(Self {
+            days: row.get(0)?,
+            activity: row.get(1)?,
+            recorded_at,
+        })
+    }
+}
+
+/// Grants each player in a guild days of downtime.
+pub(crate) fn grant_downtime(
+    conn: &mut Connection,
+    guild_id: i64,
+    player_ids: &[i64],
+    days: i64,
+) -> Result<()> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO downtime (guild_id, player_id, days, recorded_at)
+            VALUES (:guild_id, :player_id, :days, :recorded_at)",
+        )?;
+        let recorded_at = Utc::now().to_rfc3339();
+        for player_id in player_ids {
+            stmt.execute(named_params! {
+                ":guild_id": guild_id,
+                ":player_id": player_id,
+                ":days": days,
+                ":recorded_at": recorded_at,
+            })?;
+        }
+    }
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Logs a player spending days of downtime on an activity, returning how many days they have
+/// left. Spending more than they have fails, in the same transaction as the spend so two spends
+/// at once can't overdraw.
+pub(crate) fn spend_downtime(
+    conn: &mut Connection,
+    guild_id: i64,
+    player_id: i64,
+    days: i64,
+    activity: &str,
+) -> Result<i64> {
+    let tx = conn.transaction()?;
+
+    let balance = get_downtime_balance(&tx, guild_id, player_id)?;
+    if days > balance {
+        return Err(Error::InsufficientDowntime(balance));
+    }
+    tx.execute(
+        "INSERT INTO downtime (guild_id, player_id, days, activity, recorded_at)
+        VALUES (:guild_id, :player_id, :days, :activity, :recorded_at)",
+        named_params! {
+            ":guild_id": guild_id,
+            ":player_id": player_id,
+            ":days": -days,
+            ":activity": activity,
+            ":recorded_at": Utc::now().to_rfc3339(),
+        },
+    )?;
+
+    tx.commit()?;
+
+    Ok(balance - days)
+}
+
+/// Gets how many days of downtime a player has left, 0 if they've never had any.
+pub(crate) fn get_downtime_balance(
+    conn: &Connection,
+    guild_id: i64,
+    player_id: i64,
+) -> Result<i64> {
+    let balance = conn.query_row(
+        "SELECT COALESCE(SUM(days), 0) FROM downtime
+        WHERE guild_id = :guild_id AND player_id = :player_id",
+        named_params! { ":guild_id": guild_id, ":player_id": player_id },
+        |row| row.get(0),
+    )?;
+
+    Ok(balance)
+}
+
+/// Gets the downtime balances of everyone in a guild who's had any, most first.
+pub(crate) fn get_downtime_balances(conn: &Connection, guild_id: i64) -> Result<Vec<(i64, i64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT player_id, SUM(days) AS balance FROM downtime
+        WHERE guild_id = :guild_id
+        GROUP BY player_id
+        ORDER BY balance DESC, player_id",
+    )?;
+
+    let balances = stmt
+        .query_map(named_params! { ":guild_id": guild_id }, |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(balances)
+}
+
+/// Gets a player's downtime log, newest first.
+pub(crate) fn get_downtime_log(
+    conn: &Connection,
+    guild_id: i64,
+    player_id: i64,
+) -> Result<Vec<DowntimeEntry>> {
+    let query = format!(
+        "SELECT {} FROM downtime
+        WHERE guild_id = :guild_id AND player_id = :player_id
+        ORDER BY id DESC",
+        DowntimeEntry::COLUMNS
+    );
+    let mut stmt = conn.prepare(&query)?;
+
+    let entries = stmt
+        .query_map(
+            named_params! { ":guild_id": guild_id, ":player_id": player_id },
+            DowntimeEntry::from_row,
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(entries)
+}
+
+/// A player's preferences, set with `/notify` and `/myroll`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct PlayerPrefs {
+    pub player_id: i64,
+    /// Whether they want to be DMed about what concerns them.
+    pub notify: bool,
+    /// The accent color of their rolls, as RGB, if they've picked one.
+    pub roll_color: Option<u32>,
+}
+
+/// Turns DM notifications on or off for a player.
+pub(crate) fn set_notify(conn: &Connection, player_id: i64, notify: bool) -> Result<()> {
+    conn.execute(
+        "INSERT INTO player_prefs (player_id, notify) VALUES (:player_id, :notify)
+        ON CONFLICT(player_id) DO UPDATE SET notify = excluded.notify",
+        named_params! { ":player_id": player_id, ":notify": notify },
+    )?;
+    Ok(())
+}
+
+/// Gets the preferences of every player who has set any.
+pub(crate) fn get_player_prefs(conn: &Connection) -> Result<Vec<PlayerPrefs>> {
+    let mut stmt = conn.prepare("SELECT player_id, notify, roll_color FROM player_prefs")?;
+
+    let prefs = stmt
+        .query_map([], |row| {
+            Ok(PlayerPrefs {
+                player_id: row.get(0)?,
+                notify: row.get(1)?,
+                roll_color: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(prefs)
+}
+
+/// Sets the accent color of a player's rolls, or clears it with `None`.
+pub(crate) fn set_roll_color(conn: &Connection, player_id: i64, color: Option<u32>) -> Result<()> {
+    conn.execute(
+        "INSERT INTO player_prefs (player_id, roll_color) VALUES (:player_id, :color)
+        ON CONFLICT(player_id) DO UPDATE SET roll_color = excluded.roll_color",
+        named_params! { ":player_id": player_id, ":color": color },
+    )?;
+    Ok(())
+}
+
+/// Sets how rolls are shown in a channel, or clears it with `None` so the server's mode is used.
+pub(crate) fn set_channel_roll_mode(
+    conn: &Connection,
+    guild_id: i64,
+    channel_id: i64,
+    mode: Option<&str>,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO channel_settings (channel_id, guild_id, roll_mode)
+        VALUES (:channel_id, :guild_id, :mode)
+        ON CONFLICT(channel_id) DO UPDATE SET roll_mode = excluded.roll_mode",
+        named_params! { ":channel_id": channel_id, ":guild_id": guild_id, ":mode": mode },
+    )?;
+    Ok(())
+}
+
+/// Gets how rolls are shown in a channel, if it has its own mode.
+pub(crate) fn get_channel_roll_mode(conn: &Connection, channel_id: i64) -> Result<Option<String>> {
+    let mode = conn
+        .query_row(
+            "SELECT roll_mode FROM channel_settings WHERE channel_id = :channel_id",
+            named_params! { ":channel_id": channel_id },
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(mode.flatten())
+}
+
+/// Gets the accent color of a player's rolls, if they've picked one.
+pub(crate) fn get_roll_color(conn: &Connection, player_id: i64) -> Result<Option<u32>> {
+    let color = conn
+        .query_row(
+            "SELECT roll_color FROM player_prefs WHERE player_id = :player_id",
+            named_params! { ":player_id": player_id },
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(color.flatten())
+}
+
+/// How much experience a player earned over a session.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct XpGain {
+    pub player_id: i64,
+    /// Their experience when the session started, or 0 if they registered during it.
+    pub start: i64,
+    pub end: i64,
+}
+
+impl XpGain {
+    pub(crate) fn gained(&self) -> i64 {
+        self.end - self.start
+    }
+}
+
+/// A finished session and what everyone earned.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct SessionSummary {
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub gains: Vec<XpGain>,
+}
+
+/// Starts a session in a guild, snapshotting the experience of its players. False if one is
+/// already running.
+pub(crate) fn start_session(conn: &mut Connection, guild_id: i64) -> Result<bool> {
+    let tx = conn.transaction()?;
+
+    let started = tx.execute(
+        "INSERT INTO sessions (guild_id, started_at)
+        SELECT :guild_id, :started_at
+        WHERE NOT EXISTS (
+            SELECT 1 FROM sessions WHERE guild_id = :guild_id AND ended_at IS NULL
+        )",
+        named_params! { ":guild_id": guild_id, ":started_at": Utc::now().to_rfc3339() },
+    )?;
+    if started == 0 {
+        tx.rollback()?;
+
+        return Ok(false);
+    }
+
+    // Players registered before guilds were tracked count as members of every guild.
+    tx.execute(
+        "INSERT INTO session_xp (session_id, player_id, start_xp)
+        SELECT :session_id, id, experience FROM players
+        WHERE guild_id = :guild_id OR guild_id IS NULL",
+        named_params! { ":session_id": tx.last_insert_rowid(), ":guild_id": guild_id },
+    )?;
+
+    tx.commit()?;
+
+    Ok(true)
+}
+
+/// Ends a guild's running session, returning how much experience each of its players earned,
+/// most first. None if no session was running.
+pub(crate) fn end_session(conn: &mut Connection, guild_id: i64) -> Result<Option<SessionSummary>> {
+    let tx = conn.transaction()?;
+
+    let session = tx
+        .query_row(
+            "SELECT id, started_at FROM sessions WHERE guild_id = :guild_id AND ended_at IS NULL",
+            named_params! { ":guild_id": guild_id },
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+        )
+        .optional()?;
+    let Some((session_id, started_at)) = session else {
+        tx.rollback()?;
+
+        return Ok(None);
+    };
+    let started_at = DateTime::parse_from_rfc3339(&started_at)?.with_timezone(&Utc);
+    let ended_at = Utc::now();
+
+    // Players registered during the session weren't snapshotted, and started with 0.
+    let gains = tx
+        .prepare(
+            "SELECT players.id, COALESCE(session_xp.start_xp, 0), players.experience
+            FROM players
+            LEFT JOIN session_xp
+            ON session_xp.session_id = :session_id AND session_xp.player_id = players.id
+            WHERE players.guild_id = :guild_id OR players.guild_id IS NULL
+            ORDER BY players.experience - COALESCE(session_xp.start_xp, 0) DESC, players.id",
+        )?
+        .query_map(
+            named_params! { ":session_id": session_id, ":guild_id": guild_id },
+            |row| {
+                Ok(XpGain {
+                    player_id: row.get(0)?,
+                    start: row.get(1)?,
+                    end: row.get(2)?,
+                })
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    tx.execute(
+        "UPDATE sessions SET ended_at = :ended_at WHERE id = :id",
+        named_params! { ":id": session_id, ":ended_at": ended_at.to_rfc3339() },
+    )?;
+
+    tx.commit()?;
+
+    Ok(Some(SessionSummary {
+        started_at,
+        ended_at,
+        gains,
+    }))
+}
+
+/// How many sessions a guild has started, including any that's running.
+pub(crate) fn count_sessions(conn: &Connection, guild_id: i64) -> Result<i64> {
+    Ok(conn.query_row(
+        "SELECT COUNT(*) FROM sessions WHERE guild_id = :guild_id",
+        named_params! { ":guild_id": guild_id },
+        |row| row.get(0),
+    )?)
+}
+
+/// Someone taking part in a fight.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Combatant {
+    pub name: String,
+    pub initiative: i64,
+    pub modifier: i64,
+    /// A d20 roll-off, for breaking ties the modifier doesn't.
+    pub tiebreak: i64,
+    /// Hit points, once they've been set.
+    pub hp: Option<i64>,
+    pub max_hp: Option<i64>,
+    /// Whether only a rough description of their hit points is shown, e.g. for monsters.
+    pub hidden_hp: bool,
+}
+
+/// A fight running in a channel.
+#[derive(Debug)]
+pub(crate) struct Combat {
+    pub round: i64,
+    /// Whose turn it is, or None before the first turn.
+    pub current: Option<String>,
+    /// In no particular order.
+    pub combatants: Vec<Combatant>,
+    pub conditions: Vec<Condition>,
+}
+
+/// A condition affecting a combatant, like Frightened or Prone.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Condition {
+    pub target: String,
+    pub name: String,
+    /// How many more rounds it lasts, or None until it's removed.
+    pub rounds: Option<i64>,
+}
+
+/// Starts a fight in a channel, returning false if one is already running there.
+pub(crate) fn start_combat(conn: &Connection, channel_id: i64) -> Result<bool> {
+    let started = conn.execute(
+        "INSERT INTO combats (channel_id, round) VALUES (:channel_id, 1)
+        ON CONFLICT(channel_id) DO NOTHING",
+        named_params! { ":channel_id": channel_id },
+    )?;
+
+    Ok(started > 0)
+}
+
+pub(crate) fn get_combat(conn: &Connection, channel_id: i64) -> Result<Option<Combat>> {
+    let combat: Option<(i64, Option<String>)> = conn
+        .query_row(
+            "SELECT round, current FROM combats WHERE channel_id = :channel_id",
+            named_params! { ":channel_id": channel_id },
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let Some((round, current)) = combat else {
+        return Ok(None);
+    };
+
+    let mut stmt = conn.prepare(
+        "SELECT name, initiative, modifier, tiebreak, hp, max_hp, hidden_hp FROM combatants
+        WHERE channel_id = :channel_id",
+    )?;
+    let combatants = stmt
+        .query_map(named_params! { ":channel_id": channel_id }, |row| {
+            Ok(Combatant {
+                name: row.get(0)?,
+                initiative: row.get(1)?,
+                modifier: row.get(2)?,
+                tiebreak: row.get(3)?,
+                hp: row.get(4)?,
+                max_hp: row.get(5)?,
+                hidden_hp: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Some(Combat {
+        round,
+        current,
+        combatants,
+        conditions: get_conditions(conn, channel_id)?,
+    }))
+}
+
+/// Adds a combatant to a channel's fight. Adding one with the same name again rerolls their
+/// initiative, and keeps their hit points and conditions.
+pub(crate) fn add_combatant(
+    conn: &Connection,
+    channel_id: i64,
+    combatant: &Combatant,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO combatants (channel_id, name, initiative, modifier, tiebreak)
+        VALUES (:channel_id, :name, :initiative, :modifier, :tiebreak)
+        ON CONFLICT(channel_id, name) DO UPDATE SET
+            initiative = excluded.initiative,
+            modifier = excluded.modifier,
+            tiebreak = excluded.tiebreak",
+        named_params! {
+            ":channel_id": channel_id,
+            ":name": combatant.name,
+            ":initiative": combatant.initiative,
+            ":modifier": combatant.modifier,
+            ":tiebreak": combatant.tiebreak,
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Sets a combatant's hit points, returning false if there's no such combatant.
+pub(crate) fn set_hp(conn: &Connection, channel_id: i64, combatant: &Combatant) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE combatants SET hp = :hp, max_hp = :max_hp, hidden_hp = :hidden_hp
+        WHERE channel_id = :channel_id AND name = :name",
+        named_params! {
+            ":channel_id": channel_id,
+            ":name": combatant.name,
+            ":hp": combatant.hp,
+            ":max_hp": combatant.max_hp,
+            ":hidden_hp": combatant.hidden_hp,
+        },
+    )?;
+
+    Ok(updated > 0)
+}
+
+/// Removes a combatant from a channel's fight, returning false if there was no such combatant.
+pub(crate) fn remove_combatant(conn: &Connection, channel_id: i64, name: &str) -> Result<bool> {
+    let removed = conn.execute(
+        "DELETE FROM combatants WHERE channel_id = :channel_id AND name = :name",
+        named_params! { ":channel_id": channel_id, ":name": name },
+    )?;
+
+    Ok(removed > 0)
+}
+
+pub(crate) fn set_turn(
+    conn: &Connection,
+    channel_id: i64,
+    current: Option<&str>,
+    round: i64,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE combats SET current = :current, round = :round WHERE channel_id = :channel_id",
+        named_params! { ":channel_id": channel_id, ":current": current, ":round": round },
+    )?;
+
+    Ok(())
+}
+
+/// Gets the conditions on every combatant in a channel's fight.
+pub(crate) fn get_conditions(conn: &Connection, channel_id: i64) -> Result<Vec<Condition>> {
+    let mut stmt = conn.prepare(
+        "SELECT target, name, rounds FROM conditions WHERE channel_id = :channel_id
+        ORDER BY target, name",
+    )?;
+    let conditions = stmt
+        .query_map(named_params! { ":channel_id": channel_id }, |row| {
+            Ok(Condition {
+                target: row.get(0)?,
+                name: row.get(1)?,
+                rounds: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(conditions)
+}
+
+/// Puts a condition on a combatant, replacing how long it lasts if they already have it.
+pub(crate) fn set_condition(
+    conn: &Connection,
+    channel_id: i64,
+    condition: &Condition,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO conditions (channel_id, target, name, rounds)
+        VALUES (:channel_id, :target, :name, :rounds)
+        ON CONFLICT(channel_id, target, name) DO UPDATE SET rounds = excluded.rounds",
+        named_params! {
+            ":channel_id": channel_id,
+            ":target": condition.target,
+            ":name": condition.name,
+            ":rounds": condition.rounds,
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Removes a condition from a combatant, returning false if they didn't have it.
+pub(crate) fn remove_condition(
+    conn: &Connection,
+    channel_id: i64,
+    target: &str,
+    name: &str,
+) -> Result<bool> {
+    let removed = conn.execute(
+        "DELETE FROM conditions
+        WHERE channel_id = :channel_id AND target = :target AND name = :name",
+        named_params! { ":channel_id": channel_id, ":target": target, ":name": name },
+    )?;
+
+    Ok(removed > 0)
+}
+
+/// Ends a channel's fight, returning false if none was running.
+pub(crate) fn end_combat(conn: &Connection, channel_id: i64) -> Result<bool> {
+    let ended = conn.execute(
+        "DELETE FROM combats WHERE channel_id = :channel_id",
+        named_params! { ":channel_id": channel_id },
+    )?;
+
+    Ok(ended > 0)
+}
+
+/// Gets a setting for every guild that has configured it, as `(guild_id, value)` pairs.
+pub(crate) fn get_setting_for_all(conn: &Connection, key: &str) -> Result<Vec<(i64, String)>> {
+    let mut stmt = conn.prepare("SELECT guild_id, value FROM guild_settings WHERE key = :key")?;
+
+    let values = stmt
+        .query_map(named_params! { ":key": key }, |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(values)
+}
+
+pub(crate) fn delete_setting(conn: &Connection, guild_id: i64, key: &str) -> Result<()> {
+    conn.execute(
+        "DELETE FROM guild_settings WHERE guild_id = :guild_id AND key = :key",
+        named_params! { ":guild_id": guild_id, ":key": key },
+    )?;
+
+    Ok(())
+}
+
+pub(crate) fn set_setting(conn: &Connection, guild_id: i64, key: &str, value: &str) -> Result<()> {
+    let query = "INSERT INTO guild_settings (guild_id, key, value) VALUES (:guild_id, :key, :value)
+    ON CONFLICT (guild_id, key) DO UPDATE SET value = excluded.value";
+    conn.execute(
+        query,
+        named_params! {
+            ":guild_id": guild_id,
+            ":key": key,
+            ":value": value
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Statistics about the database, for diagnostics.
+#[derive(Clone, Debug)]
+pub(crate) struct DbStats {
+    pub size_bytes: i64,
+    pub schema_version: i64,
+    /// The number of rows in each table, by table name.
+    pub row_counts: Vec<(String, i64)>,
+    /// The result of `PRAGMA integrity_check`, `"ok"` when no problems were found.
+    pub integrity: String,
+}
+
+/// Gathers statistics about the database. This runs a full integrity check, which can be slow on
+/// large databases.
+pub(crate) fn stats(conn: &Connection) -> Result<DbStats> {
+    let page_count: i64 = conn.pragma_query_value(None, "page_count", |row| row.get(0))?;
+    let page_size: i64 = conn.pragma_query_value(None, "page_size", |row| row.get(0))?;
+
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'
+        ORDER BY name",
+    )?;
+    let tables = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let row_counts = tables
+        .into_iter()
+        .map(|table| {
+            let query = format!("SELECT COUNT(*) FROM \"{}\"", table);
+            let count = conn.query_row(&query, [], |row| row.get(0))?;
+            Ok((table, count))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(DbStats {
+        size_bytes: page_count * page_size,
+        schema_version: schema_version(conn)?,
+        row_counts,
+        integrity: integrity_check(conn)?,
+    })
+}
+
+/// Runs `PRAGMA quick_check`, a faster integrity check that skips checking indexes against their
+/// tables. Returns `"ok"` when no problems were found, or the problems, one per line.
+pub(crate) fn quick_check(conn: &Connection) -> Result<String> {
+    let mut stmt = conn.prepare("PRAGMA quick_check")?;
+    let integrity = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?
+        .join("\n");
+
+    Ok(integrity)
+}
+
+/// Runs `PRAGMA integrity_check`, returning `"ok"` when no problems were found, or the problems,
+/// one per line.
+pub(crate) fn integrity_check(conn: &Connection) -> Result<String> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let integrity = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?
+        .join("\n");
+
+    Ok(integrity)
+}
+
+/// Checks that the directory the database at `db_path` goes in exists and is writable, so a bad
+/// `DATABASE_PATH` fails at startup rather than on the first query. The directory is created
+/// when it's missing and `create` is set.
+pub(crate) fn check_dir(db_path: &Path, create: bool) -> Result<()> {
+    let dir = match db_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if !dir.is_dir() {
+        if !create {
+            return Err(Error::MissingDir(dir.to_path_buf()));
+        }
+        log::info!("Creating the database directory {}", dir.display());
+        fs::create_dir_all(dir).map_err(|e| Error::UnwritableDir(dir.to_path_buf(), e))?;
+    }
+
+    // Permissions alone don't say whether e.g. a read-only mount can be written to.
+    let probe = dir.join(format!(".write-check-{}", std::process::id()));
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .and_then(|_| fs::remove_file(&probe))
+        .map_err(|e| Error::UnwritableDir(dir.to_path_buf(), e))
+}
+
+/// Moves everything in the write-ahead log into the database file, e.g. before shutting down.
+pub(crate) fn checkpoint(conn: &Connection) -> Result<()> {
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+
+    Ok(())
+}
+
+/// Configures a newly opened connection. WAL and a busy timeout let the scheduler and command
+/// handlers write concurrently without failing with `database is locked`.
+///
+/// In WAL mode SQLite keeps `-wal` and `-shm` files next to the database while it's open. They're
+/// part of the database, so copy or delete them along with it; `checkpoint` empties the `-wal` on
+/// shutdown.
+pub(crate) fn configure_connection(conn: &mut Connection) -> rusqlite::Result<()> {
+    // Some filesystems, and in-memory databases, silently keep the old journal mode.
+    let journal_mode: String = conn.query_row("PRAGMA journal_mode = WAL", [], |row| row.get(0))?;
+    if !journal_mode.eq_ignore_ascii_case("wal") {
+        log::warn!(
+            "Couldn't enable WAL, the journal mode is {}. Concurrent writes may fail with `database is locked`.",
+            journal_mode
+        );
+    }
+
+    conn.execute_batch(
+        "PRAGMA busy_timeout = 5000;
+    PRAGMA foreign_keys = ON;
+    PRAGMA synchronous = NORMAL;",
+    )
+}
+
+/// A single schema change. Migrations are applied in order, and the index of the last applied
+/// migration (starting at 1) is stored in the database's `user_version`.
+struct Migration {
+    sql: &'static str,
+    /// Runs after `sql`, for changes that can't be expressed idempotently in SQL.
+    fixup: Option<fn(&Connection) -> Result<()>>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    // 1: Players, MVP votes and the scheduled message.
+    Migration {
+        sql: "CREATE TABLE IF NOT EXISTS players (
+        id INTEGER PRIMARY KEY,
+        experience INTEGER NOT NULL DEFAULT 0
+    );
+
+    CREATE TABLE IF NOT EXISTS mvp (
+        id INTEGER PRIMARY KEY,
+        playerid INTEGER NOT NULL UNIQUE,
+        mvpid INTEGER NOT NULL,
+        FOREIGN KEY(playerid) REFERENCES players(id),
+        FOREIGN KEY(mvpid) REFERENCES players(id)
+    );
+
+    CREATE TABLE IF NOT EXISTS schedule (
+        id INTEGER PRIMARY KEY,
+        channel_id INTEGER NOT NULL,
+        scheduled TEXT NOT NULL,
+        msg TEXT NOT NULL
+    );",
+        fixup: None,
+    },
+    // 2: Per-guild settings.
+    Migration {
+        sql: "CREATE TABLE IF NOT EXISTS guild_settings (
+        guild_id INTEGER NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY(guild_id, key)
+    );",
+        fixup: None,
+    },
+    // 3: Embed title and image for scheduled messages.
+    Migration {
+        sql: "",
+        fixup: Some(add_schedule_embed_columns),
+    },
+    // 4: The guild and time a player registered in. Existing players have neither.
+    Migration {
+        sql: "ALTER TABLE players ADD COLUMN guild_id INTEGER;
+    ALTER TABLE players ADD COLUMN registered_at TEXT;",
+        fixup: None,
+    },
+    // 5: Custom dice with labelled faces, stored as a JSON array.
+    Migration {
+        sql: "CREATE TABLE IF NOT EXISTS custom_dice (
+        guild_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        faces TEXT NOT NULL,
+        PRIMARY KEY(guild_id, name)
+    );",
+        fixup: None,
+    },
+    // 6: Each channel's draw pile, stored as a JSON array.
+    Migration {
+        sql: "CREATE TABLE IF NOT EXISTS deck_state (
+        channel_id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        cards TEXT NOT NULL
+    );",
+        fixup: None,
+    },
+    // 7: Each user's recent rolls.
+    Migration {
+        sql: "CREATE TABLE IF NOT EXISTS roll_log (
+        id INTEGER PRIMARY KEY,
+        user_id INTEGER NOT NULL,
+        expression TEXT NOT NULL,
+        rolled_at TEXT NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS roll_log_user_id ON roll_log(user_id);",
+        fixup: None,
+    },
+    // 8: Initiative tracking, one fight per channel.
+    Migration {
+        sql: "CREATE TABLE IF NOT EXISTS combats (
+        channel_id INTEGER PRIMARY KEY,
+        round INTEGER NOT NULL,
+        current TEXT
+    );
+
+    CREATE TABLE IF NOT EXISTS combatants (
+        channel_id INTEGER NOT NULL REFERENCES combats(channel_id) ON DELETE CASCADE,
+        name TEXT NOT NULL,
+        initiative INTEGER NOT NULL,
+        modifier INTEGER NOT NULL,
+        tiebreak INTEGER NOT NULL,
+        PRIMARY KEY (channel_id, name)
+    );",
+        fixup: None,
+    },
+    // 9: Conditions on combatants, which go when the combatant does.
+    Migration {
+        sql: "CREATE TABLE IF NOT EXISTS conditions (
+        channel_id INTEGER NOT NULL,
+        target TEXT NOT NULL,
+        name TEXT NOT NULL,
+        rounds INTEGER,
+        PRIMARY KEY (channel_id, target, name),
+        FOREIGN KEY (channel_id, target)
+            REFERENCES combatants(channel_id, name) ON DELETE CASCADE
+    );",
+        fixup: None,
+    },
+    // 10: Combatants' hit points.
+    Migration {
+        sql: "ALTER TABLE combatants ADD COLUMN hp INTEGER;
+    ALTER TABLE combatants ADD COLUMN max_hp INTEGER;
+    ALTER TABLE combatants ADD COLUMN hidden_hp INTEGER NOT NULL DEFAULT 0;",
+        fixup: None,
+    },
+    // 11: Random tables, whose entries go when the table does.
+    Migration {
+        sql: "CREATE TABLE IF NOT EXISTS random_tables (
+        guild_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        PRIMARY KEY (guild_id, name)
+    );
+
+    CREATE TABLE IF NOT EXISTS random_table_entries (
+        id INTEGER PRIMARY KEY,
+        guild_id INTEGER NOT NULL,
+        table_name TEXT NOT NULL,
+        weight INTEGER NOT NULL,
+        text TEXT NOT NULL,
+        FOREIGN KEY (guild_id, table_name)
+            REFERENCES random_tables(guild_id, name) ON DELETE CASCADE
+    );
+
+    CREATE INDEX IF NOT EXISTS random_table_entries_table
+        ON random_table_entries(guild_id, table_name);",
+        fixup: None,
+    },
+    // 12: Countdown timers, one per channel, so they survive restarts.
+    Migration {
+        sql: "CREATE TABLE IF NOT EXISTS timers (
+        channel_id INTEGER PRIMARY KEY,
+        message_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        label TEXT,
+        ends_at TEXT NOT NULL
+    );",
+        fixup: None,
+    },
+    // 13: Session recaps.
+    Migration {
+        sql: "CREATE TABLE IF NOT EXISTS recaps (
+        id INTEGER PRIMARY KEY,
+        guild_id INTEGER NOT NULL,
+        session INTEGER NOT NULL,
+        author_id INTEGER NOT NULL,
+        text TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS recaps_guild_id ON recaps(guild_id);",
+        fixup: None,
+    },
+    // 14: Polls and their votes, one vote per user.
+    Migration {
+        sql: "CREATE TABLE IF NOT EXISTS polls (
+        id INTEGER PRIMARY KEY,
+        channel_id INTEGER NOT NULL,
+        message_id INTEGER,
+        creator_id INTEGER NOT NULL,
+        question TEXT NOT NULL,
+        options TEXT NOT NULL,
+        closes_at TEXT,
+        closed INTEGER NOT NULL DEFAULT 0
+    );
+
+    CREATE TABLE IF NOT EXISTS poll_votes (
+        poll_id INTEGER NOT NULL REFERENCES polls(id) ON DELETE CASCADE,
+        user_id INTEGER NOT NULL,
+        option INTEGER NOT NULL,
+        PRIMARY KEY (poll_id, user_id)
+    );",
+        fixup: None,
+    },
+    // 15: Players' characters.
+    Migration {
+        sql: "ALTER TABLE players ADD COLUMN character_name TEXT;
+    ALTER TABLE players ADD COLUMN character_class TEXT;
+    ALTER TABLE players ADD COLUMN sheet_url TEXT;",
+        fixup: None,
+    },
+    // 16: Players' limited resources, like spell slots.
+    Migration {
+        sql: "CREATE TABLE IF NOT EXISTS resources (
+        guild_id INTEGER NOT NULL,
+        player_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        current INTEGER NOT NULL,
+        max INTEGER NOT NULL,
+        restore_on_rest INTEGER NOT NULL DEFAULT 1,
+        PRIMARY KEY(guild_id, player_id, name)
+    );",
+        fixup: None,
+    },
+    // 17: Dying players' death saves.
+    Migration {
+        sql: "CREATE TABLE IF NOT EXISTS death_saves (
+        guild_id INTEGER NOT NULL,
+        player_id INTEGER NOT NULL,
+        successes INTEGER NOT NULL,
+        failures INTEGER NOT NULL,
+        PRIMARY KEY(guild_id, player_id)
+    );",
+        fixup: None,
+    },
+    // 18: Play sessions, with each player's experience when they started.
+    Migration {
+        sql: "CREATE TABLE IF NOT EXISTS sessions (
+        id INTEGER PRIMARY KEY,
+        guild_id INTEGER NOT NULL,
+        started_at TEXT NOT NULL,
+        ended_at TEXT
+    );
+
+    CREATE TABLE IF NOT EXISTS session_xp (
+        session_id INTEGER NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+        player_id INTEGER NOT NULL,
+        start_xp INTEGER NOT NULL,
+        PRIMARY KEY(session_id, player_id)
+    );
+
+    CREATE INDEX IF NOT EXISTS sessions_guild_id ON sessions(guild_id);",
+        fixup: None,
+    },
+    // 19: Downtime days granted and spent, kept as a ledger for the log.
+    Migration {
+        sql: "CREATE TABLE IF NOT EXISTS downtime (
+        id INTEGER PRIMARY KEY,
+        guild_id INTEGER NOT NULL,
+        player_id INTEGER NOT NULL,
+        days INTEGER NOT NULL,
+        activity TEXT,
+        recorded_at TEXT NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS downtime_guild_player ON downtime(guild_id, player_id);",
+        fixup: None,
+    },
+    // 20: Players' DM notification preferences, and who scheduled the scheduled message.
+    Migration {
+        sql: "CREATE TABLE IF NOT EXISTS player_prefs (
+        player_id INTEGER PRIMARY KEY,
+        notify INTEGER NOT NULL DEFAULT 0
+    );
+
+    ALTER TABLE schedule ADD COLUMN created_by INTEGER;",
+        fixup: None,
+    },
+    // 21: The accent color players pick for their rolls.
+    Migration {
+        sql: "ALTER TABLE player_prefs ADD COLUMN roll_color INTEGER;",
+        fixup: None,
+    },
+    // 22: Message templates, and whether the scheduled message is one, and where it's from.
+    Migration {
+        sql: "CREATE TABLE IF NOT EXISTS templates (
+        guild_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        text TEXT NOT NULL,
+        PRIMARY KEY (guild_id, name)
+    );
+
+    ALTER TABLE schedule ADD COLUMN guild_id INTEGER;
+    ALTER TABLE schedule ADD COLUMN templated INTEGER NOT NULL DEFAULT 0;",
+        fixup: None,
+    },
+    // 23: Per-channel settings, overriding the guild's.
+    Migration {
+        sql: "CREATE TABLE IF NOT EXISTS channel_settings (
+        channel_id INTEGER PRIMARY KEY,
+        guild_id INTEGER NOT NULL,
+        roll_mode TEXT
+    );",
+        fixup: None,
+    },
+];
+
+// Databases created before migrations existed may already have these columns.
+fn add_schedule_embed_columns(conn: &Connection) -> Result<()> {
+    for column in ["title", "image_url"] {
+        if !has_column(conn, "schedule", column)? {
+            conn.execute(
+                &format!("ALTER TABLE schedule ADD COLUMN {} TEXT", column),
+                [],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let mut names = stmt.query_map([], |row| row.get::<_, String>(1))?;
+
+    Ok(names.any(|name| name.is_ok_and(|name| name == column)))
+}
+
+/// The schema version of the database, as recorded by [`run_migrations`].
+pub(crate) fn schema_version(conn: &Connection) -> Result<i64> {
+    let version = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    Ok(version)
+}
+
+/// Applies all pending migrations in a single transaction.
+///
+/// Fails with [`Error::UnknownSchemaVersion`] if the database was migrated by a newer version of
+/// the bot, rather than risk running against a schema it doesn't understand.
+pub(crate) fn run_migrations(conn: &mut Connection) -> Result<()> {
+    let version = schema_version(conn)?;
+    let latest = MIGRATIONS.len() as i64;
+
+    if version > latest {
+        return Err(Error::UnknownSchemaVersion(version));
+    }
+
+    if version == latest {
+        log::info!("Database schema is up to date at version {}", version);
+        return Ok(());
+    }
+
+    let tx = conn.transaction()?;
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        log::info!("Applying database migration {}", i + 1);
+        tx.execute_batch(migration.sql)?;
+
+        if let Some(fixup) = migration.fixup {
+            fixup(&tx)?;
+        }
+    }
+
+    tx.pragma_update(None, "user_version", latest)?;
+    tx.commit()?;
+
+    log::info!(
+        "Migrated database schema from version {} to {}",
+        version,
+        latest
+    );
+    Ok(())
+}
+
+/// A fresh in-memory database with every migration applied, for tests.
+#[cfg(test)]
+pub(crate) fn test_connection() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    configure_connection(&mut conn).unwrap();
+    run_migrations(&mut conn).unwrap();
+    conn
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    const GUILD: i64 = 10;
+
+    fn conn() -> Connection {
+        test_connection()
+    }
+
+    fn schedule(msg: &str) -> ScheduledMessage {
+        ScheduledMessage {
+            channel_id: 20,
+            msg: msg.to_string(),
+            on: Local.with_ymd_and_hms(2030, 1, 2, 19, 30, 0).unwrap(),
+            title: None,
+            image_url: None,
+            created_by: Some(1),
+            guild_id: Some(GUILD as u64),
+            templated: false,
+        }
+    }
+
+    fn tables(conn: &Connection) -> Vec<String> {
+        conn.prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn migrating_from_v1_keeps_data() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0].sql).unwrap();
+        conn.pragma_update(None, "user_version", 1).unwrap();
+        let on = Local.with_ymd_and_hms(2030, 1, 2, 19, 30, 0).unwrap();
+        conn.execute_batch(&format!(
+            "INSERT INTO players (id, experience) VALUES (1, 300), (2, 50);
+            INSERT INTO mvp (playerid, mvpid) VALUES (1, 2);
+            INSERT INTO schedule (id, channel_id, scheduled, msg) VALUES (1, 20, '{}', 'Game!');",
+            on.to_rfc3339()
+        ))
+        .unwrap();
+
+        run_migrations(&mut conn).unwrap();
+
+        assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len() as i64);
+        let tables = tables(&conn);
+        for table in ["players", "mvp", "schedule", "guild_settings", "templates"] {
+            assert!(
+                tables.iter().any(|name| name == table),
+                "no {} table",
+                table
+            );
+        }
+        for (table, column) in [
+            ("players", "guild_id"),
+            ("players", "character_name"),
+            ("schedule", "title"),
+            ("schedule", "templated"),
+        ] {
+            assert!(
+                has_column(&conn, table, column).unwrap(),
+                "no {}.{}",
+                table,
+                column
+            );
+        }
+
+        let player = get_player(&conn, 1).unwrap();
+        assert_eq!(player.experience, 300);
+        assert_eq!(player.guild_id, None);
+        assert_eq!(player.registered_at, None);
+        assert_eq!(get_player(&conn, 2).unwrap().experience, 50);
+        let sch = get_schedule(&conn).unwrap().unwrap();
+        assert_eq!(sch.msg, "Game!");
+        assert_eq!(sch.on, on);
+        assert_eq!(sch.title, None);
+        assert!(!sch.templated);
+        // The vote survived, so resolving counts it. Player 2 still has to vote.
+        assert!(matches!(
+            resolve_mvp(&mut conn, GUILD),
+            Err(Error::MissingVotes)
+        ));
+        assert_eq!(clear_votes(&conn, GUILD).unwrap(), 1);
+    }
+
+    #[test]
+    fn migrating_twice_changes_nothing() {
+        let mut conn = conn();
+        let before = tables(&conn);
+
+        run_migrations(&mut conn).unwrap();
+        assert_eq!(tables(&conn), before);
+        assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len() as i64);
+    }
+
+    #[test]
+    fn refusing_a_newer_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let newer = MIGRATIONS.len() as i64 + 1;
+        conn.pragma_update(None, "user_version", newer).unwrap();
+
+        assert!(matches!(
+            run_migrations(&mut conn),
+            Err(Error::UnknownSchemaVersion(version)) if version == newer
+        ));
+    }
+
+    /// A database file in the temp directory, deleted along with its WAL files when dropped.
+    struct TempDb(PathBuf);
+
+    impl TempDb {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "tabletop-bot-{}-{}.db",
+                name,
+                std::process::id()
+            ));
+            let db = Self(path);
+            db.remove();
+            db
+        }
+
+        /// A pool configured like the bot's.
+        fn pool(&self) -> r2d2::Pool<r2d2_sqlite::SqliteConnectionManager> {
+            let mgr =
+                r2d2_sqlite::SqliteConnectionManager::file(&self.0).with_init(configure_connection);
+            let pool = r2d2::Pool::builder().max_size(2).build(mgr).unwrap();
+            run_migrations(&mut pool.get().unwrap()).unwrap();
+            pool
+        }
+
+        fn remove(&self) {
+            for suffix in ["", "-wal", "-shm"] {
+                let mut path = self.0.clone().into_os_string();
+                path.push(suffix);
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            self.remove();
+        }
+    }
+
+    #[test]
+    fn pooled_connections_are_configured() {
+        let db = TempDb::new("pragmas");
+        let pool = db.pool();
+        let first = pool.get().unwrap();
+        let second = pool.get().unwrap();
+
+        for conn in [&first, &second] {
+            let journal_mode: String = conn
+                .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(journal_mode, "wal");
+            let foreign_keys: i64 = conn
+                .query_row("PRAGMA foreign_keys", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(foreign_keys, 1);
+            let busy_timeout: i64 = conn
+                .query_row("PRAGMA busy_timeout", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(busy_timeout, 5000);
+            // 1 is NORMAL.
+            let synchronous: i64 = conn
+                .query_row("PRAGMA synchronous", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(synchronous, 1);
+        }
+    }
+
+    #[test]
+    fn pooled_connections_read_while_writing() {
+        let db = TempDb::new("concurrent");
+        let pool = db.pool();
+        let mut writer = pool.get().unwrap();
+        let reader = pool.get().unwrap();
+        create_player(&writer, 1, GUILD).unwrap();
+
+        // With WAL, reading doesn't wait for a write that's in progress.
+        let tx = writer.transaction().unwrap();
+        set_xp(&tx, 1, 100).unwrap();
+        assert_eq!(get_player(&reader, 1).unwrap().experience, 0);
+        tx.commit().unwrap();
+        assert_eq!(get_player(&reader, 1).unwrap().experience, 100);
+    }
+
+    #[test]
+    fn foreign_keys_are_enforced() {
+        let conn = conn();
+
+        let inserted = conn.execute("INSERT INTO mvp (playerid, mvpid) VALUES (1, 2)", []);
+        assert!(matches!(
+            inserted,
+            Err(rusqlite::Error::SqliteFailure(e, _))
+                if e.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_FOREIGNKEY
+        ));
+    }
+
+    #[test]
+    fn player_roundtrips() {
+        let conn = conn();
+        create_player(&conn, 1, GUILD).unwrap();
+        let character = Character {
+            name: "Vex".to_string(),
+            class: Some("Ranger 5".to_string()),
+            sheet_url: None,
+        };
+        set_character(&conn, 1, &character).unwrap();
+
+        let player = get_player(&conn, 1).unwrap();
+        assert_eq!(player.id, 1);
+        assert_eq!(player.guild_id, Some(GUILD));
+        assert_eq!(player.experience, 0);
+        assert!(player.registered_at.is_some());
+        assert_eq!(player.character, Some(character));
+    }
+
+    #[test]
+    fn player_from_before_guilds() {
+        let conn = conn();
+        conn.execute("INSERT INTO players (id, experience) VALUES (1, 40)", [])
+            .unwrap();
+
+        assert_eq!(
+            get_players(&conn).unwrap(),
+            vec![Player {
+                id: 1,
+                guild_id: None,
+                experience: 40,
+                registered_at: None,
+                character: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn player_with_bad_registration_time() {
+        let conn = conn();
+        conn.execute(
+            "INSERT INTO players (id, registered_at) VALUES (1, 'yesterday')",
+            [],
+        )
+        .unwrap();
+
+        // The row isn't silently skipped.
+        assert!(matches!(
+            get_players(&conn),
+            Err(Error::Sqlite(rusqlite::Error::FromSqlConversionFailure(
+                3,
+                Type::Text,
+                _
+            )))
+        ));
+    }
+
+    #[test]
+    fn xp_roundtrips() {
+        let conn = conn();
+        create_player(&conn, 1, GUILD).unwrap();
+
+        assert_eq!(get_player(&conn, 1).unwrap().experience, 0);
+        set_xp(&conn, 1, 250).unwrap();
+        assert_eq!(get_player(&conn, 1).unwrap().experience, 250);
+    }
+
+    #[test]
+    fn xp_of_unknown_player() {
+        let conn = conn();
+
+        assert!(matches!(get_player(&conn, 1), Err(Error::UnknownPlayer(1))));
+        assert!(matches!(set_xp(&conn, 1, 5), Err(Error::UnknownPlayer(1))));
+    }
+
+    #[test]
+    fn registering_twice() {
+        let conn = conn();
+        create_player(&conn, 1, GUILD).unwrap();
+
+        assert!(matches!(
+            create_player(&conn, 1, GUILD),
+            Err(Error::PlayerExists(1))
+        ));
+    }
+
+    #[test]
+    fn registering_many() {
+        let mut conn = conn();
+        create_player(&conn, 1, GUILD).unwrap();
+        create_player(&conn, 2, GUILD + 1).unwrap();
+        conn.execute("INSERT INTO players (id) VALUES (3)", [])
+            .unwrap();
+
+        let registered = create_players(&mut conn, &[1, 2, 3, 4, 5], GUILD).unwrap();
+        assert_eq!(
+            registered,
+            Registered {
+                created: 2,
+                existing: 2,
+                elsewhere: vec![2],
+            }
+        );
+        assert_eq!(get_player(&conn, 4).unwrap().guild_id, Some(GUILD));
+        // Whoever is registered elsewhere stays there.
+        assert_eq!(get_player(&conn, 2).unwrap().guild_id, Some(GUILD + 1));
+        assert_eq!(
+            create_players(&mut conn, &[4, 5], GUILD).unwrap(),
+            Registered {
+                created: 0,
+                existing: 2,
+                elsewhere: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn changing_a_vote() {
+        let conn = conn();
+        for id in 1..=3 {
+            create_player(&conn, id, GUILD).unwrap();
+        }
+
+        vote_for_mvp(&conn, 1, 2).unwrap();
+        vote_for_mvp(&conn, 1, 3).unwrap();
+
+        let votes: Vec<(i64, i64)> = conn
+            .prepare("SELECT playerid, mvpid FROM mvp")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(votes, vec![(1, 3)]);
+    }
+
+    #[test]
+    fn voting_unregistered() {
+        let conn = conn();
+        create_player(&conn, 1, GUILD).unwrap();
+
+        assert!(matches!(
+            vote_for_mvp(&conn, 2, 1),
+            Err(Error::UnknownPlayer(2))
+        ));
+        assert!(matches!(
+            vote_for_mvp(&conn, 1, 3),
+            Err(Error::UnknownPlayer(3))
+        ));
+    }
+
+    #[test]
+    fn resolving_a_winner() {
+        let mut conn = conn();
+        for id in 1..=3 {
+            create_player(&conn, id, GUILD).unwrap();
+        }
+        vote_for_mvp(&conn, 1, 2).unwrap();
+        vote_for_mvp(&conn, 2, 3).unwrap();
+        vote_for_mvp(&conn, 3, 2).unwrap();
+
+        let result = resolve_mvp(&mut conn, GUILD).unwrap();
+        assert_eq!(result.winners, vec![2]);
+        assert_eq!(result.tally, vec![(2, 2), (3, 1)]);
+        // The votes are cleared once resolved.
+        assert_eq!(clear_votes(&conn, GUILD).unwrap(), 0);
+    }
+
+    #[test]
+    fn resolving_a_tie() {
+        let mut conn = conn();
+        for id in 1..=2 {
+            create_player(&conn, id, GUILD).unwrap();
+        }
+        vote_for_mvp(&conn, 1, 2).unwrap();
+        vote_for_mvp(&conn, 2, 1).unwrap();
+
+        let result = resolve_mvp(&mut conn, GUILD).unwrap();
+        assert_eq!(result.winners, vec![1, 2]);
+    }
+
+    #[test]
+    fn resolving_a_two_way_tie() {
+        let mut conn = conn();
+        for id in 1..=5 {
+            create_player(&conn, id, GUILD).unwrap();
+        }
+        // 4 and 2 get two votes each, 5 gets one. Players are voted for out of id order, so the
+        // order comes from the query rather than the votes.
+        vote_for_mvp(&conn, 1, 4).unwrap();
+        vote_for_mvp(&conn, 2, 5).unwrap();
+        vote_for_mvp(&conn, 3, 4).unwrap();
+        vote_for_mvp(&conn, 4, 2).unwrap();
+        vote_for_mvp(&conn, 5, 2).unwrap();
+        conn.execute(
+            "INSERT INTO players (id, guild_id, character_name) VALUES (6, :guild_id, 'Vex')",
+            named_params! { ":guild_id": GUILD },
+        )
+        .unwrap();
+        vote_for_mvp(&conn, 6, 6).unwrap();
+
+        let result = resolve_mvp(&mut conn, GUILD).unwrap();
+        assert_eq!(result.winners, vec![2, 4]);
+        assert_eq!(result.tally, vec![(2, 2), (4, 2), (5, 1), (6, 1)]);
+        assert_eq!(result.characters, HashMap::from([(6, "Vex".to_string())]));
+    }
+
+    #[test]
+    fn resolving_without_every_vote() {
+        let mut conn = conn();
+        for id in 1..=2 {
+            create_player(&conn, id, GUILD).unwrap();
+        }
+        vote_for_mvp(&conn, 1, 2).unwrap();
+
+        assert!(matches!(
+            resolve_mvp(&mut conn, GUILD),
+            Err(Error::MissingVotes)
+        ));
+        // The votes cast so far are kept.
+        assert_eq!(clear_votes(&conn, GUILD).unwrap(), 1);
+    }
+
+    #[test]
+    fn resolving_without_players() {
+        let mut conn = conn();
+
+        assert!(matches!(
+            resolve_mvp(&mut conn, GUILD),
+            Err(Error::MissingVotes)
+        ));
+    }
+
+    #[test]
+    fn resolving_one_guild() {
+        let mut conn = conn();
+        for id in 1..=2 {
+            create_player(&conn, id, GUILD).unwrap();
+        }
+        create_player(&conn, 3, GUILD + 1).unwrap();
+        vote_for_mvp(&conn, 1, 2).unwrap();
+        vote_for_mvp(&conn, 2, 2).unwrap();
+
+        // The other guild's player hasn't voted, and their vote wouldn't count here anyway.
+        assert_eq!(resolve_mvp(&mut conn, GUILD).unwrap().winners, vec![2]);
+        vote_for_mvp(&conn, 3, 3).unwrap();
+        assert!(matches!(
+            resolve_mvp(&mut conn, GUILD),
+            Err(Error::MissingVotes)
+        ));
+        assert_eq!(
+            resolve_mvp(&mut conn, GUILD + 1).unwrap().tally,
+            vec![(3, 1)]
+        );
+    }
+
+    #[test]
+    fn clearing_one_guild() {
+        let conn = conn();
+        create_player(&conn, 1, GUILD).unwrap();
+        create_player(&conn, 2, GUILD + 1).unwrap();
+        vote_for_mvp(&conn, 1, 1).unwrap();
+        vote_for_mvp(&conn, 2, 2).unwrap();
+
+        assert_eq!(clear_votes(&conn, GUILD).unwrap(), 1);
+        assert_eq!(clear_votes(&conn, GUILD).unwrap(), 0);
+        assert_eq!(clear_votes(&conn, GUILD + 1).unwrap(), 1);
+    }
+
+    #[test]
+    fn spending_downtime() {
+        let mut conn = conn();
+        grant_downtime(&mut conn, GUILD, &[1, 2], 5).unwrap();
+
+        assert_eq!(
+            spend_downtime(&mut conn, GUILD, 1, 3, "crafting").unwrap(),
+            2
+        );
+        assert_eq!(
+            spend_downtime(&mut conn, GUILD, 1, 2, "carousing").unwrap(),
+            0
+        );
+        assert_eq!(get_downtime_balance(&conn, GUILD, 1).unwrap(), 0);
+        assert_eq!(get_downtime_balance(&conn, GUILD, 2).unwrap(), 5);
+        assert_eq!(get_downtime_balance(&conn, GUILD + 1, 2).unwrap(), 0);
+    }
+
+    #[test]
+    fn overdrawing_downtime() {
+        let mut conn = conn();
+        grant_downtime(&mut conn, GUILD, &[1], 2).unwrap();
+
+        assert!(matches!(
+            spend_downtime(&mut conn, GUILD, 1, 3, "research"),
+            Err(Error::InsufficientDowntime(2))
+        ));
+        assert!(matches!(
+            spend_downtime(&mut conn, GUILD + 1, 1, 1, "research"),
+            Err(Error::InsufficientDowntime(0))
+        ));
+        // Nothing was spent.
+        assert_eq!(get_downtime_balance(&conn, GUILD, 1).unwrap(), 2);
+        assert_eq!(get_downtime_log(&conn, GUILD, 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn schedule_roundtrips() {
+        let conn = conn();
+        assert!(get_schedule(&conn).unwrap().is_none());
+
+        create_schedule(&conn, &schedule("first")).unwrap();
+        create_schedule(&conn, &schedule("second")).unwrap();
+        let sch = get_schedule(&conn).unwrap().unwrap();
+        assert_eq!(sch.msg, "second");
+        assert_eq!(sch.on, schedule("second").on);
+        assert_eq!(sch.guild_id, Some(GUILD as u64));
+
+        delete_schedule(&conn).unwrap();
+        assert!(get_schedule(&conn).unwrap().is_none());
+    }
+
+    #[test]
+    fn updating_missing_schedule() {
+        let conn = conn();
+
+        assert!(!update_schedule(&conn, &schedule("edited")).unwrap());
+        assert!(get_schedule(&conn).unwrap().is_none());
+    }
+
+    #[test]
+    fn updating_schedule() {
+        let conn = conn();
+        create_schedule(&conn, &schedule("first")).unwrap();
+
+        let mut edited = schedule("edited");
+        edited.channel_id = 30;
+        edited.on += chrono::Duration::days(1);
+        // Who scheduled it, and where, isn't changed by an edit.
+        edited.created_by = Some(2);
+        assert!(update_schedule(&conn, &edited).unwrap());
+
+        let sch = get_schedule(&conn).unwrap().unwrap();
+        assert_eq!(sch.msg, "edited");
+        assert_eq!(sch.channel_id, 30);
+        assert_eq!(sch.on, edited.on);
+        assert_eq!(sch.created_by, Some(1));
+    }
+
+    #[test]
+    fn schedule_with_bad_datetime() {
+        let conn = conn();
+        conn.execute(
+            "INSERT INTO schedule (id, channel_id, scheduled, msg) VALUES (1, 20, 'tomorrow', 'hi')",
+            [],
+        )
+        .unwrap();
+
+        assert!(matches!(
+            get_schedule(&conn),
+            Err(Error::Sqlite(rusqlite::Error::FromSqlConversionFailure(
+                1,
+                Type::Text,
+                _
+            )))
+        ));
+    }
+}
@@ -0,0 +1,131 @@
+use rand::Rng;
+
+use crate::db::DeathSaves;
+
+/// How many successes stabilize a dying character, and how many failures kill them.
+const NEEDED: i64 = 3;
+
+/// What a death save did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    Success,
+    Failure,
+    /// A natural 1, which counts as two failures.
+    Fumble,
+    /// A natural 20: they regain 1 hit point and are no longer dying.
+    Revived,
+    /// The third success.
+    Stable,
+    /// The third failure.
+    Dead,
+}
+
+/// Records a death save of `roll` on a d20: 10 or more succeeds, a natural 1 is two failures and
+/// a natural 20 revives them. The saves are reset once they're revived, stable or dead, ready
+/// for the next time they're dying.
+pub(crate) fn record(saves: DeathSaves, roll: u32) -> (DeathSaves, Outcome) {
+    let (saves, outcome) = match roll {
+        20 => return (DeathSaves::default(), Outcome::Revived),
+        1 => (
+            DeathSaves {
+                failures: saves.failures + 2,
+                ..saves
+            },
+            Outcome::Fumble,
+        ),
+        roll if roll >= 10 => (
+            DeathSaves {
+                successes: saves.successes + 1,
+                ..saves
+            },
+            Outcome::Success,
+        ),
+        _ => (
+            DeathSaves {
+                failures: saves.failures + 1,
+                ..saves
+            },
+            Outcome::Failure,
+        ),
+    };
+
+    if saves.failures >= NEEDED {
+        (DeathSaves::default(), Outcome::Dead)
+    } else if saves.successes >= NEEDED {
+        (DeathSaves::default(), Outcome::Stable)
+    } else {
+        (saves, outcome)
+    }
+}
+
+/// Rolls a death save, returning the d20 roll along with [`record`]'s result.
+pub(crate) fn roll<R: Rng + ?Sized>(saves: DeathSaves, rng: &mut R) -> (u32, DeathSaves, Outcome) {
+    let roll = rng.gen_range(1..=20);
+    let (saves, outcome) = record(saves, roll);
+    (roll, saves, outcome)
+}
+
+fn plural(count: i64, one: &str, many: &str) -> String {
+    if count == 1 {
+        format!("1 {}", one)
+    } else {
+        format!("{} {}", count, many)
+    }
+}
+
+/// The running tally, like `2 successes / 1 failure`.
+pub(crate) fn format_tally(saves: &DeathSaves) -> String {
+    format!(
+        "{} / {}",
+        plural(saves.successes, "success", "successes"),
+        plural(saves.failures, "failure", "failures")
+    )
+}
+
+/// A roll with its article, like `an 8` or `a natural 1`.
+fn rolled(roll: u32) -> String {
+    match roll {
+        1 | 20 => format!("a natural {}", roll),
+        8 | 11 | 18 => format!("an {}", roll),
+        roll => format!("a {}", roll),
+    }
+}
+
+/// Announces a death save `name` rolled.
+pub(crate) fn announcement(name: &str, roll: u32, saves: &DeathSaves, outcome: Outcome) -> String {
+    match outcome {
+        Outcome::Success => format!(
+            "🎲 {} rolled {}: a success. {}",
+            name,
+            rolled(roll),
+            format_tally(saves)
+        ),
+        Outcome::Failure => format!(
+            "🎲 {} rolled {}: a failure. {}",
+            name,
+            rolled(roll),
+            format_tally(saves)
+        ),
+        Outcome::Fumble => format!(
+            "🎲 {} rolled a natural 1: two failures! {}",
+            name,
+            format_tally(saves)
+        ),
+        Outcome::Revived => format!(
+            "✨ {} rolled a natural 20 and regains 1 hit point, back on their feet!",
+            name
+        ),
+        Outcome::Stable => format!(
+            "🩹 {} rolled {}: a third success. {} is stable.",
+            name,
+            rolled(roll),
+            name
+        ),
+        Outcome::Dead => format!(
+            "💀 {} rolled {}. The third failure... {} draws their last breath.",
+            name,
+            rolled(roll),
+            name
+        ),
+    }
+}
@@ -1,6 +1,162 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local, Utc};
+use futures::{future, future::BoxFuture, FutureExt, StreamExt};
 use poise::serenity_prelude as serenity;
+use tabletop_bot::roll;
+
+use crate::{
+    characters,
+    db::{DbStats, MvpResult, Player, ScheduledMessage, SessionSummary},
+    locale::Locale,
+    Context, Error,
+};
+
+type NickKey = (Option<serenity::GuildId>, serenity::UserId);
+
+/// A short-lived cache of resolved nicknames, to avoid calling the Discord API for every player
+/// each time a listing is rendered.
+pub(crate) struct NickCache {
+    ttl: Duration,
+    nicks: RwLock<HashMap<NickKey, (String, Instant)>>,
+}
+
+impl NickCache {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            nicks: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Gets a cached nickname, if it hasn't expired.
+    pub(crate) fn get(&self, key: &NickKey) -> Option<String> {
+        let nicks = self.nicks.read().expect("Unable to read nick cache");
+        match nicks.get(key) {
+            Some((nick, cached_at)) if cached_at.elapsed() < self.ttl => Some(nick.clone()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn insert(&self, key: NickKey, nick: String) {
+        let mut nicks = self.nicks.write().expect("Unable to write nick cache");
+        // Expired entries are only dropped on insert, which is enough to keep the cache bounded
+        // by the number of players.
+        nicks.retain(|_, (_, cached_at)| cached_at.elapsed() < self.ttl);
+        nicks.insert(key, (nick, Instant::now()));
+    }
+
+    /// Forgets a nickname, e.g. when the member changes it.
+    pub(crate) fn invalidate(&self, key: &NickKey) {
+        self.nicks
+            .write()
+            .expect("Unable to write nick cache")
+            .remove(key);
+    }
+}
+
+/// Who granted how much experience to which player.
+type GrantKey = (serenity::UserId, i64, u32);
+
+/// Remembers recent experience grants, to catch a GM granting the same thing twice by accident,
+/// e.g. by double-clicking.
+pub(crate) struct RecentGrants {
+    window: Duration,
+    grants: RwLock<HashMap<GrantKey, Instant>>,
+}
+
+impl RecentGrants {
+    pub(crate) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            grants: RwLock::new(HashMap::new()),
+        }
+    }
 
-use crate::{Context, Error};
+    /// Records a grant, returning whether the same grant was already made within the window.
+    pub(crate) fn record(&self, key: GrantKey, now: Instant) -> bool {
+        let mut grants = self.grants.write().expect("Unable to write recent grants");
+        grants.retain(|_, granted_at| now.duration_since(*granted_at) < self.window);
+        grants.insert(key, now).is_some()
+    }
+}
+
+/// What's known about an interaction that's already been seen.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Processed {
+    /// It's still being processed, and will be replied to when it's done.
+    Running,
+    Replied(String),
+}
+
+/// Remembers the replies to recent commands that change something, by interaction id, so an
+/// interaction Discord retries after a slow response is answered again rather than applied
+/// twice. Only the most recent `capacity` interactions are remembered.
+pub(crate) struct ProcessedInteractions {
+    capacity: usize,
+    seen: Mutex<(HashMap<u64, Processed>, VecDeque<u64>)>,
+}
+
+impl ProcessedInteractions {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            seen: Mutex::new((HashMap::new(), VecDeque::new())),
+        }
+    }
+
+    /// Claims an interaction before it's processed, returning `None` the first time it's seen,
+    /// or what's known about it when it's a retry.
+    pub(crate) fn claim(&self, id: u64) -> Option<Processed> {
+        let mut seen = self
+            .seen
+            .lock()
+            .expect("Unable to lock processed interactions");
+        let (processed, order) = &mut *seen;
+        if let Some(known) = processed.get(&id) {
+            return Some(known.clone());
+        }
+
+        processed.insert(id, Processed::Running);
+        order.push_back(id);
+        while order.len() > self.capacity {
+            if let Some(oldest) = order.pop_front() {
+                processed.remove(&oldest);
+            }
+        }
+        None
+    }
+
+    /// Records the reply to a claimed interaction, for any retries of it.
+    pub(crate) fn reply(&self, id: u64, reply: &str) {
+        let mut seen = self
+            .seen
+            .lock()
+            .expect("Unable to lock processed interactions");
+        if let Some(known) = seen.0.get_mut(&id) {
+            *known = Processed::Replied(reply.to_string());
+        }
+    }
+
+    /// Gives up the claim on an interaction that finished without a reply to record, e.g.
+    /// because it failed or changed nothing, so a retry of it runs again rather than being
+    /// ignored. Interactions that were replied to are kept.
+    pub(crate) fn release(&self, id: u64) {
+        let mut seen = self
+            .seen
+            .lock()
+            .expect("Unable to lock processed interactions");
+        let (processed, order) = &mut *seen;
+        if processed.get(&id) == Some(&Processed::Running) {
+            processed.remove(&id);
+            order.retain(|seen| *seen != id);
+        }
+    }
+}
 
 /// Gets a user by id from Discord.
 pub(crate) async fn get_user(ctx: Context<'_>, id: &i64) -> Result<serenity::User, Error> {
@@ -11,8 +167,176 @@ pub(crate) async fn get_user(ctx: Context<'_>, id: &i64) -> Result<serenity::Use
         .map_err(|e| e.into())
 }
 
+/// Where nicknames are looked up when they aren't cached. Discord is the only real source; the
+/// trait lets tests count lookups without it.
+pub(crate) trait NickLookup {
+    /// Fetches a user's nickname for a guild, or their name.
+    fn nick(
+        &self,
+        guild_id: Option<serenity::GuildId>,
+        id: i64,
+    ) -> BoxFuture<'_, Result<String, Error>>;
+}
+
+impl NickLookup for Context<'_> {
+    fn nick(
+        &self,
+        guild_id: Option<serenity::GuildId>,
+        id: i64,
+    ) -> BoxFuture<'_, Result<String, Error>> {
+        let ctx = *self;
+        async move {
+            // Serenity checks its own cache before calling Discord, and a member includes its
+            // user, so the user is only fetched separately when they aren't a member.
+            match guild_id {
+                Some(guild_id) => {
+                    match guild_id.member(ctx, serenity::UserId::new(id as u64)).await {
+                        Ok(member) => Ok(member.nick.unwrap_or(member.user.name)),
+                        Err(e) => Ok(non_member_name(get_user(ctx, &id).await?, &e)),
+                    }
+                }
+                None => Ok(get_user(ctx, &id).await?.name),
+            }
+        }
+        .boxed()
+    }
+}
+
+/// Gets a user's nickname for the current guild, or defaults to name, by id. Cached nicknames
+/// are used without fetching the user from Discord.
+pub(crate) async fn get_nick_or_name_by_id(ctx: Context<'_>, id: &i64) -> Result<String, Error> {
+    cached_nick(&ctx.data().nicks, &ctx, ctx.guild_id(), *id).await
+}
+
+/// Gets a user's nickname from the cache, looking it up and caching it on a miss.
+async fn cached_nick(
+    nicks: &NickCache,
+    lookup: &impl NickLookup,
+    guild_id: Option<serenity::GuildId>,
+    id: i64,
+) -> Result<String, Error> {
+    let key = (guild_id, serenity::UserId::new(id as u64));
+    if let Some(nick) = nicks.get(&key) {
+        return Ok(nick);
+    }
+
+    let nick = lookup.nick(guild_id, id).await?;
+    nicks.insert(key, nick.clone());
+    Ok(nick)
+}
+
+/// The most members Discord lists in one request.
+const MEMBER_LIST_LIMIT: u64 = 1000;
+
+/// Gets several users' nicknames for the current guild, or their names, keyed by id. Rather than
+/// fetching each member, the guild's members are listed in one request, and only users missing
+/// from it, e.g. those who've left, are fetched one by one. Listing members needs the Server
+/// Members intent enabled for the bot; without it, everyone is fetched one by one.
+pub(crate) async fn resolve_nicks(
+    ctx: Context<'_>,
+    ids: &[i64],
+) -> Result<HashMap<i64, String>, Error> {
+    let mut nicks = HashMap::new();
+    let mut misses = Vec::new();
+    for &id in ids {
+        match ctx
+            .data()
+            .nicks
+            .get(&(ctx.guild_id(), serenity::UserId::new(id as u64)))
+        {
+            Some(nick) => {
+                nicks.insert(id, nick);
+            }
+            None => misses.push(id),
+        }
+    }
+
+    // A single miss is fetched as cheaply as the list.
+    if let Some(guild_id) = ctx.guild_id().filter(|_| misses.len() > 1) {
+        match guild_id.members(ctx, Some(MEMBER_LIST_LIMIT), None).await {
+            Ok(members) => {
+                let listed = nicks_of_members(&misses, &members);
+                for (id, nick) in &listed {
+                    let key = (Some(guild_id), serenity::UserId::new(*id as u64));
+                    ctx.data().nicks.insert(key, nick.clone());
+                }
+                misses.retain(|id| !listed.contains_key(id));
+                nicks.extend(listed);
+            }
+            Err(e) => log::warn!("Error listing the members of {}: {}", guild_id, e),
+        }
+    }
+
+    let fetched =
+        future::try_join_all(misses.iter().map(|id| get_nick_or_name_by_id(ctx, id))).await?;
+    nicks.extend(misses.into_iter().zip(fetched));
+    Ok(nicks)
+}
+
+/// Lists the members of a guild who have a role, leaving out bots. Discord lists members a page at
+/// a time, so big guilds take a request per [`MEMBER_LIST_LIMIT`] members. Like
+/// [`resolve_nicks`], this needs the Server Members intent, and fails with a
+/// [forbidden](is_forbidden) error without it.
+pub(crate) async fn members_with_role(
+    ctx: Context<'_>,
+    guild_id: serenity::GuildId,
+    role_id: serenity::RoleId,
+) -> serenity::Result<Vec<serenity::UserId>> {
+    let mut with_role = Vec::new();
+    let mut after = None;
+    loop {
+        let members = guild_id
+            .members(ctx, Some(MEMBER_LIST_LIMIT), after)
+            .await?;
+        with_role.extend(
+            members
+                .iter()
+                .filter(|member| !member.user.bot && member.roles.contains(&role_id))
+                .map(|member| member.user.id),
+        );
+        match members.last() {
+            Some(last) if members.len() as u64 == MEMBER_LIST_LIMIT => after = Some(last.user.id),
+            _ => return Ok(with_role),
+        }
+    }
+}
+
+/// The nicknames, or names, of the members with any of `ids`, keyed by id.
+fn nicks_of_members(ids: &[i64], members: &[serenity::Member]) -> HashMap<i64, String> {
+    let ids = ids.iter().copied().collect::<HashSet<_>>();
+    members
+        .iter()
+        .filter(|member| ids.contains(&(member.user.id.get() as i64)))
+        .map(|member| {
+            let nick = member
+                .nick
+                .clone()
+                .unwrap_or_else(|| member.user.name.clone());
+            (member.user.id.get() as i64, nick)
+        })
+        .collect()
+}
+
+/// Gets a player's name for lists, preferring their character's name. See
+/// [`characters::display_name`].
+pub(crate) async fn get_player_name(ctx: Context<'_>, player: &Player) -> Result<String, Error> {
+    let nick = get_nick_or_name_by_id(ctx, &player.id).await?;
+    Ok(characters::display_name(player, &nick))
+}
+
 /// Gets a user's nickname for the current guild, or defaults to name, from Discord.
 pub(crate) async fn get_nick_or_name(ctx: Context<'_>, user: serenity::User) -> String {
+    let key = (ctx.guild_id(), user.id);
+    if let Some(nick) = ctx.data().nicks.get(&key) {
+        return nick;
+    }
+
+    let nick = fetch_nick_or_name(ctx, user).await;
+    ctx.data().nicks.insert(key, nick.clone());
+    nick
+}
+
+async fn fetch_nick_or_name(ctx: Context<'_>, user: serenity::User) -> String {
     if let Some(guild_id) = ctx.guild_id() {
         if log::log_enabled!(log::Level::Debug) {
             if let Some(guild) = guild_id.to_guild_cached(&ctx) {
@@ -23,16 +347,664 @@ pub(crate) async fn get_nick_or_name(ctx: Context<'_>, user: serenity::User) ->
                 );
             }
         }
-        user.nick_in(&ctx.serenity_context(), guild_id)
-            .await
-            .unwrap_or(user.name)
+        match guild_id.member(ctx, user.id).await {
+            Ok(member) => member.nick.unwrap_or(user.name),
+            Err(e) => non_member_name(user, &e),
+        }
     } else {
         user.name
     }
 }
 
+/// The name to show for a user whose guild member couldn't be fetched.
+fn non_member_name(user: serenity::User, e: &serenity::Error) -> String {
+    if is_unknown_member(e) {
+        return format!("{} (left server)", user.name);
+    }
+
+    log::warn!("Error getting member {}: {}", user.name, e);
+    user.name
+}
+
+/// Whether Discord refused a request, e.g. to list members without the Server Members intent.
+pub(crate) fn is_forbidden(e: &serenity::Error) -> bool {
+    match e {
+        serenity::Error::Http(e) => e.status_code() == Some(serenity::StatusCode::FORBIDDEN),
+        _ => false,
+    }
+}
+
+/// Whether Discord responded that the user isn't a member of the guild.
+fn is_unknown_member(e: &serenity::Error) -> bool {
+    match e {
+        serenity::Error::Http(e) => e.status_code() == Some(serenity::StatusCode::NOT_FOUND),
+        _ => false,
+    }
+}
+
+/// The maximum number of characters Discord allows in a message.
+pub(crate) const MESSAGE_LIMIT: usize = 2000;
+
+/// The maximum number of characters Discord allows in an embed's description.
+pub(crate) const EMBED_DESCRIPTION_LIMIT: usize = 4096;
+
+/// The maximum number of suggestions Discord accepts for an autocomplete.
+pub(crate) const AUTOCOMPLETE_LIMIT: usize = 25;
+
+/// Parses a user id from a mention like <@123> or <@!123>, or a bare id.
+pub(crate) fn parse_user_id(value: &str) -> Option<serenity::UserId> {
+    value
+        .trim()
+        .trim_start_matches("<@")
+        .trim_start_matches('!')
+        .trim_end_matches('>')
+        .parse::<u64>()
+        .ok()
+        .filter(|id| *id != 0)
+        .map(serenity::UserId::new)
+}
+
+/// Parses a hex color like `#ff8800` or `ff8800` into its RGB value.
+pub(crate) fn parse_color(value: &str) -> Option<u32> {
+    let hex = value.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    u32::from_str_radix(hex, 16).ok()
+}
+
+/// Builds the embed a roll is shown in, in the color the player picked with `/myroll`, if any.
+pub(crate) fn roll_embed(line: &str, color: Option<u32>) -> serenity::CreateEmbed {
+    let embed = serenity::CreateEmbed::new().description(line);
+    match color {
+        Some(color) => embed.colour(color),
+        None => embed,
+    }
+}
+
+/// A roll's message after it's rolled again: each earlier result struck out, then the new one.
+/// The earliest results are left out when they don't all fit in `limit` characters.
+pub(crate) fn reroll_content(previous: &str, new: &str, limit: usize) -> String {
+    let mut lines = previous
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| format!("~~{}~~", line.replace("~~", "")))
+        .collect::<Vec<_>>();
+    lines.push(new.to_string());
+    while lines.len() > 1 && lines.join("\n").chars().count() > limit {
+        lines.remove(0);
+    }
+
+    lines.join("\n")
+}
+
+/// Parses every user mentioned in text like `<@123> and <@!456>`, in order. Anyone mentioned twice
+/// is only listed once.
+pub(crate) fn parse_mentions(text: &str) -> Vec<serenity::UserId> {
+    let mut users = Vec::new();
+    for user in text
+        .split('<')
+        .filter_map(|mention| mention.split_once('>'))
+        .filter_map(|(mention, _)| mention.strip_prefix('@'))
+        .filter_map(parse_user_id)
+    {
+        if !users.contains(&user) {
+            users.push(user);
+        }
+    }
+
+    users
+}
+
+/// Splits lines into pages of at most `limit` characters, each ending in a "Page i/n" indicator
+/// when there's more than one page. Blank lines are skipped.
+pub(crate) fn paginate(lines: &[String], limit: usize) -> Vec<String> {
+    // Leave room for the page indicator, e.g. "\n\nPage 10/12".
+    let limit = limit - 32;
+    let mut pages: Vec<String> = Vec::new();
+    let mut page = String::new();
+
+    for line in lines
+        .iter()
+        .map(|line| line.trim())
+        .filter(|l| !l.is_empty())
+    {
+        if !page.is_empty() && page.len() + line.len() + 1 > limit {
+            pages.push(std::mem::take(&mut page));
+        }
+        if !page.is_empty() {
+            page.push('\n');
+        }
+        page.push_str(line);
+    }
+
+    if !page.is_empty() {
+        pages.push(page);
+    }
+
+    if pages.len() > 1 {
+        let count = pages.len();
+        for (i, page) in pages.iter_mut().enumerate() {
+            page.push_str(&format!("\n\nPage {}/{}", i + 1, count));
+        }
+    }
+
+    pages
+}
+
+/// Sends pages as a single message, or with navigation buttons if there's more than one.
+pub(crate) async fn send_pages(ctx: Context<'_>, pages: &[String]) -> Result<(), Error> {
+    match pages {
+        [] => {}
+        [page] => {
+            ctx.say(page).await?;
+        }
+        pages => {
+            let pages = pages.iter().map(String::as_str).collect::<Vec<_>>();
+            poise::builtins::paginate(ctx, &pages).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Splits content into messages of at most `limit` characters, breaking between lines. A line
+/// that's too long for a message on its own is split wherever it has to be.
+pub(crate) fn split_message(content: &str, limit: usize) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut message = String::new();
+    let mut len = 0;
+
+    for mut line in content.lines() {
+        while let Some((split, _)) = line.char_indices().nth(limit) {
+            if !message.is_empty() {
+                messages.push(std::mem::take(&mut message));
+                len = 0;
+            }
+            messages.push(line[..split].to_string());
+            line = &line[split..];
+        }
+
+        let line_len = line.chars().count();
+        if !message.is_empty() && len + 1 + line_len > limit {
+            messages.push(std::mem::take(&mut message));
+            len = 0;
+        }
+        // Blank lines at the start of a message would only push it down.
+        if message.is_empty() && line.trim().is_empty() {
+            continue;
+        }
+        if !message.is_empty() {
+            message.push('\n');
+            len += 1;
+        }
+        message.push_str(line);
+        len += line_len;
+    }
+
+    if !message.is_empty() {
+        messages.push(message);
+    }
+
+    messages
+}
+
+/// Sends content that may be too long for one message as several, in order. Returns the first
+/// message, if there was anything to send.
+pub(crate) async fn send_chunked<'a>(
+    ctx: Context<'a>,
+    content: &str,
+) -> Result<Option<poise::ReplyHandle<'a>>, Error> {
+    let mut first = None;
+    for message in split_message(content, MESSAGE_LIMIT) {
+        let reply = ctx.say(message).await?;
+        first.get_or_insert(reply);
+    }
+
+    Ok(first)
+}
+
+/// How long the invoking user has to answer a confirmation prompt.
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Asks the invoking user to confirm a destructive action with Confirm and Cancel buttons. Only
+/// the invoking user can answer; returns `false` if they cancel or the prompt times out.
+pub(crate) async fn confirm(ctx: Context<'_>, prompt: &str) -> Result<bool, Error> {
+    let ctx_id = ctx.id().to_string();
+    let confirm_id = format!("{}confirm", ctx_id);
+    let cancel_id = format!("{}cancel", ctx_id);
+
+    let buttons = serenity::CreateActionRow::Buttons(vec![
+        serenity::CreateButton::new(&confirm_id)
+            .label("Confirm")
+            .style(serenity::ButtonStyle::Danger),
+        serenity::CreateButton::new(&cancel_id)
+            .label("Cancel")
+            .style(serenity::ButtonStyle::Secondary),
+    ]);
+    let reply = ctx
+        .send(
+            poise::CreateReply::default()
+                .content(prompt)
+                .components(vec![buttons]),
+        )
+        .await?;
+
+    let mut presses = serenity::ComponentInteractionCollector::new(ctx)
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id))
+        .timeout(CONFIRM_TIMEOUT)
+        .stream();
+
+    while let Some(press) = presses.next().await {
+        if press.user.id != ctx.author().id {
+            let response = serenity::CreateInteractionResponseMessage::new()
+                .content("Only the person who ran the command can answer this.")
+                .ephemeral(true);
+            press
+                .create_response(ctx, serenity::CreateInteractionResponse::Message(response))
+                .await?;
+            continue;
+        }
+
+        let confirmed = press.data.custom_id == confirm_id;
+        let outcome = if confirmed {
+            "Confirmed."
+        } else {
+            "Cancelled."
+        };
+        let response = serenity::CreateInteractionResponseMessage::new()
+            .content(format!("{}\n{}", prompt, outcome))
+            .components(vec![]);
+        press
+            .create_response(
+                ctx,
+                serenity::CreateInteractionResponse::UpdateMessage(response),
+            )
+            .await?;
+
+        return Ok(confirmed);
+    }
+
+    reply
+        .edit(
+            ctx,
+            poise::CreateReply::default()
+                .content(format!("{}\nTimed out, nothing was changed.", prompt))
+                .components(vec![]),
+        )
+        .await?;
+
+    Ok(false)
+}
+
+/// Reacts to a reply with an emoji. Failures, e.g. when the bot lacks the Add Reactions
+/// permission, are logged rather than surfaced to the user.
+pub(crate) async fn react(ctx: Context<'_>, reply: &poise::ReplyHandle<'_>, emoji: &str) {
+    let reaction = match serenity::ReactionType::try_from(emoji) {
+        Ok(reaction) => reaction,
+        Err(e) => {
+            log::warn!("Invalid reaction {}: {}", emoji, e);
+            return;
+        }
+    };
+
+    let reacted = match reply.message().await {
+        Ok(msg) => msg.react(ctx, reaction).await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = reacted {
+        log::warn!("Unable to react with {}: {}", emoji, e);
+    }
+}
+
+/// Posts to a channel, e.g. a guild's announcement channel. When that fails, e.g. because the
+/// channel was deleted or the bot can't send messages there, it's replied in the invoking
+/// channel instead, with a warning.
+pub(crate) async fn announce(
+    ctx: Context<'_>,
+    channel_id: u64,
+    content: &str,
+) -> Result<(), Error> {
+    let channel = serenity::ChannelId::new(channel_id);
+    let sent = channel
+        .send_message(
+            ctx,
+            serenity::CreateMessage::new()
+                .content(content)
+                .allowed_mentions(serenity::CreateAllowedMentions::new()),
+        )
+        .await;
+    if let Err(e) = sent {
+        log::warn!("Unable to announce in channel {}: {}", channel_id, e);
+        ctx.send(
+            poise::CreateReply::default()
+                .content(format!(
+                    "⚠️ Couldn't post in <#{}>, so here it is instead:\n{}",
+                    channel_id, content
+                ))
+                .allowed_mentions(serenity::CreateAllowedMentions::new()),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// DMs a user. Users who can't be DMed, e.g. because they've closed their DMs to the server, are
+/// common, so failing is only logged.
+pub(crate) async fn notify(
+    http: impl serenity::CacheHttp,
+    user_id: serenity::UserId,
+    content: &str,
+) {
+    let sent = user_id
+        .direct_message(http, serenity::CreateMessage::new().content(content))
+        .await;
+    if let Err(e) = sent {
+        log::warn!("Unable to DM user {}: {}", user_id, e);
+    }
+}
+
+/// Describes an experience grant for the announcement channel, like
+/// `+300 XP to Valeros (Dave) — defeated the wight (granted by Sam)`.
+pub(crate) fn format_xp_grant(
+    experience: u32,
+    name: &str,
+    reason: Option<&str>,
+    granted_by: &str,
+) -> String {
+    match reason {
+        Some(reason) => format!(
+            "+{} XP to {} — {} (granted by {})",
+            experience, name, reason, granted_by
+        ),
+        None => format!("+{} XP to {} (granted by {})", experience, name, granted_by),
+    }
+}
+
+/// Builds the embed for a scheduled message, or `None` if it should be sent as plain text.
+pub(crate) fn schedule_embed(sch: &ScheduledMessage) -> Option<serenity::CreateEmbed> {
+    if sch.title.is_none() && sch.image_url.is_none() {
+        return None;
+    }
+
+    let mut embed = serenity::CreateEmbed::new().description(&sch.msg).field(
+        "When",
+        format!("<t:{}:R>", sch.on.timestamp()),
+        false,
+    );
+
+    if let Some(title) = &sch.title {
+        embed = embed.title(title);
+    }
+
+    if let Some(image_url) = &sch.image_url {
+        embed = embed.image(image_url);
+    }
+
+    Some(embed)
+}
+
+/// The bot's status when nothing is scheduled.
+/// Longest option value logged in full.
+pub(crate) const LOGGED_OPTION_LEN: usize = 50;
+
+/// Shortens `s` to at most `max` characters, marking where it was cut.
+pub(crate) fn truncate(s: &str, max: usize) -> String {
+    match s.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", &s[..end]),
+        None => s.to_string(),
+    }
+}
+
+/// Formats a command's options for the log, like `dice="2d6" count=3`, with long strings
+/// truncated.
+pub(crate) fn format_options(options: &[serenity::ResolvedOption]) -> String {
+    options
+        .iter()
+        .map(|option| {
+            let value = match &option.value {
+                serenity::ResolvedValue::String(s) => {
+                    format!("{:?}", truncate(s, LOGGED_OPTION_LEN))
+                }
+                serenity::ResolvedValue::Autocomplete { value, .. } => {
+                    format!("{:?}", truncate(value, LOGGED_OPTION_LEN))
+                }
+                serenity::ResolvedValue::Integer(n) => n.to_string(),
+                serenity::ResolvedValue::Number(n) => n.to_string(),
+                serenity::ResolvedValue::Boolean(b) => b.to_string(),
+                serenity::ResolvedValue::User(user, _) => user.id.to_string(),
+                serenity::ResolvedValue::Channel(channel) => channel.id.to_string(),
+                serenity::ResolvedValue::Role(role) => role.id.to_string(),
+                serenity::ResolvedValue::Attachment(attachment) => {
+                    format!("{:?}", truncate(&attachment.filename, LOGGED_OPTION_LEN))
+                }
+                serenity::ResolvedValue::SubCommand(options)
+                | serenity::ResolvedValue::SubCommandGroup(options) => {
+                    format!("({})", format_options(options))
+                }
+                _ => "?".to_string(),
+            };
+            format!("{}={}", option.name, value)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub(crate) const IDLE_ACTIVITY: &str = "Rolling dice | /help";
+
+/// The bot's status for the next scheduled game, e.g. "Next game tomorrow at 18:00".
+pub(crate) fn activity_text(next_game: Option<DateTime<Local>>, now: DateTime<Local>) -> String {
+    let Some(on) = next_game.filter(|on| *on > now) else {
+        return IDLE_ACTIVITY.to_string();
+    };
+
+    let days = (on.date_naive() - now.date_naive()).num_days();
+    let when = match days {
+        0 => format!("today at {}", on.format("%H:%M")),
+        1 => format!("tomorrow at {}", on.format("%H:%M")),
+        2..=6 => on.format("%a %H:%M").to_string(),
+        _ => on.format("%a %-d %b").to_string(),
+    };
+
+    format!("Next game {}", when)
+}
+
+/// How long until something, in its two largest units, e.g. "2 days, 4 hours".
+pub(crate) fn countdown(until: chrono::Duration) -> String {
+    let plural = |n: i64, unit: &str| {
+        if n == 1 {
+            format!("1 {}", unit)
+        } else {
+            format!("{} {}s", n, unit)
+        }
+    };
+
+    let (days, hours, minutes) = (
+        until.num_days(),
+        until.num_hours() % 24,
+        until.num_minutes() % 60,
+    );
+    match (days, hours, minutes) {
+        (0, 0, 0) => "less than a minute".to_string(),
+        (0, 0, minutes) => plural(minutes, "minute"),
+        (0, hours, 0) => plural(hours, "hour"),
+        (0, hours, minutes) => format!("{}, {}", plural(hours, "hour"), plural(minutes, "minute")),
+        (days, 0, _) => plural(days, "day"),
+        (days, hours, _) => format!("{}, {}", plural(days, "day"), plural(hours, "hour")),
+    }
+}
+
+/// Builds the embed for `/dbstats`.
+pub(crate) fn stats_embed(
+    stats: &DbStats,
+    last_backup: Option<DateTime<Utc>>,
+) -> serenity::CreateEmbed {
+    let tables = stats
+        .row_counts
+        .iter()
+        .map(|(table, count)| format!("{}: {}", table, count))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let last_backup = match last_backup {
+        Some(on) => format!("<t:{}:R>", on.timestamp()),
+        None => "Never".to_string(),
+    };
+
+    serenity::CreateEmbed::new()
+        .title("Database")
+        .field("Size", format!("{} KiB", stats.size_bytes / 1024), true)
+        .field("Schema version", stats.schema_version.to_string(), true)
+        .field("Last backup", last_backup, true)
+        .field("Rows", tables, false)
+        .field("Integrity", format!("```\n{}\n```", stats.integrity), false)
+}
+
+/// Builds the embed summing up a session, given a line for each player's experience.
+pub(crate) fn session_embed(summary: &SessionSummary, lines: &[String]) -> serenity::CreateEmbed {
+    let earned = if summary.gains.iter().any(|gain| gain.gained() != 0) {
+        lines.join("\n")
+    } else {
+        "No one earned any experience this session.".to_string()
+    };
+
+    serenity::CreateEmbed::new()
+        .title("Session over")
+        .description(truncate(&earned, EMBED_DESCRIPTION_LIMIT - 1))
+        .field(
+            "Played",
+            format!(
+                "<t:{}:t> to <t:{}:t>",
+                summary.started_at.timestamp(),
+                summary.ended_at.timestamp()
+            ),
+            false,
+        )
+}
+
+/// What `/about` reports about the running bot.
+pub(crate) struct About {
+    pub version: &'static str,
+    pub git_hash: Option<&'static str>,
+    pub uptime: Duration,
+    pub latency: Duration,
+    pub guilds: usize,
+    pub schema_version: i64,
+    /// How many times each command has run, most used first.
+    pub command_counts: Vec<(String, u64)>,
+}
+
+/// Formats a duration as days, hours and minutes, e.g. "2d 3h 15m".
+pub(crate) fn format_uptime(uptime: Duration) -> String {
+    let minutes = uptime.as_secs() / 60;
+    let (days, hours, minutes) = (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+    if days > 0 {
+        format!("{}d {}h {}m", days, hours, minutes)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+/// How many of the most used commands `/about` lists.
+const ABOUT_TOP_COMMANDS: usize = 5;
+
+/// Builds the embed for `/about`.
+pub(crate) fn about_embed(about: &About) -> serenity::CreateEmbed {
+    let version = match about.git_hash {
+        Some(hash) => format!("{} ({})", about.version, hash),
+        None => about.version.to_string(),
+    };
+    // The gateway hasn't measured a heartbeat yet shortly after connecting.
+    let latency = if about.latency.is_zero() {
+        "Unknown".to_string()
+    } else {
+        format!("{} ms", about.latency.as_millis())
+    };
+    let commands = if about.command_counts.is_empty() {
+        "None yet".to_string()
+    } else {
+        about
+            .command_counts
+            .iter()
+            .take(ABOUT_TOP_COMMANDS)
+            .map(|(command, count)| format!("/{}: {}", command, count))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    serenity::CreateEmbed::new()
+        .title("About")
+        .field("Version", version, true)
+        .field("Uptime", format_uptime(about.uptime), true)
+        .field("Latency", latency, true)
+        .field("Servers", about.guilds.to_string(), true)
+        .field("Schema version", about.schema_version.to_string(), true)
+        .field("Commands run", commands, false)
+}
+
+/// Builds the embed for `/selfcheck`, from the report of what it found.
+pub(crate) fn selfcheck_embed(report: &str, ok: bool) -> serenity::CreateEmbed {
+    let title = if ok {
+        "Self-check passed"
+    } else {
+        "Self-check found problems"
+    };
+    serenity::CreateEmbed::new()
+        .title(title)
+        .description(truncate(report, EMBED_DESCRIPTION_LIMIT - 1))
+}
+
+/// Builds the embed for `/roll-help`, the dice syntax cheat sheet.
+pub(crate) fn syntax_embed() -> serenity::CreateEmbed {
+    serenity::CreateEmbed::new()
+        .title("Dice notation")
+        .description(roll::syntax_help())
+        .footer(serenity::CreateEmbedFooter::new(
+            "Natural 20s and 1s on a leading d20 are called out.",
+        ))
+}
+
+/// Builds the embed announcing an MVP, with the full vote tally.
+pub(crate) fn mvp_embed(title: &str, result: &MvpResult) -> serenity::CreateEmbed {
+    let name = |id: &i64| match result.characters.get(id) {
+        Some(character) => format!("{} (<@{}>)", character, id),
+        None => format!("<@{}>", id),
+    };
+    let winners = result
+        .winners
+        .iter()
+        .map(name)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let tally = result
+        .tally
+        .iter()
+        .map(|(id, votes)| {
+            let plural = if *votes == 1 { "" } else { "s" };
+            format!("{}: {} vote{}", name(id), votes, plural)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let winners_name = if result.winners.len() > 1 {
+        "Co-MVPs"
+    } else {
+        "MVP"
+    };
+
+    serenity::CreateEmbed::new()
+        .title(title)
+        .field(winners_name, winners, false)
+        .field("Votes", tally, false)
+}
+
 use std::fmt::Display;
 
+/// Shows a die's roll: bold when it counts towards the total, struck through when it was dropped
+/// or rerolled, like `[**5**, ~~2~~]`.
 pub(crate) struct RollDisplay<'a>(pub &'a evaluroll::ast::Roll);
 
 impl<'a> Display for RollDisplay<'a> {
@@ -41,26 +1013,474 @@ impl<'a> Display for RollDisplay<'a> {
         if roll.keep {
             write!(f, "**{}**", roll.result)
         } else {
-            write!(f, "{}", roll.result)
+            write!(f, "~~{}~~", roll.result)
         }
     }
 }
 
-pub(crate) struct Output<'a>(pub &'a evaluroll::ast::Output);
+/// Groups the digits of a number into thousands, e.g. `1234567` becomes `1,234,567` with a `,`
+/// separator.
+pub(crate) fn group_thousands(n: i32, separator: char) -> String {
+    let digits = n.unsigned_abs().to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3 + 1);
+
+    if n < 0 {
+        grouped.push('-');
+    }
+
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(separator);
+        }
+        grouped.push(digit);
+    }
+
+    grouped
+}
+
+pub(crate) struct Output<'a> {
+    pub output: &'a evaluroll::ast::Output,
+    /// Notes on dice whose rolls don't show what they're worth, listed after the rolls.
+    pub notes: &'a [roll::Note],
+    /// Whether the total should have its thousands grouped. Individual dice are never grouped.
+    pub group_thousands: bool,
+    pub locale: Locale,
+}
+
+impl Output<'_> {
+    /// The total, without the dice that make it up.
+    pub(crate) fn total(&self) -> String {
+        if self.group_thousands {
+            group_thousands(self.output.total, self.locale.thousands_separator())
+        } else {
+            self.output.total.to_string()
+        }
+    }
+}
 
 impl<'a> Display for Output<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
             "{} [{}]",
-            self.0.total,
-            self.0
+            self.total(),
+            self.output
                 .rolls
                 .iter()
                 .map(RollDisplay)
                 .map(|x| x.to_string())
+                .chain(self.notes.iter().map(ToString::to_string))
                 .collect::<Vec<_>>()
                 .join(", "),
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Looks up nicknames without Discord, counting the lookups.
+    #[derive(Default)]
+    struct FakeLookup {
+        looked_up: Mutex<Vec<i64>>,
+    }
+
+    impl FakeLookup {
+        fn looked_up(&self) -> Vec<i64> {
+            self.looked_up.lock().unwrap().clone()
+        }
+    }
+
+    impl NickLookup for FakeLookup {
+        fn nick(
+            &self,
+            guild_id: Option<serenity::GuildId>,
+            id: i64,
+        ) -> BoxFuture<'_, Result<String, Error>> {
+            self.looked_up.lock().unwrap().push(id);
+            let nick = match guild_id {
+                Some(guild_id) => format!("nick {} in {}", id, guild_id),
+                None => format!("user {}", id),
+            };
+            future::ok(nick).boxed()
+        }
+    }
+
+    const GUILD: Option<serenity::GuildId> = Some(serenity::GuildId::new(10));
+
+    fn key(id: u64) -> NickKey {
+        (GUILD, serenity::UserId::new(id))
+    }
+
+    #[test]
+    fn cached_nick_is_returned() {
+        let nicks = NickCache::new(Duration::from_secs(60));
+
+        assert_eq!(nicks.get(&key(1)), None);
+        nicks.insert(key(1), "Gimli".to_string());
+        assert_eq!(nicks.get(&key(1)), Some("Gimli".to_string()));
+        assert_eq!(nicks.get(&key(2)), None);
+    }
+
+    #[test]
+    fn expired_nick_is_a_miss() {
+        let nicks = NickCache::new(Duration::ZERO);
+        nicks.insert(key(1), "Gimli".to_string());
+
+        assert_eq!(nicks.get(&key(1)), None);
+    }
+
+    #[test]
+    fn invalidated_nick_is_a_miss() {
+        let nicks = NickCache::new(Duration::from_secs(60));
+        nicks.insert(key(1), "Gimli".to_string());
+        nicks.insert(key(2), "Legolas".to_string());
+        nicks.invalidate(&key(1));
+
+        assert_eq!(nicks.get(&key(1)), None);
+        assert_eq!(nicks.get(&key(2)), Some("Legolas".to_string()));
+    }
+
+    #[tokio::test]
+    async fn nick_is_looked_up_once_while_cached() {
+        let nicks = NickCache::new(Duration::from_secs(60));
+        let lookup = FakeLookup::default();
+
+        assert_eq!(
+            cached_nick(&nicks, &lookup, GUILD, 1).await.unwrap(),
+            "nick 1 in 10"
+        );
+        assert_eq!(
+            cached_nick(&nicks, &lookup, GUILD, 1).await.unwrap(),
+            "nick 1 in 10"
+        );
+        assert_eq!(lookup.looked_up(), vec![1]);
+    }
+
+    #[test]
+    fn grouping_thousands() {
+        assert_eq!(group_thousands(0, ','), "0");
+        assert_eq!(group_thousands(999, ','), "999");
+        assert_eq!(group_thousands(1000, ','), "1,000");
+        assert_eq!(group_thousands(1234567, ','), "1,234,567");
+        assert_eq!(group_thousands(123456, '.'), "123.456");
+    }
+
+    #[test]
+    fn grouping_negative_thousands() {
+        assert_eq!(group_thousands(-5, ','), "-5");
+        assert_eq!(group_thousands(-999, ','), "-999");
+        assert_eq!(group_thousands(-1000, ','), "-1,000");
+        assert_eq!(group_thousands(i32::MIN, ','), "-2,147,483,648");
+        assert_eq!(group_thousands(i32::MAX, ','), "2,147,483,647");
+    }
+
+    #[tokio::test]
+    async fn expired_nick_is_looked_up_again() {
+        let nicks = NickCache::new(Duration::ZERO);
+        let lookup = FakeLookup::default();
+        cached_nick(&nicks, &lookup, GUILD, 1).await.unwrap();
+        cached_nick(&nicks, &lookup, GUILD, 1).await.unwrap();
+
+        assert_eq!(lookup.looked_up(), vec![1, 1]);
+    }
+
+    #[tokio::test]
+    async fn invalidated_nick_is_looked_up_again() {
+        let nicks = NickCache::new(Duration::from_secs(60));
+        let lookup = FakeLookup::default();
+        cached_nick(&nicks, &lookup, GUILD, 1).await.unwrap();
+        cached_nick(&nicks, &lookup, GUILD, 2).await.unwrap();
+        // As when the member changes their nickname.
+        nicks.invalidate(&key(1));
+        cached_nick(&nicks, &lookup, GUILD, 1).await.unwrap();
+        cached_nick(&nicks, &lookup, GUILD, 2).await.unwrap();
+
+        assert_eq!(lookup.looked_up(), vec![1, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn nicks_are_cached_per_guild() {
+        let nicks = NickCache::new(Duration::from_secs(60));
+        let lookup = FakeLookup::default();
+        let other = Some(serenity::GuildId::new(11));
+
+        assert_eq!(
+            cached_nick(&nicks, &lookup, GUILD, 1).await.unwrap(),
+            "nick 1 in 10"
+        );
+        assert_eq!(
+            cached_nick(&nicks, &lookup, other, 1).await.unwrap(),
+            "nick 1 in 11"
+        );
+        assert_eq!(
+            cached_nick(&nicks, &lookup, None, 1).await.unwrap(),
+            "user 1"
+        );
+        assert_eq!(lookup.looked_up(), vec![1, 1, 1]);
+    }
+
+    fn embed_fields(embed: serenity::CreateEmbed) -> Vec<(String, String)> {
+        let embed = serde_json::to_value(embed).unwrap();
+        embed["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|field| {
+                let text = |key: &str| field[key].as_str().unwrap().to_string();
+                (text("name"), text("value"))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mvp_embed_shows_the_tally() {
+        let result = MvpResult {
+            winners: vec![2],
+            tally: vec![(2, 2), (3, 1)],
+            characters: HashMap::from([(3, "Vex".to_string())]),
+        };
+
+        assert_eq!(
+            embed_fields(mvp_embed("MVP", &result)),
+            vec![
+                ("MVP".to_string(), "<@2>".to_string()),
+                (
+                    "Votes".to_string(),
+                    "<@2>: 2 votes\nVex (<@3>): 1 vote".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn mvp_embed_lists_co_mvps() {
+        let result = MvpResult {
+            winners: vec![1, 2],
+            tally: vec![(1, 1), (2, 1)],
+            characters: HashMap::new(),
+        };
+
+        assert_eq!(
+            embed_fields(mvp_embed("MVP", &result))[0],
+            ("Co-MVPs".to_string(), "<@1>, <@2>".to_string())
+        );
+    }
+
+    #[test]
+    fn repeated_grant_inside_window() {
+        let grants = RecentGrants::new(Duration::from_secs(10));
+        let start = Instant::now();
+        let gm = serenity::UserId::new(1);
+
+        assert!(!grants.record((gm, 2, 100), start));
+        assert!(grants.record((gm, 2, 100), start + Duration::from_secs(9)));
+        // A different amount, target or GM isn't a repeat.
+        assert!(!grants.record((gm, 2, 50), start));
+        assert!(!grants.record((gm, 3, 100), start));
+        assert!(!grants.record((serenity::UserId::new(4), 2, 100), start));
+    }
+
+    #[test]
+    fn repeated_grant_outside_window() {
+        let grants = RecentGrants::new(Duration::from_secs(10));
+        let start = Instant::now();
+        let gm = serenity::UserId::new(1);
+
+        assert!(!grants.record((gm, 2, 100), start));
+        assert!(!grants.record((gm, 2, 100), start + Duration::from_secs(10)));
+        // The window restarts from the latest grant.
+        assert!(grants.record((gm, 2, 100), start + Duration::from_secs(15)));
+    }
+
+    #[test]
+    fn processed_interaction_is_claimed_once() {
+        let processed = ProcessedInteractions::new(10);
+
+        assert_eq!(processed.claim(1), None);
+        assert_eq!(processed.claim(1), Some(Processed::Running));
+        processed.reply(1, "Done");
+        assert_eq!(
+            processed.claim(1),
+            Some(Processed::Replied("Done".to_string()))
+        );
+        assert_eq!(processed.claim(2), None);
+    }
+
+    #[test]
+    fn oldest_processed_interaction_is_evicted() {
+        let processed = ProcessedInteractions::new(2);
+        for id in 1..=3 {
+            assert_eq!(processed.claim(id), None);
+        }
+
+        assert_eq!(processed.claim(3), Some(Processed::Running));
+        assert_eq!(processed.claim(2), Some(Processed::Running));
+        assert_eq!(processed.claim(1), None);
+    }
+
+    #[test]
+    fn reply_to_unclaimed_interaction_is_ignored() {
+        let processed = ProcessedInteractions::new(10);
+        processed.reply(1, "Done");
+
+        assert_eq!(processed.claim(1), None);
+    }
+
+    #[test]
+    fn released_interaction_runs_again() {
+        let processed = ProcessedInteractions::new(2);
+        processed.claim(1);
+        processed.release(1);
+
+        assert_eq!(processed.claim(1), None);
+        // Released ids are forgotten, so they don't evict what's claimed after them twice.
+        processed.release(1);
+        processed.claim(1);
+        processed.claim(2);
+        assert_eq!(processed.claim(1), Some(Processed::Running));
+    }
+
+    #[test]
+    fn replied_interaction_is_kept_on_release() {
+        let processed = ProcessedInteractions::new(10);
+        processed.claim(1);
+        processed.reply(1, "Done");
+        processed.release(1);
+
+        assert_eq!(
+            processed.claim(1),
+            Some(Processed::Replied("Done".to_string()))
+        );
+    }
+
+    fn local(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        use chrono::TimeZone;
+        // 4 March 2024 is a Monday.
+        Local
+            .with_ymd_and_hms(2024, 3, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn activity_for_a_game_today_or_tomorrow() {
+        let now = local(4, 12, 0);
+
+        assert_eq!(
+            activity_text(Some(local(4, 19, 30)), now),
+            "Next game today at 19:30"
+        );
+        assert_eq!(
+            activity_text(Some(local(5, 9, 5)), now),
+            "Next game tomorrow at 09:05"
+        );
+    }
+
+    #[test]
+    fn activity_for_a_game_this_week() {
+        let now = local(4, 12, 0);
+
+        assert_eq!(
+            activity_text(Some(local(6, 19, 0)), now),
+            "Next game Wed 19:00"
+        );
+        assert_eq!(
+            activity_text(Some(local(10, 19, 0)), now),
+            "Next game Sun 19:00"
+        );
+    }
+
+    #[test]
+    fn activity_for_a_game_more_than_a_week_away() {
+        assert_eq!(
+            activity_text(Some(local(11, 19, 0)), local(4, 12, 0)),
+            "Next game Mon 11 Mar"
+        );
+    }
+
+    #[test]
+    fn activity_without_an_upcoming_game() {
+        let now = local(4, 12, 0);
+
+        assert_eq!(activity_text(None, now), IDLE_ACTIVITY);
+        assert_eq!(activity_text(Some(local(4, 11, 0)), now), IDLE_ACTIVITY);
+        assert_eq!(activity_text(Some(now), now), IDLE_ACTIVITY);
+    }
+
+    #[test]
+    fn formatting_uptime() {
+        assert_eq!(format_uptime(Duration::from_secs(59)), "0m");
+        assert_eq!(format_uptime(Duration::from_secs(15 * 60)), "15m");
+        assert_eq!(format_uptime(Duration::from_secs(60 * 60)), "1h 0m");
+        assert_eq!(
+            format_uptime(Duration::from_secs(((2 * 24 + 3) * 60 + 15) * 60 + 30)),
+            "2d 3h 15m"
+        );
+    }
+
+    fn about() -> About {
+        About {
+            version: "1.2.3",
+            git_hash: Some("abc1234"),
+            uptime: Duration::from_secs(90 * 60),
+            latency: Duration::from_millis(42),
+            guilds: 3,
+            schema_version: 7,
+            command_counts: vec![("roll".to_string(), 12), ("exp".to_string(), 4)],
+        }
+    }
+
+    #[test]
+    fn about_embed_fields() {
+        assert_eq!(
+            embed_fields(about_embed(&about())),
+            [
+                ("Version", "1.2.3 (abc1234)"),
+                ("Uptime", "1h 30m"),
+                ("Latency", "42 ms"),
+                ("Servers", "3"),
+                ("Schema version", "7"),
+                ("Commands run", "/roll: 12\n/exp: 4"),
+            ]
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+        );
+    }
+
+    #[test]
+    fn about_embed_before_anything_is_measured() {
+        let about = About {
+            git_hash: None,
+            latency: Duration::ZERO,
+            command_counts: Vec::new(),
+            ..about()
+        };
+        let fields = embed_fields(about_embed(&about));
+
+        assert_eq!(fields[0].1, "1.2.3");
+        assert_eq!(fields[2].1, "Unknown");
+        assert_eq!(fields[5].1, "None yet");
+    }
+
+    #[test]
+    fn about_embed_lists_the_top_commands() {
+        let about = About {
+            command_counts: (0..ABOUT_TOP_COMMANDS as u64 + 2)
+                .map(|i| (format!("command{}", i), 100 - i))
+                .collect(),
+            ..about()
+        };
+        let fields = embed_fields(about_embed(&about));
+
+        assert_eq!(fields[5].1.lines().count(), ABOUT_TOP_COMMANDS);
+        assert!(fields[5].1.starts_with("/command0: 100\n"));
+    }
+
+    #[test]
+    fn truncating() {
+        assert_eq!(truncate("d20", 5), "d20");
+        assert_eq!(truncate("fireball", 4), "fire…");
+        assert_eq!(truncate("🎲🎲🎲", 2), "🎲🎲…");
+        assert_eq!(truncate("", 0), "");
+    }
+}
@@ -0,0 +1,62 @@
+use std::fmt::Display;
+
+use crate::db::DowntimeEntry;
+
+/// The most downtime days a grant or spend can be for.
+pub(crate) const MAX_DAYS: u32 = 365;
+
+/// The longest a downtime activity can be.
+pub(crate) const MAX_ACTIVITY_LEN: usize = 100;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Error {
+    EmptyActivity,
+    ActivityTooLong,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::EmptyActivity => write!(f, "Say what the days are spent on"),
+            Error::ActivityTooLong => write!(
+                f,
+                "An activity can be at most {} characters long",
+                MAX_ACTIVITY_LEN
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Trims an activity, checking it isn't blank or too long for the log.
+pub(crate) fn normalize_activity(activity: &str) -> Result<String, Error> {
+    let activity = activity.trim();
+    if activity.is_empty() {
+        return Err(Error::EmptyActivity);
+    }
+    if activity.chars().count() > MAX_ACTIVITY_LEN {
+        return Err(Error::ActivityTooLong);
+    }
+
+    Ok(activity.to_string())
+}
+
+/// A number of days, like `1 day` or `3 days`.
+pub(crate) fn days(count: i64) -> String {
+    if count == 1 {
+        "1 day".to_string()
+    } else {
+        format!("{} days", count)
+    }
+}
+
+/// Describes an entry in a player's log, like `<t:…:d> spent 3 days: crafting a longsword`.
+pub(crate) fn format_entry(entry: &DowntimeEntry) -> String {
+    let when = format!("<t:{}:d>", entry.recorded_at.timestamp());
+    match &entry.activity {
+        Some(activity) => format!("{} spent {}: {}", when, days(-entry.days), activity),
+        None if entry.days < 0 => format!("{} spent {}", when, days(-entry.days)),
+        None => format!("{} granted {}", when, days(entry.days)),
+    }
+}
@@ -0,0 +1,205 @@
+use std::{collections::HashMap, fmt::Display, sync::OnceLock};
+
+use rand::Rng;
+use serde_json::Value;
+
+use crate::{db::TableEntry, tables};
+
+/// The default generators, as JSON keyed by kind. Each has weighted `templates`, and the weighted
+/// `parts` their placeholders are filled in from. An entry is either its text, or an object with
+/// its `text` and `weight`.
+const DEFAULTS: &str = include_str!("generators.json");
+
+/// How deep placeholders are expanded within parts, so parts that refer to each other still end.
+const MAX_DEPTH: usize = 4;
+
+#[derive(Debug)]
+pub(crate) enum Error {
+    Json(serde_json::Error),
+    /// JSON that isn't shaped like generators, saying what's wrong.
+    Invalid(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Json(e) => write!(f, "Invalid JSON: {}", e),
+            Error::Invalid(what) => write!(f, "Invalid generators: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+/// What `/generate` can make.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, poise::ChoiceParameter)]
+pub(crate) enum Kind {
+    #[name = "npc"]
+    Npc,
+    #[name = "tavern"]
+    Tavern,
+    #[name = "town"]
+    Town,
+}
+
+impl Kind {
+    pub(crate) fn key(self) -> &'static str {
+        match self {
+            Kind::Npc => "npc",
+            Kind::Tavern => "tavern",
+            Kind::Town => "town",
+        }
+    }
+
+    /// The prefix of the random tables that override this kind's parts, like `tavern.animal`, or
+    /// its templates with `tavern.templates`.
+    pub(crate) fn table_prefix(self) -> String {
+        format!("{}.", self.key())
+    }
+}
+
+/// Templates like `The {adjective} {animal}`, and the parts their placeholders are filled in from.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Generator {
+    pub templates: Vec<TableEntry>,
+    pub parts: HashMap<String, Vec<TableEntry>>,
+}
+
+impl Generator {
+    /// Replaces the templates and parts with any overrides, keyed by part name, or `templates`.
+    pub(crate) fn with_overrides(&self, overrides: HashMap<String, Vec<TableEntry>>) -> Self {
+        let mut generator = self.clone();
+        for (name, entries) in overrides {
+            if name == "templates" {
+                generator.templates = entries;
+            } else {
+                generator.parts.insert(name, entries);
+            }
+        }
+
+        generator
+    }
+
+    /// Generates a result from one of the templates.
+    pub(crate) fn generate<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<String> {
+        let template = tables::draw(&self.templates, 1, rng).pop()?;
+        Some(expand(&template.text, &self.parts, rng))
+    }
+}
+
+/// Fills in a template's `{part}` placeholders with entries drawn from each part. Placeholders
+/// for parts that are missing or empty, or that aren't closed, are left as they are.
+pub(crate) fn expand<R: Rng + ?Sized>(
+    template: &str,
+    parts: &HashMap<String, Vec<TableEntry>>,
+    rng: &mut R,
+) -> String {
+    expand_at(template, parts, rng, 0)
+}
+
+fn expand_at<R: Rng + ?Sized>(
+    template: &str,
+    parts: &HashMap<String, Vec<TableEntry>>,
+    rng: &mut R,
+    depth: usize,
+) -> String {
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        expanded.push_str(&rest[..start]);
+        let placeholder = &rest[start..start + len + 1];
+        let name = &placeholder[1..placeholder.len() - 1];
+        rest = &rest[start + len + 1..];
+
+        let drawn = parts
+            .get(name)
+            .and_then(|entries| tables::draw(entries, 1, rng).pop());
+        match drawn {
+            Some(entry) if depth < MAX_DEPTH => {
+                expanded.push_str(&expand_at(&entry.text, parts, rng, depth + 1))
+            }
+            Some(entry) => expanded.push_str(&entry.text),
+            None => expanded.push_str(placeholder),
+        }
+    }
+    expanded.push_str(rest);
+
+    expanded
+}
+
+/// Parses generators from JSON keyed by kind. See [`DEFAULTS`].
+pub(crate) fn parse(json: &str) -> Result<HashMap<String, Generator>, Error> {
+    let Value::Object(kinds) = serde_json::from_str(json)? else {
+        return Err(Error::Invalid(
+            "they must be an object keyed by kind".to_string(),
+        ));
+    };
+    kinds
+        .into_iter()
+        .map(|(kind, generator)| {
+            let templates = parse_entries(&generator["templates"])
+                .ok_or_else(|| Error::Invalid(format!("{}'s templates must be a list", kind)))?;
+            let parts = match &generator["parts"] {
+                Value::Object(parts) => parts
+                    .iter()
+                    .map(|(name, entries)| {
+                        let entries = parse_entries(entries).ok_or_else(|| {
+                            Error::Invalid(format!("{}'s {} must be a list", kind, name))
+                        })?;
+                        Ok((name.clone(), entries))
+                    })
+                    .collect::<Result<HashMap<_, _>, Error>>()?,
+                Value::Null => HashMap::new(),
+                _ => {
+                    return Err(Error::Invalid(format!(
+                        "{}'s parts must be an object",
+                        kind
+                    )))
+                }
+            };
+
+            Ok((kind, Generator { templates, parts }))
+        })
+        .collect()
+}
+
+/// Parses a list of entries, each its text or an object with its `text` and `weight`.
+fn parse_entries(value: &Value) -> Option<Vec<TableEntry>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|entry| match entry {
+            Value::String(text) => Some(TableEntry {
+                weight: 1,
+                text: text.clone(),
+            }),
+            Value::Object(_) => Some(TableEntry {
+                weight: match &entry["weight"] {
+                    Value::Null => 1,
+                    weight => u32::try_from(weight.as_u64()?).ok()?,
+                },
+                text: entry["text"].as_str()?.to_string(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The default generator for a kind.
+pub(crate) fn default_generator(kind: Kind) -> &'static Generator {
+    static GENERATORS: OnceLock<HashMap<String, Generator>> = OnceLock::new();
+
+    GENERATORS
+        .get_or_init(|| parse(DEFAULTS).expect("the default generators are valid"))
+        .get(kind.key())
+        .expect("every kind has a default generator")
+}
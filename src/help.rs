@@ -0,0 +1,375 @@
+use tabletop_bot::roll;
+
+use crate::templates;
+
+const MVP_WORKFLOW: &str = "How MVP works: a GM registers players with /registerplayer, every \
+    player votes with /mvp, then a GM runs /resolve-mvp once everyone has voted. If the server \
+    has an mvp_channel setting, the MVP is also resolved there weekly. /mvp-reset throws the \
+    votes away without picking anyone.";
+
+pub(crate) fn exp() -> String {
+    "Adds experience to a registered player. Start typing a name to pick from the registered \
+    players, and give a `reason` to say what it's for. GM only.\n\n\
+    When the `xp_announce_channel` setting is set, every grant is also posted there, e.g. \
+    `+300 XP to Valeros — defeated the wight (granted by Sam)`. When `xp_require_reason` is \
+    true, grants without a reason are refused. Players who use `/notify on` are DMed about grants \
+    of at least the `xp_notify_threshold` setting."
+        .to_string()
+}
+
+pub(crate) fn experience() -> String {
+    "Lists the experience of every registered player, with pages for long lists. Players who \
+    have set up a character with `/character set` are listed by their character's name."
+        .to_string()
+}
+
+pub(crate) fn show_xp() -> String {
+    "Shows a player's experience. Right-click them and pick Apps, then Show XP.".to_string()
+}
+
+pub(crate) fn mvp() -> String {
+    format!(
+        "Votes for a player as this week's MVP, or right-click them and pick Apps, then Vote MVP. \
+        Voting again changes your vote.\n\n{}",
+        MVP_WORKFLOW
+    )
+}
+
+pub(crate) fn notify() -> String {
+    "Turns DMs on or off for things that concern you: winning MVP, your scheduled message being \
+    sent or failing to send, and experience grants of at least the server's \
+    `xp_notify_threshold`. They're off until you turn them on, and if your DMs are closed \
+    nothing is sent."
+        .to_string()
+}
+
+pub(crate) fn register_player() -> String {
+    format!(
+        "Registers a member as a player, so they can earn experience and vote for MVP. GM only.\n\n{}",
+        MVP_WORKFLOW
+    )
+}
+
+pub(crate) fn register_role() -> String {
+    "Registers every member with a role as a player at once, e.g. a Players role, skipping bots \
+    and anyone already registered. Members registered in another server are left there and \
+    counted separately. GM only.\n\n\
+    Listing the server's members needs the Server Members intent, which is turned on for the bot \
+    in the Discord developer portal. Without it, register players one at a time with \
+    `/registerplayer`."
+        .to_string()
+}
+
+pub(crate) fn character() -> String {
+    "Keeps track of the character each registered player is playing, so lists like \
+    `/experience` and the MVP results show e.g. `Valeros (Dave)` instead of just Discord names.\n\n\
+    `/character set Valeros Fighter 3 https://example.com/sheet` sets up your character, with an \
+    optional class and a link to their sheet. Running it again replaces all three, so include \
+    the class and link again to keep them. Names can be at most 60 characters, and links must \
+    start with http:// or https://.\n\n\
+    `/character show` shows your character, or someone else's with `player`."
+        .to_string()
+}
+
+pub(crate) fn resolve_mvp() -> String {
+    format!(
+        "Announces the MVP with the full vote tally, and clears the votes. Ties are co-MVPs. GM \
+        only.\n\n{}",
+        MVP_WORKFLOW
+    )
+}
+
+pub(crate) fn mvp_reset() -> String {
+    format!(
+        "Clears the current MVP votes without picking an MVP. GM only.\n\n{}",
+        MVP_WORKFLOW
+    )
+}
+
+pub(crate) fn roll() -> String {
+    format!(
+        "Rolls dice, e.g. `/roll 1d20+5`. Natural 20s and 1s on a leading d20 are called \
+        out. Press 🔁 Reroll on the result to roll it again, up to 3 times within 5 minutes; \
+        earlier results are struck out. Pick from your recent rolls as you type.\n\n\
+        Without any dice, `/roll` rolls the server's `default_dice` setting, e.g. 1d20, if it \
+        has one. Dice you give always take precedence over it. When the bot accepts text \
+        commands, `!roll 2d6+3` works too, with the server's `prefix` setting.\n\n\
+        A roll of a single die, like `1d20+5`, can add a message for its result from the \
+        server's `roll_triggers` setting, e.g. `{{\"1\": \"The gods frown upon you\"}}`.\n\n{}",
+        roll::syntax_help()
+    )
+}
+
+pub(crate) fn channelmode() -> String {
+    "Sets how rolls are shown in this channel. `compact` shows only the dice and the total, e.g. \
+    for a roleplay channel, `verbose` also shows every die, and `embed` shows that in an embed. \
+    Leave the mode out to use the server's `roll_mode` setting again, which is verbose unless \
+    it's been changed. Administrators only."
+        .to_string()
+}
+
+pub(crate) fn roll_help() -> String {
+    "Shows a cheat sheet of the dice notation /roll accepts, with an example of each feature. \
+    Only you can see it."
+        .to_string()
+}
+
+pub(crate) fn myroll() -> String {
+    "Sets how your rolls look. `/myroll color #ff8800` shows your single rolls in an embed with \
+    that accent color, and `/myroll color default` goes back to plain messages. Only you can \
+    see the reply."
+        .to_string()
+}
+
+pub(crate) fn roll_last() -> String {
+    "Rolls the dice from your last /roll again, optionally up to 10 times at once, e.g. for \
+    repeated attacks."
+        .to_string()
+}
+
+pub(crate) fn groupcheck() -> String {
+    "Rolls the same check for several players at once, each getting their own roll. \
+    `/groupcheck 1d20+2 @Ann @Bob @Cat` rolls for everyone mentioned, or use `all` for every \
+    registered player, up to 20 of them.\n\n\
+    Give a `dc` to mark who passed, and whether the group succeeds as a whole, which it does \
+    when at least half of them pass."
+        .to_string()
+}
+
+pub(crate) fn deck() -> String {
+    "Custom dice with labelled faces, for narrative dice or random tables. Define one with \
+    `/deck define elements Fire, Water, Earth, Air`, then roll it with `/deck roll elements`, \
+    optionally several times at once.\n\n\
+    To draw cards without replacement instead, `/deck shuffle elements` shuffles the faces into \
+    the channel's draw pile and `/deck draw` draws from it until it's empty. Shuffle again to \
+    start over."
+        .to_string()
+}
+
+pub(crate) fn table() -> String {
+    "Random tables for encounters, trinkets, weather and the like. `/table create weather` \
+    creates one, then `/table addentry weather 3 Clear skies` adds an entry with a weight of 3, \
+    making it three times as likely as an entry with a weight of 1. `/table import` adds a text \
+    file's lines as entries with a weight of 1 each.\n\n\
+    `/table roll weather` draws an entry, and `/table roll weather 3` draws three, without \
+    repeating any until every entry has come up. Entries with a weight of 0 are never drawn. \
+    `/table list` shows the server's tables or one table's entries, and `/table delete` deletes \
+    a table."
+        .to_string()
+}
+
+pub(crate) fn generate() -> String {
+    "Generates a random NPC, tavern or town for when the players go somewhere you didn't plan \
+    for, e.g. `/generate tavern`.\n\n\
+    Each is made from templates like `The {adjective} {animal}`, with every placeholder filled \
+    in from a list of parts. To use your own, create a random table named after the kind and \
+    part, like `tavern.animal`, and it replaces the built-in list. A `tavern.templates` table \
+    replaces the templates, and can use parts of your own."
+        .to_string()
+}
+
+pub(crate) fn timer() -> String {
+    "Counts down in this channel, e.g. for turn timers or breaks. `/timer start 5 Break` posts \
+    a countdown that's updated every 30 seconds, and pings you when the 5 minutes are up. Each \
+    channel can have one timer at a time, which `/timer cancel` stops. Timers keep running \
+    across restarts, and one that ended while the bot was offline is announced when it's back."
+        .to_string()
+}
+
+pub(crate) fn session() -> String {
+    "Sums up the experience everyone earned over a session. GM only.\n\n\
+    `/session start` notes each player's experience when the game starts, and `/session end` \
+    posts what each of them earned since, along with their new total. Players registered \
+    during the session count everything they earned in it."
+        .to_string()
+}
+
+pub(crate) fn downtime() -> String {
+    "Tracks the downtime days players have between sessions. A GM grants them with \
+    `/downtime grant 5 @Ann @Bob`, and `/downtime spend @Ann 3 crafting a longsword` spends \
+    them, as long as there are enough left.\n\n\
+    `/downtime show` lists how many days everyone has left, and `/downtime log @Ann` shows \
+    everything Ann has been granted and spent, newest first."
+        .to_string()
+}
+
+pub(crate) fn recap() -> String {
+    "Keeps recaps of past sessions, so everyone remembers where they left off. \
+    `/recap add` adds one for the session after the last recap, or for the session you give, \
+    and recaps can be up to 4000 characters long. `/recap last` shows the most recent one, \
+    `/recap list` lists them all, newest first, and `/recap search` finds the ones that \
+    mention something."
+        .to_string()
+}
+
+pub(crate) fn resource() -> String {
+    "Tracks limited resources like spell slots, ki points or uses of a magic item, per player.\n\n\
+    `/resource create spell_slots_3 3` gives you a full resource with a maximum of 3. Creating \
+    one with the same name again replaces it. `/resource use spell_slots_3` uses one, or more \
+    with `amount`, and won't use more than you have left. `/resource restore spell_slots_3` \
+    refills it, or restores `amount` of them, never past the maximum.\n\n\
+    `/resource show` lists everyone's resources by player, or one player's with `player`. \
+    Resources are restored by `/rest` unless created with `restore_on_rest: False`."
+        .to_string()
+}
+
+pub(crate) fn rest() -> String {
+    "Takes a rest, fully restoring every player's resources in this server, except those \
+    created with `restore_on_rest: False`. GM only."
+        .to_string()
+}
+
+pub(crate) fn deathsave() -> String {
+    "Rolls death saves for a dying player, D&D style, keeping the running tally.\n\n\
+    `/deathsave roll` rolls a d20 for you, or for `player`. 10 or more is a success and anything \
+    lower a failure. A natural 1 counts as two failures, and a natural 20 regains 1 hit point. \
+    Three successes and they're stable, three failures and they die. Either way, or on a natural \
+    20, the tally starts over.\n\n\
+    `/deathsave reset` clears the tally, e.g. when someone is healed before it's settled."
+        .to_string()
+}
+
+pub(crate) fn loot() -> String {
+    "Settles who gets what after a haul.\n\n\
+    `/loot split 100 3` splits 100 gp evenly between 3 players, or between everyone mentioned \
+    with e.g. `/loot split 100 @Ann @Bob @Cat`. Add `sp` and `cp` to split those too. Shares are \
+    broken into silver and copper as needed, and any copper that can't be split evenly is left \
+    over.\n\n\
+    `/loot roll Flame Tongue @Ann @Bob` gives an item to one of the mentioned players at random, \
+    showing the roll."
+        .to_string()
+}
+
+pub(crate) fn poll() -> String {
+    "Puts a question to a vote, e.g. which dungeon to do next. \
+    `/poll start Where next? Crypt Tower Swamp` posts the question with a numbered button for \
+    each of up to five options. Everyone gets one vote, which they can change by pressing \
+    another button, and the counts update as they vote.\n\n\
+    Give `minutes` to close the poll by itself, or close it early with `/poll close`, which \
+    closes your latest poll in the channel. The result is announced when it closes, and ties \
+    are called ties."
+        .to_string()
+}
+
+pub(crate) fn init() -> String {
+    "Tracks initiative for a fight in this channel. `/init start` starts one, then \
+    `/init add Goblin 2` rolls 1d20 + 2 for the goblin; pass `roll` instead for players who roll \
+    their own. Ties go to the higher modifier, then to a d20 roll-off.\n\n\
+    `/init next` moves on to the next turn, starting a new round after the last, and \
+    `/init list` shows the order. `/init remove` takes someone out, and `/init end` ends the \
+    fight. Each channel can run its own fight, and fights survive restarts."
+        .to_string()
+}
+
+pub(crate) fn condition() -> String {
+    "Tracks conditions on combatants in this channel's `/init` fight. \
+    `/condition add Goblin Frightened 2` frightens the goblin for 2 rounds; leave out the rounds \
+    for conditions that last until they're removed with `/condition remove`. Timed conditions \
+    count down each time `/init next` starts a new round, and the channel is told when they \
+    end. `/condition list` shows them all, and `/init list` shows them next to each combatant."
+        .to_string()
+}
+
+pub(crate) fn hp() -> String {
+    "Tracks hit points for combatants in this channel's `/init` fight. `/hp-set Goblin 7 12` gives \
+    the goblin 7 of 12 hit points; add `hidden: True` for monsters, so `/init list` only shows \
+    whether they're healthy or bloodied. `/damage` never goes below 0, and `remove: True` takes a \
+    hidden monster out of the fight when it drops. `/heal` stops at the maximum, unless it's \
+    `temp: True` temporary hit points."
+        .to_string()
+}
+
+pub(crate) fn schedule() -> String {
+    "Schedules a message to be sent to a channel, replacing any scheduled message. GM only.\n\n\
+    `on` is a date and time like `2024-05-01T19:30:00Z`, with a `Z` for UTC or an offset like \
+    `-04:00`. Add a title or an image URL to send the message as an embed. Instead of a \
+    message, you can send a template saved with `/template save`, whose placeholders are \
+    filled in when it's sent. If you use `/notify on`, you're DMed when it's sent, or if it \
+    can't be.\n\n\
+    With `dry_run`, nothing is saved: you're shown what would be sent, where, and when, in \
+    your timezone and in UTC, to catch a wrong offset before it's too late."
+        .to_string()
+}
+
+pub(crate) fn template() -> String {
+    let placeholders = templates::PLACEHOLDERS
+        .iter()
+        .map(|(name, description)| format!("- `{{{}}}`: {}", name, description))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "Saves messages for `/schedule` to send, for announcements that always have the same \
+        shape. `/template save name text` saves one, replacing any with the same name, \
+        `/template list` shows them and `/template delete name` deletes one. GM only.\n\n\
+        Placeholders in the text are filled in when the message is sent, so they're up to date \
+        however long ago it was scheduled. A placeholder that isn't one of these is refused \
+        when the template is saved:\n{}",
+        placeholders
+    )
+}
+
+pub(crate) fn next_game() -> String {
+    "Shows when the next scheduled game is, and how long until it starts.".to_string()
+}
+
+pub(crate) fn schedule_edit() -> String {
+    "Changes the scheduled message's channel, message or time, without scheduling it all over \
+    again. Only what you give is changed, and a new time has to be in the future. GM only.\n\n\
+    There's only ever one scheduled message, so there's no id to pick: this edits whatever \
+    `/schedule` last set up. To change its title, image or template, schedule it again."
+        .to_string()
+}
+
+pub(crate) fn schedule_fire() -> String {
+    "Sends the scheduled message right away instead of waiting, then clears the schedule like a \
+    normal send would. GM only."
+        .to_string()
+}
+
+pub(crate) fn settings() -> String {
+    "Shows or changes this server's settings with `/settings show`, `/settings set key value` \
+    and `/settings unset key`. Administrators only."
+        .to_string()
+}
+
+pub(crate) fn backup() -> String {
+    "Backs up the database, and attaches the backup if it's small enough.".to_string()
+}
+
+pub(crate) fn dbstats() -> String {
+    "Shows the database size, row counts and last backup, and checks its integrity.".to_string()
+}
+
+pub(crate) fn selfcheck() -> String {
+    "Checks that the scheduled message's channel and each server's `gm_role` and \
+    `xp_announce_channel` still exist and can be used, and runs a quick database integrity \
+    check. The same check runs at startup, DMing bot owners about any problems. Bot owners only."
+        .to_string()
+}
+
+pub(crate) fn about() -> String {
+    "Shows the bot's version, uptime, gateway latency, server count and database schema version."
+        .to_string()
+}
+
+pub(crate) fn connections() -> String {
+    "Shows how many database connections are open and idle.".to_string()
+}
+
+pub(crate) fn help() -> String {
+    "Lists every command, or explains one with `/help command`.".to_string()
+}
+
+pub(crate) fn sql() -> String {
+    "Runs a single SQL statement against the bot's database and shows the results, attaching \
+    them as a file when they're too long. Statements that change the database need `write: True`; \
+    PRAGMA, ATTACH, DETACH and VACUUM are never allowed. Every statement is logged. Bot owners \
+    only."
+        .to_string()
+}
+
+pub(crate) fn register() -> String {
+    "Re-syncs the bot's slash commands with Discord, in this server or globally, without a \
+    restart. Bot owners only."
+        .to_string()
+}
@@ -0,0 +1,437 @@
+use std::{cmp::Reverse, fmt::Display};
+
+use rand::Rng;
+
+use crate::db::{Combatant, Condition};
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Error {
+    EmptyName,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::EmptyName => write!(f, "A combatant needs a name"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Trims a combatant's name, which must not be blank.
+pub(crate) fn normalize_name(name: &str) -> Result<String, Error> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Error::EmptyName);
+    }
+
+    Ok(name.to_string())
+}
+
+/// Rolls a combatant's initiative, 1d20 plus their modifier, unless they rolled it themselves.
+/// Their d20 roll-off for breaking ties is rolled either way.
+pub(crate) fn roll<R: Rng + ?Sized>(
+    name: String,
+    modifier: i64,
+    rolled: Option<i64>,
+    rng: &mut R,
+) -> Combatant {
+    let initiative = rolled.unwrap_or_else(|| rng.gen_range(1..=20) + modifier);
+    Combatant {
+        name,
+        initiative,
+        modifier,
+        tiebreak: rng.gen_range(1..=20),
+        hp: None,
+        max_hp: None,
+        hidden_hp: false,
+    }
+}
+
+/// Sorts combatants into turn order: highest initiative first, with ties going to the higher
+/// modifier, then the higher roll-off.
+pub(crate) fn sort(combatants: &mut [Combatant]) {
+    combatants.sort_by_key(|c| {
+        (
+            Reverse(c.initiative),
+            Reverse(c.modifier),
+            Reverse(c.tiebreak),
+            c.name.clone(),
+        )
+    });
+}
+
+/// Whose turn it is after `current`'s, in the sorted `order`, and the round it's in. The first
+/// turn of a fight goes to the top of the order, and passing the end starts a new round.
+pub(crate) fn next_turn(
+    order: &[Combatant],
+    current: Option<&str>,
+    round: i64,
+) -> Option<(String, i64)> {
+    let first = order.first()?;
+    let Some(current) = current.and_then(|current| order.iter().position(|c| c.name == current))
+    else {
+        return Some((first.name.clone(), round));
+    };
+
+    match order.get(current + 1) {
+        Some(next) => Some((next.name.clone(), round)),
+        None => Some((first.name.clone(), round + 1)),
+    }
+}
+
+/// Whose turn it is once `removed` leaves the sorted `order`. Removing whoever's turn it is
+/// passes the turn on, as if they'd ended it.
+pub(crate) fn after_removal(
+    order: &[Combatant],
+    current: Option<&str>,
+    round: i64,
+    removed: &str,
+) -> (Option<String>, i64) {
+    if current != Some(removed) {
+        return (current.map(str::to_string), round);
+    }
+
+    match next_turn(order, current, round) {
+        Some((next, round)) if next != removed => (Some(next), round),
+        _ => (None, round),
+    }
+}
+
+/// Counts down every timed condition at the start of a new round, returning those still in
+/// effect and those that have ended.
+pub(crate) fn tick(conditions: Vec<Condition>) -> (Vec<Condition>, Vec<Condition>) {
+    conditions
+        .into_iter()
+        .map(|condition| Condition {
+            rounds: condition.rounds.map(|rounds| rounds - 1),
+            ..condition
+        })
+        .partition(|condition| condition.rounds.is_none_or(|rounds| rounds > 0))
+}
+
+/// Hit points after taking `amount` damage, which can't go below 0.
+pub(crate) fn damage(hp: i64, amount: i64) -> i64 {
+    (hp - amount).max(0)
+}
+
+/// Hit points after healing `amount`, which stops at the maximum unless the healing is temporary
+/// hit points. Healing never takes away temporary hit points already over the maximum.
+pub(crate) fn heal(hp: i64, max_hp: Option<i64>, amount: i64, temp: bool) -> i64 {
+    match max_hp {
+        Some(max_hp) if !temp => (hp + amount).min(max_hp.max(hp)),
+        _ => hp + amount,
+    }
+}
+
+/// Describes a combatant's hit points: exactly, or roughly when they're hidden so players can't
+/// see a monster's numbers. None until their hit points are set.
+pub(crate) fn format_hp(combatant: &Combatant) -> Option<String> {
+    let hp = combatant.hp?;
+    let readout = match (combatant.hidden_hp, combatant.max_hp) {
+        (_, _) if hp == 0 => "down".to_string(),
+        (true, Some(max_hp)) if hp * 2 <= max_hp => "bloodied".to_string(),
+        (true, _) => "healthy".to_string(),
+        (false, Some(max_hp)) => format!("{}/{} HP", hp, max_hp),
+        (false, None) => format!("{} HP", hp),
+    };
+
+    Some(readout)
+}
+
+/// Describes a condition and how long it has left, like `Frightened (2 rounds)`.
+pub(crate) fn format_condition(condition: &Condition) -> String {
+    match condition.rounds {
+        Some(1) => format!("{} (1 round)", condition.name),
+        Some(rounds) => format!("{} ({} rounds)", condition.name, rounds),
+        None => condition.name.clone(),
+    }
+}
+
+/// Lists the sorted `order` for chat with everyone's conditions, pointing at whoever's turn it
+/// is.
+pub(crate) fn format_order(
+    order: &[Combatant],
+    conditions: &[Condition],
+    current: Option<&str>,
+    round: i64,
+) -> String {
+    if order.is_empty() {
+        return "No one has joined the fight yet. Add combatants with /init add.".to_string();
+    }
+
+    let lines = order
+        .iter()
+        .map(|c| {
+            let marker = if current == Some(c.name.as_str()) {
+                "➡️"
+            } else {
+                "▫️"
+            };
+            let affecting = conditions
+                .iter()
+                .filter(|condition| condition.target == c.name)
+                .map(format_condition)
+                .collect::<Vec<_>>()
+                .join(", ");
+            [format_hp(c), Some(affecting).filter(|a| !a.is_empty())]
+                .into_iter()
+                .flatten()
+                .fold(
+                    format!("{} **{}** {}", marker, c.initiative, c.name),
+                    |line, detail| format!("{} — {}", line, detail),
+                )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!("Round {}\n{}", round, lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::mock::StepRng, SeedableRng};
+    use rand_hc::Hc128Rng;
+
+    use super::*;
+
+    fn combatant(name: &str, initiative: i64, modifier: i64, tiebreak: i64) -> Combatant {
+        Combatant {
+            name: name.to_string(),
+            initiative,
+            modifier,
+            tiebreak,
+            hp: None,
+            max_hp: None,
+            hidden_hp: false,
+        }
+    }
+
+    fn order() -> Vec<Combatant> {
+        vec![
+            combatant("Ayla", 18, 2, 5),
+            combatant("Goblin", 12, 2, 9),
+            combatant("Brom", 7, 0, 3),
+        ]
+    }
+
+    fn names(order: &[Combatant]) -> Vec<&str> {
+        order.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    #[test]
+    fn normalizing_names() {
+        assert_eq!(normalize_name("  Goblin 2 "), Ok("Goblin 2".to_string()));
+        assert_eq!(normalize_name(" "), Err(Error::EmptyName));
+    }
+
+    #[test]
+    fn rolling_initiative() {
+        let mut rng = Hc128Rng::seed_from_u64(1349);
+        for _ in 0..100 {
+            let c = roll("Goblin".to_string(), 3, None, &mut rng);
+            assert!((4..=23).contains(&c.initiative));
+            assert!((1..=20).contains(&c.tiebreak));
+        }
+    }
+
+    #[test]
+    fn rolled_initiative_is_kept() {
+        // StepRng always generates 0, the lowest roll.
+        let c = roll("Ayla".to_string(), 2, Some(17), &mut StepRng::new(0, 0));
+
+        assert_eq!(c.initiative, 17);
+        assert_eq!(c.modifier, 2);
+        assert_eq!(c.tiebreak, 1);
+    }
+
+    #[test]
+    fn sorting_breaks_ties() {
+        let mut order = vec![
+            combatant("Brom", 12, 0, 20),
+            combatant("Cade", 12, 2, 4),
+            combatant("Ayla", 15, -1, 1),
+            combatant("Dara", 12, 2, 11),
+            combatant("Zed", 12, 2, 11),
+            combatant("Eve", 12, 2, 11),
+        ];
+        sort(&mut order);
+
+        assert_eq!(
+            names(&order),
+            vec!["Ayla", "Dara", "Eve", "Zed", "Cade", "Brom"]
+        );
+    }
+
+    #[test]
+    fn first_turn_goes_to_the_top() {
+        assert_eq!(next_turn(&order(), None, 1), Some(("Ayla".to_string(), 1)));
+        // Someone who's since left is treated as no one.
+        assert_eq!(
+            next_turn(&order(), Some("Orc"), 2),
+            Some(("Ayla".to_string(), 2))
+        );
+        assert_eq!(next_turn(&[], None, 1), None);
+    }
+
+    #[test]
+    fn turns_pass_down_the_order() {
+        assert_eq!(
+            next_turn(&order(), Some("Ayla"), 1),
+            Some(("Goblin".to_string(), 1))
+        );
+        assert_eq!(
+            next_turn(&order(), Some("Goblin"), 1),
+            Some(("Brom".to_string(), 1))
+        );
+    }
+
+    #[test]
+    fn passing_the_end_starts_a_new_round() {
+        assert_eq!(
+            next_turn(&order(), Some("Brom"), 1),
+            Some(("Ayla".to_string(), 2))
+        );
+    }
+
+    #[test]
+    fn removing_someone_else_keeps_the_turn() {
+        assert_eq!(
+            after_removal(&order(), Some("Ayla"), 3, "Goblin"),
+            (Some("Ayla".to_string()), 3)
+        );
+        assert_eq!(after_removal(&order(), None, 1, "Goblin"), (None, 1));
+    }
+
+    #[test]
+    fn removing_the_current_combatant_passes_the_turn() {
+        assert_eq!(
+            after_removal(&order(), Some("Goblin"), 3, "Goblin"),
+            (Some("Brom".to_string()), 3)
+        );
+        assert_eq!(
+            after_removal(&order(), Some("Brom"), 3, "Brom"),
+            (Some("Ayla".to_string()), 4)
+        );
+    }
+
+    #[test]
+    fn removing_the_last_combatant_ends_the_turns() {
+        let order = vec![combatant("Ayla", 18, 2, 5)];
+
+        assert_eq!(after_removal(&order, Some("Ayla"), 2, "Ayla"), (None, 2));
+    }
+
+    #[test]
+    fn formatting_the_order() {
+        assert_eq!(
+            format_order(&order(), &[], Some("Goblin"), 2),
+            "Round 2\n▫️ **18** Ayla\n➡️ **12** Goblin\n▫️ **7** Brom"
+        );
+        assert!(format_order(&[], &[], None, 1).starts_with("No one has joined"));
+    }
+
+    fn condition(target: &str, name: &str, rounds: Option<i64>) -> Condition {
+        Condition {
+            target: target.to_string(),
+            name: name.to_string(),
+            rounds,
+        }
+    }
+
+    #[test]
+    fn conditions_count_down_each_round() {
+        let (active, ended) = tick(vec![
+            condition("Goblin", "Frightened", Some(2)),
+            condition("Brom", "Prone", None),
+            condition("Ayla", "Blessed", Some(1)),
+        ]);
+
+        assert_eq!(
+            active,
+            vec![
+                condition("Goblin", "Frightened", Some(1)),
+                condition("Brom", "Prone", None),
+            ]
+        );
+        assert_eq!(ended, vec![condition("Ayla", "Blessed", Some(0))]);
+    }
+
+    #[test]
+    fn formatting_conditions() {
+        assert_eq!(
+            format_condition(&condition("Goblin", "Frightened", Some(2))),
+            "Frightened (2 rounds)"
+        );
+        assert_eq!(
+            format_condition(&condition("Goblin", "Frightened", Some(1))),
+            "Frightened (1 round)"
+        );
+        assert_eq!(format_condition(&condition("Brom", "Prone", None)), "Prone");
+    }
+
+    #[test]
+    fn formatting_the_order_with_conditions() {
+        let conditions = [
+            condition("Goblin", "Frightened", Some(2)),
+            condition("Goblin", "Prone", None),
+        ];
+
+        assert_eq!(
+            format_order(&order(), &conditions, None, 1),
+            "Round 1\n▫️ **18** Ayla\n▫️ **12** Goblin — Frightened (2 rounds), Prone\n▫️ **7** Brom"
+        );
+    }
+
+    #[test]
+    fn damage_stops_at_zero() {
+        assert_eq!(damage(10, 4), 6);
+        assert_eq!(damage(10, 10), 0);
+        assert_eq!(damage(3, 8), 0);
+    }
+
+    #[test]
+    fn healing_stops_at_the_maximum() {
+        assert_eq!(heal(4, Some(10), 3, false), 7);
+        assert_eq!(heal(8, Some(10), 5, false), 10);
+        assert_eq!(heal(8, None, 5, false), 13);
+    }
+
+    #[test]
+    fn temporary_hit_points_go_over_the_maximum() {
+        assert_eq!(heal(8, Some(10), 5, true), 13);
+        // Healing doesn't take away temporary hit points already over the maximum.
+        assert_eq!(heal(13, Some(10), 2, false), 13);
+    }
+
+    #[test]
+    fn formatting_hit_points() {
+        let mut c = combatant("Goblin", 12, 2, 9);
+        assert_eq!(format_hp(&c), None);
+
+        c.hp = Some(7);
+        assert_eq!(format_hp(&c), Some("7 HP".to_string()));
+        c.max_hp = Some(15);
+        assert_eq!(format_hp(&c), Some("7/15 HP".to_string()));
+        c.hp = Some(0);
+        assert_eq!(format_hp(&c), Some("down".to_string()));
+    }
+
+    #[test]
+    fn formatting_hidden_hit_points() {
+        let mut c = combatant("Goblin", 12, 2, 9);
+        c.hidden_hp = true;
+        c.max_hp = Some(15);
+
+        c.hp = Some(8);
+        assert_eq!(format_hp(&c), Some("healthy".to_string()));
+        c.hp = Some(7);
+        assert_eq!(format_hp(&c), Some("bloodied".to_string()));
+        c.hp = Some(0);
+        assert_eq!(format_hp(&c), Some("down".to_string()));
+        c.max_hp = None;
+        c.hp = Some(1);
+        assert_eq!(format_hp(&c), Some("healthy".to_string()));
+    }
+}
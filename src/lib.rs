@@ -0,0 +1,11 @@
+//! The dice roller behind the bot, usable without Discord. The bot itself is the binary target.
+
+pub mod roll;
+
+pub use evaluroll::{
+    ast::{Output, Roll},
+    Error,
+};
+/// Parses and rolls a dice expression. See [`roll::roll`].
+pub use roll::roll as eval;
+pub use roll::{Crit, Note};
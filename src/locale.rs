@@ -0,0 +1,77 @@
+use std::{fmt::Display, str::FromStr};
+
+use tabletop_bot::roll::Crit;
+
+/// The language replies are rendered in. Dice notation is the same in every locale.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Locale {
+    #[default]
+    En,
+    Fr,
+    Es,
+}
+
+impl Locale {
+    /// Separates groups of thousands in totals, e.g. `1,234` or `1 234`.
+    pub(crate) fn thousands_separator(self) -> char {
+        match self {
+            Locale::En => ',',
+            // A narrow no-break space, as French typography uses.
+            Locale::Fr => '\u{202f}',
+            Locale::Es => '.',
+        }
+    }
+
+    /// Introduces a roll's result, e.g. "Rolled **1d20** = 12".
+    pub(crate) fn rolled(self) -> &'static str {
+        match self {
+            Locale::En => "Rolled",
+            Locale::Fr => "Jet de",
+            Locale::Es => "Tirada de",
+        }
+    }
+
+    /// Calls out a natural 20 or natural 1.
+    pub(crate) fn crit(self, crit: Crit) -> &'static str {
+        match (self, crit) {
+            (Locale::En, Crit::Success) => "Natural 20!",
+            (Locale::En, Crit::Fumble) => "Natural 1!",
+            (Locale::Fr, Crit::Success) => "20 naturel, succès critique !",
+            (Locale::Fr, Crit::Fumble) => "1 naturel, échec critique !",
+            (Locale::Es, Crit::Success) => "¡20 natural, éxito crítico!",
+            (Locale::Es, Crit::Fumble) => "¡1 natural, pifia!",
+        }
+    }
+
+    /// Points out a roll that had no dice in it, e.g. "2+2".
+    pub(crate) fn no_dice(self) -> &'static str {
+        match self {
+            Locale::En => "(no dice were rolled)",
+            Locale::Fr => "(aucun dé n'a été lancé)",
+            Locale::Es => "(no se tiró ningún dado)",
+        }
+    }
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "en" | "english" => Ok(Locale::En),
+            "fr" | "french" | "français" => Ok(Locale::Fr),
+            "es" | "spanish" | "español" => Ok(Locale::Es),
+            s => Err(format!("Unknown locale `{}`, expected en, fr or es", s)),
+        }
+    }
+}
+
+impl Display for Locale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Locale::En => write!(f, "en"),
+            Locale::Fr => write!(f, "fr"),
+            Locale::Es => write!(f, "es"),
+        }
+    }
+}
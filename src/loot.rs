@@ -0,0 +1,128 @@
+use std::fmt::Display;
+
+use poise::serenity_prelude as serenity;
+use rand::Rng;
+
+use crate::discord;
+
+/// The most people loot can be split between.
+pub(crate) const MAX_SHARES: u32 = 100;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Error {
+    NoPlayers,
+    TooManyPlayers,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::NoPlayers => write!(
+                f,
+                "Give the number of players, or mention who's getting a share"
+            ),
+            Error::TooManyPlayers => write!(
+                f,
+                "Loot can be split between at most {} players",
+                MAX_SHARES
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Who loot is split between.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Players {
+    Count(u32),
+    Mentioned(Vec<serenity::UserId>),
+}
+
+impl Players {
+    pub(crate) fn count(&self) -> u32 {
+        match self {
+            Players::Count(count) => *count,
+            Players::Mentioned(users) => users.len() as u32,
+        }
+    }
+}
+
+/// Parses who loot is split between: a number of players, or mentions of each of them. Anyone
+/// mentioned twice only gets one share.
+pub(crate) fn parse_players(text: &str) -> Result<Players, Error> {
+    let players = match text.trim().parse::<u32>() {
+        Ok(count) => Players::Count(count),
+        Err(_) => Players::Mentioned(discord::parse_mentions(text)),
+    };
+
+    match players.count() {
+        0 => Err(Error::NoPlayers),
+        count if count > MAX_SHARES => Err(Error::TooManyPlayers),
+        _ => Ok(players),
+    }
+}
+
+/// An amount of coins, in gold, silver and copper pieces.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Coins {
+    pub gp: u64,
+    pub sp: u64,
+    pub cp: u64,
+}
+
+impl Coins {
+    /// Converts copper pieces into as few coins as possible: 10 cp to a silver and 10 sp to a
+    /// gold.
+    pub(crate) fn from_cp(cp: u64) -> Self {
+        Self {
+            gp: cp / 100,
+            sp: cp / 10 % 10,
+            cp: cp % 10,
+        }
+    }
+
+    pub(crate) fn to_cp(self) -> u64 {
+        self.gp * 100 + self.sp * 10 + self.cp
+    }
+}
+
+impl Display for Coins {
+    /// Lists each denomination there are any of, like `33 gp, 3 sp`, or `0 gp` for nothing.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts = [(self.gp, "gp"), (self.sp, "sp"), (self.cp, "cp")]
+            .into_iter()
+            .filter(|(amount, _)| *amount > 0)
+            .map(|(amount, coin)| format!("{} {}", amount, coin))
+            .collect::<Vec<_>>();
+        if parts.is_empty() {
+            write!(f, "0 gp")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
+}
+
+/// Splits coins evenly into `shares`, breaking them into smaller coins as needed. Returns each
+/// share and the copper pieces left over that can't be split.
+pub(crate) fn split(total: Coins, shares: u32) -> (Coins, Coins) {
+    let total = total.to_cp();
+    let shares = u64::from(shares.max(1));
+    (
+        Coins::from_cp(total / shares),
+        Coins::from_cp(total % shares),
+    )
+}
+
+/// Picks who gets an item, returning the d`n` roll and the winner.
+pub(crate) fn award<R: Rng + ?Sized>(
+    players: &[serenity::UserId],
+    rng: &mut R,
+) -> Option<(usize, serenity::UserId)> {
+    if players.is_empty() {
+        return None;
+    }
+
+    let roll = rng.gen_range(1..=players.len());
+    Some((roll, players[roll - 1]))
+}
@@ -1,24 +1,56 @@
+mod backup;
+mod characters;
+mod checks;
 mod command;
+mod components;
+mod config;
+mod customdice;
 mod db;
+mod deathsaves;
 mod discord;
+mod downtime;
+mod generator;
+mod help;
+mod initiative;
+mod locale;
+mod loot;
+mod metrics;
+mod notify;
+mod permissions;
+mod polls;
+mod recaps;
+mod resources;
 mod scheduler;
+mod selfcheck;
+mod settings;
+mod sql;
+mod tables;
+mod templates;
+mod timers;
 
+use backup::Backups;
+use chrono::{DateTime, Local};
+use config::{Config, Registration};
 use dotenvy::dotenv;
-use poise::{
-    serenity_prelude::{self as serenity, GuildId},
-    FrameworkError,
-};
+use metrics::Metrics;
+use poise::{serenity_prelude as serenity, FrameworkError};
 use r2d2_sqlite::SqliteConnectionManager;
 use rand::{Rng, SeedableRng};
 use rand_hc::Hc128Rng;
 use scheduler::Scheduler;
+use settings::Settings;
 use std::{
-    env,
-    sync::{Arc, RwLock},
+    collections::HashSet,
+    path::Path,
+    sync::{Arc, Mutex, OnceLock, RwLock},
+    time::{Duration, Instant},
 };
+use tokio::sync::watch;
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
-type Context<'a> = poise::Context<'a, Data<serenity::Context, Hc128Rng>, Error>;
+/// The data the bot runs with.
+type BotData = Data<serenity::Context, Hc128Rng>;
+type Context<'a> = poise::Context<'a, BotData, Error>;
 type Result<T> = core::result::Result<T, Error>;
 
 // User data, which is stored and accessible in all command invocations
@@ -29,85 +61,674 @@ where
 {
     pool: r2d2::Pool<SqliteConnectionManager>,
     scheduler: Arc<RwLock<Scheduler<T>>>,
-    rng: R,
+    nicks: discord::NickCache,
+    recent_grants: discord::RecentGrants,
+    /// The replies to recent commands that change something, to answer retries with.
+    processed: discord::ProcessedInteractions,
+    timers: timers::Timers,
+    backups: Option<Backups>,
+    /// When the bot started, for reporting uptime.
+    started: Instant,
+    metrics: Arc<Metrics>,
+    /// Handles presses of buttons that outlive the command that sent them.
+    components: components::Router,
+    rng: Mutex<R>,
+}
+
+/// A specific message for errors the user can do something about, or None for unexpected ones.
+fn user_message(error: &Error) -> Option<String> {
+    if let Some(e) = error.downcast_ref::<db::Error>() {
+        return db_user_message(e);
+    }
+    if let Some(e) = error.downcast_ref::<scheduler::Error>() {
+        return match e {
+            scheduler::Error::Db(e) => db_user_message(e),
+            scheduler::Error::ShutDown => {
+                Some("The bot is shutting down, try again in a moment.".to_string())
+            }
+            scheduler::Error::R2d2(_) => None,
+        };
+    }
+    // Only owners can run /sql, so every problem with the statement is worth showing.
+    if let Some(e) = error.downcast_ref::<sql::Error>() {
+        return Some(e.to_string());
+    }
+    if let Some(e) = error.downcast_ref::<evaluroll::Error>() {
+        return Some(format!("Couldn't roll that: {}", e));
+    }
+
+    None
+}
+
+/// Whether an error came from the database, rather than from what the user asked for.
+fn is_db_error(error: &Error) -> bool {
+    if let Some(e) = error.downcast_ref::<db::Error>() {
+        return db_user_message(e).is_none();
+    }
+    if let Some(e) = error.downcast_ref::<scheduler::Error>() {
+        return match e {
+            scheduler::Error::Db(e) => db_user_message(e).is_none(),
+            scheduler::Error::R2d2(_) => true,
+            scheduler::Error::ShutDown => false,
+        };
+    }
+
+    error.is::<rusqlite::Error>() || error.is::<r2d2::Error>() || error.is::<backup::Error>()
+}
+
+fn db_user_message(error: &db::Error) -> Option<String> {
+    match error {
+        db::Error::MissingVotes => Some("Not everyone has voted for MVP yet.".to_string()),
+        db::Error::UnknownPlayer(_) => {
+            Some("That player isn't registered. Use /registerplayer first.".to_string())
+        }
+        db::Error::PlayerExists(_) => Some("That player is already registered.".to_string()),
+        _ => None,
+    }
+}
+
+async fn handle_error(error: FrameworkError<'_, Data<serenity::Context, Hc128Rng>, Error>) {
+    match error {
+        // Checks that fail without an error have already told the user why.
+        FrameworkError::CommandCheckFailed { error: None, .. } => {}
+        FrameworkError::Command { error, ctx, .. }
+        | FrameworkError::CommandCheckFailed {
+            error: Some(error),
+            ctx,
+            ..
+        } => {
+            record_command(ctx).await;
+            release_interaction(ctx);
+            if is_db_error(&error) {
+                ctx.data().metrics.record_db_error();
+            }
+
+            // Unexpected errors are logged in full, and the user gets an id to report instead.
+            let msg = user_message(&error).unwrap_or_else(|| {
+                let id = format!("{:08x}", rand::random::<u32>());
+                log::error!(
+                    "Error {} in /{}: {}",
+                    id,
+                    ctx.command().qualified_name,
+                    error
+                );
+                format!(
+                    "Something went wrong. If it keeps happening, give an admin the error id `{}`.",
+                    id
+                )
+            });
+
+            let reply = poise::CreateReply::default().content(msg).ephemeral(true);
+            if let Err(e) = ctx.send(reply).await {
+                log::error!("Error sending error message: {}", e);
+            }
+        }
+        // The rest, like invalid arguments or missing permissions, are already user-facing.
+        error => {
+            log::warn!("Error: {}", error);
+
+            if let Err(e) = poise::builtins::on_error(error).await {
+                log::error!("Error sending error message: {}", e);
+            }
+        }
+    }
+}
+
+/// Logs who ran a command and with what, to debug reports of the bot not responding, and starts
+/// timing it.
+async fn before_command(ctx: Context<'_>) {
+    let options = match ctx {
+        poise::Context::Application(ctx) => discord::format_options(ctx.args),
+        poise::Context::Prefix(ctx) => discord::truncate(ctx.args, discord::LOGGED_OPTION_LEN),
+    };
+    let guild = match ctx.guild_id() {
+        Some(guild_id) => format!("guild {}", guild_id),
+        None => "DMs".to_string(),
+    };
+    log::info!(
+        "/{} by {} in {}: {}",
+        ctx.command().qualified_name,
+        ctx.author().id,
+        guild,
+        options
+    );
+
+    ctx.set_invocation_data(Instant::now()).await;
 }
 
-async fn handle_error<T>(error: FrameworkError<'_, T, Error>) {
-    log::error!("Error: {}", error);
+/// Logs and records how long a command took, timed from `before_command`. Commands rejected by
+/// a check never started, so aren't recorded.
+async fn record_command(ctx: Context<'_>) {
+    let started = ctx
+        .invocation_data::<Instant>()
+        .await
+        .map(|started| *started);
+    if let Some(started) = started {
+        let elapsed = started.elapsed();
+        log::info!(
+            "/{} took {} ms",
+            ctx.command().qualified_name,
+            elapsed.as_millis()
+        );
+        ctx.data()
+            .metrics
+            .record_command(&ctx.command().qualified_name, elapsed);
+    }
+}
 
-    if let Some(ctx) = error.ctx() {
-        if let Err(e) = ctx.say(format!("Error: {}", error)).await {
-            log::error!("Error sending error message: {}", e);
+/// Lets a retry of a command that didn't record its reply, because it failed or stopped before
+/// changing anything, run again instead of being ignored.
+fn release_interaction(ctx: Context<'_>) {
+    ctx.data().processed.release(ctx.id());
+}
+
+async fn handle_event(
+    ctx: &serenity::Context,
+    event: &serenity::FullEvent,
+    owners: &HashSet<serenity::UserId>,
+    data: &Data<serenity::Context, Hc128Rng>,
+) -> Result<()> {
+    // Member events only arrive with the privileged guild members intent. Without it, cached
+    // nicknames just expire.
+    match event {
+        serenity::FullEvent::GuildMemberUpdate { event: update, .. } => {
+            data.nicks
+                .invalidate(&(Some(update.guild_id), update.user.id));
+        }
+        serenity::FullEvent::GuildMemberRemoval { guild_id, user, .. } => {
+            data.nicks.invalidate(&(Some(*guild_id), user.id));
         }
+        serenity::FullEvent::ShardStageUpdate { event } => {
+            data.metrics
+                .set_connected(event.new == serenity::ConnectionStage::Connected);
+        }
+        serenity::FullEvent::InteractionCreate {
+            interaction: serenity::Interaction::Component(press),
+        } => handle_component(ctx, press, data).await?,
+        // Channels are checked against the cache, so the self-check waits until it's filled.
+        serenity::FullEvent::CacheReady { .. } => startup_selfcheck(ctx, owners, data).await,
+        _ => {}
     }
+
+    Ok(())
+}
+
+/// Handles presses of buttons that outlive the command that sent them, by their custom id's
+/// prefix. Buttons that are only waited on while a command runs, like confirmation prompts, are
+/// collected by the command instead and ignored here.
+async fn handle_component(
+    ctx: &serenity::Context,
+    press: &serenity::ComponentInteraction,
+    data: &Data<serenity::Context, Hc128Rng>,
+) -> Result<()> {
+    data.components.dispatch(ctx, press, data).await
+}
+
+/// Checks the configuration once the bot is connected, DMing the owners about any problems so a
+/// deleted channel doesn't go unnoticed.
+async fn startup_selfcheck(
+    ctx: &serenity::Context,
+    owners: &HashSet<serenity::UserId>,
+    data: &Data<serenity::Context, Hc128Rng>,
+) {
+    let problems = match selfcheck::run(ctx, &data.pool).await {
+        Ok(problems) => problems,
+        Err(e) => {
+            log::error!("Error running the startup self-check: {}", e);
+            return;
+        }
+    };
+    if problems.is_empty() {
+        log::info!("Self-check passed");
+        return;
+    }
+
+    let report = format!(
+        "The startup self-check found problems:\n{}",
+        selfcheck::report(&problems)
+    );
+    let report = discord::truncate(&report, discord::MESSAGE_LIMIT - 1);
+    for owner in owners {
+        discord::notify(ctx, *owner, &report).await;
+    }
+}
+
+/// Registers the handler of every kind of routed component.
+fn component_router() -> components::Router {
+    let mut router = components::Router::default();
+    router.register(polls::BUTTON_PREFIX, polls::handle_vote);
+    router.register(command::REROLL_PREFIX, command::handle_reroll);
+    router
+}
+
+/// Whether the bot may use the Message Content intent, which text commands need. When it may not,
+/// text commands are disabled with a warning.
+async fn message_content_allowed(token: &str) -> bool {
+    let flags = serenity::ApplicationFlags::GATEWAY_MESSAGE_CONTENT
+        | serenity::ApplicationFlags::GATEWAY_MESSAGE_CONTENT_LIMITED;
+    match serenity::Http::new(token)
+        .get_current_application_info()
+        .await
+    {
+        Ok(info) if info.flags.is_some_and(|f| f.intersects(flags)) => true,
+        Ok(_) => {
+            log::warn!(
+                "Text commands are disabled: enable the Message Content intent for the bot in \
+                the Discord developer portal"
+            );
+            false
+        }
+        Err(e) => {
+            log::warn!(
+                "Text commands are disabled: unable to check the bot's intents: {}",
+                e
+            );
+            false
+        }
+    }
+}
+
+/// The prefix of text commands where a message was sent. DMs use the default.
+async fn guild_prefix(
+    ctx: poise::PartialContext<'_, Data<serenity::Context, Hc128Rng>, Error>,
+) -> Result<Option<String>> {
+    let Some(guild_id) = ctx.guild_id else {
+        return Ok(Some(Settings::default().prefix));
+    };
+
+    let conn = ctx.data.pool.get()?;
+    Ok(Some(Settings::load(&conn, guild_id.get() as i64)?.prefix))
+}
+
+/// Every command the bot registers.
+fn commands() -> Vec<poise::Command<BotData, Error>> {
+    vec![
+        command::exp(),
+        command::experience(),
+        command::mvp(),
+        command::vote_mvp(),
+        command::notify(),
+        command::show_xp(),
+        command::register_player(),
+        command::character(),
+        command::resolve_mvp(),
+        command::mvp_reset(),
+        command::roll(),
+        command::roll_last(),
+        command::roll_help(),
+        command::myroll(),
+        command::groupcheck(),
+        command::deck(),
+        command::table(),
+        command::generate(),
+        command::init(),
+        command::condition(),
+        command::hp_set(),
+        command::damage(),
+        command::heal(),
+        command::resource(),
+        command::rest(),
+        command::deathsave(),
+        command::loot(),
+        command::timer(),
+        command::schedule(),
+        command::schedule_fire(),
+        command::schedule_edit(),
+        command::template(),
+        command::channel_mode(),
+        command::register_role(),
+        command::next_game(),
+        command::poll(),
+        command::session(),
+        command::downtime(),
+        command::recap(),
+        command::connections(),
+        command::settings(),
+        command::backup(),
+        command::dbstats(),
+        command::selfcheck(),
+        command::about(),
+        command::help(),
+        command::sql(),
+        command::register(),
+    ]
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let started = Instant::now();
+
     // Load values from .env, if available.
     dotenv().ok();
-    pretty_env_logger::init();
 
-    // Login with a bot token from the env.
-    let token = env::var("DISCORD_TOKEN").expect("Expected DISCORD_TOKEN in the environment");
-    let db_path = env::var("DATABASE_PATH").expect("Expected DATABASE_PATH in the environment");
-    let guild_id: u64 = env::var("GUILD_ID")
-        .expect("Expected GUILD_ID in the environment")
-        .parse()
-        .expect("GUILD_ID must be a number");
+    let config = Config::from_env();
+    let mut logger = pretty_env_logger::formatted_builder();
+    if let Some(log_level) = config.as_ref().ok().and_then(|c| c.log_level.as_deref()) {
+        logger.parse_filters(log_level);
+    }
+    logger.init();
+
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration:\n{}", e);
+            std::process::exit(1);
+        }
+    };
+    log::info!("Starting with configuration:\n{}", config);
+    let Config {
+        token,
+        db_path,
+        create_db_dir,
+        registration,
+        pool_max_size,
+        pool_timeout,
+        backups,
+        backup_interval,
+        metrics_addr,
+        owner_id,
+        exp_repeat_window,
+        prefix_commands,
+        ..
+    } = config;
+    // The application's owner is added as well, when the framework starts.
+    let owners = owner_id.into_iter().collect();
+
+    if let Err(e) = db::check_dir(Path::new(&db_path), create_db_dir) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    let mgr = SqliteConnectionManager::file(db_path).with_init(db::configure_connection);
+    let pool = r2d2::Pool::builder()
+        .max_size(pool_max_size)
+        .connection_timeout(pool_timeout)
+        .build(mgr)
+        .expect("Failed to create connection pool");
+    // Catch corruption before it causes confusing errors in the middle of a command.
+    match db::integrity_check(&*pool.get()?) {
+        Ok(integrity) if integrity == "ok" => log::info!("Database integrity check passed"),
+        Ok(integrity) => log::error!("Database integrity check found problems:\n{}", integrity),
+        Err(e) => log::error!("Error checking database integrity: {}", e),
+    }
+
+    // A port that's already taken is logged, and the bot runs without metrics.
+    let metrics = Arc::new(Metrics::default());
+    if let Some(addr) = metrics_addr {
+        let metrics = metrics.clone();
+        let pool = pool.clone();
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(addr, metrics, pool).await {
+                log::error!("Error serving metrics: {}", e);
+            }
+        });
+    }
+    // Requesting the Message Content intent without it enabled stops the bot connecting at all.
+    let prefix_commands = prefix_commands && message_content_allowed(&token).await;
+    let mut intents = serenity::GatewayIntents::non_privileged();
+    if prefix_commands {
+        intents |= serenity::GatewayIntents::MESSAGE_CONTENT;
+    }
+
+    // Set once the framework is set up, so it can be shut down along with the client.
+    let scheduler_slot = Arc::new(OnceLock::new());
 
+    let setup_pool = pool.clone();
+    let setup_scheduler_slot = scheduler_slot.clone();
+    let setup_metrics = metrics.clone();
     let framework = poise::Framework::builder()
         .options(poise::FrameworkOptions {
-            commands: vec![
-                command::exp(),
-                command::experience(),
-                command::mvp(),
-                command::register_player(),
-                command::resolve_mvp(),
-                command::roll(),
-                command::schedule(),
-                command::connections(),
-            ],
+            commands: commands(),
+            owners,
+            prefix_options: poise::PrefixFrameworkOptions {
+                dynamic_prefix: if prefix_commands {
+                    Some(|ctx| Box::pin(guild_prefix(ctx)))
+                } else {
+                    None
+                },
+                mention_as_prefix: prefix_commands,
+                ..Default::default()
+            },
+            pre_command: |ctx| Box::pin(before_command(ctx)),
+            post_command: |ctx| {
+                Box::pin(async move {
+                    record_command(ctx).await;
+                    release_interaction(ctx);
+                })
+            },
             on_error: |error| Box::pin(handle_error(error)),
+            event_handler: |ctx, event, framework, data| {
+                Box::pin(handle_event(ctx, event, &framework.options().owners, data))
+            },
             ..Default::default()
         })
         .setup(move |ctx, ready, framework| {
             Box::pin(async move {
                 log::info!("Connected to Discord as {}!", ready.user.name);
-                let mgr = SqliteConnectionManager::file(db_path);
-                let pool = r2d2::Pool::new(mgr).expect("Failed to create connection pool");
+                setup_metrics.set_connected(true);
+                let pool = setup_pool;
 
-                let connection = pool.get().expect("Failed to get connection from pool");
+                let mut connection = pool.get().expect("Failed to get connection from pool");
 
-                db::setup(&connection).expect("Failed to setup database");
-                poise::builtins::register_in_guild(
-                    &ctx,
-                    &framework.options().commands,
-                    GuildId::new(guild_id),
-                )
-                .await?;
-                // Uncomment to register globally.
-                // poise::builtins::register_globally(ctx, &framework.options().commands).await?;
+                db::run_migrations(&mut connection).expect("Failed to migrate database");
+                let commands = &framework.options().commands;
+                match registration {
+                    Registration::Global => {
+                        log::info!("Registering commands globally");
+                        poise::builtins::register_globally(ctx, commands).await?;
+                    }
+                    Registration::Guilds(guild_ids) => {
+                        for guild_id in guild_ids {
+                            log::info!("Registering commands in guild {}", guild_id);
+                            poise::builtins::register_in_guild(ctx, commands, guild_id).await?;
+                        }
+                    }
+                }
 
-                let mut scheduler = Scheduler::new(pool.clone(), ctx.clone());
+                let mut scheduler =
+                    Scheduler::new(pool.clone(), ctx.clone(), setup_metrics.clone());
                 scheduler.sync_schedule()?;
+                scheduler.sync_all_mvp()?;
+                scheduler.sync_polls()?;
+                if let (Some(backups), Some(interval)) = (backups.clone(), backup_interval) {
+                    log::info!(
+                        "Backing up the database every {}h to {}",
+                        interval.as_secs() / 60 / 60,
+                        backups.dir.display()
+                    );
+                    scheduler.schedule_backups(backups, interval)?;
+                }
+                watch_next_game(ctx.clone(), scheduler.subscribe_next_game());
+                let scheduler = Arc::new(RwLock::new(scheduler));
+                let _ = setup_scheduler_slot.set(scheduler.clone());
+                let timers = timers::Timers::default();
+                timers.resume(ctx.clone(), pool.clone())?;
 
                 Ok(Data {
                     pool,
-                    scheduler: Arc::new(RwLock::new(scheduler)),
-                    rng: Hc128Rng::from_entropy(),
+                    scheduler,
+                    nicks: discord::NickCache::new(Duration::from_secs(10 * 60)),
+                    recent_grants: discord::RecentGrants::new(exp_repeat_window),
+                    processed: discord::ProcessedInteractions::new(1000),
+                    timers,
+                    backups,
+                    started,
+                    metrics: setup_metrics,
+                    components: component_router(),
+                    rng: Mutex::new(Hc128Rng::from_entropy()),
                 })
             })
         })
         .build();
 
-    let mut client =
-        serenity::ClientBuilder::new(token, serenity::GatewayIntents::non_privileged())
-            .framework(framework)
-            .await?;
+    let mut client = serenity::ClientBuilder::new(token, intents)
+        .framework(framework)
+        .await?;
+
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        log::info!("Shutting down...");
+        shard_manager.shutdown_all().await;
+    });
 
     log::info!("Connecting to Discord...");
     client.start().await?;
 
+    if let Some(scheduler) = scheduler_slot.get() {
+        scheduler
+            .write()
+            .expect("Unable to get mut scheduler")
+            .shutdown();
+    }
+    match pool
+        .get()
+        .map_err(Error::from)
+        .and_then(|conn| Ok(db::checkpoint(&conn)?))
+    {
+        Ok(()) => log::info!("Shut down cleanly"),
+        Err(e) => log::error!("Error flushing the database on shutdown: {}", e),
+    }
+
     Ok(())
 }
+
+/// Keeps the bot's status showing when the next game is.
+fn watch_next_game(
+    ctx: serenity::Context,
+    mut next_game: watch::Receiver<Option<DateTime<Local>>>,
+) {
+    tokio::spawn(async move {
+        loop {
+            let on = *next_game.borrow_and_update();
+            let activity = discord::activity_text(on, Local::now());
+            ctx.set_activity(Some(serenity::ActivityData::custom(activity)));
+
+            // Recheck at least hourly, so "tomorrow" becomes "today". The sender only goes away
+            // with the scheduler.
+            let changed = tokio::time::timeout(Duration::from_secs(60 * 60), next_game.changed());
+            if let Ok(Err(_)) = changed.await {
+                break;
+            }
+        }
+    });
+}
+
+/// Waits for Ctrl+C, or SIGTERM on unix.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            log::error!("Error listening for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                log::error!("Error listening for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {},
+        () = terminate => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_command_has_help() {
+        // A group's help covers its subcommands, which only need a description.
+        for command in commands() {
+            let help = command.help_text.as_deref().unwrap_or_default();
+            assert!(!help.trim().is_empty(), "{} has no help", command.name);
+            for subcommand in &command.subcommands {
+                let description = subcommand.description.as_deref().unwrap_or_default();
+                assert!(
+                    !description.trim().is_empty(),
+                    "{} {} has no description",
+                    command.name,
+                    subcommand.name
+                );
+            }
+        }
+    }
+
+    fn sqlite_error() -> rusqlite::Error {
+        rusqlite::Error::QueryReturnedNoRows
+    }
+
+    #[test]
+    fn known_db_errors_are_explained() {
+        for e in [
+            db::Error::MissingVotes,
+            db::Error::UnknownPlayer(1),
+            db::Error::PlayerExists(1),
+        ] {
+            let error: Error = e.into();
+            assert!(user_message(&error).is_some());
+            assert!(!is_db_error(&error));
+        }
+    }
+
+    #[test]
+    fn unexpected_db_errors_are_hidden() {
+        let error: Error = db::Error::Sqlite(sqlite_error()).into();
+        assert_eq!(user_message(&error), None);
+        assert!(is_db_error(&error));
+
+        let error: Error = sqlite_error().into();
+        assert_eq!(user_message(&error), None);
+        assert!(is_db_error(&error));
+    }
+
+    #[test]
+    fn scheduler_errors() {
+        let error: Error = scheduler::Error::Db(db::Error::MissingVotes).into();
+        assert!(user_message(&error).is_some());
+        assert!(!is_db_error(&error));
+
+        let error: Error = scheduler::Error::Db(db::Error::Sqlite(sqlite_error())).into();
+        assert_eq!(user_message(&error), None);
+        assert!(is_db_error(&error));
+
+        let error: Error = scheduler::Error::ShutDown.into();
+        assert!(user_message(&error).is_some());
+        assert!(!is_db_error(&error));
+    }
+
+    #[test]
+    fn roll_and_sql_errors_are_explained() {
+        let error: Error =
+            Box::new(tabletop_bot::roll::roll("1d", &mut rand::thread_rng()).unwrap_err());
+        assert!(user_message(&error)
+            .unwrap()
+            .starts_with("Couldn't roll that"));
+        assert!(!is_db_error(&error));
+
+        let error: Error = sql::Error::MultipleStatements.into();
+        assert_eq!(
+            user_message(&error),
+            Some(sql::Error::MultipleStatements.to_string())
+        );
+        assert!(!is_db_error(&error));
+    }
+
+    #[test]
+    fn other_errors_are_unexpected() {
+        let error: Error = "something else".into();
+        assert_eq!(user_message(&error), None);
+        assert!(!is_db_error(&error));
+    }
+}
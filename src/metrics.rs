@@ -0,0 +1,422 @@
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    fmt::Write,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use hyper::{
+    header::{HeaderValue, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+
+/// How long `/healthz` waits for a database connection before reporting the database as down.
+const HEALTH_DB_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Upper bounds of the command latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 10] = [0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Default)]
+struct Histogram {
+    /// Observations per bucket, not yet cumulative.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|&le| seconds <= le) {
+            self.buckets[i] += 1;
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+/// The database connection pool's state, as of a scrape.
+pub(crate) struct PoolStats {
+    pub connections: u32,
+    pub idle_connections: u32,
+}
+
+impl From<r2d2::State> for PoolStats {
+    fn from(state: r2d2::State) -> Self {
+        Self {
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+        }
+    }
+}
+
+/// Counters exposed for Prometheus to scrape, and what `/healthz` needs to know.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    /// Whether the gateway connection is up.
+    connected: AtomicBool,
+    rolls: AtomicU64,
+    db_errors: AtomicU64,
+    /// Scheduled jobs run, by job.
+    scheduler_fires: Mutex<BTreeMap<&'static str, u64>>,
+    /// Scheduled jobs waiting to run, by job.
+    scheduled: Mutex<BTreeMap<&'static str, u64>>,
+    /// Command latency, by qualified command name.
+    commands: Mutex<BTreeMap<String, Histogram>>,
+}
+
+impl Metrics {
+    pub(crate) fn set_connected(&self, connected: bool) {
+        self.connected.store(connected, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_scheduled(&self, job: &'static str, pending: u64) {
+        self.scheduled
+            .lock()
+            .expect("Unable to lock scheduled jobs")
+            .insert(job, pending);
+    }
+
+    pub(crate) fn record_rolls(&self, count: u64) {
+        self.rolls.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_db_error(&self) {
+        self.db_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_fire(&self, job: &'static str) {
+        *self
+            .scheduler_fires
+            .lock()
+            .expect("Unable to lock scheduler fires")
+            .entry(job)
+            .or_default() += 1;
+    }
+
+    pub(crate) fn record_command(&self, command: &str, elapsed: Duration) {
+        self.commands
+            .lock()
+            .expect("Unable to lock command metrics")
+            .entry(command.to_string())
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// How many times each command has run since startup, most used first.
+    pub(crate) fn command_counts(&self) -> Vec<(String, u64)> {
+        let mut counts = self
+            .commands
+            .lock()
+            .expect("Unable to lock command metrics")
+            .iter()
+            .map(|(command, histogram)| (command.clone(), histogram.count))
+            .collect::<Vec<_>>();
+        counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        counts
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    pub(crate) fn render(&self, pool: &PoolStats) -> String {
+        let mut out = String::new();
+
+        write_header(
+            &mut out,
+            "tabletop_gateway_connected",
+            "gauge",
+            "Whether the bot is connected to Discord.",
+        );
+        let _ = writeln!(
+            out,
+            "tabletop_gateway_connected {}",
+            u8::from(self.is_connected())
+        );
+
+        write_header(
+            &mut out,
+            "tabletop_db_pool_connections",
+            "gauge",
+            "Open database connections.",
+        );
+        let _ = writeln!(out, "tabletop_db_pool_connections {}", pool.connections);
+        write_header(
+            &mut out,
+            "tabletop_db_pool_idle_connections",
+            "gauge",
+            "Idle database connections.",
+        );
+        let _ = writeln!(
+            out,
+            "tabletop_db_pool_idle_connections {}",
+            pool.idle_connections
+        );
+
+        write_header(
+            &mut out,
+            "tabletop_rolls_total",
+            "counter",
+            "Dice rolls evaluated.",
+        );
+        let _ = writeln!(
+            out,
+            "tabletop_rolls_total {}",
+            self.rolls.load(Ordering::Relaxed)
+        );
+
+        write_header(
+            &mut out,
+            "tabletop_db_errors_total",
+            "counter",
+            "Commands that failed with a database error.",
+        );
+        let _ = writeln!(
+            out,
+            "tabletop_db_errors_total {}",
+            self.db_errors.load(Ordering::Relaxed)
+        );
+
+        write_header(
+            &mut out,
+            "tabletop_scheduler_fires_total",
+            "counter",
+            "Scheduled jobs run, by job.",
+        );
+        for (job, count) in self
+            .scheduler_fires
+            .lock()
+            .expect("Unable to lock scheduler fires")
+            .iter()
+        {
+            let _ = writeln!(
+                out,
+                "tabletop_scheduler_fires_total{{job=\"{}\"}} {}",
+                job, count
+            );
+        }
+
+        write_header(
+            &mut out,
+            "tabletop_scheduler_pending",
+            "gauge",
+            "Scheduled jobs waiting to run, by job.",
+        );
+        for (job, pending) in self
+            .scheduled
+            .lock()
+            .expect("Unable to lock scheduled jobs")
+            .iter()
+        {
+            let _ = writeln!(
+                out,
+                "tabletop_scheduler_pending{{job=\"{}\"}} {}",
+                job, pending
+            );
+        }
+
+        write_header(
+            &mut out,
+            "tabletop_command_duration_seconds",
+            "histogram",
+            "How long commands take to run, by command.",
+        );
+        for (command, histogram) in self
+            .commands
+            .lock()
+            .expect("Unable to lock command metrics")
+            .iter()
+        {
+            let mut cumulative = 0;
+            for (le, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "tabletop_command_duration_seconds_bucket{{command=\"{}\",le=\"{}\"}} {}",
+                    command, le, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "tabletop_command_duration_seconds_bucket{{command=\"{}\",le=\"+Inf\"}} {}",
+                command, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "tabletop_command_duration_seconds_sum{{command=\"{}\"}} {}",
+                command, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "tabletop_command_duration_seconds_count{{command=\"{}\"}} {}",
+                command, histogram.count
+            );
+        }
+
+        out
+    }
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn text(status: StatusCode, body: impl Into<Body>) -> Response<Body> {
+    let mut response = Response::new(body.into());
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; version=0.0.4"),
+    );
+    response
+}
+
+/// Healthy when the gateway is connected and the database hands out a connection.
+async fn health(metrics: &Metrics, pool: Pool<SqliteConnectionManager>) -> Response<Body> {
+    if !metrics.is_connected() {
+        return text(StatusCode::SERVICE_UNAVAILABLE, "Not connected to Discord");
+    }
+
+    let db_up = tokio::task::spawn_blocking(move || pool.get_timeout(HEALTH_DB_TIMEOUT).is_ok())
+        .await
+        .unwrap_or(false);
+    if !db_up {
+        return text(StatusCode::SERVICE_UNAVAILABLE, "Database unavailable");
+    }
+
+    text(StatusCode::OK, "OK")
+}
+
+async fn respond(
+    req: Request<Body>,
+    metrics: Arc<Metrics>,
+    pool: Pool<SqliteConnectionManager>,
+) -> Response<Body> {
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => text(
+            StatusCode::OK,
+            metrics.render(&PoolStats::from(pool.state())),
+        ),
+        (&Method::GET, "/healthz") => health(&metrics, pool).await,
+        _ => text(StatusCode::NOT_FOUND, "Not found"),
+    }
+}
+
+/// Serves `/metrics` and `/healthz` on `addr` until the process exits.
+pub(crate) async fn serve(
+    addr: SocketAddr,
+    metrics: Arc<Metrics>,
+    pool: Pool<SqliteConnectionManager>,
+) -> hyper::Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let metrics = metrics.clone();
+        let pool = pool.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let response = respond(req, metrics.clone(), pool.clone());
+                async move { Ok::<_, Infallible>(response.await) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    log::info!("Serving metrics and health checks on http://{}", addr);
+    server.await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POOL: PoolStats = PoolStats {
+        connections: 2,
+        idle_connections: 1,
+    };
+
+    #[test]
+    fn commands_are_counted_most_used_first() {
+        let metrics = Metrics::default();
+        for command in ["roll", "exp", "roll", "about", "exp", "roll"] {
+            metrics.record_command(command, Duration::from_millis(5));
+        }
+
+        assert_eq!(
+            metrics.command_counts(),
+            [("roll", 3), ("exp", 2), ("about", 1)]
+                .map(|(command, count)| (command.to_string(), count))
+        );
+    }
+
+    #[test]
+    fn ties_are_counted_in_name_order() {
+        let metrics = Metrics::default();
+        for command in ["roll", "exp"] {
+            metrics.record_command(command, Duration::from_millis(5));
+        }
+
+        assert_eq!(
+            metrics.command_counts(),
+            [("exp", 1), ("roll", 1)].map(|(command, count)| (command.to_string(), count))
+        );
+    }
+
+    #[test]
+    fn rendering_counters_and_gauges() {
+        let metrics = Metrics::default();
+        metrics.set_connected(true);
+        metrics.record_rolls(3);
+        metrics.record_rolls(2);
+        metrics.record_db_error();
+        metrics.record_fire("mvp");
+        metrics.record_fire("mvp");
+        metrics.set_scheduled("mvp", 1);
+
+        let rendered = metrics.render(&POOL);
+        for line in [
+            "# TYPE tabletop_gateway_connected gauge",
+            "tabletop_gateway_connected 1",
+            "tabletop_db_pool_connections 2",
+            "tabletop_db_pool_idle_connections 1",
+            "tabletop_rolls_total 5",
+            "tabletop_db_errors_total 1",
+            "tabletop_scheduler_fires_total{job=\"mvp\"} 2",
+            "tabletop_scheduler_pending{job=\"mvp\"} 1",
+        ] {
+            assert!(rendered.lines().any(|l| l == line), "{} is missing", line);
+        }
+    }
+
+    #[test]
+    fn rendering_command_latency() {
+        let metrics = Metrics::default();
+        metrics.record_command("roll", Duration::from_millis(20));
+        metrics.record_command("roll", Duration::from_millis(300));
+        // Slower than every bucket, so only counted in +Inf.
+        metrics.record_command("roll", Duration::from_secs(20));
+
+        let rendered = metrics.render(&POOL);
+        for line in [
+            "# TYPE tabletop_command_duration_seconds histogram",
+            "tabletop_command_duration_seconds_bucket{command=\"roll\",le=\"0.01\"} 0",
+            "tabletop_command_duration_seconds_bucket{command=\"roll\",le=\"0.025\"} 1",
+            "tabletop_command_duration_seconds_bucket{command=\"roll\",le=\"0.25\"} 1",
+            "tabletop_command_duration_seconds_bucket{command=\"roll\",le=\"0.5\"} 2",
+            "tabletop_command_duration_seconds_bucket{command=\"roll\",le=\"10\"} 2",
+            "tabletop_command_duration_seconds_bucket{command=\"roll\",le=\"+Inf\"} 3",
+            "tabletop_command_duration_seconds_sum{command=\"roll\"} 20.32",
+            "tabletop_command_duration_seconds_count{command=\"roll\"} 3",
+        ] {
+            assert!(rendered.lines().any(|l| l == line), "{} is missing", line);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::HashSet;
+
+use poise::serenity_prelude as serenity;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+
+use crate::{
+    db::{self, PlayerPrefs},
+    discord,
+};
+
+/// Turning DM notifications on or off with `/notify`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, poise::ChoiceParameter)]
+pub(crate) enum Toggle {
+    #[name = "on"]
+    On,
+    #[name = "off"]
+    Off,
+}
+
+/// Something players can be DMed about.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Event {
+    MvpWon,
+    /// The scheduled message was sent to its channel.
+    ScheduleSent {
+        channel_id: u64,
+    },
+    /// The scheduled message couldn't be sent, and why.
+    ScheduleFailed {
+        channel_id: u64,
+        error: String,
+    },
+    /// Experience granted, and the server's threshold for telling the player about it, if any.
+    XpGranted {
+        experience: u32,
+        reason: Option<String>,
+        threshold: Option<u32>,
+    },
+}
+
+impl Event {
+    /// The DM sent about it.
+    pub(crate) fn message(&self) -> String {
+        match self {
+            Event::MvpWon => "🏆 You were voted MVP!".to_string(),
+            Event::ScheduleSent { channel_id } => {
+                format!("📅 Your scheduled message was sent in <#{}>.", channel_id)
+            }
+            Event::ScheduleFailed { channel_id, error } => format!(
+                "⚠️ Your scheduled message couldn't be sent in <#{}>: {}",
+                channel_id, error
+            ),
+            Event::XpGranted {
+                experience,
+                reason: Some(reason),
+                ..
+            } => format!("✨ You were granted {}xp for {}.", experience, reason),
+            Event::XpGranted { experience, .. } => {
+                format!("✨ You were granted {}xp.", experience)
+            }
+        }
+    }
+}
+
+/// Who of `candidates` gets DMed about an event: those who've turned notifications on, and for
+/// experience, only when the server has a threshold and the grant meets it.
+pub(crate) fn recipients(event: &Event, candidates: &[i64], prefs: &[PlayerPrefs]) -> Vec<i64> {
+    if let Event::XpGranted {
+        experience,
+        threshold,
+        ..
+    } = event
+    {
+        if !threshold.is_some_and(|threshold| *experience >= threshold) {
+            return Vec::new();
+        }
+    }
+
+    let opted_in = prefs
+        .iter()
+        .filter(|prefs| prefs.notify)
+        .map(|prefs| prefs.player_id)
+        .collect::<HashSet<_>>();
+    let mut seen = HashSet::new();
+    candidates
+        .iter()
+        .copied()
+        .filter(|id| opted_in.contains(id) && seen.insert(*id))
+        .collect()
+}
+
+/// DMs whoever of `candidates` should hear about an event. Failures are logged rather than
+/// returned, since a missed notification shouldn't fail what caused it.
+pub(crate) async fn send(
+    http: impl serenity::CacheHttp,
+    pool: &Pool<SqliteConnectionManager>,
+    event: &Event,
+    candidates: &[i64],
+) {
+    let prefs = match pool
+        .get()
+        .map_err(crate::Error::from)
+        .and_then(|conn| Ok(db::get_player_prefs(&conn)?))
+    {
+        Ok(prefs) => prefs,
+        Err(e) => {
+            log::error!("Error getting notification preferences: {}", e);
+            return;
+        }
+    };
+
+    let message = event.message();
+    for id in recipients(event, candidates, &prefs) {
+        discord::notify(&http, serenity::UserId::new(id as u64), &message).await;
+    }
+}
@@ -0,0 +1,97 @@
+use poise::{serenity_prelude as serenity, CreateReply};
+
+use crate::{
+    settings::{self, Settings},
+    Context, Result,
+};
+
+/// Whether a member may run GM commands: administrators always can, otherwise they need the
+/// guild's GM role, or the Manage Server permission when no role is configured.
+pub(crate) fn is_gm_member(
+    roles: &[serenity::RoleId],
+    gm_role: Option<u64>,
+    permissions: serenity::Permissions,
+) -> bool {
+    if permissions.administrator() {
+        return true;
+    }
+
+    match gm_role {
+        Some(gm_role) => roles.iter().any(|role| role.get() == gm_role),
+        None => permissions.manage_guild(),
+    }
+}
+
+/// A poise check for GM commands, which tells the member why they were rejected.
+pub(crate) async fn is_gm(ctx: Context<'_>) -> Result<bool> {
+    let Some(guild_id) = ctx.guild_id() else {
+        reject(ctx, "GM commands can only be used in a server.").await?;
+        return Ok(false);
+    };
+    let Some(member) = ctx.author_member().await else {
+        reject(ctx, "Couldn't look up your roles, please try again.").await?;
+        return Ok(false);
+    };
+
+    let gm_role = {
+        let conn = ctx.data().pool.clone().get()?;
+        Settings::load(&conn, guild_id.get() as i64)?.gm_role
+    };
+    let permissions = member.permissions.unwrap_or_default();
+
+    if is_gm_member(&member.roles, gm_role, permissions) {
+        return Ok(true);
+    }
+
+    let msg = match gm_role {
+        Some(role_id) => format!(
+            "Sorry, only members with the <@&{}> role can use this command.",
+            role_id
+        ),
+        None => "Sorry, only members who can manage the server can use this command.".to_string(),
+    };
+    reject(ctx, &msg).await?;
+
+    Ok(false)
+}
+
+/// Whether dice can be rolled in a channel, given the guild's roll channels. An empty list allows
+/// every channel.
+pub(crate) fn is_roll_channel(roll_channels: &[u64], channel_id: serenity::ChannelId) -> bool {
+    roll_channels.is_empty() || roll_channels.contains(&channel_id.get())
+}
+
+/// A poise check for rolling commands, which points the member to the guild's roll channels.
+pub(crate) async fn in_roll_channel(ctx: Context<'_>) -> Result<bool> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(true);
+    };
+
+    let roll_channels = {
+        let conn = ctx.data().pool.clone().get()?;
+        Settings::load(&conn, guild_id.get() as i64)?.roll_channels
+    };
+    if is_roll_channel(&roll_channels, ctx.channel_id()) {
+        return Ok(true);
+    }
+
+    let msg = format!(
+        "Please roll in {}",
+        settings::mention_channels(&roll_channels)
+    );
+    reject(ctx, &msg).await?;
+
+    Ok(false)
+}
+
+async fn reject(ctx: Context<'_>, msg: &str) -> Result<()> {
+    ctx.send(
+        CreateReply::default()
+            .content(msg)
+            .ephemeral(true)
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,264 @@
+use std::fmt::Display;
+
+use futures::future::BoxFuture;
+use poise::serenity_prelude as serenity;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+
+use crate::{
+    components,
+    db::{self, Poll},
+    BotData,
+};
+
+/// Routes poll buttons, whose custom ids are `poll:poll_id:option`.
+pub(crate) const BUTTON_PREFIX: &str = "poll";
+
+/// The most options a poll can have, one button each.
+pub(crate) const MAX_OPTIONS: usize = 5;
+
+/// The longest a poll can run, in minutes.
+pub(crate) const MAX_MINUTES: u32 = 7 * 24 * 60;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Error {
+    EmptyQuestion,
+    TooFewOptions,
+    TooManyOptions,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::EmptyQuestion => write!(f, "A poll needs a question"),
+            Error::TooFewOptions => write!(f, "A poll needs at least two options"),
+            Error::TooManyOptions => {
+                write!(f, "A poll can have at most {} options", MAX_OPTIONS)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Trims a poll's question, which must not be blank.
+pub(crate) fn normalize_question(question: &str) -> Result<String, Error> {
+    let question = question.trim();
+    if question.is_empty() {
+        return Err(Error::EmptyQuestion);
+    }
+
+    Ok(question.to_string())
+}
+
+/// Trims a poll's options, skipping blank ones. There must be at least two left, and no more
+/// than there are buttons for.
+pub(crate) fn normalize_options(options: &[Option<String>]) -> Result<Vec<String>, Error> {
+    let options = options
+        .iter()
+        .flatten()
+        .map(|option| option.trim())
+        .filter(|option| !option.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if options.len() < 2 {
+        return Err(Error::TooFewOptions);
+    }
+    if options.len() > MAX_OPTIONS {
+        return Err(Error::TooManyOptions);
+    }
+
+    Ok(options)
+}
+
+/// Counts the votes for each of `options` options, given the option each voter picked. Votes for
+/// options that don't exist are ignored.
+pub(crate) fn tally(options: usize, votes: &[usize]) -> Vec<u64> {
+    let mut counts = vec![0; options];
+    for vote in votes {
+        if let Some(count) = counts.get_mut(*vote) {
+            *count += 1;
+        }
+    }
+
+    counts
+}
+
+/// The options with the most votes, more than one for a tie. None without any votes.
+pub(crate) fn winners(tally: &[u64]) -> Vec<usize> {
+    let most = tally.iter().copied().max().unwrap_or(0);
+    if most == 0 {
+        return Vec::new();
+    }
+
+    (0..tally.len()).filter(|i| tally[*i] == most).collect()
+}
+
+fn plural_votes(votes: u64) -> String {
+    if votes == 1 {
+        "1 vote".to_string()
+    } else {
+        format!("{} votes", votes)
+    }
+}
+
+/// Announces how a poll turned out, calling ties ties.
+pub(crate) fn result_text(poll: &Poll, tally: &[u64]) -> String {
+    let winners = winners(tally);
+    let names = winners
+        .iter()
+        .map(|i| format!("**{}**", poll.options[*i]))
+        .collect::<Vec<_>>();
+    match names.as_slice() {
+        [] => format!("No one voted on \"{}\".", poll.question),
+        [winner] => format!(
+            "{} wins \"{}\" with {}.",
+            winner,
+            poll.question,
+            plural_votes(tally[winners[0]])
+        ),
+        [tied @ .., last] => format!(
+            "\"{}\" is a tie between {} and {}, with {} each.",
+            poll.question,
+            tied.join(", "),
+            last,
+            plural_votes(tally[winners[0]])
+        ),
+    }
+}
+
+/// Builds a poll's embed, with the votes so far.
+pub(crate) fn embed(poll: &Poll, tally: &[u64]) -> serenity::CreateEmbed {
+    let lines = poll
+        .options
+        .iter()
+        .zip(tally)
+        .enumerate()
+        .map(|(i, (option, votes))| format!("**{}.** {} — {}", i + 1, option, plural_votes(*votes)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let status = if poll.closed {
+        "Closed".to_string()
+    } else {
+        match poll.closes_at {
+            Some(closes_at) => format!("Closes <t:{}:R>", closes_at.timestamp()),
+            None => format!(
+                "Open until <@{}> closes it with /poll close",
+                poll.creator_id
+            ),
+        }
+    };
+
+    serenity::CreateEmbed::new()
+        .title(&poll.question)
+        .description(lines)
+        .field("Status", status, false)
+}
+
+/// A numbered button for each of a poll's options, disabled once it's closed.
+pub(crate) fn buttons(poll: &Poll) -> Vec<serenity::CreateActionRow> {
+    let buttons = (0..poll.options.len())
+        .map(|i| {
+            let id = components::encode(BUTTON_PREFIX, &[&poll.id.to_string(), &i.to_string()]);
+            serenity::CreateButton::new(id)
+                .label((i + 1).to_string())
+                .style(serenity::ButtonStyle::Primary)
+                .disabled(poll.closed)
+        })
+        .collect();
+
+    vec![serenity::CreateActionRow::Buttons(buttons)]
+}
+
+/// Routes a press of a poll button to [`vote`].
+pub(crate) fn handle_vote<'a>(
+    ctx: &'a serenity::Context,
+    press: &'a serenity::ComponentInteraction,
+    data: &'a BotData,
+    payload: &'a str,
+) -> BoxFuture<'a, crate::Result<()>> {
+    Box::pin(vote(ctx, press, &data.pool, payload))
+}
+
+/// Records a vote from a poll button, whose custom id's payload is `poll_id:option`, and updates
+/// the poll's message with the new counts.
+async fn vote(
+    ctx: &serenity::Context,
+    press: &serenity::ComponentInteraction,
+    pool: &Pool<SqliteConnectionManager>,
+    payload: &str,
+) -> crate::Result<()> {
+    let Some((poll_id, option)) = components::fields(payload, 2)
+        .and_then(|fields| Some((fields[0].parse().ok()?, fields[1].parse().ok()?)))
+    else {
+        log::warn!("Ignoring a malformed poll button: {}", press.data.custom_id);
+        return Ok(());
+    };
+
+    let updated = {
+        let conn = pool.get()?;
+        match db::get_poll(&conn, poll_id)? {
+            Some(poll) if !poll.closed && option < poll.options.len() => {
+                db::vote(&conn, poll_id, press.user.id.get() as i64, option)?;
+                let tally = tally(poll.options.len(), &db::get_votes(&conn, poll_id)?);
+                Some((poll, tally))
+            }
+            _ => None,
+        }
+    };
+
+    let response = match updated {
+        Some((poll, tally)) => serenity::CreateInteractionResponse::UpdateMessage(
+            serenity::CreateInteractionResponseMessage::new().embed(embed(&poll, &tally)),
+        ),
+        None => serenity::CreateInteractionResponse::Message(
+            serenity::CreateInteractionResponseMessage::new()
+                .content("This poll is closed.")
+                .ephemeral(true),
+        ),
+    };
+    press.create_response(ctx, response).await?;
+    Ok(())
+}
+
+/// Closes a poll, showing the final counts on its message and announcing the result. Does
+/// nothing if it's already closed, so it can't be announced twice.
+pub(crate) async fn close(
+    http: impl serenity::CacheHttp,
+    pool: &Pool<SqliteConnectionManager>,
+    poll_id: i64,
+) -> crate::Result<()> {
+    let closed = {
+        let conn = pool.get()?;
+        if db::close_poll(&conn, poll_id)? {
+            let poll = db::get_poll(&conn, poll_id)?;
+            let votes = db::get_votes(&conn, poll_id)?;
+            poll.map(|poll| {
+                let tally = tally(poll.options.len(), &votes);
+                (poll, tally)
+            })
+        } else {
+            None
+        }
+    };
+    let Some((poll, tally)) = closed else {
+        return Ok(());
+    };
+
+    let channel_id = serenity::ChannelId::new(poll.channel_id as u64);
+    if let Some(message_id) = poll.message_id {
+        let edit = serenity::EditMessage::new()
+            .embed(embed(&poll, &tally))
+            .components(buttons(&poll));
+        if let Err(e) = channel_id
+            .edit_message(&http, message_id as u64, edit)
+            .await
+        {
+            log::warn!("Error updating closed poll {}: {}", poll.id, e);
+        }
+    }
+    channel_id
+        .say(http.http(), format!("🗳️ {}", result_text(&poll, &tally)))
+        .await?;
+    Ok(())
+}
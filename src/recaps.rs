@@ -0,0 +1,62 @@
+use std::fmt::Display;
+
+use poise::serenity_prelude as serenity;
+
+use crate::{db::Recap, discord};
+
+/// The longest a recap can be, which still fits in an embed's description.
+pub(crate) const MAX_LEN: usize = 4000;
+
+/// How much of a recap `/recap list` and `/recap search` show.
+const PREVIEW_LEN: usize = 80;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Error {
+    Empty,
+    TooLong,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Empty => write!(f, "A recap needs some text"),
+            Error::TooLong => write!(f, "A recap can be at most {} characters long", MAX_LEN),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Trims a recap, which must not be blank or too long.
+pub(crate) fn normalize_text(text: &str) -> Result<String, Error> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(Error::Empty);
+    }
+    if text.chars().count() > MAX_LEN {
+        return Err(Error::TooLong);
+    }
+
+    Ok(text.to_string())
+}
+
+/// A one-line summary of a recap for lists, with the start of its first line. The author isn't
+/// mentioned, so listing recaps doesn't ping everyone who wrote one.
+pub(crate) fn summary(recap: &Recap) -> String {
+    let first_line = recap.text.lines().next().unwrap_or_default();
+    format!(
+        "**Session {}** <t:{}:d>: {}",
+        recap.session,
+        recap.created_at.timestamp(),
+        discord::truncate(first_line, PREVIEW_LEN)
+    )
+}
+
+/// Builds the embed showing a recap in full.
+pub(crate) fn embed(recap: &Recap) -> serenity::CreateEmbed {
+    serenity::CreateEmbed::new()
+        .title(format!("Session {} recap", recap.session))
+        .description(&recap.text)
+        .field("Written by", format!("<@{}>", recap.author_id), true)
+        .timestamp(serenity::Timestamp::from(recap.created_at))
+}
@@ -0,0 +1,88 @@
+use std::fmt::Display;
+
+use crate::db::Resource;
+
+/// The longest a resource's name can be.
+pub(crate) const MAX_NAME_LEN: usize = 40;
+
+/// The most of a resource a player can have.
+pub(crate) const MAX_AMOUNT: u32 = 1000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Error {
+    EmptyName,
+    NameTooLong,
+    /// Using more than is left, with how many are.
+    NotEnough(i64),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::EmptyName => write!(f, "A resource needs a name"),
+            Error::NameTooLong => write!(
+                f,
+                "A resource's name can be at most {} characters long",
+                MAX_NAME_LEN
+            ),
+            Error::NotEnough(left) => write!(f, "There's only {} left", left),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Normalizes a resource's name, so names are matched regardless of case.
+pub(crate) fn normalize_name(name: &str) -> Result<String, Error> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() {
+        return Err(Error::EmptyName);
+    }
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(Error::NameTooLong);
+    }
+
+    Ok(name)
+}
+
+/// How many are left after using `amount` of what's `current`ly left. Using more than is left
+/// fails instead of going below 0.
+pub(crate) fn spend(current: i64, amount: i64) -> Result<i64, Error> {
+    if amount > current {
+        return Err(Error::NotEnough(current));
+    }
+
+    Ok(current - amount)
+}
+
+/// How many there are after restoring `amount`, or all of them without an amount. Restoring
+/// never goes past the maximum.
+pub(crate) fn restore(current: i64, max: i64, amount: Option<i64>) -> i64 {
+    match amount {
+        Some(amount) => (current + amount).min(max).max(current),
+        None => max.max(current),
+    }
+}
+
+/// Describes a resource, like `spell_slots_3: 2/3`, noting when a rest doesn't restore it.
+pub(crate) fn format_resource(resource: &Resource) -> String {
+    let line = format!("{}: {}/{}", resource.name, resource.current, resource.max);
+    if resource.restore_on_rest {
+        line
+    } else {
+        format!("{} (not restored by /rest)", line)
+    }
+}
+
+/// Groups resources sorted by player into each player's resources.
+pub(crate) fn group_by_player(resources: &[Resource]) -> Vec<(i64, Vec<&Resource>)> {
+    let mut groups: Vec<(i64, Vec<&Resource>)> = Vec::new();
+    for resource in resources {
+        match groups.last_mut() {
+            Some((player_id, group)) if *player_id == resource.player_id => group.push(resource),
+            _ => groups.push((resource.player_id, vec![resource])),
+        }
+    }
+
+    groups
+}
@@ -0,0 +1,640 @@
+use evaluroll::{
+    ast::{DropDice, Expression, Factor, KeepDice, Output, Roll, RollExpr},
+    Eval,
+};
+use rand::Rng;
+use std::{fmt::Display, ops::Range};
+
+/// Examples of the dice syntax with what they do, for `/help roll` and `/roll-help`. Every
+/// example must parse.
+pub const EXAMPLES: &[(&str, &str)] = &[
+    ("d20", "Rolls one twenty-sided die"),
+    ("2d6+3", "Rolls two six-sided dice and adds 3"),
+    ("4d6k3", "Keeps the highest 3 dice, `kh3` works too"),
+    ("2d20kl1", "Keeps the lowest die, e.g. disadvantage"),
+    ("4d6d1", "Drops the lowest die, `dl1` works too"),
+    ("4d6dh1", "Drops the highest die"),
+    (
+        "6d6k>=4",
+        "Keeps every die that rolled 4 or more, and adds them up",
+    ),
+    ("(1d4)d6", "Rolls a d4, then that many d6s"),
+    (
+        "10 + -2d6",
+        "Subtracts a roll, after keeping or dropping any dice",
+    ),
+    (
+        "avg 3d6+2",
+        "Takes the average of 3d6, rounded down, instead of rolling",
+    ),
+    (
+        "3d6p",
+        "Penetrating dice: a max roll rolls again and adds 1 less, shown like `6→4-1`",
+    ),
+    (
+        "(2d6+3)*2",
+        "Groups with parentheses, and supports `*`, `/` and `%`",
+    ),
+];
+
+/// A cheat sheet for the dice syntax, built from [`EXAMPLES`].
+pub fn syntax_help() -> String {
+    EXAMPLES
+        .iter()
+        .map(|(example, description)| format!("`{}` {}", example, description))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Suggests expressions from a user's history, most recent first, for what they've typed so far.
+/// Expressions starting with it come before those that only contain it, ignoring case and
+/// spaces.
+pub fn suggest<'a>(history: &'a [String], partial: &str, limit: usize) -> Vec<&'a str> {
+    let squash = |s: &str| {
+        s.chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_lowercase()
+    };
+    let partial = squash(partial);
+
+    let (starting, containing): (Vec<_>, Vec<_>) = history
+        .iter()
+        .filter(|expression| squash(expression).contains(&partial))
+        .partition(|expression| squash(expression).starts_with(&partial));
+    starting
+        .into_iter()
+        .chain(containing)
+        .map(String::as_str)
+        .take(limit)
+        .collect()
+}
+
+/// Parses and rolls a dice expression, detecting a critical success or fumble. Dice that are
+/// averaged or penetrate are returned separately as notes, since their rolls alone don't add up
+/// to what they're worth.
+pub fn roll<R: Rng + ?Sized>(
+    dice: &str,
+    rng: &mut R,
+) -> Result<(Option<Crit>, Output, Vec<Note>), evaluroll::Error> {
+    let negated = negate_dice(dice.trim());
+    let dice = negated.as_str();
+
+    // The parser can't roll special dice itself, so they're worked out first and replaced with
+    // their totals. Their rolls come before the rest of the expression's.
+    let mut expanded = String::new();
+    let mut special_rolls = Vec::new();
+    let mut notes = Vec::new();
+    let mut leading = None;
+    let mut rest = 0;
+    for (range, special) in parse_specials(dice)? {
+        let (total, rolls, mut special_notes) = special.eval(rng)?;
+        notes.append(&mut special_notes);
+        if range.start == 0 {
+            leading = Some((special, rolls.clone()));
+        }
+
+        expanded.push_str(&dice[rest..range.start]);
+        // Parentheses keep the total from running into a neighbouring number.
+        expanded.push_str(&format!("({})", total));
+        special_rolls.extend(rolls);
+        rest = range.end;
+    }
+    expanded.push_str(&dice[rest..]);
+
+    let expr = evaluroll::parse(&expanded)?;
+    let mut output = expr.eval(rng)?;
+    let crit = match leading {
+        Some((Special::RerollOnce { sides: 20, .. }, rolls)) => natural(rolls.iter()),
+        _ => crit(&expr, &output),
+    };
+    special_rolls.append(&mut output.rolls);
+    output.rolls = special_rolls;
+
+    Ok((crit, output, notes))
+}
+
+/// Wraps dice with a minus in front, like `-2d6` or the `-1d4` in `5 + -1d4`, as `(0-2d6)`, since
+/// the parser only takes a minus in front of a number or the whole expression. The dice keep
+/// their rolls, and keeping or dropping comes first: `-4d6k3` subtracts the highest 3.
+fn negate_dice(dice: &str) -> String {
+    let mut negated = String::with_capacity(dice.len());
+    // Whether a minus here negates what follows, rather than subtracting it.
+    let mut unary = true;
+    let mut at = 0;
+    while let Some(c) = dice[at..].chars().next() {
+        if c == '-' && unary {
+            let token = &dice[at + 1..];
+            let token = &token[..token
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '>' || c == '='))
+                .unwrap_or(token.len())];
+            if token
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .starts_with('d')
+            {
+                negated.push_str(&format!("(0-{})", token));
+                at += 1 + token.len();
+                unary = false;
+                continue;
+            }
+        }
+
+        negated.push(c);
+        if !c.is_whitespace() {
+            unary = "+-*/%(".contains(c);
+        }
+        at += c.len_utf8();
+    }
+
+    negated
+}
+
+/// Marks dice to be averaged rather than rolled, e.g. `avg 3d6`.
+const AVERAGE_KEYWORD: &str = "avg";
+
+/// Marks the value dice are rerolled once on, e.g. `1d20ro1`.
+const REROLL_ONCE_KEYWORD: &str = "ro";
+
+/// Marks dice that penetrate, e.g. `3d6p`.
+const PENETRATING_KEYWORD: &str = "p";
+
+/// Marks the least a die must roll to be kept, e.g. `6d6k>=4`.
+const KEEP_AT_LEAST_KEYWORD: &str = "k>=";
+
+/// The most times one penetrating die rolls again, so a d2 can't keep rolling forever.
+const MAX_PENETRATIONS: usize = 100;
+
+/// Dice the parser doesn't support, which are worked out before the rest of the expression.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Special {
+    /// `avg NdS`: the dice's average, instead of rolling them.
+    Average { count: u32, sides: u32 },
+    /// `NdSroV`: each die that comes up V is rerolled once, and the new roll is kept even if
+    /// it's worse.
+    RerollOnce { count: u32, sides: u32, target: u32 },
+    /// `NdSp`: each die that rolls its maximum rolls again, adding the new roll minus 1, for as
+    /// long as it keeps rolling the maximum. Unlike exploding dice, which add the full value.
+    Penetrating { count: u32, sides: u32 },
+    /// `NdSk>=V`: every die that rolls at least V is kept and added up, however many that is.
+    /// Unlike `NdSkN`, which keeps a fixed number of dice, so the two can't be combined.
+    KeepAtLeast { count: u32, sides: u32, min: u32 },
+}
+
+impl Special {
+    /// The dice's total, their rolls, including any that were rerolled and not kept, and notes
+    /// for the dice whose rolls don't show what they're worth.
+    fn eval<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+    ) -> Result<(i32, Vec<Roll>, Vec<Note>), evaluroll::Error> {
+        let too_many = || evaluroll::Error::ParseError("Too many dice".to_string());
+
+        match *self {
+            // Rounded down as in published stat blocks, e.g. 7 for 2d6 and 4 for 1d8.
+            Special::Average { count, sides } => {
+                let total = u64::from(count) * (u64::from(sides) + 1) / 2;
+                let total = i32::try_from(total).map_err(|_| too_many())?;
+                let averaged = Averaged {
+                    count,
+                    sides,
+                    total,
+                };
+                Ok((total, Vec::new(), vec![Note::Averaged(averaged)]))
+            }
+            Special::RerollOnce {
+                count,
+                sides,
+                target,
+            } => {
+                let mut rolls = Vec::new();
+                for _ in 0..count {
+                    let result = rng.gen_range(1..=sides);
+                    if result == target {
+                        rolls.push(Roll {
+                            result,
+                            keep: false,
+                        });
+                        rolls.push(Roll {
+                            result: rng.gen_range(1..=sides),
+                            keep: true,
+                        });
+                    } else {
+                        rolls.push(Roll { result, keep: true });
+                    }
+                }
+
+                let total = rolls
+                    .iter()
+                    .filter(|roll| roll.keep)
+                    .map(|roll| u64::from(roll.result))
+                    .sum::<u64>();
+                Ok((
+                    i32::try_from(total).map_err(|_| too_many())?,
+                    rolls,
+                    Vec::new(),
+                ))
+            }
+            Special::Penetrating { count, sides } => {
+                // Dice that don't penetrate are plain rolls, the rest are shown as their chain.
+                let mut rolls = Vec::new();
+                let mut notes = Vec::new();
+                for _ in 0..count {
+                    let result = rng.gen_range(1..=sides);
+                    if result != sides {
+                        rolls.push(Roll { result, keep: true });
+                        continue;
+                    }
+
+                    let mut chain = vec![result];
+                    while chain.last() == Some(&sides) && chain.len() <= MAX_PENETRATIONS {
+                        chain.push(rng.gen_range(1..=sides));
+                    }
+                    notes.push(Note::Penetrated(Penetrated { chain }));
+                }
+
+                let total = rolls
+                    .iter()
+                    .map(|roll| u64::from(roll.result))
+                    .chain(notes.iter().map(|note| match note {
+                        Note::Penetrated(penetrated) => penetrated.total(),
+                        Note::Averaged(_) => 0,
+                    }))
+                    .sum::<u64>();
+                Ok((i32::try_from(total).map_err(|_| too_many())?, rolls, notes))
+            }
+            Special::KeepAtLeast { count, sides, min } => {
+                let rolls = (0..count)
+                    .map(|_| {
+                        let result = rng.gen_range(1..=sides);
+                        Roll {
+                            result,
+                            keep: result >= min,
+                        }
+                    })
+                    .collect::<Vec<_>>();
+
+                let total = rolls
+                    .iter()
+                    .filter(|roll| roll.keep)
+                    .map(|roll| u64::from(roll.result))
+                    .sum::<u64>();
+                Ok((
+                    i32::try_from(total).map_err(|_| too_many())?,
+                    rolls,
+                    Vec::new(),
+                ))
+            }
+        }
+    }
+}
+
+/// Something about a roll that its dice don't show, listed after them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Note {
+    Averaged(Averaged),
+    Penetrated(Penetrated),
+}
+
+impl Display for Note {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Note::Averaged(averaged) => averaged.fmt(f),
+            Note::Penetrated(penetrated) => penetrated.fmt(f),
+        }
+    }
+}
+
+/// A penetrating die that rolled its maximum, and the rolls that followed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Penetrated {
+    /// Every roll of the die, starting with its maximum.
+    pub chain: Vec<u32>,
+}
+
+impl Penetrated {
+    /// What the die is worth: the first roll, plus each roll after it minus 1.
+    pub fn total(&self) -> u64 {
+        self.chain
+            .iter()
+            .enumerate()
+            .map(|(i, roll)| u64::from(*roll) - u64::from(i > 0))
+            .sum()
+    }
+}
+
+impl Display for Penetrated {
+    /// The chain of rolls with the 1 taken off each penetration, like `6→6-1→2-1`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let chain = self
+            .chain
+            .iter()
+            .enumerate()
+            .map(|(i, roll)| {
+                if i == 0 {
+                    roll.to_string()
+                } else {
+                    format!("{}-1", roll)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("→");
+        write!(f, "{}", chain)
+    }
+}
+
+/// Dice that were averaged rather than rolled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Averaged {
+    pub count: u32,
+    pub sides: u32,
+    pub total: i32,
+}
+
+impl Display for Averaged {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}d{} = {}",
+            AVERAGE_KEYWORD, self.count, self.sides, self.total
+        )
+    }
+}
+
+/// Parses a special die from its keyword at `at`, looking no further back than `from`.
+type SpecialParser = fn(&str, usize, usize) -> Result<(Range<usize>, Special), evaluroll::Error>;
+
+/// Finds the special dice in an expression, in order, with where each is written.
+fn parse_specials(dice: &str) -> Result<Vec<(Range<usize>, Special)>, evaluroll::Error> {
+    let parsers: [(&str, SpecialParser); 4] = [
+        (AVERAGE_KEYWORD, parse_average),
+        (REROLL_ONCE_KEYWORD, parse_reroll_once),
+        (PENETRATING_KEYWORD, parse_penetrating),
+        (KEEP_AT_LEAST_KEYWORD, parse_keep_at_least),
+    ];
+
+    let lower = dice.to_ascii_lowercase();
+    let mut specials = Vec::new();
+    let mut from = 0;
+    while let Some((at, parse)) = parsers
+        .iter()
+        .filter_map(|(keyword, parse)| lower[from..].find(keyword).map(|i| (from + i, parse)))
+        .min_by_key(|(at, _)| *at)
+    {
+        let special = parse(&lower, from, at)?;
+        from = special.0.end;
+        specials.push(special);
+    }
+
+    Ok(specials)
+}
+
+/// Parses `avg NdS` starting at `at`. Only plain dice can be averaged, without keeping,
+/// dropping or rerolling.
+fn parse_average(
+    dice: &str,
+    _from: usize,
+    at: usize,
+) -> Result<(Range<usize>, Special), evaluroll::Error> {
+    let invalid = || {
+        evaluroll::Error::ParseError(format!(
+            "{} must be followed by plain dice, e.g. {} 3d6",
+            AVERAGE_KEYWORD, AVERAGE_KEYWORD
+        ))
+    };
+
+    let after = dice[at + AVERAGE_KEYWORD.len()..].trim_start();
+    let (count, after) = split_digits(after);
+    let after = after.strip_prefix('d').ok_or_else(invalid)?;
+    let (sides, after) = split_digits(after);
+    if sides.is_empty() || after.starts_with(['k', 'd', 'r', 'p']) {
+        return Err(invalid());
+    }
+
+    let (count, sides) = parse_dice(count, sides, invalid)?;
+    let end = dice.len() - after.len();
+    Ok((at..end, Special::Average { count, sides }))
+}
+
+/// Parses `NdSroV` around the `ro` at `at`, looking no further back than `from`. The dice must
+/// have a fixed count, and can't also keep or drop.
+fn parse_reroll_once(
+    dice: &str,
+    from: usize,
+    at: usize,
+) -> Result<(Range<usize>, Special), evaluroll::Error> {
+    let invalid = || {
+        evaluroll::Error::ParseError(format!(
+            "{} must follow plain dice and be followed by the value to reroll, e.g. 1d20{}1",
+            REROLL_ONCE_KEYWORD, REROLL_ONCE_KEYWORD
+        ))
+    };
+
+    let (start, count, sides) = parse_dice_before(dice, from, at, invalid)?;
+    let (target, after) = split_digits(&dice[at + REROLL_ONCE_KEYWORD.len()..]);
+    if target.is_empty() || after.starts_with(['k', 'd', 'r', 'p']) {
+        return Err(invalid());
+    }
+
+    let target = target.parse::<u32>().map_err(|_| invalid())?;
+    if !(1..=sides).contains(&target) {
+        return Err(evaluroll::Error::ParseError(format!(
+            "A d{} can't roll a {} to reroll",
+            sides, target
+        )));
+    }
+
+    let end = dice.len() - after.len();
+    Ok((
+        start..end,
+        Special::RerollOnce {
+            count,
+            sides,
+            target,
+        },
+    ))
+}
+
+/// Parses `NdSp` around the `p` at `at`, looking no further back than `from`. The dice must have
+/// a fixed count, and can't also keep, drop or reroll.
+fn parse_penetrating(
+    dice: &str,
+    from: usize,
+    at: usize,
+) -> Result<(Range<usize>, Special), evaluroll::Error> {
+    let invalid = || {
+        evaluroll::Error::ParseError(format!(
+            "{} must follow plain dice, e.g. 3d6{}",
+            PENETRATING_KEYWORD, PENETRATING_KEYWORD
+        ))
+    };
+
+    let (start, count, sides) = parse_dice_before(dice, from, at, invalid)?;
+    let after = &dice[at + PENETRATING_KEYWORD.len()..];
+    if after.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return Err(invalid());
+    }
+
+    Ok((
+        start..at + PENETRATING_KEYWORD.len(),
+        Special::Penetrating { count, sides },
+    ))
+}
+
+/// Parses `NdSk>=V` around the `k>=` at `at`, looking no further back than `from`. The dice must
+/// have a fixed count, and can't also keep or drop a number of dice, since it's unclear which
+/// would come first.
+fn parse_keep_at_least(
+    dice: &str,
+    from: usize,
+    at: usize,
+) -> Result<(Range<usize>, Special), evaluroll::Error> {
+    let invalid = || {
+        evaluroll::Error::ParseError(format!(
+            "{} must follow plain dice and be followed by the least to keep, e.g. 6d6{}4",
+            KEEP_AT_LEAST_KEYWORD, KEEP_AT_LEAST_KEYWORD
+        ))
+    };
+    let contradictory = || {
+        evaluroll::Error::ParseError(
+            "Dice can keep a number of dice or every die at least a value, not both".to_string(),
+        )
+    };
+
+    // Keeping or dropping by count before `k>=` leaves letters after the dice's `d`, e.g. `6d6k2`.
+    let before = &dice[from..at];
+    let token = &before[before
+        .trim_end_matches(|c: char| c.is_ascii_alphanumeric())
+        .len()..];
+    if token
+        .split_once('d')
+        .is_some_and(|(_, after)| after.contains(|c: char| c.is_ascii_alphabetic()))
+    {
+        return Err(contradictory());
+    }
+
+    let (start, count, sides) = parse_dice_before(dice, from, at, invalid)?;
+    let (min, after) = split_digits(&dice[at + KEEP_AT_LEAST_KEYWORD.len()..]);
+    if min.is_empty() {
+        return Err(invalid());
+    }
+    if after.starts_with(['k', 'd']) {
+        return Err(contradictory());
+    }
+    if after.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return Err(invalid());
+    }
+
+    let min = min.parse::<u32>().map_err(|_| invalid())?;
+    if !(1..=sides).contains(&min) {
+        return Err(evaluroll::Error::ParseError(format!(
+            "The least to keep on a d{} must be from 1 to {}",
+            sides, sides
+        )));
+    }
+
+    let end = dice.len() - after.len();
+    Ok((start..end, Special::KeepAtLeast { count, sides, min }))
+}
+
+/// Parses the plain dice `NdS` that end at `at`, looking no further back than `from`. Returns
+/// where they start with their count and sides.
+fn parse_dice_before(
+    dice: &str,
+    from: usize,
+    at: usize,
+    invalid: impl Fn() -> evaluroll::Error,
+) -> Result<(usize, u32, u32), evaluroll::Error> {
+    let before = &dice[from..at];
+    let sides = &before[before.trim_end_matches(|c: char| c.is_ascii_digit()).len()..];
+    let before = before[..before.len() - sides.len()]
+        .strip_suffix('d')
+        .ok_or_else(&invalid)?;
+    let count = &before[before.trim_end_matches(|c: char| c.is_ascii_digit()).len()..];
+    let start = from + before.len() - count.len();
+    if sides.is_empty() || dice[..start].ends_with(')') {
+        return Err(invalid());
+    }
+
+    let (count, sides) = parse_dice(count, sides, invalid)?;
+    Ok((start, count, sides))
+}
+
+/// Parses the count and sides of plain dice, where a missing count means 1.
+fn parse_dice(
+    count: &str,
+    sides: &str,
+    invalid: impl Fn() -> evaluroll::Error,
+) -> Result<(u32, u32), evaluroll::Error> {
+    let count = if count.is_empty() {
+        1
+    } else {
+        count.parse::<u32>().map_err(|_| invalid())?
+    };
+    let sides = sides.parse::<u32>().map_err(|_| invalid())?;
+    if count == 0 {
+        return Err(evaluroll::Error::InvalidCount);
+    }
+    if sides < 2 {
+        return Err(evaluroll::Error::InvalidSides);
+    }
+
+    Ok((count, sides))
+}
+
+/// Splits the leading digits off a string.
+fn split_digits(s: &str) -> (&str, &str) {
+    s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()))
+}
+
+/// A natural 20 or natural 1 on a d20 roll.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Crit {
+    Success,
+    Fumble,
+}
+
+/// Detects a critical success or fumble when an expression leads with a d20 roll, e.g. `1d20+5`
+/// or `2d20k1`, and exactly one of its d20s was kept.
+pub fn crit(expr: &Expression, output: &Output) -> Option<Crit> {
+    let Factor::DiceRoll(dice) = expr.term.factor.as_ref() else {
+        return None;
+    };
+
+    if !matches!(dice.sides.as_ref(), RollExpr::Number(20)) {
+        return None;
+    }
+
+    // Nested expressions roll dice of their own, which come before the d20s in the output.
+    let count = match dice.count.as_deref() {
+        None => 1,
+        Some(RollExpr::Number(count)) => *count as usize,
+        Some(RollExpr::Expression(_)) => return None,
+    };
+    let keep = match &dice.keep {
+        Some(KeepDice::High(keep) | KeepDice::Low(keep)) => Some(keep.as_ref()),
+        None => None,
+    };
+    let drop = match &dice.drop {
+        Some(DropDice::High(drop) | DropDice::Low(drop)) => Some(drop.as_ref()),
+        None => None,
+    };
+    if [keep, drop]
+        .into_iter()
+        .flatten()
+        .any(|e| matches!(e, RollExpr::Expression(_)))
+    {
+        return None;
+    }
+
+    natural(output.rolls.iter().take(count))
+}
+
+/// A natural 20 or natural 1 when exactly one of a d20 roll's dice was kept.
+fn natural<'a>(rolls: impl Iterator<Item = &'a Roll>) -> Option<Crit> {
+    let mut kept = rolls.filter(|roll| roll.keep);
+    match (kept.next(), kept.next()) {
+        (Some(roll), None) if roll.result == 20 => Some(Crit::Success),
+        (Some(roll), None) if roll.result == 1 => Some(Crit::Fumble),
+        _ => None,
+    }
+}
@@ -1,15 +1,27 @@
 use std::{
-    fmt::Display,
-    sync::{Mutex, RwLock},
+    collections::HashMap, fmt::Display, future::Future, str::FromStr, sync::Arc, time::Duration,
 };
 
+use chrono::{DateTime, Datelike, Days, Local, NaiveTime, Utc, Weekday};
 use poise::serenity_prelude::{self as serenity, CacheHttp};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
-use timer::{Guard, Timer};
-use tokio::runtime::Handle;
+use tokio::{
+    sync::watch,
+    task::JoinHandle,
+    time::{Instant, MissedTickBehavior},
+};
 
-use crate::db::{self, ScheduledMessage};
+use crate::{
+    backup::Backups,
+    db::{self, ScheduledMessage},
+    discord,
+    metrics::Metrics,
+    notify::{self, Event},
+    polls,
+    settings::{Key, Settings},
+    templates,
+};
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
@@ -17,6 +29,7 @@ type Result<T, E = Error> = std::result::Result<T, E>;
 pub(crate) enum Error {
     Db(db::Error),
     R2d2(r2d2::Error),
+    ShutDown,
 }
 
 impl From<db::Error> for Error {
@@ -36,30 +49,144 @@ impl Display for Error {
         match self {
             Error::Db(e) => write!(f, "Database error: {}", e),
             Error::R2d2(e) => write!(f, "R2D2 error: {}", e),
+            Error::ShutDown => write!(f, "The scheduler has been shut down"),
         }
     }
 }
 
 impl std::error::Error for Error {}
 
+/// A time of the week, in UTC, written like `sun 20:00`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Weekly {
+    pub weekday: Weekday,
+    pub time: NaiveTime,
+}
+
+impl Weekly {
+    /// The next occurrence strictly after `now`.
+    pub(crate) fn next_after(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let days_ahead =
+            (7 + self.weekday.num_days_from_monday() - now.weekday().num_days_from_monday()) % 7;
+        let next = (now.date_naive() + Days::new(days_ahead as u64))
+            .and_time(self.time)
+            .and_utc();
+
+        if next <= now {
+            next + Days::new(7)
+        } else {
+            next
+        }
+    }
+}
+
+impl Default for Weekly {
+    fn default() -> Self {
+        Self {
+            weekday: Weekday::Sun,
+            time: NaiveTime::from_hms_opt(20, 0, 0).expect("valid time"),
+        }
+    }
+}
+
+impl FromStr for Weekly {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (weekday, time) = s
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| format!("Expected a day and time, like `sun 20:00`, got `{}`", s))?;
+
+        Ok(Self {
+            weekday: weekday
+                .parse()
+                .map_err(|_| format!("`{}` is not a day of the week", weekday))?,
+            time: NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .map_err(|_| format!("`{}` is not a time like 20:00", time))?,
+        })
+    }
+}
+
+impl Display for Weekly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} UTC", self.weekday, self.time.format("%H:%M"))
+    }
+}
+
+/// A spawned task that's cancelled when it's dropped or replaced.
+struct Job(JoinHandle<()>);
+
+impl Job {
+    fn spawn(task: impl Future<Output = ()> + Send + 'static) -> Self {
+        Self(tokio::spawn(task))
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Waits until a time, or not at all if it's already passed.
+async fn sleep_until(on: DateTime<Utc>) {
+    let delay = (on - Utc::now()).to_std().unwrap_or_default();
+    tokio::time::sleep_until(Instant::now() + delay).await;
+}
+
 pub(crate) struct Scheduler<T>
 where
     T: AsRef<serenity::Http> + Clone + Send + Sync + 'static,
 {
-    timer: Mutex<timer::Timer>,
     pool: Pool<SqliteConnectionManager>,
-    guard: RwLock<Option<Guard>>,
+    /// Sends the scheduled message.
+    job: Option<Job>,
+    backup_job: Option<Job>,
+    /// Weekly MVP resolution, by guild id.
+    mvp_jobs: HashMap<u64, Job>,
+    /// Closing timed polls, by poll id.
+    poll_jobs: HashMap<i64, Job>,
     ctx: T,
+    shut_down: bool,
+    /// When the scheduled message is sent, if there is one.
+    next_game: watch::Sender<Option<DateTime<Local>>>,
+    metrics: Arc<Metrics>,
 }
 
-impl<'a, T: AsRef<serenity::Http> + CacheHttp + Clone + Send + Sync + 'a> Scheduler<T> {
-    pub(crate) fn new(pool: Pool<SqliteConnectionManager>, ctx: T) -> Self {
+impl<T: AsRef<serenity::Http> + CacheHttp + Clone + Send + Sync> Scheduler<T> {
+    pub(crate) fn new(pool: Pool<SqliteConnectionManager>, ctx: T, metrics: Arc<Metrics>) -> Self {
         Self {
-            timer: Mutex::new(Timer::new()),
             pool,
-            guard: RwLock::new(None),
+            job: None,
+            backup_job: None,
+            mvp_jobs: HashMap::new(),
+            poll_jobs: HashMap::new(),
             ctx,
+            shut_down: false,
+            next_game: watch::Sender::new(None),
+            metrics,
+        }
+    }
+
+    /// Watches when the next scheduled message is sent, e.g. to show it in the bot's status.
+    pub(crate) fn subscribe_next_game(&self) -> watch::Receiver<Option<DateTime<Local>>> {
+        self.next_game.subscribe()
+    }
+
+    /// Cancels everything that's scheduled, and refuses to schedule anything else.
+    pub(crate) fn shutdown(&mut self) {
+        self.shut_down = true;
+
+        self.job.take();
+        self.backup_job.take();
+        self.mvp_jobs.clear();
+        self.poll_jobs.clear();
+        for job in ["schedule", "backup", "mvp"] {
+            self.metrics.set_scheduled(job, 0);
         }
+
+        log::info!("Scheduler shut down");
     }
 
     pub(crate) fn sync_schedule(&mut self) -> Result<()> {
@@ -79,65 +206,523 @@ impl<'a, T: AsRef<serenity::Http> + CacheHttp + Clone + Send + Sync + 'a> Schedu
     }
 
     pub(crate) fn schedule(&mut self, sch: &ScheduledMessage) -> Result<()> {
+        if self.shut_down {
+            return Err(Error::ShutDown);
+        }
+
         let conn = self.pool.clone().get()?;
 
         db::create_schedule(&conn, sch)?;
         self.inner_schedule(sch)
     }
 
+    /// Changes the scheduled message and waits for its new time instead. False if nothing is
+    /// scheduled any more, in which case nothing is changed.
+    pub(crate) fn reschedule(&mut self, sch: &ScheduledMessage) -> Result<bool> {
+        if self.shut_down {
+            return Err(Error::ShutDown);
+        }
+
+        let conn = self.pool.clone().get()?;
+
+        if !db::update_schedule(&conn, sch)? {
+            return Ok(false);
+        }
+        // Replacing the job stops the old timer.
+        self.inner_schedule(sch)?;
+        Ok(true)
+    }
+
+    /// Backs up the database every `interval`, starting one interval from now.
+    pub(crate) fn schedule_backups(&mut self, backups: Backups, interval: Duration) -> Result<()> {
+        if self.shut_down {
+            return Err(Error::ShutDown);
+        }
+
+        let pool = self.pool.clone();
+        let metrics = self.metrics.clone();
+
+        let job = Job::spawn(async move {
+            let mut ticks = tokio::time::interval_at(Instant::now() + interval, interval);
+            // A backup that runs long pushes the next one back, rather than running two at once.
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                metrics.record_fire("backup");
+
+                let pool = pool.clone();
+                let backups = backups.clone();
+                let backed_up = tokio::task::spawn_blocking(move || {
+                    pool.get()
+                        .map_err(|e| e.into())
+                        .and_then(|conn| backups.run(&conn))
+                })
+                .await;
+
+                match backed_up {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => log::error!("Error backing up database: {}", e),
+                    Err(e) => log::error!("Error backing up database: {}", e),
+                }
+            }
+        });
+
+        self.backup_job.replace(job);
+        self.metrics.set_scheduled("backup", 1);
+
+        Ok(())
+    }
+
+    /// Arms weekly MVP resolution for every guild that has configured an MVP channel.
+    pub(crate) fn sync_all_mvp(&mut self) -> Result<()> {
+        let conn = self.pool.clone().get()?;
+
+        for (guild_id, _) in db::get_setting_for_all(&conn, Key::MvpChannel.name())? {
+            self.sync_mvp(guild_id as u64)?;
+        }
+
+        Ok(())
+    }
+
+    /// Arms, re-arms or stops weekly MVP resolution for a guild to match its settings.
+    pub(crate) fn sync_mvp(&mut self, guild_id: u64) -> Result<()> {
+        if self.shut_down {
+            return Err(Error::ShutDown);
+        }
+
+        let conn = self.pool.clone().get()?;
+
+        let settings = Settings::load(&conn, guild_id as i64)?;
+        let Some(channel_id) = settings.mvp_channel else {
+            if self.mvp_jobs.remove(&guild_id).is_some() {
+                log::info!("Stopped weekly MVP resolution for guild {}", guild_id);
+            }
+            self.metrics
+                .set_scheduled("mvp", self.mvp_jobs.len() as u64);
+            return Ok(());
+        };
+
+        let weekly = settings.mvp_time;
+        let mut next = weekly.next_after(Utc::now());
+        log::info!(
+            "Resolving the MVP for guild {} every {}, next on {}",
+            guild_id,
+            weekly,
+            next
+        );
+
+        let ctx = self.ctx.clone();
+        let pool = self.pool.clone();
+        let metrics = self.metrics.clone();
+
+        let job = Job::spawn(async move {
+            loop {
+                sleep_until(next).await;
+                metrics.record_fire("mvp");
+                Self::resolve_mvp(&ctx, &pool, guild_id, channel_id).await;
+                // From the last run rather than now, so waking a moment early can't run it twice.
+                next = weekly.next_after(next);
+            }
+        });
+
+        self.mvp_jobs.insert(guild_id, job);
+        self.metrics
+            .set_scheduled("mvp", self.mvp_jobs.len() as u64);
+        Ok(())
+    }
+
+    /// Arms closing every open poll that closes by itself, e.g. after a restart.
+    pub(crate) fn sync_polls(&mut self) -> Result<()> {
+        let conn = self.pool.clone().get()?;
+
+        for poll in db::get_timed_polls(&conn)? {
+            if let Some(closes_at) = poll.closes_at {
+                self.schedule_poll_close(poll.id, closes_at)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Closes a poll and announces its result at `closes_at`.
+    pub(crate) fn schedule_poll_close(
+        &mut self,
+        poll_id: i64,
+        closes_at: DateTime<Utc>,
+    ) -> Result<()> {
+        if self.shut_down {
+            return Err(Error::ShutDown);
+        }
+
+        let ctx = self.ctx.clone();
+        let pool = self.pool.clone();
+        let metrics = self.metrics.clone();
+
+        let job = Job::spawn(async move {
+            sleep_until(closes_at).await;
+            metrics.record_fire("poll");
+            if let Err(e) = polls::close(&ctx, &pool, poll_id).await {
+                log::error!("Error closing poll {}: {}", poll_id, e);
+            }
+        });
+
+        // Polls that have already closed don't need their jobs any more.
+        self.poll_jobs.retain(|_, job| !job.0.is_finished());
+        self.poll_jobs.insert(poll_id, job);
+        Ok(())
+    }
+
+    /// Stops waiting to close a poll, e.g. because it's been closed early.
+    pub(crate) fn cancel_poll(&mut self, poll_id: i64) {
+        self.poll_jobs.remove(&poll_id);
+    }
+
     fn inner_schedule(&mut self, sch: &ScheduledMessage) -> Result<()> {
+        if self.shut_down {
+            return Err(Error::ShutDown);
+        }
+
         let sch = sch.clone();
-        let handle = Handle::current();
 
         let ctx = self.ctx.clone();
         let pool = self.pool.clone();
+        let next_game = self.next_game.clone();
+        let metrics = self.metrics.clone();
+        self.next_game.send_replace(Some(sch.on));
+        self.metrics.set_scheduled("schedule", 1);
 
-        let guard = self
-            .timer
-            .lock()
-            .expect("Unable to lock timer")
-            .schedule_with_date(sch.on, move || {
-                Self::send_msg(ctx.clone(), &pool, handle.clone(), &sch)
-            });
+        let job = Job::spawn(async move {
+            sleep_until(sch.on.with_timezone(&Utc)).await;
+            metrics.record_fire("schedule");
+            log::info!("Sending scheduled message");
 
-        let old_guard = self
-            .guard
-            .write()
-            .expect("Unable to get mut guard")
-            .replace(guard);
+            let event = match Self::send_now(&ctx, &pool, &sch).await {
+                Ok(_) => Event::ScheduleSent {
+                    channel_id: sch.channel_id,
+                },
+                Err(e) => {
+                    log::error!("Error sending scheduled message: {}", e);
+                    Event::ScheduleFailed {
+                        channel_id: sch.channel_id,
+                        error: e.to_string(),
+                    }
+                }
+            };
+            if let Some(created_by) = sch.created_by {
+                notify::send(&ctx, &pool, &event, &[created_by as i64]).await;
+            }
+            next_game.send_replace(None);
+            metrics.set_scheduled("schedule", 0);
+        });
 
-        drop(old_guard);
+        self.job.replace(job);
 
         Ok(())
     }
 
-    fn send_msg(
-        ctx: T,
+    /// Stops waiting to send the scheduled message, e.g. because it's been sent early.
+    pub(crate) fn cancel_pending(&mut self) {
+        self.job.take();
+        self.next_game.send_replace(None);
+        self.metrics.set_scheduled("schedule", 0);
+    }
+
+    /// Sends a scheduled message, then deletes it now that it's been sent.
+    pub(crate) async fn send_now(
+        ctx: &T,
+        pool: &Pool<SqliteConnectionManager>,
+        sch: &ScheduledMessage,
+    ) -> serenity::Result<serenity::MessageId> {
+        let sch = if sch.templated {
+            Self::fill_template(pool, sch)
+        } else {
+            sch.clone()
+        };
+        let channel = serenity::ChannelId::from(sch.channel_id);
+        let msg = match discord::schedule_embed(&sch) {
+            Some(embed) => {
+                channel
+                    .send_message(ctx, serenity::CreateMessage::new().embed(embed))
+                    .await?
+            }
+            None => channel.say(ctx, &sch.msg).await?,
+        };
+        log::info!("Scheduled message sent: {}", msg.id);
+
+        pool.get()
+            .map(|conn| {
+                db::delete_schedule(&conn).unwrap_or_else(|e| {
+                    log::error!("Error deleting schedule: {}", e);
+                })
+            })
+            .unwrap_or_else(|e| {
+                log::error!("Error getting connection: {}", e);
+            });
+
+        Ok(msg.id)
+    }
+
+    /// Fills in a templated message's placeholders as of now, so they're up to date however long
+    /// ago it was scheduled. If they can't be filled in, it's sent as it is.
+    fn fill_template(
         pool: &Pool<SqliteConnectionManager>,
-        handle: Handle,
         sch: &ScheduledMessage,
+    ) -> ScheduledMessage {
+        let values = pool.get().map_err(Error::from).and_then(|conn| {
+            let guild_id = sch.guild_id.map(|guild_id| guild_id as i64);
+            // Players registered before guilds were tracked count as members of every guild.
+            let players = db::get_players(&conn)?
+                .into_iter()
+                .filter(|player| player.guild_id.is_none() || player.guild_id == guild_id)
+                .map(|player| player.id)
+                .collect();
+            let session_number = match guild_id {
+                Some(guild_id) => db::count_sessions(&conn, guild_id)? + 1,
+                None => 1,
+            };
+            Ok(templates::Values {
+                on: sch.on.with_timezone(&Utc),
+                players,
+                session_number,
+            })
+        });
+
+        match values {
+            Ok(values) => ScheduledMessage {
+                msg: templates::render(&sch.msg, &values),
+                ..sch.clone()
+            },
+            Err(e) => {
+                log::error!("Error filling in the scheduled message's template: {}", e);
+                sch.clone()
+            }
+        }
+    }
+
+    async fn resolve_mvp(
+        ctx: &T,
+        pool: &Pool<SqliteConnectionManager>,
+        guild_id: u64,
+        channel_id: u64,
     ) {
-        handle.block_on(async {
-            log::info!("Sending scheduled message");
+        log::info!("Resolving weekly MVP for guild {}", guild_id);
 
-            match serenity::ChannelId::from(sch.channel_id)
-                .say(&ctx, &sch.msg)
-                .await
-            {
-                Ok(msg) => {
-                    log::info!("Scheduled message sent: {}", msg.content);
-                    pool.get()
-                        .map(|conn| {
-                            db::delete_schedule(&conn).unwrap_or_else(|e| {
-                                log::error!("Error deleting schedule: {}", e);
-                            })
-                        })
-                        .unwrap_or_else(|e| {
-                            log::error!("Error getting connection: {}", e);
-                        })
-                }
-                Err(e) => log::error!("Error sending scheduled message: {}", e),
+        let resolved = pool
+            .get()
+            .map_err(Error::from)
+            .and_then(|mut conn| Ok(db::resolve_mvp(&mut conn, guild_id as i64)?));
+
+        let (msg, winners) = match resolved {
+            Ok(result) => (
+                serenity::CreateMessage::new()
+                    .embed(discord::mvp_embed("This week's MVP results", &result)),
+                result.winners,
+            ),
+            Err(Error::Db(db::Error::MissingVotes)) => (
+                serenity::CreateMessage::new()
+                    .content("Not everyone has voted for MVP yet! Use /mvp to cast your vote."),
+                Vec::new(),
+            ),
+            Err(e) => {
+                log::error!("Error resolving weekly MVP: {}", e);
+                return;
             }
+        };
+
+        if let Err(e) = serenity::ChannelId::from(channel_id)
+            .send_message(ctx, msg)
+            .await
+        {
+            log::error!("Error sending weekly MVP: {}", e);
+        }
+        notify::send(ctx, pool, &Event::MvpWon, &winners).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::metrics::PoolStats;
+
+    use super::*;
+
+    /// A scheduler whose jobs are never meant to reach Discord, with a migrated in-memory database.
+    fn scheduler() -> (Scheduler<Arc<serenity::Http>>, Arc<Metrics>) {
+        // A single connection, so every use of the pool sees the same in-memory database.
+        let pool = Pool::builder()
+            .max_size(1)
+            .build(SqliteConnectionManager::memory())
+            .unwrap();
+        db::run_migrations(&mut pool.get().unwrap()).unwrap();
+        let metrics = Arc::new(Metrics::default());
+        let http = Arc::new(serenity::Http::new(""));
+
+        (Scheduler::new(pool, http, metrics.clone()), metrics)
+    }
+
+    fn message_in(delay: chrono::Duration) -> ScheduledMessage {
+        ScheduledMessage {
+            channel_id: 1,
+            msg: "Game time".to_string(),
+            on: Local::now() + delay,
+            title: None,
+            image_url: None,
+            created_by: None,
+            guild_id: None,
+            templated: false,
+        }
+    }
+
+    fn fires(metrics: &Metrics, job: &str) -> bool {
+        let rendered = metrics.render(&PoolStats {
+            connections: 0,
+            idle_connections: 0,
         });
+        rendered.contains(&format!(
+            "tabletop_scheduler_fires_total{{job=\"{}\"}}",
+            job
+        ))
+    }
+
+    /// A job that counts how many times it ran, once `delay` has passed.
+    fn counting_job(delay: chrono::Duration, runs: &Arc<AtomicUsize>) -> Job {
+        let on = Utc::now() + delay;
+        let runs = runs.clone();
+        Job::spawn(async move {
+            sleep_until(on).await;
+            runs.fetch_add(1, Ordering::SeqCst);
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn job_fires_at_its_time() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let _job = counting_job(chrono::Duration::minutes(5), &runs);
+
+        tokio::time::sleep(Duration::from_secs(5 * 60 - 1)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn job_in_the_past_fires_at_once() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let _job = counting_job(chrono::Duration::minutes(-5), &runs);
+
+        tokio::task::yield_now().await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropped_job_never_fires() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        drop(counting_job(chrono::Duration::minutes(5), &runs));
+
+        tokio::time::sleep(Duration::from_secs(10 * 60)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn replaced_job_never_fires() {
+        let first = Arc::new(AtomicUsize::new(0));
+        let second = Arc::new(AtomicUsize::new(0));
+        let mut job = Some(counting_job(chrono::Duration::minutes(5), &first));
+        job.replace(counting_job(chrono::Duration::minutes(10), &second));
+
+        tokio::time::sleep(Duration::from_secs(15 * 60)).await;
+        assert_eq!(first.load(Ordering::SeqCst), 0);
+        assert_eq!(second.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rescheduling_replaces_the_pending_message() {
+        let (mut scheduler, metrics) = scheduler();
+        let next_game = scheduler.subscribe_next_game();
+
+        let sooner = message_in(chrono::Duration::minutes(5));
+        scheduler.inner_schedule(&sooner).unwrap();
+        let later = message_in(chrono::Duration::hours(1));
+        scheduler.inner_schedule(&later).unwrap();
+        assert_eq!(*next_game.borrow(), Some(later.on));
+
+        // Well past the first time, but before the second.
+        tokio::time::sleep(Duration::from_secs(30 * 60)).await;
+        assert!(!fires(&metrics, "schedule"));
+        // Don't let the second one try to reach Discord.
+        scheduler.cancel_pending();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancelled_message_never_fires() {
+        let (mut scheduler, metrics) = scheduler();
+        let next_game = scheduler.subscribe_next_game();
+
+        scheduler
+            .inner_schedule(&message_in(chrono::Duration::minutes(5)))
+            .unwrap();
+        scheduler.cancel_pending();
+        assert!(scheduler.job.is_none());
+        assert_eq!(*next_game.borrow(), None);
+
+        tokio::time::sleep(Duration::from_secs(10 * 60)).await;
+        assert!(!fires(&metrics, "schedule"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_drops_every_job() {
+        let (mut scheduler, metrics) = scheduler();
+        let sch = message_in(chrono::Duration::minutes(5));
+        scheduler.schedule(&sch).unwrap();
+        scheduler
+            .schedule_poll_close(1, Utc::now() + chrono::Duration::minutes(5))
+            .unwrap();
+        scheduler
+            .mvp_jobs
+            .insert(1, Job::spawn(std::future::pending()));
+        let backups = Backups {
+            dir: std::env::temp_dir(),
+            retain: 1,
+        };
+        scheduler
+            .schedule_backups(backups, Duration::from_secs(60 * 60))
+            .unwrap();
+
+        scheduler.shutdown();
+        assert!(scheduler.job.is_none());
+        assert!(scheduler.backup_job.is_none());
+        assert!(scheduler.mvp_jobs.is_empty());
+        assert!(scheduler.poll_jobs.is_empty());
+
+        tokio::time::sleep(Duration::from_secs(2 * 60 * 60)).await;
+        assert!(!fires(&metrics, "schedule"));
+        assert!(!fires(&metrics, "poll"));
+        assert!(!fires(&metrics, "backup"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn nothing_is_scheduled_after_shutdown() {
+        let (mut scheduler, _) = scheduler();
+        scheduler.shutdown();
+
+        let sch = message_in(chrono::Duration::minutes(5));
+        assert!(matches!(scheduler.schedule(&sch), Err(Error::ShutDown)));
+        assert!(matches!(scheduler.reschedule(&sch), Err(Error::ShutDown)));
+        assert!(matches!(scheduler.sync_mvp(1), Err(Error::ShutDown)));
+        let backups = Backups {
+            dir: std::env::temp_dir(),
+            retain: 1,
+        };
+        assert!(matches!(
+            scheduler.schedule_backups(backups, Duration::from_secs(60)),
+            Err(Error::ShutDown)
+        ));
+        assert!(matches!(
+            scheduler.schedule_poll_close(1, Utc::now()),
+            Err(Error::ShutDown)
+        ));
+        assert!(scheduler.job.is_none());
+        assert!(scheduler.poll_jobs.is_empty());
     }
 }
@@ -0,0 +1,235 @@
+use std::fmt::Display;
+
+use poise::serenity_prelude as serenity;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::Connection;
+
+use crate::{db, settings::Key};
+
+/// Whether the bot can use a channel or role its configuration points to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Access {
+    Ok,
+    Missing,
+    /// It exists, but the bot isn't allowed to use it, e.g. to send messages in a channel.
+    Forbidden,
+    /// Discord couldn't be asked, and why.
+    Unknown(String),
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Access::Ok => write!(f, "is fine"),
+            Access::Missing => write!(f, "no longer exists"),
+            Access::Forbidden => write!(f, "can't be used by the bot"),
+            Access::Unknown(e) => write!(f, "couldn't be checked: {}", e),
+        }
+    }
+}
+
+/// The Discord lookups a self-check makes, so checking doesn't need Discord itself.
+pub(crate) trait Lookup {
+    /// Whether a channel exists and the bot can send messages in it.
+    async fn channel(&self, channel_id: u64) -> Access;
+
+    /// Whether a role exists in a guild.
+    async fn role(&self, guild_id: u64, role_id: u64) -> Access;
+}
+
+/// What a self-check looks at, loaded from the database.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Config {
+    /// The channel of the scheduled message, if one is pending.
+    pub schedule_channel: Option<u64>,
+    /// Each guild's `gm_role` setting, as guild and role ids.
+    pub gm_roles: Vec<(u64, u64)>,
+    /// Each guild's `xp_announce_channel` setting, as guild and channel ids.
+    pub xp_announce_channels: Vec<(u64, u64)>,
+    /// The result of `PRAGMA quick_check`, `"ok"` when no problems were found.
+    pub integrity: String,
+}
+
+impl Config {
+    pub(crate) fn load(conn: &Connection) -> Result<Self, db::Error> {
+        let ids = |key: Key| -> Result<Vec<(u64, u64)>, db::Error> {
+            Ok(db::get_setting_for_all(conn, key.name())?
+                .into_iter()
+                .filter_map(|(guild_id, value)| Some((guild_id as u64, value.parse().ok()?)))
+                .collect())
+        };
+
+        Ok(Self {
+            schedule_channel: db::get_schedule(conn)?.map(|sch| sch.channel_id),
+            gm_roles: ids(Key::GmRole)?,
+            xp_announce_channels: ids(Key::XpAnnounceChannel)?,
+            integrity: db::quick_check(conn)?,
+        })
+    }
+}
+
+/// Something wrong with the bot's configuration or database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Problem {
+    ScheduleChannel {
+        channel_id: u64,
+        access: Access,
+    },
+    GmRole {
+        guild_id: u64,
+        role_id: u64,
+        access: Access,
+    },
+    XpAnnounceChannel {
+        guild_id: u64,
+        channel_id: u64,
+        access: Access,
+    },
+    /// The database's integrity check found problems, one per line.
+    Integrity(String),
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::ScheduleChannel { channel_id, access } => write!(
+                f,
+                "The scheduled message's channel {} {}",
+                channel_id, access
+            ),
+            Problem::GmRole {
+                guild_id,
+                role_id,
+                access,
+            } => write!(f, "Server {}'s gm_role {} {}", guild_id, role_id, access),
+            Problem::XpAnnounceChannel {
+                guild_id,
+                channel_id,
+                access,
+            } => write!(
+                f,
+                "Server {}'s xp_announce_channel {} {}",
+                guild_id, channel_id, access
+            ),
+            Problem::Integrity(integrity) => {
+                write!(f, "The database failed its integrity check: {}", integrity)
+            }
+        }
+    }
+}
+
+/// Checks everything in `config`, returning what's wrong, if anything.
+pub(crate) async fn check(lookup: &impl Lookup, config: &Config) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    if let Some(channel_id) = config.schedule_channel {
+        let access = lookup.channel(channel_id).await;
+        if access != Access::Ok {
+            problems.push(Problem::ScheduleChannel { channel_id, access });
+        }
+    }
+    for &(guild_id, role_id) in &config.gm_roles {
+        let access = lookup.role(guild_id, role_id).await;
+        if access != Access::Ok {
+            problems.push(Problem::GmRole {
+                guild_id,
+                role_id,
+                access,
+            });
+        }
+    }
+    for &(guild_id, channel_id) in &config.xp_announce_channels {
+        let access = lookup.channel(channel_id).await;
+        if access != Access::Ok {
+            problems.push(Problem::XpAnnounceChannel {
+                guild_id,
+                channel_id,
+                access,
+            });
+        }
+    }
+    if config.integrity != "ok" {
+        problems.push(Problem::Integrity(config.integrity.clone()));
+    }
+
+    problems
+}
+
+/// Loads the configuration and checks it on Discord, logging any problems.
+pub(crate) async fn run(
+    ctx: &serenity::Context,
+    pool: &Pool<SqliteConnectionManager>,
+) -> crate::Result<Vec<Problem>> {
+    let pool = pool.clone();
+    let config = tokio::task::spawn_blocking(move || -> crate::Result<_> {
+        let conn = pool.get()?;
+        Ok(Config::load(&conn)?)
+    })
+    .await??;
+
+    let problems = check(&DiscordLookup(ctx), &config).await;
+    for problem in &problems {
+        log::warn!("Self-check: {}", problem);
+    }
+    Ok(problems)
+}
+
+/// Describes what a self-check found, one problem per line.
+pub(crate) fn report(problems: &[Problem]) -> String {
+    if problems.is_empty() {
+        return "Everything checks out.".to_string();
+    }
+
+    problems
+        .iter()
+        .map(|problem| format!("⚠️ {}", problem))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Looks channels and roles up on Discord.
+pub(crate) struct DiscordLookup<'a>(pub &'a serenity::Context);
+
+impl DiscordLookup<'_> {
+    fn access(e: serenity::Error) -> Access {
+        let status = match &e {
+            serenity::Error::Http(e) => e.status_code(),
+            _ => None,
+        };
+        match status.map(|status| status.as_u16()) {
+            Some(404) => Access::Missing,
+            Some(403) => Access::Forbidden,
+            _ => Access::Unknown(e.to_string()),
+        }
+    }
+}
+
+impl Lookup for DiscordLookup<'_> {
+    async fn channel(&self, channel_id: u64) -> Access {
+        let channel = match serenity::ChannelId::new(channel_id)
+            .to_channel(self.0)
+            .await
+        {
+            Ok(serenity::Channel::Guild(channel)) => channel,
+            Ok(_) => return Access::Ok,
+            Err(e) => return Self::access(e),
+        };
+
+        let bot_id = self.0.cache.current_user().id;
+        match channel.permissions_for_user(self.0, bot_id) {
+            Ok(permissions) if permissions.send_messages() => Access::Ok,
+            Ok(_) => Access::Forbidden,
+            Err(e) => Access::Unknown(e.to_string()),
+        }
+    }
+
+    async fn role(&self, guild_id: u64, role_id: u64) -> Access {
+        let role_id = serenity::RoleId::new(role_id);
+        match serenity::GuildId::new(guild_id).roles(self.0).await {
+            Ok(roles) if roles.contains_key(&role_id) => Access::Ok,
+            Ok(_) => Access::Missing,
+            Err(e) => Self::access(e),
+        }
+    }
+}
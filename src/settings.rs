@@ -0,0 +1,669 @@
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
+
+use poise::serenity_prelude as serenity;
+use rusqlite::Connection;
+use tabletop_bot::roll;
+
+use crate::{db, locale::Locale, scheduler::Weekly};
+
+/// The longest a text command prefix can be.
+const MAX_PREFIX_LEN: usize = 5;
+
+/// The longest message a roll trigger can post.
+const MAX_TRIGGER_LEN: usize = 200;
+
+/// A per-guild setting that can be changed with `/settings`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Key {
+    GroupThousands,
+    CritEmoji,
+    FumbleEmoji,
+    MvpChannel,
+    MvpTime,
+    GmRole,
+    Locale,
+    RollChannels,
+    NoDiceNote,
+    XpAnnounceChannel,
+    XpRequireReason,
+    DefaultDice,
+    Prefix,
+    XpNotifyThreshold,
+    RollTriggers,
+    RollMode,
+}
+
+impl Key {
+    pub(crate) const ALL: &'static [Key] = &[
+        Key::GroupThousands,
+        Key::CritEmoji,
+        Key::FumbleEmoji,
+        Key::MvpChannel,
+        Key::MvpTime,
+        Key::GmRole,
+        Key::Locale,
+        Key::RollChannels,
+        Key::NoDiceNote,
+        Key::XpAnnounceChannel,
+        Key::XpRequireReason,
+        Key::DefaultDice,
+        Key::Prefix,
+        Key::XpNotifyThreshold,
+        Key::RollTriggers,
+        Key::RollMode,
+    ];
+
+    /// The name the setting is stored and set by.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Key::GroupThousands => "group_thousands",
+            Key::CritEmoji => "crit_emoji",
+            Key::FumbleEmoji => "fumble_emoji",
+            Key::MvpChannel => "mvp_channel",
+            Key::MvpTime => "mvp_time",
+            Key::GmRole => "gm_role",
+            Key::Locale => "locale",
+            Key::RollChannels => "roll_channels",
+            Key::NoDiceNote => "no_dice_note",
+            Key::XpAnnounceChannel => "xp_announce_channel",
+            Key::XpRequireReason => "xp_require_reason",
+            Key::DefaultDice => "default_dice",
+            Key::Prefix => "prefix",
+            Key::XpNotifyThreshold => "xp_notify_threshold",
+            Key::RollTriggers => "roll_triggers",
+            Key::RollMode => "roll_mode",
+        }
+    }
+
+    pub(crate) fn description(self) -> &'static str {
+        match self {
+            Key::GroupThousands => "Group the thousands of roll totals, e.g. 1,234,567",
+            Key::CritEmoji => "The reaction added to a natural 20",
+            Key::FumbleEmoji => "The reaction added to a natural 1",
+            Key::MvpChannel => "The channel weekly MVP results are posted to, unset to disable",
+            Key::MvpTime => "When the MVP is resolved each week, in UTC, e.g. sun 20:00",
+            Key::GmRole => "The role allowed to run GM commands, instead of Manage Server",
+            Key::Locale => "The language of roll results and number formatting, en, fr or es",
+            Key::RollChannels => "The only channels dice can be rolled in, unset to allow any",
+            Key::NoDiceNote => "Point out rolls without any dice, e.g. 2+2, which may be typos",
+            Key::XpAnnounceChannel => {
+                "The channel experience grants are posted to, unset to disable"
+            }
+            Key::XpRequireReason => "Require a reason for every experience grant",
+            Key::DefaultDice => {
+                "What /roll rolls without any dice, e.g. 1d20, unset to require dice"
+            }
+            Key::Prefix => "What text commands like !roll 2d6 start with, when they're enabled",
+            Key::XpNotifyThreshold => {
+                "Experience grants of at least this much DM players who use /notify, unset to disable"
+            }
+            Key::RollTriggers => {
+                "Messages added to single-die rolls by result, e.g. {\"1\": \"The gods frown upon you\"}"
+            }
+            Key::RollMode => {
+                "How rolls are shown, compact, verbose or embed, unless /channelmode says otherwise"
+            }
+        }
+    }
+
+    /// Validates a value, returning it in the form it's stored in.
+    pub(crate) fn normalize(self, value: &str) -> Result<String, String> {
+        let value = value.trim();
+        let normalized = match self {
+            Key::GroupThousands | Key::NoDiceNote | Key::XpRequireReason => {
+                value.parse::<bool>().ok().map(|v| v.to_string())
+            }
+            Key::CritEmoji | Key::FumbleEmoji => serenity::ReactionType::try_from(value)
+                .ok()
+                .map(|_| value.to_string()),
+            // Accepts a channel mention like <#123>, or a bare id.
+            Key::MvpChannel | Key::XpAnnounceChannel => value
+                .trim_start_matches("<#")
+                .trim_end_matches('>')
+                .parse::<u64>()
+                .ok()
+                .map(|id| id.to_string()),
+            Key::MvpTime => value.parse::<Weekly>().ok().map(|_| value.to_string()),
+            // Accepts a role mention like <@&123>, or a bare id.
+            Key::GmRole => value
+                .trim_start_matches("<@&")
+                .trim_end_matches('>')
+                .parse::<u64>()
+                .ok()
+                .map(|id| id.to_string()),
+            Key::Locale => value
+                .parse::<Locale>()
+                .ok()
+                .map(|locale| locale.to_string()),
+            Key::RollChannels => value
+                .parse::<ChannelList>()
+                .ok()
+                .filter(|channels| !channels.0.is_empty())
+                .map(|channels| channels.to_string()),
+            Key::Prefix => Some(value)
+                .filter(|prefix| {
+                    !prefix.is_empty()
+                        && prefix.chars().count() <= MAX_PREFIX_LEN
+                        && !prefix.contains(char::is_whitespace)
+                })
+                .map(str::to_string),
+            Key::XpNotifyThreshold => value
+                .parse::<u32>()
+                .ok()
+                .filter(|threshold| *threshold > 0)
+                .map(|threshold| threshold.to_string()),
+            Key::RollTriggers => value
+                .parse::<RollTriggers>()
+                .ok()
+                .filter(|triggers| !triggers.0.is_empty())
+                .map(|triggers| triggers.to_string()),
+            Key::RollMode => value.parse::<RollMode>().ok().map(|mode| mode.to_string()),
+            // Rolls it once to check it can be rolled.
+            Key::DefaultDice => roll::roll(value, &mut rand::thread_rng())
+                .ok()
+                .map(|_| value.to_string()),
+        };
+
+        normalized.ok_or_else(|| format!("`{}` is not a valid value for {}", value, self))
+    }
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Key::ALL
+            .iter()
+            .copied()
+            .find(|key| key.name() == s.trim())
+            .ok_or_else(|| {
+                let valid = Key::ALL
+                    .iter()
+                    .map(|key| key.name())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!(
+                    "Unknown setting `{}`. Valid settings are: {}",
+                    s.trim(),
+                    valid
+                )
+            })
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A guild's settings, with defaults for anything that hasn't been configured.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Settings {
+    pub group_thousands: bool,
+    pub crit_emoji: String,
+    pub fumble_emoji: String,
+    pub mvp_channel: Option<u64>,
+    pub mvp_time: Weekly,
+    pub gm_role: Option<u64>,
+    pub locale: Locale,
+    /// Empty when dice can be rolled in any channel.
+    pub roll_channels: Vec<u64>,
+    pub no_dice_note: bool,
+    pub xp_announce_channel: Option<u64>,
+    pub xp_require_reason: bool,
+    /// What a bare `/roll` rolls, if anything.
+    pub default_dice: Option<String>,
+    pub prefix: String,
+    /// The least experience a grant DMs the player about, if any.
+    pub xp_notify_threshold: Option<u32>,
+    /// Messages added to single-die rolls, by the die's result. Empty when there aren't any.
+    pub roll_triggers: BTreeMap<u32, String>,
+    /// How rolls are shown in channels without their own mode, if the server has picked one.
+    pub roll_mode: Option<RollMode>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            group_thousands: false,
+            crit_emoji: "🎉".to_string(),
+            fumble_emoji: "💀".to_string(),
+            mvp_channel: None,
+            mvp_time: Weekly::default(),
+            gm_role: None,
+            locale: Locale::default(),
+            roll_channels: Vec::new(),
+            no_dice_note: true,
+            xp_announce_channel: None,
+            xp_require_reason: false,
+            default_dice: None,
+            prefix: "!".to_string(),
+            xp_notify_threshold: None,
+            roll_triggers: BTreeMap::new(),
+            roll_mode: None,
+        }
+    }
+}
+
+impl Settings {
+    pub(crate) fn load(conn: &Connection, guild_id: i64) -> Result<Self, db::Error> {
+        let defaults = Self::default();
+
+        Ok(Self {
+            group_thousands: db::get_setting(conn, guild_id, Key::GroupThousands.name())?
+                .unwrap_or(defaults.group_thousands),
+            crit_emoji: db::get_setting(conn, guild_id, Key::CritEmoji.name())?
+                .unwrap_or(defaults.crit_emoji),
+            fumble_emoji: db::get_setting(conn, guild_id, Key::FumbleEmoji.name())?
+                .unwrap_or(defaults.fumble_emoji),
+            mvp_channel: db::get_setting(conn, guild_id, Key::MvpChannel.name())?
+                .or(defaults.mvp_channel),
+            mvp_time: db::get_setting(conn, guild_id, Key::MvpTime.name())?
+                .unwrap_or(defaults.mvp_time),
+            gm_role: db::get_setting(conn, guild_id, Key::GmRole.name())?.or(defaults.gm_role),
+            locale: db::get_setting(conn, guild_id, Key::Locale.name())?.unwrap_or(defaults.locale),
+            roll_channels: db::get_setting::<ChannelList>(
+                conn,
+                guild_id,
+                Key::RollChannels.name(),
+            )?
+            .map_or(defaults.roll_channels, |channels| channels.0),
+            no_dice_note: db::get_setting(conn, guild_id, Key::NoDiceNote.name())?
+                .unwrap_or(defaults.no_dice_note),
+            xp_announce_channel: db::get_setting(conn, guild_id, Key::XpAnnounceChannel.name())?
+                .or(defaults.xp_announce_channel),
+            xp_require_reason: db::get_setting(conn, guild_id, Key::XpRequireReason.name())?
+                .unwrap_or(defaults.xp_require_reason),
+            default_dice: db::get_setting(conn, guild_id, Key::DefaultDice.name())?
+                .or(defaults.default_dice),
+            prefix: db::get_setting(conn, guild_id, Key::Prefix.name())?.unwrap_or(defaults.prefix),
+            xp_notify_threshold: db::get_setting(conn, guild_id, Key::XpNotifyThreshold.name())?
+                .or(defaults.xp_notify_threshold),
+            roll_triggers: db::get_setting::<RollTriggers>(
+                conn,
+                guild_id,
+                Key::RollTriggers.name(),
+            )?
+            .map_or(defaults.roll_triggers, |triggers| triggers.0),
+            roll_mode: db::get_setting(conn, guild_id, Key::RollMode.name())?
+                .or(defaults.roll_mode),
+        })
+    }
+
+    /// The value of a setting, formatted for display.
+    pub(crate) fn display(&self, key: Key) -> String {
+        match key {
+            Key::GroupThousands => self.group_thousands.to_string(),
+            Key::CritEmoji => self.crit_emoji.clone(),
+            Key::FumbleEmoji => self.fumble_emoji.clone(),
+            Key::MvpChannel => match self.mvp_channel {
+                Some(channel_id) => format!("<#{}>", channel_id),
+                None => "unset".to_string(),
+            },
+            Key::MvpTime => self.mvp_time.to_string(),
+            Key::GmRole => match self.gm_role {
+                Some(role_id) => format!("<@&{}>", role_id),
+                None => "unset".to_string(),
+            },
+            Key::Locale => self.locale.to_string(),
+            Key::RollChannels if self.roll_channels.is_empty() => "any".to_string(),
+            Key::RollChannels => mention_channels(&self.roll_channels),
+            Key::NoDiceNote => self.no_dice_note.to_string(),
+            Key::XpAnnounceChannel => match self.xp_announce_channel {
+                Some(channel_id) => format!("<#{}>", channel_id),
+                None => "unset".to_string(),
+            },
+            Key::XpRequireReason => self.xp_require_reason.to_string(),
+            Key::DefaultDice => match &self.default_dice {
+                Some(dice) => format!("`{}`", dice),
+                None => "unset".to_string(),
+            },
+            Key::Prefix => format!("`{}`", self.prefix),
+            Key::XpNotifyThreshold => match self.xp_notify_threshold {
+                Some(threshold) => format!("{}xp", threshold),
+                None => "unset".to_string(),
+            },
+            Key::RollTriggers if self.roll_triggers.is_empty() => "unset".to_string(),
+            Key::RollTriggers => self
+                .roll_triggers
+                .iter()
+                .map(|(result, message)| format!("{}: {}", result, message))
+                .collect::<Vec<_>>()
+                .join("; "),
+            Key::RollMode => match self.roll_mode {
+                Some(mode) => mode.to_string(),
+                None => format!("unset ({})", RollMode::default()),
+            },
+        }
+    }
+}
+
+/// Channel ids separated by commas or spaces, as mentions like <#123> or bare ids.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ChannelList(pub Vec<u64>);
+
+impl FromStr for ChannelList {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|channel| !channel.is_empty())
+            .map(|channel| {
+                channel
+                    .trim_start_matches("<#")
+                    .trim_end_matches('>')
+                    .parse::<u64>()
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(ChannelList)
+    }
+}
+
+impl Display for ChannelList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ids = self
+            .0
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        write!(f, "{}", ids)
+    }
+}
+
+/// How a roll is shown.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, poise::ChoiceParameter)]
+pub(crate) enum RollMode {
+    /// Only the expression and the total, e.g. for a roleplay channel.
+    #[name = "compact"]
+    Compact,
+    /// Every die, as well as the total.
+    #[default]
+    #[name = "verbose"]
+    Verbose,
+    /// Every die, in an embed.
+    #[name = "embed"]
+    Embed,
+}
+
+impl RollMode {
+    /// The mode of a channel: its own, or else the server's, or else the default.
+    pub(crate) fn resolve(channel: Option<RollMode>, guild: Option<RollMode>) -> RollMode {
+        channel.or(guild).unwrap_or_default()
+    }
+}
+
+impl FromStr for RollMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "compact" => Ok(RollMode::Compact),
+            "verbose" => Ok(RollMode::Verbose),
+            "embed" => Ok(RollMode::Embed),
+            s => Err(format!(
+                "Unknown roll mode `{}`, expected compact, verbose or embed",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for RollMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RollMode::Compact => write!(f, "compact"),
+            RollMode::Verbose => write!(f, "verbose"),
+            RollMode::Embed => write!(f, "embed"),
+        }
+    }
+}
+
+/// How rolls are shown in a channel, given its server's settings.
+pub(crate) fn roll_mode(
+    conn: &Connection,
+    settings: &Settings,
+    channel_id: u64,
+) -> Result<RollMode, db::Error> {
+    let channel =
+        db::get_channel_roll_mode(conn, channel_id as i64)?.and_then(|mode| mode.parse().ok());
+    Ok(RollMode::resolve(channel, settings.roll_mode))
+}
+
+/// Messages keyed by a die's result, as a JSON object like `{"1": "The gods frown upon you"}`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct RollTriggers(pub BTreeMap<u32, String>);
+
+impl FromStr for RollTriggers {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let triggers: BTreeMap<String, String> =
+            serde_json::from_str(s).map_err(|e| e.to_string())?;
+        triggers
+            .into_iter()
+            .map(|(result, message)| {
+                let result = result
+                    .trim()
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|result| *result > 0)
+                    .ok_or_else(|| format!("`{}` isn't a die result", result))?;
+                let message = message.trim();
+                if message.is_empty() || message.chars().count() > MAX_TRIGGER_LEN {
+                    return Err(format!(
+                        "The message for {} must be 1 to {} characters",
+                        result, MAX_TRIGGER_LEN
+                    ));
+                }
+                Ok((result, message.to_string()))
+            })
+            .collect::<Result<BTreeMap<_, _>, _>>()
+            .map(RollTriggers)
+    }
+}
+
+impl Display for RollTriggers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let triggers = self
+            .0
+            .iter()
+            .map(|(result, message)| (result.to_string(), message))
+            .collect::<BTreeMap<_, _>>();
+        write!(
+            f,
+            "{}",
+            serde_json::to_string(&triggers).map_err(|_| std::fmt::Error)?
+        )
+    }
+}
+
+/// Mentions channels in a list, e.g. "<#1> or <#2>".
+pub(crate) fn mention_channels(channel_ids: &[u64]) -> String {
+    channel_ids
+        .iter()
+        .map(|id| format!("<#{}>", id))
+        .collect::<Vec<_>>()
+        .join(" or ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUILD: i64 = 10;
+
+    fn normalize(key: &str, value: &str) -> Result<String, String> {
+        key.parse::<Key>().unwrap().normalize(value)
+    }
+
+    #[test]
+    fn every_key_parses_by_name() {
+        for key in Key::ALL {
+            assert_eq!(key.name().parse::<Key>(), Ok(*key));
+            assert_eq!(format!(" {} ", key).parse::<Key>(), Ok(*key));
+        }
+    }
+
+    #[test]
+    fn unknown_key_lists_the_valid_ones() {
+        let e = "xp_bonus".parse::<Key>().unwrap_err();
+
+        assert!(e.contains("`xp_bonus`"));
+        for key in Key::ALL {
+            assert!(e.contains(key.name()), "{} isn't listed", key);
+        }
+    }
+
+    #[test]
+    fn normalizing_flags() {
+        for key in ["group_thousands", "no_dice_note", "xp_require_reason"] {
+            assert_eq!(normalize(key, " true "), Ok("true".to_string()));
+            assert_eq!(normalize(key, "false"), Ok("false".to_string()));
+            assert!(normalize(key, "yes").is_err());
+        }
+    }
+
+    #[test]
+    fn normalizing_emoji() {
+        for key in ["crit_emoji", "fumble_emoji"] {
+            assert_eq!(normalize(key, "🎲"), Ok("🎲".to_string()));
+            assert_eq!(
+                normalize(key, "<:nat20:600404340292059257>"),
+                Ok("<:nat20:600404340292059257>".to_string())
+            );
+            assert!(normalize(key, "<:broken").is_err());
+            assert!(normalize(key, "").is_err());
+        }
+    }
+
+    #[test]
+    fn normalizing_channels_and_roles() {
+        for key in ["mvp_channel", "xp_announce_channel"] {
+            assert_eq!(normalize(key, "<#123>"), Ok("123".to_string()));
+            assert_eq!(normalize(key, "123"), Ok("123".to_string()));
+            assert!(normalize(key, "general").is_err());
+        }
+        assert_eq!(normalize("gm_role", "<@&456>"), Ok("456".to_string()));
+        assert_eq!(normalize("gm_role", "456"), Ok("456".to_string()));
+        assert!(normalize("gm_role", "GM").is_err());
+        assert_eq!(
+            normalize("roll_channels", "<#1>, 2 <#3>"),
+            Ok("1,2,3".to_string())
+        );
+        assert!(normalize("roll_channels", " , ").is_err());
+        assert!(normalize("roll_channels", "1,dice").is_err());
+    }
+
+    #[test]
+    fn normalizing_mvp_time() {
+        assert_eq!(
+            normalize("mvp_time", "sun 20:00"),
+            Ok("sun 20:00".to_string())
+        );
+        assert!(normalize("mvp_time", "sunday").is_err());
+        assert!(normalize("mvp_time", "sun 25:00").is_err());
+    }
+
+    #[test]
+    fn normalizing_locale_and_roll_mode() {
+        assert_eq!(normalize("locale", "French"), Ok("fr".to_string()));
+        assert!(normalize("locale", "de").is_err());
+        assert_eq!(normalize("roll_mode", "EMBED"), Ok("embed".to_string()));
+        assert!(normalize("roll_mode", "loud").is_err());
+    }
+
+    #[test]
+    fn normalizing_prefix() {
+        assert_eq!(normalize("prefix", " ? "), Ok("?".to_string()));
+        assert!(normalize("prefix", "").is_err());
+        assert!(normalize("prefix", "! !").is_err());
+        assert!(normalize("prefix", "toolong").is_err());
+    }
+
+    #[test]
+    fn normalizing_numbers_and_dice() {
+        assert_eq!(
+            normalize("xp_notify_threshold", "500"),
+            Ok("500".to_string())
+        );
+        assert!(normalize("xp_notify_threshold", "0").is_err());
+        assert!(normalize("xp_notify_threshold", "lots").is_err());
+        assert_eq!(normalize("default_dice", "1d20"), Ok("1d20".to_string()));
+        assert!(normalize("default_dice", "1d").is_err());
+    }
+
+    #[test]
+    fn normalizing_roll_triggers() {
+        assert_eq!(
+            normalize("roll_triggers", r#"{" 1": " Ouch ", "20": "Yes!"}"#),
+            Ok(r#"{"1":"Ouch","20":"Yes!"}"#.to_string())
+        );
+        assert!(normalize("roll_triggers", "{}").is_err());
+        assert!(normalize("roll_triggers", r#"{"0": "Zero"}"#).is_err());
+        assert!(normalize("roll_triggers", r#"{"1": ""}"#).is_err());
+        assert!(normalize("roll_triggers", "not json").is_err());
+    }
+
+    #[test]
+    fn unconfigured_settings_are_defaults() {
+        let conn = db::test_connection();
+
+        assert_eq!(Settings::load(&conn, GUILD).unwrap(), Settings::default());
+    }
+
+    #[test]
+    fn settings_roundtrip() {
+        let conn = db::test_connection();
+        for (key, value) in [
+            (Key::GroupThousands, "true"),
+            (Key::MvpChannel, "<#123>"),
+            (Key::MvpTime, "fri 18:30"),
+            (Key::Locale, "es"),
+            (Key::RollChannels, "1 2"),
+            (Key::Prefix, "?"),
+            (Key::XpNotifyThreshold, "100"),
+            (Key::RollMode, "compact"),
+        ] {
+            let value = key.normalize(value).unwrap();
+            db::set_setting(&conn, GUILD, key.name(), &value).unwrap();
+        }
+
+        let settings = Settings::load(&conn, GUILD).unwrap();
+        assert!(settings.group_thousands);
+        assert_eq!(settings.mvp_channel, Some(123));
+        assert_eq!(settings.mvp_time, "fri 18:30".parse().unwrap());
+        assert_eq!(settings.locale, Locale::Es);
+        assert_eq!(settings.roll_channels, vec![1, 2]);
+        assert_eq!(settings.prefix, "?");
+        assert_eq!(settings.xp_notify_threshold, Some(100));
+        assert_eq!(settings.roll_mode, Some(RollMode::Compact));
+        // Other guilds aren't affected.
+        assert_eq!(
+            Settings::load(&conn, GUILD + 1).unwrap(),
+            Settings::default()
+        );
+        // Unsetting goes back to the default.
+        db::delete_setting(&conn, GUILD, Key::Prefix.name()).unwrap();
+        assert_eq!(Settings::load(&conn, GUILD).unwrap().prefix, "!");
+    }
+
+    #[test]
+    fn invalid_stored_values_are_ignored() {
+        let conn = db::test_connection();
+        db::set_setting(&conn, GUILD, Key::GroupThousands.name(), "maybe").unwrap();
+        db::set_setting(&conn, GUILD, Key::MvpChannel.name(), "general").unwrap();
+        db::set_setting(&conn, GUILD, Key::XpRequireReason.name(), "true").unwrap();
+
+        let settings = Settings::load(&conn, GUILD).unwrap();
+        assert!(!settings.group_thousands);
+        assert_eq!(settings.mvp_channel, None);
+        // Valid settings are still loaded.
+        assert!(settings.xp_require_reason);
+        assert_eq!(
+            db::get_setting::<bool>(&conn, GUILD, Key::GroupThousands.name()).unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,286 @@
+use std::fmt::Display;
+
+use rusqlite::{types::ValueRef, Batch, Connection};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The most rows `/sql` reads, so a careless `SELECT *` can't exhaust memory.
+pub(crate) const MAX_ROWS: usize = 1000;
+
+/// Keywords that reach outside the database or change how it's opened, which no query needs.
+const BLOCKED_KEYWORDS: &[&str] = &["PRAGMA", "ATTACH", "DETACH", "VACUUM"];
+
+#[derive(Debug)]
+pub(crate) enum Error {
+    /// The query uses a keyword that's never allowed.
+    Blocked(&'static str),
+    /// The query would change the database, without `write: true`.
+    ReadOnly,
+    /// There's no statement to run.
+    Empty,
+    /// There's more than one statement.
+    MultipleStatements,
+    Sqlite(rusqlite::Error),
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Sqlite(e)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Blocked(keyword) => write!(f, "{} isn't allowed", keyword),
+            Error::ReadOnly => write!(
+                f,
+                "That query changes the database. Run it again with write: True"
+            ),
+            Error::Empty => write!(f, "There's no statement to run"),
+            Error::MultipleStatements => write!(f, "Only one statement can be run at a time"),
+            Error::Sqlite(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// What running a query produced.
+pub(crate) enum Outcome {
+    Rows {
+        columns: Vec<String>,
+        rows: Vec<Vec<String>>,
+        /// Whether there were more than `MAX_ROWS` rows.
+        truncated: bool,
+    },
+    Changed(usize),
+}
+
+/// The first blocked keyword in `query`, matched as a whole word in any case.
+fn blocked_keyword(query: &str) -> Option<&'static str> {
+    query
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .find_map(|word| {
+            BLOCKED_KEYWORDS
+                .iter()
+                .find(|keyword| word.eq_ignore_ascii_case(keyword))
+                .copied()
+        })
+}
+
+/// Runs a single statement. Statements that change the database are refused unless `write` is
+/// set.
+pub(crate) fn run(conn: &Connection, query: &str, write: bool) -> Result<Outcome> {
+    if let Some(keyword) = blocked_keyword(query) {
+        return Err(Error::Blocked(keyword));
+    }
+
+    let mut batch = Batch::new(conn, query);
+    let mut stmt = batch.next()?.ok_or(Error::Empty)?;
+    if batch.next()?.is_some() {
+        return Err(Error::MultipleStatements);
+    }
+
+    if !stmt.readonly() && !write {
+        return Err(Error::ReadOnly);
+    }
+
+    if stmt.column_count() == 0 {
+        return Ok(Outcome::Changed(stmt.execute([])?));
+    }
+
+    let columns = stmt
+        .column_names()
+        .into_iter()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    let mut rows = Vec::new();
+    let mut truncated = false;
+    let mut results = stmt.query([])?;
+    while let Some(row) = results.next()? {
+        if rows.len() == MAX_ROWS {
+            truncated = true;
+            break;
+        }
+        rows.push(
+            (0..columns.len())
+                .map(|i| row.get_ref(i).map(format_value))
+                .collect::<rusqlite::Result<Vec<_>>>()?,
+        );
+    }
+
+    Ok(Outcome::Rows {
+        columns,
+        rows,
+        truncated,
+    })
+}
+
+fn format_value(value: ValueRef) -> String {
+    match value {
+        ValueRef::Null => "NULL".to_string(),
+        ValueRef::Integer(n) => n.to_string(),
+        ValueRef::Real(n) => n.to_string(),
+        ValueRef::Text(text) => String::from_utf8_lossy(text).into_owned(),
+        ValueRef::Blob(blob) => format!("<{} bytes>", blob.len()),
+    }
+}
+
+/// Lays out rows as a plain-text table, with each column as wide as its widest value.
+pub(crate) fn format_table(columns: &[String], rows: &[Vec<String>]) -> String {
+    let widths = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            rows.iter()
+                .filter_map(|row| row.get(i))
+                .chain([column])
+                .map(|value| value.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+
+    let line = |values: &[String]| {
+        values
+            .iter()
+            .zip(&widths)
+            .map(|(value, width)| format!("{:width$}", value, width = width))
+            .collect::<Vec<_>>()
+            .join(" | ")
+            .trim_end()
+            .to_string()
+    };
+    let separator = widths
+        .iter()
+        .map(|width| "-".repeat(*width))
+        .collect::<Vec<_>>()
+        .join("-+-");
+
+    [line(columns), separator]
+        .into_iter()
+        .chain(rows.iter().map(|row| line(row)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conn() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE players (id INTEGER PRIMARY KEY, name TEXT);
+            INSERT INTO players VALUES (1, 'Ædelgar'), (2, NULL);",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn table_pads_multibyte_values_by_character() {
+        let table = format_table(
+            &strings(&["id", "name"]),
+            &[strings(&["1", "Ædelgar"]), strings(&["22", "Bo"])],
+        );
+
+        assert_eq!(
+            table,
+            "id | name\n\
+            ---+--------\n\
+            1  | Ædelgar\n\
+            22 | Bo"
+        );
+    }
+
+    #[test]
+    fn table_without_rows() {
+        let table = format_table(&strings(&["id", "name"]), &[]);
+
+        assert_eq!(table, "id | name\n---+-----");
+    }
+
+    #[test]
+    fn selecting_rows() {
+        let Outcome::Rows {
+            columns,
+            rows,
+            truncated,
+        } = run(&conn(), "SELECT id, name FROM players ORDER BY id", false).unwrap()
+        else {
+            panic!("expected rows");
+        };
+
+        assert_eq!(columns, strings(&["id", "name"]));
+        assert_eq!(
+            rows,
+            vec![strings(&["1", "Ædelgar"]), strings(&["2", "NULL"])]
+        );
+        assert!(!truncated);
+    }
+
+    #[test]
+    fn selecting_too_many_rows() {
+        let query = format!(
+            "WITH RECURSIVE n(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM n LIMIT {})
+            SELECT x FROM n",
+            MAX_ROWS + 1
+        );
+        let Outcome::Rows {
+            rows, truncated, ..
+        } = run(&conn(), &query, false).unwrap()
+        else {
+            panic!("expected rows");
+        };
+
+        assert_eq!(rows.len(), MAX_ROWS);
+        assert!(truncated);
+    }
+
+    #[test]
+    fn blocked_keywords() {
+        let conn = conn();
+
+        assert!(matches!(
+            run(&conn, "PRAGMA journal_mode", false),
+            Err(Error::Blocked("PRAGMA"))
+        ));
+        assert!(matches!(
+            run(&conn, "attach database 'x.db' as x", true),
+            Err(Error::Blocked("ATTACH"))
+        ));
+        // Only whole words are blocked.
+        assert!(run(&conn, "SELECT 1 AS pragmatic", false).is_ok());
+    }
+
+    #[test]
+    fn one_statement_at_a_time() {
+        let conn = conn();
+
+        assert!(matches!(
+            run(&conn, "SELECT 1; SELECT 2", false),
+            Err(Error::MultipleStatements)
+        ));
+        assert!(matches!(run(&conn, "  ", false), Err(Error::Empty)));
+    }
+
+    #[test]
+    fn writes_need_write() {
+        let conn = conn();
+
+        assert!(matches!(
+            run(&conn, "UPDATE players SET name = 'x'", false),
+            Err(Error::ReadOnly)
+        ));
+        assert!(matches!(
+            run(&conn, "UPDATE players SET name = 'x'", true),
+            Ok(Outcome::Changed(2))
+        ));
+    }
+}
@@ -0,0 +1,229 @@
+use std::fmt::Display;
+
+use rand::Rng;
+
+use crate::db::TableEntry;
+
+/// The most entries that can be drawn from a random table at once.
+pub(crate) const MAX_DRAWS: u32 = 20;
+
+/// The longest an entry can be, so a handful of draws fit in one message.
+pub(crate) const MAX_ENTRY_LEN: usize = 300;
+
+/// The largest file `/table import` reads.
+pub(crate) const MAX_IMPORT_BYTES: u32 = 64 * 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Error {
+    EmptyName,
+    EmptyEntry,
+    EntryTooLong,
+    NoEntries,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::EmptyName => write!(f, "A random table needs a name"),
+            Error::EmptyEntry => write!(f, "An entry needs some text"),
+            Error::EntryTooLong => write!(
+                f,
+                "An entry can be at most {} characters long",
+                MAX_ENTRY_LEN
+            ),
+            Error::NoEntries => write!(f, "There are no entries to import, one per line"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Normalizes a random table's name, so names are matched regardless of case.
+pub(crate) fn normalize_name(name: &str) -> Result<String, Error> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() {
+        return Err(Error::EmptyName);
+    }
+
+    Ok(name)
+}
+
+/// Trims an entry's text, which must not be blank or too long.
+pub(crate) fn normalize_entry(text: &str) -> Result<String, Error> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(Error::EmptyEntry);
+    }
+    if text.chars().count() > MAX_ENTRY_LEN {
+        return Err(Error::EntryTooLong);
+    }
+
+    Ok(text.to_string())
+}
+
+/// Parses an imported file with one entry per line, each with a weight of 1. Blank lines are
+/// ignored.
+pub(crate) fn parse_entries(text: &str) -> Result<Vec<TableEntry>, Error> {
+    let entries = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            Ok(TableEntry {
+                weight: 1,
+                text: normalize_entry(line)?,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if entries.is_empty() {
+        return Err(Error::NoEntries);
+    }
+
+    Ok(entries)
+}
+
+/// Draws `count` entries at random, each as likely as its share of the total weight. Entries
+/// aren't drawn twice until every entry has been, and entries with no weight are never drawn, so
+/// nothing is drawn from a table whose entries all have no weight.
+pub(crate) fn draw<'a, R: Rng + ?Sized>(
+    entries: &'a [TableEntry],
+    count: u32,
+    rng: &mut R,
+) -> Vec<&'a TableEntry> {
+    let weighted = entries
+        .iter()
+        .filter(|entry| entry.weight > 0)
+        .collect::<Vec<_>>();
+    if weighted.is_empty() {
+        return Vec::new();
+    }
+
+    let mut pool = Vec::new();
+    let mut drawn = Vec::new();
+    for _ in 0..count {
+        if pool.is_empty() {
+            pool = weighted.clone();
+        }
+
+        let total = pool
+            .iter()
+            .map(|entry| u64::from(entry.weight))
+            .sum::<u64>();
+        let mut pick = rng.gen_range(0..total);
+        let i = pool
+            .iter()
+            .position(|entry| match pick.checked_sub(u64::from(entry.weight)) {
+                Some(rest) => {
+                    pick = rest;
+                    false
+                }
+                None => true,
+            })
+            .expect("pick is less than the total weight");
+        drawn.push(pool.remove(i));
+    }
+
+    drawn
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rand::{rngs::mock::StepRng, SeedableRng};
+    use rand_hc::Hc128Rng;
+
+    use super::*;
+
+    fn entry(weight: u32, text: &str) -> TableEntry {
+        TableEntry {
+            weight,
+            text: text.to_string(),
+        }
+    }
+
+    fn texts(drawn: &[&TableEntry]) -> Vec<String> {
+        drawn.iter().map(|entry| entry.text.clone()).collect()
+    }
+
+    #[test]
+    fn normalizing() {
+        assert_eq!(normalize_name(" Wild Magic "), Ok("wild magic".to_string()));
+        assert_eq!(normalize_name(""), Err(Error::EmptyName));
+        assert_eq!(normalize_entry(" A goat "), Ok("A goat".to_string()));
+        assert_eq!(normalize_entry(" "), Err(Error::EmptyEntry));
+        assert_eq!(
+            normalize_entry(&"x".repeat(MAX_ENTRY_LEN + 1)),
+            Err(Error::EntryTooLong)
+        );
+    }
+
+    #[test]
+    fn parsing_imported_entries() {
+        assert_eq!(
+            parse_entries("A goat\n\n  A storm \r\n"),
+            Ok(vec![entry(1, "A goat"), entry(1, "A storm")])
+        );
+        assert_eq!(parse_entries("\n \n"), Err(Error::NoEntries));
+    }
+
+    #[test]
+    fn single_entry_is_always_drawn() {
+        let entries = [entry(3, "A goat")];
+        let drawn = draw(&entries, 3, &mut Hc128Rng::seed_from_u64(1352));
+
+        assert_eq!(texts(&drawn), vec!["A goat", "A goat", "A goat"]);
+    }
+
+    #[test]
+    fn entries_without_weight_are_never_drawn() {
+        let entries = [entry(0, "A dragon"), entry(1, "A goat"), entry(0, "A lich")];
+        let mut rng = Hc128Rng::seed_from_u64(1352);
+
+        assert_eq!(texts(&draw(&entries, 5, &mut rng)), vec!["A goat"; 5]);
+        assert!(draw(&[entry(0, "A dragon")], 5, &mut rng).is_empty());
+        assert!(draw(&[], 5, &mut rng).is_empty());
+    }
+
+    #[test]
+    fn entries_are_picked_by_weight() {
+        let entries = [entry(1, "A goat"), entry(3, "A storm")];
+
+        // StepRng picks from the start of the total weight, so the first entry...
+        assert_eq!(
+            texts(&draw(&entries, 1, &mut StepRng::new(0, 0))),
+            vec!["A goat"]
+        );
+        // ...and then from the end, which falls within the heavier entry's share.
+        assert_eq!(
+            texts(&draw(&entries, 1, &mut StepRng::new(u64::MAX, 0))),
+            vec!["A storm"]
+        );
+    }
+
+    #[test]
+    fn weights_set_how_often_entries_are_drawn() {
+        let entries = [entry(1, "A goat"), entry(3, "A storm")];
+        let mut rng = Hc128Rng::seed_from_u64(1352);
+        let storms = (0..1000)
+            .flat_map(|_| draw(&entries, 1, &mut rng))
+            .filter(|entry| entry.text == "A storm")
+            .count();
+
+        assert!((700..800).contains(&storms), "{} storms", storms);
+    }
+
+    #[test]
+    fn entries_are_not_drawn_twice_until_all_are() {
+        let entries = [entry(1, "A goat"), entry(5, "A storm"), entry(2, "A lich")];
+        let drawn = draw(&entries, 7, &mut Hc128Rng::seed_from_u64(1352));
+
+        assert_eq!(drawn.len(), 7);
+        for round in drawn.chunks(3) {
+            let unique = round
+                .iter()
+                .map(|entry| &entry.text)
+                .collect::<HashSet<_>>();
+            assert_eq!(unique.len(), round.len());
+        }
+    }
+}
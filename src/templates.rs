@@ -0,0 +1,135 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, Utc};
+
+/// The longest a template's name can be.
+pub(crate) const MAX_NAME_LEN: usize = 50;
+
+/// What a template's placeholders can be, and what each is replaced with when it's sent.
+pub(crate) const PLACEHOLDERS: &[(&str, &str)] = &[
+    ("date", "when it's scheduled for, in each reader's timezone"),
+    ("relative", "how long until or since then, e.g. in 2 hours"),
+    ("mention", "mentions of the server's registered players"),
+    (
+        "session_number",
+        "the number of the next session, counting those run with /session",
+    ),
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Error {
+    EmptyName,
+    NameTooLong,
+    EmptyText,
+    UnknownPlaceholder(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::EmptyName => write!(f, "A template needs a name"),
+            Error::NameTooLong => write!(
+                f,
+                "A template's name can be at most {} characters long",
+                MAX_NAME_LEN
+            ),
+            Error::EmptyText => write!(f, "A template needs some text"),
+            Error::UnknownPlaceholder(name) => {
+                let known = PLACEHOLDERS
+                    .iter()
+                    .map(|(name, _)| format!("{{{}}}", name))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "There's no placeholder {{{}}}, only {}", name, known)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Normalizes a template's name, so names are matched regardless of case.
+pub(crate) fn normalize_name(name: &str) -> Result<String, Error> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() {
+        return Err(Error::EmptyName);
+    }
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(Error::NameTooLong);
+    }
+
+    Ok(name)
+}
+
+/// Trims a template's text, checking every placeholder in it is known, so a typo is caught when
+/// the template is saved rather than when it's sent.
+pub(crate) fn normalize_text(text: &str) -> Result<String, Error> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(Error::EmptyText);
+    }
+    if let Some(unknown) = placeholders(text).find(|name| !is_placeholder(name)) {
+        return Err(Error::UnknownPlaceholder(unknown.to_string()));
+    }
+
+    Ok(text.to_string())
+}
+
+fn is_placeholder(name: &str) -> bool {
+    PLACEHOLDERS.iter().any(|(known, _)| *known == name)
+}
+
+/// The names of the `{placeholder}`s in a text. Braces that aren't closed aren't placeholders.
+fn placeholders(text: &str) -> impl Iterator<Item = &str> {
+    text.split('{')
+        .skip(1)
+        .filter_map(|rest| rest.split_once('}'))
+        .map(|(name, _)| name)
+}
+
+/// What a template's placeholders are replaced with when it's sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Values {
+    pub on: DateTime<Utc>,
+    pub players: Vec<i64>,
+    pub session_number: i64,
+}
+
+/// Replaces a template's placeholders with their values. Anything that isn't a known placeholder
+/// is left as it is.
+pub(crate) fn render(text: &str, values: &Values) -> String {
+    let mut rendered = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        // Only the last brace opened before one's closed starts a placeholder, as in `{{date}`.
+        if rest[start + 1..start + len].contains('{') {
+            rendered.push_str(&rest[..=start]);
+            rest = &rest[start + 1..];
+            continue;
+        }
+        rendered.push_str(&rest[..start]);
+        let placeholder = &rest[start..start + len + 1];
+        rest = &rest[start + len + 1..];
+
+        match &placeholder[1..placeholder.len() - 1] {
+            "date" => rendered.push_str(&format!("<t:{}:F>", values.on.timestamp())),
+            "relative" => rendered.push_str(&format!("<t:{}:R>", values.on.timestamp())),
+            "mention" => rendered.push_str(
+                &values
+                    .players
+                    .iter()
+                    .map(|id| format!("<@{}>", id))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            "session_number" => rendered.push_str(&values.session_number.to_string()),
+            _ => rendered.push_str(placeholder),
+        }
+    }
+    rendered.push_str(rest);
+
+    rendered
+}
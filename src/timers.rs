@@ -0,0 +1,169 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use chrono::Utc;
+use poise::serenity_prelude as serenity;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use tokio::task::AbortHandle;
+
+use crate::{
+    db::{self, Timer},
+    Error, Result,
+};
+
+/// The longest a timer can run, in minutes.
+pub(crate) const MAX_MINUTES: u32 = 12 * 60;
+
+/// How often a timer's message is updated with the time left.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often it's updated in the last minute. Editing a message much more often than this runs
+/// into Discord's rate limits.
+const FINAL_UPDATE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The running timers, by channel, so they can be cancelled.
+#[derive(Default)]
+pub(crate) struct Timers {
+    running: Mutex<HashMap<u64, AbortHandle>>,
+}
+
+impl Timers {
+    /// Runs a timer until it ends, updating its message and pinging whoever started it at the
+    /// end. Replaces any timer already running in the channel.
+    pub(crate) fn spawn(
+        &self,
+        http: impl AsRef<serenity::Http> + Send + 'static,
+        pool: Pool<SqliteConnectionManager>,
+        timer: Timer,
+    ) {
+        let channel_id = timer.channel_id as u64;
+        let task = tokio::spawn(async move {
+            run(http.as_ref(), &timer).await;
+            let ended = pool
+                .get()
+                .map_err(Error::from)
+                .and_then(|conn| Ok(db::delete_timer(&conn, timer.channel_id)?));
+            if let Err(e) = ended {
+                log::error!("Error removing timer in {}: {}", timer.channel_id, e);
+            }
+        });
+
+        if let Some(previous) = self
+            .running
+            .lock()
+            .expect("Unable to lock timers")
+            .insert(channel_id, task.abort_handle())
+        {
+            previous.abort();
+        }
+    }
+
+    /// Resumes the timers that were running when the bot stopped.
+    pub(crate) fn resume(
+        &self,
+        http: impl AsRef<serenity::Http> + Clone + Send + 'static,
+        pool: Pool<SqliteConnectionManager>,
+    ) -> Result<()> {
+        let timers = db::get_timers(&*pool.get()?)?;
+        for timer in timers {
+            log::info!("Resuming the timer in {}", timer.channel_id);
+            self.spawn(http.clone(), pool.clone(), timer);
+        }
+
+        Ok(())
+    }
+
+    /// Stops a channel's timer, if one is running.
+    pub(crate) fn cancel(&self, channel_id: u64) {
+        if let Some(task) = self
+            .running
+            .lock()
+            .expect("Unable to lock timers")
+            .remove(&channel_id)
+        {
+            task.abort();
+        }
+    }
+}
+
+async fn run(http: &serenity::Http, timer: &Timer) {
+    let channel_id = serenity::ChannelId::new(timer.channel_id as u64);
+    let message_id = serenity::MessageId::new(timer.message_id as u64);
+
+    while let Some(remaining) = (timer.ends_at - Utc::now())
+        .to_std()
+        .ok()
+        .filter(|remaining| !remaining.is_zero())
+    {
+        let edit = serenity::EditMessage::new().content(running_text(timer, remaining));
+        if let Err(e) = channel_id.edit_message(http, message_id, edit).await {
+            log::warn!("Error updating timer in {}: {}", channel_id, e);
+        }
+        tokio::time::sleep(next_update(remaining)).await;
+    }
+
+    let edit = serenity::EditMessage::new().content(ended_text(timer));
+    if let Err(e) = channel_id.edit_message(http, message_id, edit).await {
+        log::warn!("Error updating timer in {}: {}", channel_id, e);
+    }
+    // Timers that ended while the bot was down are announced late, so say when they ended.
+    let late = Utc::now() - timer.ends_at > chrono::Duration::minutes(1);
+    let ping = if late {
+        format!(
+            "<@{}> {} It ended <t:{}:R>, while I was offline.",
+            timer.user_id,
+            ended_text(timer),
+            timer.ends_at.timestamp()
+        )
+    } else {
+        format!("<@{}> {}", timer.user_id, ended_text(timer))
+    };
+    if let Err(e) = channel_id.say(http, ping).await {
+        log::error!("Error announcing timer in {}: {}", channel_id, e);
+    }
+}
+
+/// How long to wait before updating a timer's message, given the time it has left.
+pub(crate) fn next_update(remaining: Duration) -> Duration {
+    let interval = if remaining > Duration::from_secs(60) {
+        UPDATE_INTERVAL
+    } else {
+        FINAL_UPDATE_INTERVAL
+    };
+
+    interval.min(remaining)
+}
+
+/// Time left as `h:mm:ss`, or `m:ss` under an hour. Partial seconds round up, so a timer never
+/// shows 0:00 before it ends.
+pub(crate) fn format_remaining(remaining: Duration) -> String {
+    let seconds = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+fn title(timer: &Timer) -> String {
+    match &timer.label {
+        Some(label) => format!("**{}**", label),
+        None => "Timer".to_string(),
+    }
+}
+
+/// A running timer's message.
+pub(crate) fn running_text(timer: &Timer, remaining: Duration) -> String {
+    format!(
+        "⏳ {}: {} left (ends <t:{}:R>)",
+        title(timer),
+        format_remaining(remaining),
+        timer.ends_at.timestamp()
+    )
+}
+
+/// A timer's message once it has ended.
+pub(crate) fn ended_text(timer: &Timer) -> String {
+    format!("⌛ {}: time's up!", title(timer))
+}